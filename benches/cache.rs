@@ -0,0 +1,90 @@
+// Throughput of the cache under the standard workloads across page sizes and
+// capacities. Run with `cargo bench`, optionally followed by a filter.
+
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use wt_cache::workload::Workload;
+use wt_cache::WriteThroughCache;
+
+const FILE_SIZE: u64 = 64 * 1024 * 1024; // 64MiB
+const IO_SIZE: usize = 4 * 1024;
+// Fill with whole-page writes of the largest page size
+const FILL_SIZE: usize = 1024 * 1024;
+const OPS: usize = 1000;
+
+const PAGE_SIZES: [usize; 3] = [4 * 1024, 64 * 1024, 1024 * 1024];
+const CAPACITIES: [usize; 3] = [4 * 1024 * 1024, 16 * 1024 * 1024, 128 * 1024 * 1024];
+
+fn open(path: &Path, page_size: usize, capacity: usize) -> WriteThroughCache {
+    let mut cache = WriteThroughCache::builder(path)
+        .page_size(page_size)
+        .capacity(capacity)
+        .open()
+        .unwrap();
+    for op in Workload::fill(FILE_SIZE, FILL_SIZE) {
+        op.apply(&mut cache, 1).unwrap();
+    }
+    cache
+}
+
+fn bench_workload(c: &mut Criterion, name: &str, workload: impl Fn() -> Workload) {
+    let dir = tempfile::tempdir().unwrap();
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Bytes((OPS * IO_SIZE) as u64));
+    group.sample_size(10);
+
+    for page_size in PAGE_SIZES {
+        for capacity in CAPACITIES {
+            let id = format!(
+                "page_{}k/cap_{}m",
+                page_size / 1024,
+                capacity / (1024 * 1024)
+            );
+            let path = dir.path().join(id.replace('/', "_"));
+            let mut cache = None;
+            let mut ops = workload();
+            group.bench_function(BenchmarkId::from_parameter(&id), |b| {
+                // Populated lazily so filtered-out cases cost nothing
+                let cache = cache.get_or_insert_with(|| open(&path, page_size, capacity));
+                b.iter(|| ops.run(cache, OPS).unwrap())
+            });
+            drop(cache);
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+    group.finish();
+}
+
+fn random_read(c: &mut Criterion) {
+    bench_workload(c, "random_read", || {
+        Workload::random_reads(FILE_SIZE, IO_SIZE, 1)
+    });
+}
+
+fn sequential_write(c: &mut Criterion) {
+    bench_workload(c, "sequential_write", || {
+        Workload::sequential_writes(FILE_SIZE, IO_SIZE)
+    });
+}
+
+fn zipfian_read_heavy(c: &mut Criterion) {
+    bench_workload(c, "zipfian_read_heavy", || {
+        Workload::zipfian(FILE_SIZE, IO_SIZE, 0.99, 0.95, 1)
+    });
+}
+
+fn zipfian_mixed(c: &mut Criterion) {
+    bench_workload(c, "zipfian_mixed", || {
+        Workload::zipfian(FILE_SIZE, IO_SIZE, 0.99, 0.5, 1)
+    });
+}
+
+criterion_group!(
+    benches,
+    random_read,
+    sequential_write,
+    zipfian_read_heavy,
+    zipfian_mixed
+);
+criterion_main!(benches);
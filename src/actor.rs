@@ -0,0 +1,73 @@
+//! A cache owned by its own thread, used from others through handles.
+//!
+//! Each operation is sent to the cache thread as a closure and its result
+//! sent back on a channel of its own, so any number of threads can share
+//! the cache with no locking in it. Operations run one at a time, in the
+//! order the thread receives them. The thread closes the cache and exits
+//! once every handle is dropped.
+
+use std::io::Error;
+use std::sync::mpsc::{channel, sync_channel, Sender};
+
+use crate::{CacheStats, WriteThroughCache};
+
+type Job = Box<dyn FnOnce(&mut WriteThroughCache) + Send>;
+
+/// Cloneable handle to a cache running on its own thread.
+#[derive(Clone)]
+pub struct CacheHandle {
+    jobs: Sender<Job>,
+}
+
+impl CacheHandle {
+    /// Move `cache` to a new thread and return a handle to it.
+    pub fn spawn(mut cache: WriteThroughCache) -> std::io::Result<Self> {
+        let (jobs, received) = channel::<Job>();
+        std::thread::Builder::new()
+            .name("wt_cache".to_string())
+            .spawn(move || {
+                for job in received {
+                    job(&mut cache);
+                }
+            })?;
+        Ok(Self { jobs })
+    }
+
+    pub fn read(&self, address: u64, size: usize) -> std::io::Result<Vec<u8>> {
+        self.call(move |cache| cache.read(address, size))?
+    }
+
+    pub fn write(&self, address: u64, data: Vec<u8>) -> std::io::Result<()> {
+        self.call(move |cache| cache.write(address, &data))?
+    }
+
+    pub fn file_size(&self) -> std::io::Result<u64> {
+        self.call(|cache| cache.file_size())
+    }
+
+    pub fn stats(&self) -> std::io::Result<CacheStats> {
+        self.call(|cache| cache.stats())
+    }
+
+    /// Run `f` on the cache thread and return its result, for operations
+    /// without a method here or several that must run together.
+    pub fn call<R, F>(&self, f: F) -> std::io::Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut WriteThroughCache) -> R + Send + 'static,
+    {
+        let (reply, result) = sync_channel(1);
+        self.jobs
+            .send(Box::new(move |cache| {
+                // The caller may have stopped waiting
+                let _ = reply.send(f(cache));
+            }))
+            .map_err(|_| stopped())?;
+        result.recv().map_err(|_| stopped())
+    }
+}
+
+// The thread only stops early if an operation panicked on it
+fn stopped() -> Error {
+    Error::other("Cache thread has stopped")
+}
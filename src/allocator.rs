@@ -0,0 +1,243 @@
+//! Page allocation inside a cache-managed file.
+//!
+//! Page 0 holds the allocator's header. Freed pages are kept on a linked
+//! list of runs of contiguous pages, threaded through the first page of
+//! each run, and are handed out again before the file grows. Extents of
+//! several contiguous pages are taken from the first run large enough, so
+//! large objects can be laid out for sequential reads. Adjacent runs are
+//! not merged.
+
+use std::io::{Error, ErrorKind};
+use std::ops::Range;
+
+use crate::WriteThroughCache;
+
+const MAGIC: [u8; 8] = *b"WTALLOC\0";
+// magic | page count u64 | free list head u64 | free pages u64 | crc32
+const HEADER_LEN: usize = 8 + 8 + 8 + 8 + 4;
+// Start of a run on the free list: magic | next run u64 | pages in run u64
+const FREE_MAGIC: [u8; 8] = *b"WTFREE\0\0";
+const FREE_LEN: usize = 8 + 8 + 8;
+// Page ids start after the header page, so 0 can end the free list
+const NO_PAGE: u64 = 0;
+
+pub struct PageAllocator {
+    cache: WriteThroughCache,
+    // Pages in the file, including the header page
+    page_count: u64,
+    free_head: u64,
+    free_pages: u64,
+}
+
+impl PageAllocator {
+    /// Open the allocator in `cache`'s file, setting it up if the file is
+    /// empty.
+    pub fn open(mut cache: WriteThroughCache) -> std::io::Result<Self> {
+        if cache.file_size() == 0 {
+            let mut allocator = Self {
+                cache,
+                page_count: 1,
+                free_head: NO_PAGE,
+                free_pages: 0,
+            };
+            allocator.store_header()?;
+            return Ok(allocator);
+        }
+
+        let header = cache.read(0, HEADER_LEN)?;
+        let (body, crc) = header.split_at(HEADER_LEN - 4);
+        if body[..8] != MAGIC
+            || u32::from_le_bytes(crc.try_into().unwrap()) != crc32fast::hash(body)
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Invalid allocator header",
+            ));
+        }
+        let field =
+            |index: usize| u64::from_le_bytes(body[8 * index..8 * (index + 1)].try_into().unwrap());
+        Ok(Self {
+            cache,
+            page_count: field(1),
+            free_head: field(2),
+            free_pages: field(3),
+        })
+    }
+
+    /// Allocate a page, reusing a freed one if there is any. A reused page
+    /// keeps what it held before, apart from its first bytes, so callers
+    /// should write the whole page.
+    pub fn alloc_page(&mut self) -> std::io::Result<u64> {
+        self.alloc_extent(1)
+    }
+
+    /// Allocate `pages` contiguous pages and return the first. Freed pages
+    /// are reused from the first run that has enough of them, otherwise the
+    /// file grows. As with `alloc_page`, reused pages keep their contents.
+    pub fn alloc_extent(&mut self, pages: u64) -> std::io::Result<u64> {
+        if pages == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Extent must have at least one page",
+            ));
+        }
+
+        // Run before the one being looked at, with its size
+        let mut previous = (NO_PAGE, 0);
+        let mut run = self.free_head;
+        // Pages in the runs walked so far, which can't pass the recorded
+        // total unless the list is corrupt or loops
+        let mut walked = 0u64;
+        while run != NO_PAGE {
+            let (next, run_pages) = self.read_link(run)?;
+            walked = walked.saturating_add(run_pages);
+            if run_pages == 0 || walked > self.free_pages {
+                return Err(overfull_list());
+            }
+            if run_pages >= pages {
+                // Leave the rest of the run on the list in its place
+                let next = if run_pages > pages {
+                    self.write_link(run + pages, next, run_pages - pages)?;
+                    run + pages
+                } else {
+                    next
+                };
+                self.free_pages -= pages;
+                match previous {
+                    (NO_PAGE, _) => self.free_head = next,
+                    (previous, previous_pages) => {
+                        self.write_link(previous, next, previous_pages)?
+                    }
+                }
+                self.store_header()?;
+                return Ok(run);
+            }
+            previous = (run, run_pages);
+            run = next;
+        }
+
+        let page_id = self.page_count;
+        self.page_count += pages;
+        self.store_header()?;
+        Ok(page_id)
+    }
+
+    /// Return `page_id` to the free list.
+    pub fn free_page(&mut self, page_id: u64) -> std::io::Result<()> {
+        self.free_extent(page_id, 1)
+    }
+
+    /// Return the `pages` contiguous pages from `page_id` to the free list
+    /// as one run.
+    pub fn free_extent(&mut self, page_id: u64, pages: u64) -> std::io::Result<()> {
+        if page_id == NO_PAGE || pages == 0 || page_id.saturating_add(pages) > self.page_count {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Pages {}..{} were never allocated",
+                    page_id,
+                    page_id.saturating_add(pages)
+                ),
+            ));
+        }
+        if let Some(page) = self.first_free_in(page_id..page_id + pages)? {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Page {} is already free", page),
+            ));
+        }
+        // The link goes in before the header points at it
+        self.write_link(page_id, self.free_head, pages)?;
+        self.free_head = page_id;
+        self.free_pages += pages;
+        self.store_header()
+    }
+
+    /// Pages handed out and not freed.
+    pub fn allocated_pages(&self) -> u64 {
+        self.page_count - 1 - self.free_pages
+    }
+
+    /// Pages on the free list.
+    pub fn free_pages(&self) -> u64 {
+        self.free_pages
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.cache.page_size()
+    }
+
+    /// Byte address of `page_id`, for reading and writing it through
+    /// `cache`.
+    pub fn address(&self, page_id: u64) -> u64 {
+        page_id * self.cache.page_size() as u64
+    }
+
+    /// The cache, for I/O on allocated pages.
+    pub fn cache(&mut self) -> &mut WriteThroughCache {
+        &mut self.cache
+    }
+
+    pub fn into_inner(self) -> WriteThroughCache {
+        self.cache
+    }
+
+    // Next run and size of the free run starting at `page_id`
+    fn read_link(&mut self, page_id: u64) -> std::io::Result<(u64, u64)> {
+        let link = self.cache.read(self.address(page_id), FREE_LEN)?;
+        if link[..8] != FREE_MAGIC {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Page {} on the free list is not free", page_id),
+            ));
+        }
+        let field =
+            |index: usize| u64::from_le_bytes(link[8 * index..8 * (index + 1)].try_into().unwrap());
+        Ok((field(1), field(2)))
+    }
+
+    // First page of `pages` that lies in any run on the free list, found by
+    // walking the whole list, so pages inside a run are caught as well as
+    // its head
+    fn first_free_in(&mut self, pages: Range<u64>) -> std::io::Result<Option<u64>> {
+        let mut run = self.free_head;
+        let mut walked = 0u64;
+        while run != NO_PAGE {
+            let (next, run_pages) = self.read_link(run)?;
+            walked = walked.saturating_add(run_pages);
+            if run_pages == 0 || walked > self.free_pages {
+                return Err(overfull_list());
+            }
+            if run < pages.end && pages.start < run + run_pages {
+                return Ok(Some(run.max(pages.start)));
+            }
+            run = next;
+        }
+        Ok(None)
+    }
+
+    fn write_link(&mut self, page_id: u64, next: u64, pages: u64) -> std::io::Result<()> {
+        let mut link = Vec::with_capacity(FREE_LEN);
+        link.extend_from_slice(&FREE_MAGIC);
+        link.extend_from_slice(&next.to_le_bytes());
+        link.extend_from_slice(&pages.to_le_bytes());
+        self.cache.write(self.address(page_id), &link)
+    }
+
+    fn store_header(&mut self) -> std::io::Result<()> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&self.page_count.to_le_bytes());
+        header.extend_from_slice(&self.free_head.to_le_bytes());
+        header.extend_from_slice(&self.free_pages.to_le_bytes());
+        header.extend_from_slice(&crc32fast::hash(&header).to_le_bytes());
+        self.cache.write(0, &header)
+    }
+}
+
+fn overfull_list() -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        "Free list holds more pages than the header records",
+    )
+}
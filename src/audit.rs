@@ -0,0 +1,97 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use crate::pio::read_exact_at;
+
+// Record layout: offset u64 | length u64 | timestamp (ns since epoch) u64 |
+// SHA-256 of the data | SHA-256 of (previous record hash | the fields before).
+const RECORD_LEN: usize = 8 + 8 + 8 + 32 + 32;
+
+pub struct AuditLog {
+    file: File,
+    head: [u8; 32],
+}
+
+impl AuditLog {
+    pub fn path_for(file_path: &Path) -> PathBuf {
+        let mut path = file_path.as_os_str().to_owned();
+        path.push(".audit");
+        PathBuf::from(path)
+    }
+
+    pub fn open(file_path: &Path) -> std::io::Result<Self> {
+        let file = File::options()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(Self::path_for(file_path))?;
+
+        let len = file.metadata()?.len();
+        if len % RECORD_LEN as u64 != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Truncated audit log"));
+        }
+
+        let mut head = [0; 32];
+        if len > 0 {
+            read_exact_at(&file, &mut head, len - 32)?;
+        }
+
+        Ok(Self { file, head })
+    }
+
+    pub fn head(&self) -> [u8; 32] {
+        self.head
+    }
+
+    pub fn append(&mut self, offset: u64, data: &[u8]) -> std::io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+
+        let mut record = Vec::with_capacity(RECORD_LEN);
+        record.extend_from_slice(&offset.to_le_bytes());
+        record.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        record.extend_from_slice(&timestamp.to_le_bytes());
+        record.extend_from_slice(&Sha256::digest(data));
+        let hash = chain_hash(&self.head, &record);
+        record.extend_from_slice(&hash);
+
+        self.file.write_all(&record)?;
+        self.file.sync_data()?;
+        self.head = hash;
+        Ok(())
+    }
+
+    pub fn verify(file_path: &Path) -> std::io::Result<u64> {
+        let mut bytes = Vec::new();
+        File::open(Self::path_for(file_path))?.read_to_end(&mut bytes)?;
+        if bytes.len() % RECORD_LEN != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Truncated audit log"));
+        }
+
+        let mut head = [0; 32];
+        for (index, record) in bytes.chunks_exact(RECORD_LEN).enumerate() {
+            let (fields, hash) = record.split_at(RECORD_LEN - 32);
+            if chain_hash(&head, fields)[..] != *hash {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Audit log chain broken at record {}", index),
+                ));
+            }
+            head.copy_from_slice(hash);
+        }
+
+        Ok((bytes.len() / RECORD_LEN) as u64)
+    }
+}
+
+fn chain_hash(previous: &[u8; 32], fields: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(previous);
+    hasher.update(fields);
+    hasher.finalize().into()
+}
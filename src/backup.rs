@@ -0,0 +1,119 @@
+//! Consistent copies of a file taken while it stays in use.
+//!
+//! A backup copies the file a few pages at a time, with reads and writes
+//! free to carry on between steps. Pages written after the backup started
+//! are tracked, and copied again when it finishes, so the copy matches the
+//! file as of `finish`.
+
+use std::path::Path;
+
+use crate::{ChangeTracker, WriteThroughCache};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackupReport {
+    /// Pages copied by the first pass.
+    pub pages_copied: u64,
+    /// Pages copied again because they were written during the backup.
+    pub pages_recopied: u64,
+}
+
+/// A backup in progress, from `WriteThroughCache::start_backup`.
+pub struct Backup {
+    dest: WriteThroughCache,
+    epoch: u64,
+    next_page: u64,
+    // Whether tracking was turned on for this backup, and so should be
+    // turned off again once it finishes
+    owns_tracking: bool,
+    report: BackupReport,
+}
+
+impl WriteThroughCache {
+    /// Copy the file to `dest_path`, as `start_backup` followed by
+    /// `Backup::finish`.
+    pub fn backup_to(&mut self, dest_path: &Path) -> std::io::Result<BackupReport> {
+        self.start_backup(dest_path)?.finish(self)
+    }
+
+    /// Start copying the file to `dest_path`. The copy is made by calling
+    /// `Backup::step` between other use of the cache, then
+    /// `Backup::finish`.
+    pub fn start_backup(&mut self, dest_path: &Path) -> std::io::Result<Backup> {
+        if dest_path.exists() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "Backup target already exists",
+            ));
+        }
+
+        let mut dest = WriteThroughCache::builder(dest_path)
+            .page_size(self.page_size)
+            .capacity(self.page_size)
+            .open()?;
+        dest.header.partitions = self.header.partitions.clone();
+        dest.header.store(&dest.file_path)?;
+
+        let owns_tracking = self.changes.is_none();
+        let epoch = self
+            .changes
+            .get_or_insert_with(ChangeTracker::default)
+            .begin();
+        Ok(Backup {
+            dest,
+            epoch,
+            next_page: 0,
+            owns_tracking,
+            report: BackupReport::default(),
+        })
+    }
+}
+
+impl Backup {
+    /// Copy up to `max_pages` more pages, returning whether the first pass
+    /// has reached the end of the file.
+    pub fn step(&mut self, cache: &mut WriteThroughCache, max_pages: u64) -> std::io::Result<bool> {
+        let page_count = cache.file_size.div_ceil(cache.page_size as u64);
+        let end = std::cmp::min(page_count, self.next_page.saturating_add(max_pages));
+        for page_id in self.next_page..end {
+            self.copy_page(cache, page_id)?;
+            self.report.pages_copied += 1;
+        }
+        self.next_page = end;
+        Ok(end == page_count)
+    }
+
+    /// Copy the rest of the file, then every page written since the backup
+    /// started, and close the copy.
+    pub fn finish(mut self, cache: &mut WriteThroughCache) -> std::io::Result<BackupReport> {
+        while !self.step(cache, u64::MAX)? {}
+
+        let changed = match &cache.changes {
+            Some(changes) => changes.changed_since(self.epoch),
+            None => Vec::new(),
+        };
+        for page_id in changed {
+            self.copy_page(cache, page_id)?;
+            self.report.pages_recopied += 1;
+        }
+
+        // Pages are written whole, so a partial last page is cut back to
+        // where the file ends
+        if self.dest.file_size > cache.file_size {
+            self.dest.file.set_len(cache.file_size)?;
+            self.dest.file_size = cache.file_size;
+            self.dest.sync(cache.file_size / cache.page_size as u64)?;
+        }
+
+        if self.owns_tracking && cache.changes.as_ref().is_some_and(|c| !c.requested) {
+            cache.changes = None;
+        }
+        Ok(self.report)
+    }
+
+    fn copy_page(&mut self, cache: &mut WriteThroughCache, page_id: u64) -> std::io::Result<()> {
+        let address = page_id * cache.page_size as u64;
+        let len = std::cmp::min(cache.page_size as u64, cache.file_size - address);
+        let page = cache.read_range(address, len as usize)?;
+        self.dest.write(address, &page)
+    }
+}
@@ -0,0 +1,126 @@
+//! A persistent bitmap kept in pages reserved for it, for tracking which
+//! pages or slots of a larger structure are in use.
+//!
+//! Bits are read and written through the cache, so hot parts of the map
+//! stay in memory. Each update writes the single byte holding the bit, which
+//! a crash can't tear.
+
+use std::io::{Error, ErrorKind};
+
+use crate::WriteThroughCache;
+
+const MAGIC: [u8; 8] = *b"WTBITMAP";
+// magic | bits u64 | crc32, followed by the bits, lowest first in each byte
+const HEADER_LEN: usize = 8 + 8 + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bitmap {
+    // Address of the header
+    start: u64,
+    bits: u64,
+}
+
+impl Bitmap {
+    /// Pages to reserve for a bitmap of `bits` bits.
+    pub fn reserved_pages(bits: u64, page_size: usize) -> u64 {
+        (HEADER_LEN as u64 + bits.div_ceil(8)).div_ceil(page_size as u64)
+    }
+
+    /// Set up a bitmap of `bits` clear bits in the pages from `start_page`,
+    /// which must have `reserved_pages` of them.
+    pub fn create(
+        cache: &mut WriteThroughCache,
+        start_page: u64,
+        bits: u64,
+    ) -> std::io::Result<Self> {
+        let bitmap = Self {
+            start: start_page * cache.page_size() as u64,
+            bits,
+        };
+        // The bits are cleared before the header makes them valid
+        cache.write(
+            bitmap.start + HEADER_LEN as u64,
+            &vec![0; bits.div_ceil(8) as usize],
+        )?;
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&bits.to_le_bytes());
+        header.extend_from_slice(&crc32fast::hash(&header).to_le_bytes());
+        cache.write(bitmap.start, &header)?;
+        Ok(bitmap)
+    }
+
+    /// Open the bitmap created at `start_page`.
+    pub fn open(cache: &mut WriteThroughCache, start_page: u64) -> std::io::Result<Self> {
+        let start = start_page * cache.page_size() as u64;
+        let header = cache.read(start, HEADER_LEN)?;
+        let (body, crc) = header.split_at(HEADER_LEN - 4);
+        if body[..8] != MAGIC
+            || u32::from_le_bytes(crc.try_into().unwrap()) != crc32fast::hash(body)
+        {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid bitmap header"));
+        }
+        let bits = u64::from_le_bytes(body[8..16].try_into().unwrap());
+        Ok(Self { start, bits })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.bits
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    pub fn test(&self, cache: &mut WriteThroughCache, bit: u64) -> std::io::Result<bool> {
+        let (address, mask) = self.locate(bit)?;
+        Ok(cache.read(address, 1)?[0] & mask != 0)
+    }
+
+    pub fn set(&self, cache: &mut WriteThroughCache, bit: u64) -> std::io::Result<()> {
+        self.update(cache, bit, true)
+    }
+
+    pub fn clear(&self, cache: &mut WriteThroughCache, bit: u64) -> std::io::Result<()> {
+        self.update(cache, bit, false)
+    }
+
+    /// Lowest clear bit, or `None` if every bit is set.
+    pub fn find_first_clear(&self, cache: &mut WriteThroughCache) -> std::io::Result<Option<u64>> {
+        let chunk_size = cache.page_size() as u64;
+        let bytes = self.bits.div_ceil(8);
+        let mut offset = 0;
+        while offset < bytes {
+            let len = std::cmp::min(chunk_size, bytes - offset);
+            let chunk = cache.read(self.start + HEADER_LEN as u64 + offset, len as usize)?;
+            if let Some(index) = chunk.iter().position(|&byte| byte != 0xff) {
+                let bit = (offset + index as u64) * 8 + chunk[index].trailing_ones() as u64;
+                // Padding bits past the end are never set
+                return Ok((bit < self.bits).then_some(bit));
+            }
+            offset += len;
+        }
+        Ok(None)
+    }
+
+    fn update(&self, cache: &mut WriteThroughCache, bit: u64, value: bool) -> std::io::Result<()> {
+        let (address, mask) = self.locate(bit)?;
+        let byte = cache.read(address, 1)?[0];
+        let updated = if value { byte | mask } else { byte & !mask };
+        if updated != byte {
+            cache.write(address, &[updated])?;
+        }
+        Ok(())
+    }
+
+    // Address of the byte holding `bit`, and the bit's mask in it
+    fn locate(&self, bit: u64) -> std::io::Result<(u64, u8)> {
+        if bit >= self.bits {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Bit {} is past the end of a {} bit map", bit, self.bits),
+            ));
+        }
+        Ok((self.start + HEADER_LEN as u64 + bit / 8, 1 << (bit % 8)))
+    }
+}
@@ -0,0 +1,188 @@
+//! Variable-size objects stored in extents from a `PageAllocator`.
+//!
+//! Each blob takes a contiguous extent, so it is read back sequentially.
+//! The first bytes of the extent describe the blob, and the handle returned
+//! for it is the extent's first page. Blobs at least as large as a set
+//! threshold can be compressed with a codec, and are kept compressed only
+//! if that makes them smaller.
+
+use std::io::{Error, ErrorKind};
+
+use crate::allocator::PageAllocator;
+use crate::{Codec, CODEC_RAW};
+
+const MAGIC: [u8; 8] = *b"WTBLOB\0\0";
+// magic | codec u32 | length u64 | stored length u64 | pages u64 |
+// crc32 of the stored data | crc32 of the rest of the header
+const HEADER_LEN: usize = 8 + 4 + 8 + 8 + 8 + 4 + 4;
+
+/// Handle of a stored blob, which stays valid until the blob is deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlobId(pub u64);
+
+struct Header {
+    codec: u32,
+    len: u64,
+    stored_len: u64,
+    pages: u64,
+    data_crc: u32,
+}
+
+pub struct BlobStore {
+    allocator: PageAllocator,
+    codec: Option<Box<dyn Codec>>,
+    compression_threshold: usize,
+}
+
+impl BlobStore {
+    pub fn open(allocator: PageAllocator) -> Self {
+        Self {
+            allocator,
+            codec: None,
+            compression_threshold: 0,
+        }
+    }
+
+    /// Compress blobs of at least `threshold` bytes with `codec`. Blobs
+    /// already stored with the same codec can then be loaded.
+    pub fn compression(mut self, codec: Box<dyn Codec>, threshold: usize) -> Self {
+        self.codec = Some(codec);
+        self.compression_threshold = threshold;
+        self
+    }
+
+    pub fn store(&mut self, data: &[u8]) -> std::io::Result<BlobId> {
+        let (codec, stored) = match self.codec.as_mut() {
+            Some(codec) if data.len() >= self.compression_threshold => {
+                let mut output = vec![0; codec.max_compressed_len(data.len())];
+                let len = codec.compress(data, &mut output)?;
+                if len < data.len() {
+                    output.truncate(len);
+                    (codec.id(), Some(output))
+                } else {
+                    (CODEC_RAW, None)
+                }
+            }
+            _ => (CODEC_RAW, None),
+        };
+        let stored = stored.as_deref().unwrap_or(data);
+
+        let page_size = self.allocator.page_size() as u64;
+        let pages = ((HEADER_LEN + stored.len()) as u64).div_ceil(page_size);
+        let page_id = self.allocator.alloc_extent(pages)?;
+        let header = Header {
+            codec,
+            len: data.len() as u64,
+            stored_len: stored.len() as u64,
+            pages,
+            data_crc: crc32fast::hash(stored),
+        };
+        // The data goes in before the header that makes the blob valid
+        let address = self.allocator.address(page_id);
+        let cache = self.allocator.cache();
+        cache.write(address + HEADER_LEN as u64, stored)?;
+        cache.write(address, &header.encode())?;
+        Ok(BlobId(page_id))
+    }
+
+    pub fn load(&mut self, id: BlobId) -> std::io::Result<Vec<u8>> {
+        let header = self.read_header(id)?;
+        let address = self.allocator.address(id.0) + HEADER_LEN as u64;
+        let stored = self
+            .allocator
+            .cache()
+            .read(address, header.stored_len as usize)?;
+        if crc32fast::hash(&stored) != header.data_crc {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Blob {} failed its checksum", id.0),
+            ));
+        }
+        if header.codec == CODEC_RAW {
+            return Ok(stored);
+        }
+
+        let codec = match self.codec.as_mut() {
+            Some(codec) if codec.id() == header.codec => codec,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    format!("Blob {} needs codec {}", id.0, header.codec),
+                ))
+            }
+        };
+        let mut data = vec![0; header.len as usize];
+        let len = codec.decompress(&stored, &mut data)?;
+        if len != data.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Blob {} decompressed to {} bytes, not {}",
+                    id.0,
+                    len,
+                    data.len()
+                ),
+            ));
+        }
+        Ok(data)
+    }
+
+    /// Free the blob's extent. The handle must not be used again.
+    pub fn delete(&mut self, id: BlobId) -> std::io::Result<()> {
+        let header = self.read_header(id)?;
+        self.allocator.free_extent(id.0, header.pages)
+    }
+
+    /// Length of the blob as stored and loaded, before any compression.
+    pub fn size(&mut self, id: BlobId) -> std::io::Result<u64> {
+        Ok(self.read_header(id)?.len)
+    }
+
+    pub fn into_inner(self) -> PageAllocator {
+        self.allocator
+    }
+
+    fn read_header(&mut self, id: BlobId) -> std::io::Result<Header> {
+        let address = self.allocator.address(id.0);
+        let cache = self.allocator.cache();
+        if address + HEADER_LEN as u64 > cache.file_size() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("No blob {}", id.0),
+            ));
+        }
+        let header = cache.read(address, HEADER_LEN)?;
+        Header::decode(&header)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("No valid blob {}", id.0)))
+    }
+}
+
+impl Header {
+    fn encode(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&self.codec.to_le_bytes());
+        header.extend_from_slice(&self.len.to_le_bytes());
+        header.extend_from_slice(&self.stored_len.to_le_bytes());
+        header.extend_from_slice(&self.pages.to_le_bytes());
+        header.extend_from_slice(&self.data_crc.to_le_bytes());
+        header.extend_from_slice(&crc32fast::hash(&header).to_le_bytes());
+        header
+    }
+
+    fn decode(header: &[u8]) -> Option<Self> {
+        let (body, crc) = header.split_at(HEADER_LEN - 4);
+        if body[..8] != MAGIC || u32::from_le_bytes(crc.try_into().ok()?) != crc32fast::hash(body) {
+            return None;
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(body[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(body[at..at + 8].try_into().unwrap());
+        Some(Self {
+            codec: u32_at(8),
+            len: u64_at(12),
+            stored_len: u64_at(20),
+            pages: u64_at(28),
+            data_crc: u32_at(36),
+        })
+    }
+}
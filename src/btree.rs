@@ -0,0 +1,422 @@
+//! An on-disk B+tree whose nodes are pages handed out by a `PageAllocator`.
+//!
+//! Every node is a slotted page: slot 0 holds the node's kind and link, and
+//! the following slots hold its entries in key order. Leaves link to their
+//! right sibling for range scans; internal nodes link to their leftmost
+//! child. The first allocated page records the root.
+//!
+//! A split writes the new right node first, then the parent pointing at it,
+//! and the node it came from last. Until then that node keeps the entries
+//! that moved and its old link, which lookups no longer reach and scans
+//! read the same as before, so a crash part way through leaves a whole
+//! tree, with or without the insert, plus at most unreferenced pages.
+
+use std::io::{Error, ErrorKind};
+use std::ops::{Bound, RangeBounds};
+
+use crate::allocator::PageAllocator;
+use crate::slotted::SlottedPage;
+
+const MAGIC: [u8; 8] = *b"WTBTREE\0";
+// magic | root page u64 | crc32
+const META_LEN: usize = 8 + 8 + 4;
+const META_PAGE: u64 = 1;
+const KIND_LEAF: u8 = 1;
+const KIND_INTERNAL: u8 = 2;
+// Slotted page header, the node header record and its slot
+const NODE_OVERHEAD: usize = 8 + (1 + 8) + 8;
+// Per entry: key length u32 and the entry's slot
+const ENTRY_OVERHEAD: usize = 4 + 8;
+// Leaves have no right sibling, and internal nodes always have a child
+const NO_PAGE: u64 = 0;
+
+struct Node {
+    leaf: bool,
+    // Right sibling of a leaf, leftmost child of an internal node
+    link: u64,
+    // Values of an internal node are the child page holding keys from the
+    // entry's key up to the next entry's
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+// The new right half of a split node
+struct Split {
+    // First key in the right half
+    separator: Vec<u8>,
+    page_id: u64,
+}
+
+pub struct BTree {
+    allocator: PageAllocator,
+    root: u64,
+}
+
+impl BTree {
+    /// Open the tree kept in `allocator`'s file, creating an empty one if
+    /// no pages have been allocated yet.
+    pub fn open(mut allocator: PageAllocator) -> std::io::Result<Self> {
+        if allocator.allocated_pages() == 0 && allocator.free_pages() == 0 {
+            let meta = allocator.alloc_page()?;
+            debug_assert_eq!(meta, META_PAGE);
+            let root = allocator.alloc_page()?;
+            let mut tree = Self { allocator, root };
+            tree.store_node(root, &Node::new(true, NO_PAGE))?;
+            tree.store_meta(root)?;
+            return Ok(tree);
+        }
+
+        let address = allocator.address(META_PAGE);
+        let meta = allocator.cache().read(address, META_LEN)?;
+        let (body, crc) = meta.split_at(META_LEN - 4);
+        if body[..8] != MAGIC
+            || u32::from_le_bytes(crc.try_into().unwrap()) != crc32fast::hash(body)
+        {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid B-tree header"));
+        }
+        let root = u64::from_le_bytes(body[8..16].try_into().unwrap());
+        Ok(Self { allocator, root })
+    }
+
+    pub fn lookup(&mut self, key: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+        let page_id = self.find_leaf(key)?;
+        let leaf = self.load_node(page_id)?;
+        Ok(leaf
+            .search(key)
+            .ok()
+            .map(|index| leaf.entries[index].1.clone()))
+    }
+
+    /// Insert or replace the value for `key`. Returns the previous value.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+        if ENTRY_OVERHEAD + key.len() + value.len() > self.max_entry() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Key and value take {} bytes, more than the {} a node allows",
+                    key.len() + value.len(),
+                    self.max_entry() - ENTRY_OVERHEAD
+                ),
+            ));
+        }
+        let mut deferred = Vec::new();
+        let (previous, split) = self.insert_into(self.root, key, value, &mut deferred)?;
+        if let Some(Split { separator, page_id }) = split {
+            let root = self.allocator.alloc_page()?;
+            let mut node = Node::new(false, self.root);
+            node.entries
+                .push((separator, page_id.to_le_bytes().to_vec()));
+            self.store_node(root, &node)?;
+            self.store_meta(root)?;
+        }
+        self.store_deferred(deferred)?;
+        Ok(previous)
+    }
+
+    /// Remove `key`, returning its value. Nodes are not merged, so pages
+    /// emptied by deletes stay in the tree and are reused by later inserts.
+    pub fn delete(&mut self, key: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+        let page_id = self.find_leaf(key)?;
+        let mut leaf = self.load_node(page_id)?;
+        let Ok(index) = leaf.search(key) else {
+            return Ok(None);
+        };
+        let (_, value) = leaf.entries.remove(index);
+        self.store_node(page_id, &leaf)?;
+        Ok(Some(value))
+    }
+
+    /// Entries with keys in `range`, in key order. Leaves are read as the
+    /// iterator reaches them.
+    pub fn range<K: AsRef<[u8]>, R: RangeBounds<K>>(
+        &mut self,
+        range: R,
+    ) -> std::io::Result<Range<'_>> {
+        let owned = |bound: Bound<&K>| match bound {
+            Bound::Included(key) => Bound::Included(key.as_ref().to_vec()),
+            Bound::Excluded(key) => Bound::Excluded(key.as_ref().to_vec()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let start = owned(range.start_bound());
+        let end = owned(range.end_bound());
+        let page_id = match &start {
+            Bound::Included(key) | Bound::Excluded(key) => self.find_leaf(key)?,
+            Bound::Unbounded => self.find_leaf(&[])?,
+        };
+        let leaf = self.load_node(page_id)?;
+        let index = match &start {
+            Bound::Included(key) => leaf.search(key).unwrap_or_else(|index| index),
+            Bound::Excluded(key) => leaf
+                .search(key)
+                .map_or_else(|index| index, |index| index + 1),
+            Bound::Unbounded => 0,
+        };
+        Ok(Range {
+            tree: self,
+            leaf: Some(leaf),
+            index,
+            end,
+        })
+    }
+
+    /// Page holding the root node.
+    pub fn root(&self) -> u64 {
+        self.root
+    }
+
+    pub fn into_inner(self) -> PageAllocator {
+        self.allocator
+    }
+
+    // Largest entry a node takes, so that splitting a full node by size
+    // always leaves two halves that fit
+    fn max_entry(&self) -> usize {
+        (self.allocator.page_size() - NODE_OVERHEAD) / 4
+    }
+
+    fn find_leaf(&mut self, key: &[u8]) -> std::io::Result<u64> {
+        let mut page_id = self.root;
+        loop {
+            let node = self.load_node(page_id)?;
+            if node.leaf {
+                return Ok(page_id);
+            }
+            page_id = node.child(key);
+        }
+    }
+
+    // Insert into the subtree at `page_id`, returning the previous value
+    // and, if the node split, its new right half. The left halves of split
+    // nodes are pushed onto `deferred`, to be stored once their parent is.
+    fn insert_into(
+        &mut self,
+        page_id: u64,
+        key: &[u8],
+        value: &[u8],
+        deferred: &mut Vec<(u64, Node)>,
+    ) -> std::io::Result<(Option<Vec<u8>>, Option<Split>)> {
+        let mut node = self.load_node(page_id)?;
+        let previous = if node.leaf {
+            match node.search(key) {
+                Ok(index) => Some(std::mem::replace(
+                    &mut node.entries[index].1,
+                    value.to_vec(),
+                )),
+                Err(index) => {
+                    node.entries.insert(index, (key.to_vec(), value.to_vec()));
+                    None
+                }
+            }
+        } else {
+            let (previous, split) = self.insert_into(node.child(key), key, value, deferred)?;
+            let Some(Split {
+                separator,
+                page_id: child,
+            }) = split
+            else {
+                return Ok((previous, None));
+            };
+            let index = node.search(&separator).unwrap_or_else(|index| index);
+            node.entries
+                .insert(index, (separator, child.to_le_bytes().to_vec()));
+            previous
+        };
+
+        if node.encode(self.allocator.page_size()).is_some() {
+            self.store_node(page_id, &node)?;
+            self.store_deferred(std::mem::take(deferred))?;
+            return Ok((previous, None));
+        }
+        let (separator, right) = node.split();
+        let right_id = self.allocator.alloc_page()?;
+        if node.leaf {
+            node.link = right_id;
+        }
+        self.store_node(right_id, &right)?;
+        deferred.push((page_id, node));
+        let split = Split {
+            separator,
+            page_id: right_id,
+        };
+        Ok((previous, Some(split)))
+    }
+
+    fn load_node(&mut self, page_id: u64) -> std::io::Result<Node> {
+        let page = SlottedPage::load(self.allocator.cache(), page_id)?;
+        Node::decode(&page).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid node in page {}", page_id),
+            )
+        })
+    }
+
+    fn store_node(&mut self, page_id: u64, node: &Node) -> std::io::Result<()> {
+        let page = node
+            .encode(self.allocator.page_size())
+            .expect("node was split to fit its page");
+        page.store(self.allocator.cache(), page_id)
+    }
+
+    // Store left halves of splits, each after the parent above it
+    fn store_deferred(&mut self, mut deferred: Vec<(u64, Node)>) -> std::io::Result<()> {
+        while let Some((page_id, node)) = deferred.pop() {
+            self.store_node(page_id, &node)?;
+        }
+        Ok(())
+    }
+
+    fn store_meta(&mut self, root: u64) -> std::io::Result<()> {
+        let mut meta = Vec::with_capacity(META_LEN);
+        meta.extend_from_slice(&MAGIC);
+        meta.extend_from_slice(&root.to_le_bytes());
+        meta.extend_from_slice(&crc32fast::hash(&meta).to_le_bytes());
+        let address = self.allocator.address(META_PAGE);
+        self.allocator.cache().write(address, &meta)?;
+        self.root = root;
+        Ok(())
+    }
+}
+
+impl Node {
+    fn new(leaf: bool, link: u64) -> Self {
+        Self {
+            leaf,
+            link,
+            entries: Vec::new(),
+        }
+    }
+
+    fn search(&self, key: &[u8]) -> Result<usize, usize> {
+        self.entries
+            .binary_search_by(|(entry_key, _)| entry_key.as_slice().cmp(key))
+    }
+
+    // Child of an internal node whose keys include `key`
+    fn child(&self, key: &[u8]) -> u64 {
+        match self.search(key) {
+            Ok(index) => page_id(&self.entries[index].1),
+            Err(0) => self.link,
+            Err(index) => page_id(&self.entries[index - 1].1),
+        }
+    }
+
+    // Split off the upper half by size, returning the first key of the
+    // upper half and the node holding it. An internal node's first upper
+    // entry moves up to the parent, its child becoming the new link.
+    fn split(&mut self) -> (Vec<u8>, Node) {
+        let size = |(key, value): &(Vec<u8>, Vec<u8>)| ENTRY_OVERHEAD + key.len() + value.len();
+        let total: usize = self.entries.iter().map(size).sum();
+        let mut below = 0;
+        let mut mid = 0;
+        while below < total / 2 {
+            below += size(&self.entries[mid]);
+            mid += 1;
+        }
+        let mid = mid.clamp(1, self.entries.len() - 1);
+        let mut upper = self.entries.split_off(mid);
+        if self.leaf {
+            let separator = upper[0].0.clone();
+            let right = Node {
+                leaf: true,
+                link: self.link,
+                entries: upper,
+            };
+            (separator, right)
+        } else {
+            let (separator, child) = upper.remove(0);
+            let right = Node {
+                leaf: false,
+                link: page_id(&child),
+                entries: upper,
+            };
+            (separator, right)
+        }
+    }
+
+    fn encode(&self, page_size: usize) -> Option<SlottedPage> {
+        let mut page = SlottedPage::new(page_size);
+        let mut header = vec![if self.leaf { KIND_LEAF } else { KIND_INTERNAL }];
+        header.extend_from_slice(&self.link.to_le_bytes());
+        page.insert(&header)?;
+        for (key, value) in &self.entries {
+            let mut record = Vec::with_capacity(4 + key.len() + value.len());
+            record.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            record.extend_from_slice(key);
+            record.extend_from_slice(value);
+            page.insert(&record)?;
+        }
+        Some(page)
+    }
+
+    fn decode(page: &SlottedPage) -> Option<Self> {
+        let mut records = page.iter().map(|(_, record)| record);
+        let header = records.next()?;
+        let leaf = match *header.first()? {
+            KIND_LEAF => true,
+            KIND_INTERNAL => false,
+            _ => return None,
+        };
+        let link = u64::from_le_bytes(header.get(1..9)?.try_into().ok()?);
+        let mut entries = Vec::new();
+        for record in records {
+            let key_len = u32::from_le_bytes(record.get(..4)?.try_into().ok()?) as usize;
+            let key = record.get(4..4 + key_len)?;
+            let value = &record[4 + key_len..];
+            if !leaf && value.len() != 8 {
+                return None;
+            }
+            entries.push((key.to_vec(), value.to_vec()));
+        }
+        Some(Self {
+            leaf,
+            link,
+            entries,
+        })
+    }
+}
+
+fn page_id(value: &[u8]) -> u64 {
+    u64::from_le_bytes(value.try_into().unwrap())
+}
+
+/// Iterator over a range of a `BTree`'s entries, from `BTree::range`.
+pub struct Range<'a> {
+    tree: &'a mut BTree,
+    leaf: Option<Node>,
+    index: usize,
+    end: Bound<Vec<u8>>,
+}
+
+impl Iterator for Range<'_> {
+    type Item = std::io::Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let leaf = self.leaf.as_mut()?;
+            if self.index < leaf.entries.len() {
+                let entry = std::mem::take(&mut leaf.entries[self.index]);
+                self.index += 1;
+                let in_range = match &self.end {
+                    Bound::Included(end) => entry.0 <= *end,
+                    Bound::Excluded(end) => entry.0 < *end,
+                    Bound::Unbounded => true,
+                };
+                if !in_range {
+                    self.leaf = None;
+                    return None;
+                }
+                return Some(Ok(entry));
+            }
+            let next = leaf.link;
+            self.leaf = None;
+            self.index = 0;
+            if next == NO_PAGE {
+                return None;
+            }
+            match self.tree.load_node(next) {
+                Ok(node) => self.leaf = Some(node),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::sync::mpsc::{channel, Receiver};
+
+use crate::{AHashMap, WriteThroughCache};
+
+/// Marker from `WriteThroughCache::begin_tracking`, for asking which pages
+/// were written after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Epoch(pub u64);
+
+/// A completed write, sent to receivers from
+/// `WriteThroughCache::subscribe`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangeEvent {
+    pub offset: u64,
+    pub len: u64,
+    /// LSN assigned to the write, if page versions are enabled.
+    pub lsn: Option<u64>,
+}
+
+// Epoch of the last change to each page written since tracking started.
+// Epochs only grow, so the pages changed since any epoch are those whose
+// last change is at or after it.
+#[derive(Default)]
+pub(crate) struct ChangeTracker {
+    epoch: u64,
+    changed: AHashMap<u64, u64>,
+    // Whether tracking was asked for through `begin_tracking`, rather than
+    // only for a backup
+    pub(crate) requested: bool,
+}
+
+impl ChangeTracker {
+    // Start a new epoch and return it
+    pub fn begin(&mut self) -> u64 {
+        self.epoch += 1;
+        self.epoch
+    }
+
+    pub fn write(&mut self, pages: std::ops::Range<u64>) {
+        for page_id in pages {
+            self.changed.insert(page_id, self.epoch);
+        }
+    }
+
+    // Pages written at or after `epoch`, in order
+    pub fn changed_since(&self, epoch: u64) -> Vec<u64> {
+        let mut pages: Vec<u64> = self
+            .changed
+            .iter()
+            .filter(|&(_, &changed)| changed >= epoch)
+            .map(|(&page_id, _)| page_id)
+            .collect();
+        pages.sort_unstable();
+        pages
+    }
+}
+
+impl WriteThroughCache {
+    /// Start tracking written pages, if not already, and return a marker
+    /// for the pages written from now on. Tracking is kept in memory only
+    /// and lasts until the cache is closed, so a tool copying deltas must
+    /// fall back to a full copy after a restart.
+    pub fn begin_tracking(&mut self) -> Epoch {
+        let changes = self.changes.get_or_insert_with(ChangeTracker::default);
+        changes.requested = true;
+        Epoch(changes.begin())
+    }
+
+    /// Receive an event for every write that completes from now on. The
+    /// channel is unbounded, so a receiver should be drained or dropped.
+    pub fn subscribe(&mut self) -> Receiver<ChangeEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    // Note that `len` bytes from `address` were changed, by a write or a
+    // copy, and tell the subscribers
+    pub(crate) fn record_change(&mut self, address: u64, len: u64) {
+        if let Some(changes) = &mut self.changes {
+            let page_size = self.page_size as u64;
+            changes.write(address / page_size..(address + len).div_ceil(page_size));
+        }
+        if !self.subscribers.is_empty() {
+            let event = ChangeEvent {
+                offset: address,
+                len,
+                lsn: self.last_lsn(),
+            };
+            // Subscribers that dropped their receiver are forgotten
+            self.subscribers
+                .retain(|subscriber| subscriber.send(event).is_ok());
+        }
+    }
+
+    /// Pages written since `epoch` was returned by `begin_tracking`, in
+    /// order.
+    pub fn changed_pages_since(&self, epoch: Epoch) -> Vec<u64> {
+        match &self.changes {
+            Some(changes) => changes.changed_since(epoch.0),
+            None => Vec::new(),
+        }
+    }
+}
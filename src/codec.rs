@@ -0,0 +1,148 @@
+use std::io::{Error, ErrorKind};
+
+// Codec ids as recorded in the page index.
+pub const CODEC_RAW: u32 = 0;
+pub const CODEC_LZ4: u32 = 1;
+pub const CODEC_ZSTD: u32 = 2;
+pub const CODEC_ZSTD_DICT: u32 = 3;
+pub const CODEC_SNAPPY: u32 = 4;
+
+/// Ids below this value are reserved for the built-in codecs.
+pub const CUSTOM_CODEC_BASE: u32 = 0x100;
+
+/// A page compression codec. The id is recorded with every page the codec
+/// encodes, so it must stay the same for as long as those pages exist.
+pub trait Codec: Send {
+    fn id(&self) -> u32;
+
+    /// Upper bound on the compressed size of `len` input bytes.
+    fn max_compressed_len(&self, len: usize) -> usize;
+
+    /// Compress `input` into `output`, returning the number of bytes written.
+    fn compress(&mut self, input: &[u8], output: &mut [u8]) -> std::io::Result<usize>;
+
+    /// Decompress `input` into `output`, returning the number of bytes written.
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> std::io::Result<usize>;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Lz4Codec;
+
+impl Codec for Lz4Codec {
+    fn id(&self) -> u32 {
+        CODEC_LZ4
+    }
+
+    fn max_compressed_len(&self, len: usize) -> usize {
+        lz4_flex::block::get_maximum_output_size(len)
+    }
+
+    fn compress(&mut self, input: &[u8], output: &mut [u8]) -> std::io::Result<usize> {
+        lz4_flex::block::compress_into(input, output)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> std::io::Result<usize> {
+        lz4_flex::block::decompress_into(input, output)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(feature = "zstd")]
+pub struct ZstdCodec {
+    level: i32,
+    has_dictionary: bool,
+    compressor: zstd::bulk::Compressor<'static>,
+    decompressor: zstd::bulk::Decompressor<'static>,
+}
+
+#[cfg(feature = "zstd")]
+impl ZstdCodec {
+    pub fn new(level: i32) -> std::io::Result<Self> {
+        Ok(Self {
+            level,
+            has_dictionary: false,
+            compressor: zstd::bulk::Compressor::new(level)?,
+            decompressor: zstd::bulk::Decompressor::new()?,
+        })
+    }
+
+    pub fn with_dictionary(level: i32, dictionary: &[u8]) -> std::io::Result<Self> {
+        Ok(Self {
+            level,
+            has_dictionary: true,
+            compressor: zstd::bulk::Compressor::with_dictionary(level, dictionary)?,
+            decompressor: zstd::bulk::Decompressor::with_dictionary(dictionary)?,
+        })
+    }
+
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+
+    pub fn has_dictionary(&self) -> bool {
+        self.has_dictionary
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Codec for ZstdCodec {
+    fn id(&self) -> u32 {
+        if self.has_dictionary {
+            CODEC_ZSTD_DICT
+        } else {
+            CODEC_ZSTD
+        }
+    }
+
+    fn max_compressed_len(&self, len: usize) -> usize {
+        zstd::zstd_safe::compress_bound(len)
+    }
+
+    fn compress(&mut self, input: &[u8], output: &mut [u8]) -> std::io::Result<usize> {
+        self.compressor.compress_to_buffer(input, output)
+    }
+
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> std::io::Result<usize> {
+        self.decompressor.decompress_to_buffer(input, output)
+    }
+}
+
+#[cfg(feature = "snappy")]
+pub struct SnappyCodec {
+    encoder: snap::raw::Encoder,
+    decoder: snap::raw::Decoder,
+}
+
+#[cfg(feature = "snappy")]
+impl Default for SnappyCodec {
+    fn default() -> Self {
+        Self {
+            encoder: snap::raw::Encoder::new(),
+            decoder: snap::raw::Decoder::new(),
+        }
+    }
+}
+
+#[cfg(feature = "snappy")]
+impl Codec for SnappyCodec {
+    fn id(&self) -> u32 {
+        CODEC_SNAPPY
+    }
+
+    fn max_compressed_len(&self, len: usize) -> usize {
+        snap::raw::max_compress_len(len)
+    }
+
+    fn compress(&mut self, input: &[u8], output: &mut [u8]) -> std::io::Result<usize> {
+        self.encoder
+            .compress(input, output)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> std::io::Result<usize> {
+        self.decoder
+            .decompress(input, output)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
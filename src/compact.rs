@@ -0,0 +1,153 @@
+use std::path::Path;
+
+use crate::compression::{CompressedStore, DEFAULT_COMPRESSION_THRESHOLD};
+use crate::{Compression, WriteThroughCache};
+
+#[derive(Debug, Clone, Copy)]
+pub struct CompactOptions {
+    pub compression: Compression,
+    pub compression_threshold: f64,
+    /// Leave all-zero pages unwritten, as holes in an uncompressed file or
+    /// empty index entries in a compressed one.
+    pub skip_zero_pages: bool,
+    /// Page size of the new file; defaults to this file's.
+    pub page_size: Option<usize>,
+}
+
+impl Default for CompactOptions {
+    fn default() -> Self {
+        Self {
+            compression: Compression::None,
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            skip_zero_pages: true,
+            page_size: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactReport {
+    pub pages: u64,
+    pub zero_pages: u64,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl WriteThroughCache {
+    /// Rewrite the file into `dest_path` with the given compression settings
+    /// and page size. Pages are laid out contiguously in the new file, so
+    /// space left behind by relocated compressed pages is reclaimed. The
+    /// partition table carries over, so every partition has to start on a
+    /// boundary of the new page size.
+    pub fn compact_to(
+        &mut self,
+        dest_path: &Path,
+        options: CompactOptions,
+    ) -> std::io::Result<CompactReport> {
+        if dest_path.exists() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "Compaction target already exists",
+            ));
+        }
+
+        let page_size = options.page_size.unwrap_or(self.page_size);
+        if let Some(partition) = self
+            .header
+            .partitions
+            .iter()
+            .find(|partition| !partition.start.is_multiple_of(page_size as u64))
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "Partition {} does not start on a page boundary",
+                    partition.name
+                ),
+            ));
+        }
+
+        let mut dest = WriteThroughCache::builder(dest_path)
+            .page_size(page_size)
+            .capacity(page_size)
+            .compression(options.compression)
+            .compression_threshold(options.compression_threshold)
+            .open()?;
+
+        if !self.header.partitions.is_empty() {
+            dest.header.partitions = self.header.partitions.clone();
+            dest.header.store(dest_path)?;
+        }
+
+        if options.compression != Compression::None {
+            for (range, compression) in self.header.compression_ranges.clone() {
+                dest.set_range_compression(range, compression)?;
+            }
+
+            #[cfg(feature = "zstd")]
+            if let Some(dictionary) = self
+                .compressed
+                .as_ref()
+                .and_then(|store| store.dictionary())
+            {
+                dest.attach_zstd_dictionary(dictionary)?;
+            }
+        }
+
+        let mut report = CompactReport {
+            bytes_before: disk_usage(&self.file_path)?,
+            ..CompactReport::default()
+        };
+
+        let page_count = self.file_size.div_ceil(page_size as u64);
+        for page_id in 0..page_count {
+            let address = page_id * page_size as u64;
+            let page = if page_size == self.page_size {
+                self.read_page(page_id)?
+            } else {
+                let len = std::cmp::min(page_size as u64, self.file_size - address);
+                self.read_range(address, len as usize)?
+            };
+            report.pages += 1;
+
+            // The last page is always written so the logical size carries over
+            if options.skip_zero_pages && page_id + 1 < page_count && page.iter().all(|&b| b == 0) {
+                report.zero_pages += 1;
+                continue;
+            }
+
+            dest.write(address, &page)?;
+        }
+
+        drop(dest);
+        report.bytes_after = disk_usage(dest_path)?;
+        Ok(report)
+    }
+}
+
+// Allocated bytes of the data file and its page index, if any.
+fn disk_usage(file_path: &Path) -> std::io::Result<u64> {
+    let mut total = 0;
+    for path in [
+        file_path.to_path_buf(),
+        CompressedStore::path_for(file_path),
+    ] {
+        match std::fs::metadata(&path) {
+            Ok(metadata) => total += allocated_len(&metadata),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(unix)]
+fn allocated_len(metadata: &std::fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated_len(metadata: &std::fs::Metadata) -> u64 {
+    metadata.len()
+}
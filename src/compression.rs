@@ -0,0 +1,650 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use zeroize::Zeroize;
+
+#[cfg(feature = "snappy")]
+use crate::codec::SnappyCodec;
+#[cfg(feature = "zstd")]
+use crate::codec::ZstdCodec;
+use crate::codec::{
+    Codec, Lz4Codec, CODEC_LZ4, CODEC_RAW, CODEC_SNAPPY, CODEC_ZSTD, CODEC_ZSTD_DICT,
+    CUSTOM_CODEC_BASE,
+};
+use crate::events::Events;
+use crate::pio::{read_exact_at, write_all_at};
+use crate::stats::Counters;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Lz4,
+    /// Requires the `zstd` feature. Pages are compressed with the file's
+    /// dictionary once one has been attached.
+    Zstd {
+        level: i32,
+    },
+    /// Requires the `snappy` feature.
+    Snappy,
+    /// A codec registered with `CacheBuilder::codec`, by id.
+    Custom(u32),
+}
+
+impl Compression {
+    pub(crate) fn encode(self) -> (u32, i32) {
+        match self {
+            Compression::None => (CODEC_RAW, 0),
+            Compression::Lz4 => (CODEC_LZ4, 0),
+            Compression::Zstd { level } => (CODEC_ZSTD, level),
+            Compression::Snappy => (CODEC_SNAPPY, 0),
+            Compression::Custom(id) => (id, 0),
+        }
+    }
+
+    pub(crate) fn decode(codec: u32, level: i32) -> Option<Self> {
+        match codec {
+            CODEC_RAW => Some(Compression::None),
+            CODEC_LZ4 => Some(Compression::Lz4),
+            CODEC_ZSTD => Some(Compression::Zstd { level }),
+            CODEC_SNAPPY => Some(Compression::Snappy),
+            id if id >= CUSTOM_CODEC_BASE => Some(Compression::Custom(id)),
+            _ => None,
+        }
+    }
+}
+
+pub const DEFAULT_COMPRESSION_THRESHOLD: f64 = 1.1;
+const SAMPLE_SIZE: usize = 4096;
+
+// Index sidecar layout: slot 0 holds the logical file size, slot `page_id + 1`
+// the page's location in the data file: offset u64 | allocated u32 |
+// stored u32 | codec u32 | reserved u32. A stored length of 0 is a zero page.
+const ENTRY_LEN: usize = 24;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PageEntry {
+    offset: u64,
+    allocated: u32,
+    stored: u32,
+    codec: u32,
+}
+
+pub(crate) struct CompressedStore {
+    index: File,
+    entries: Vec<PageEntry>,
+    heap_end: u64,
+    // Heap extents no index entry points into, sorted and coalesced
+    free: Vec<Range<u64>>,
+    logical_size: u64,
+    compression: Compression,
+    threshold: f64,
+    // Holds page contents, compressed or not, between disk and the caller;
+    // wiped after every use in secure mode
+    scratch: Vec<u8>,
+    secure: bool,
+    // Per-range overrides of `compression`; later entries take precedence.
+    ranges: Vec<(Range<u64>, Compression)>,
+    lz4: Lz4Codec,
+    #[cfg(feature = "zstd")]
+    zstd: ZstdCodecs,
+    #[cfg(feature = "snappy")]
+    snappy: SnappyCodec,
+    custom: Vec<Box<dyn Codec>>,
+    codec_timings: Vec<(u32, CodecTimings)>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct CodecTimings {
+    compressions: u64,
+    compress_time: Duration,
+    decompressions: u64,
+    decompress_time: Duration,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompressionStats {
+    /// Uncompressed size of all stored pages.
+    pub logical_bytes: u64,
+    /// Encoded size of all stored pages.
+    pub stored_bytes: u64,
+    /// Size of the page heap, including space freed by relocated pages that
+    /// has not been reused yet.
+    pub physical_bytes: u64,
+    pub codecs: Vec<CodecStats>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CodecStats {
+    /// Codec id as recorded in the page index; 0 for pages stored raw.
+    pub codec: u32,
+    pub pages: u64,
+    pub logical_bytes: u64,
+    pub stored_bytes: u64,
+    pub compressions: u64,
+    pub compress_time: Duration,
+    pub decompressions: u64,
+    pub decompress_time: Duration,
+}
+
+impl CodecStats {
+    pub fn ratio(&self) -> f64 {
+        ratio(self.logical_bytes as usize, self.stored_bytes as usize)
+    }
+}
+
+#[cfg(feature = "zstd")]
+struct ZstdCodecs {
+    codecs: Vec<ZstdCodec>,
+    dictionary: Option<Vec<u8>>,
+}
+
+#[cfg(feature = "zstd")]
+impl ZstdCodecs {
+    // Contexts are created on first use of each level. Pages compressed
+    // before a dictionary was attached still decode without it.
+    fn get(
+        &mut self,
+        level: Option<i32>,
+        with_dictionary: bool,
+    ) -> std::io::Result<&mut ZstdCodec> {
+        let found = self.codecs.iter().position(|codec| {
+            codec.has_dictionary() == with_dictionary
+                && level.is_none_or(|level| codec.level() == level)
+        });
+        let index = match found {
+            Some(index) => index,
+            None => {
+                let level = level.unwrap_or(0);
+                let codec = match (&self.dictionary, with_dictionary) {
+                    (Some(dictionary), true) => ZstdCodec::with_dictionary(level, dictionary)?,
+                    (None, true) => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            "Missing zstd dictionary",
+                        ))
+                    }
+                    (_, false) => ZstdCodec::new(level)?,
+                };
+                self.codecs.push(codec);
+                self.codecs.len() - 1
+            }
+        };
+        Ok(&mut self.codecs[index])
+    }
+}
+
+#[cfg(not(all(feature = "zstd", feature = "snappy")))]
+fn unsupported(feature: &str) -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        format!("This codec requires the `{}` feature", feature),
+    )
+}
+
+impl CompressedStore {
+    pub fn path_for(file_path: &Path) -> PathBuf {
+        let mut path = file_path.as_os_str().to_owned();
+        path.push(".idx");
+        PathBuf::from(path)
+    }
+
+    pub fn set_ranges(&mut self, ranges: Vec<(Range<u64>, Compression)>) {
+        self.ranges = ranges;
+    }
+
+    pub fn dictionary_path_for(file_path: &Path) -> PathBuf {
+        let mut path = file_path.as_os_str().to_owned();
+        path.push(".dict");
+        PathBuf::from(path)
+    }
+
+    pub fn open(
+        file_path: &Path,
+        heap_end: u64,
+        compression: Compression,
+        threshold: f64,
+        custom: Vec<Box<dyn Codec>>,
+        secure: bool,
+    ) -> std::io::Result<Self> {
+        let mut index = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(Self::path_for(file_path))?;
+
+        let mut bytes = Vec::new();
+        index.read_to_end(&mut bytes)?;
+        if bytes.len() % ENTRY_LEN != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Truncated page index"));
+        }
+
+        let mut slots = bytes.chunks_exact(ENTRY_LEN);
+        let logical_size = slots
+            .next()
+            .map_or(0, |slot| u64::from_le_bytes(slot[0..8].try_into().unwrap()));
+        let entries = slots
+            .map(|slot| PageEntry {
+                offset: u64::from_le_bytes(slot[0..8].try_into().unwrap()),
+                allocated: u32::from_le_bytes(slot[8..12].try_into().unwrap()),
+                stored: u32::from_le_bytes(slot[12..16].try_into().unwrap()),
+                codec: u32::from_le_bytes(slot[16..20].try_into().unwrap()),
+            })
+            .collect::<Vec<_>>();
+        let free = free_extents(&entries, heap_end);
+
+        let dictionary = match std::fs::read(Self::dictionary_path_for(file_path)) {
+            Ok(dictionary) => Some(dictionary),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+
+        #[cfg(not(feature = "zstd"))]
+        if matches!(compression, Compression::Zstd { .. }) || dictionary.is_some() {
+            return Err(unsupported("zstd"));
+        }
+
+        if let Some(codec) = custom.iter().find(|codec| codec.id() < CUSTOM_CODEC_BASE) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Custom codec id {} is reserved", codec.id()),
+            ));
+        }
+
+        Ok(Self {
+            index,
+            entries,
+            heap_end,
+            free,
+            logical_size,
+            compression,
+            threshold,
+            scratch: Vec::new(),
+            secure,
+            ranges: Vec::new(),
+            lz4: Lz4Codec,
+            #[cfg(feature = "zstd")]
+            zstd: ZstdCodecs {
+                codecs: Vec::new(),
+                dictionary,
+            },
+            #[cfg(feature = "snappy")]
+            snappy: SnappyCodec::default(),
+            custom,
+            codec_timings: Vec::new(),
+        })
+    }
+
+    #[cfg(feature = "zstd")]
+    pub fn dictionary(&self) -> Option<&[u8]> {
+        self.zstd.dictionary.as_deref()
+    }
+
+    #[cfg(feature = "zstd")]
+    pub fn attach_dictionary(
+        &mut self,
+        file_path: &Path,
+        dictionary: &[u8],
+    ) -> std::io::Result<()> {
+        if self.zstd.dictionary.is_some() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                "A dictionary is already attached to this file",
+            ));
+        }
+
+        let mut file = File::create(Self::dictionary_path_for(file_path))?;
+        std::io::Write::write_all(&mut file, dictionary)?;
+        file.sync_all()?;
+
+        self.zstd.dictionary = Some(dictionary.to_vec());
+        Ok(())
+    }
+
+    fn encoder(&mut self, compression: Compression) -> std::io::Result<&mut dyn Codec> {
+        match compression {
+            Compression::None => unreachable!("raw pages are not encoded"),
+            Compression::Lz4 => Ok(&mut self.lz4),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level } => {
+                let with_dictionary = self.zstd.dictionary.is_some();
+                Ok(self.zstd.get(Some(level), with_dictionary)?)
+            }
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd { .. } => Err(unsupported("zstd")),
+            #[cfg(feature = "snappy")]
+            Compression::Snappy => Ok(&mut self.snappy),
+            #[cfg(not(feature = "snappy"))]
+            Compression::Snappy => Err(unsupported("snappy")),
+            Compression::Custom(id) => self.custom_codec(id),
+        }
+    }
+
+    fn decoder(&mut self, id: u32) -> std::io::Result<&mut dyn Codec> {
+        match id {
+            CODEC_LZ4 => Ok(&mut self.lz4),
+            #[cfg(feature = "zstd")]
+            CODEC_ZSTD | CODEC_ZSTD_DICT => Ok(self.zstd.get(None, id == CODEC_ZSTD_DICT)?),
+            #[cfg(not(feature = "zstd"))]
+            CODEC_ZSTD | CODEC_ZSTD_DICT => Err(unsupported("zstd")),
+            #[cfg(feature = "snappy")]
+            CODEC_SNAPPY => Ok(&mut self.snappy),
+            #[cfg(not(feature = "snappy"))]
+            CODEC_SNAPPY => Err(unsupported("snappy")),
+            id => self.custom_codec(id),
+        }
+    }
+
+    fn custom_codec(&mut self, id: u32) -> std::io::Result<&mut dyn Codec> {
+        match self.custom.iter_mut().find(|codec| codec.id() == id) {
+            Some(codec) => Ok(codec.as_mut()),
+            None => Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("No codec registered with id {}", id),
+            )),
+        }
+    }
+
+    pub fn logical_size(&self) -> u64 {
+        self.logical_size
+    }
+
+    pub fn stats(&self, page_size: usize) -> CompressionStats {
+        let mut stats = CompressionStats {
+            physical_bytes: self.heap_end,
+            ..CompressionStats::default()
+        };
+
+        for entry in self.entries.iter().filter(|entry| entry.stored > 0) {
+            stats.logical_bytes += page_size as u64;
+            stats.stored_bytes += entry.stored as u64;
+            let codec = codec_stats(&mut stats.codecs, entry.codec);
+            codec.pages += 1;
+            codec.logical_bytes += page_size as u64;
+            codec.stored_bytes += entry.stored as u64;
+        }
+
+        for (id, timings) in &self.codec_timings {
+            let codec = codec_stats(&mut stats.codecs, *id);
+            codec.compressions = timings.compressions;
+            codec.compress_time = timings.compress_time;
+            codec.decompressions = timings.decompressions;
+            codec.decompress_time = timings.decompress_time;
+        }
+
+        stats.codecs.sort_by_key(|codec| codec.codec);
+        stats
+    }
+
+    fn timings(&mut self, id: u32) -> &mut CodecTimings {
+        let index = match self
+            .codec_timings
+            .iter()
+            .position(|(codec, _)| *codec == id)
+        {
+            Some(index) => index,
+            None => {
+                self.codec_timings.push((id, CodecTimings::default()));
+                self.codec_timings.len() - 1
+            }
+        };
+        &mut self.codec_timings[index].1
+    }
+
+    fn wipe_scratch(&mut self) {
+        if self.secure {
+            self.scratch.zeroize();
+        }
+    }
+
+    pub fn read_page(
+        &mut self,
+        file: &File,
+        page_id: u64,
+        buffer: &mut [u8],
+    ) -> std::io::Result<()> {
+        let entry = self
+            .entries
+            .get(page_id as usize)
+            .copied()
+            .unwrap_or_default();
+        if entry.stored == 0 {
+            buffer.fill(0);
+            return Ok(());
+        }
+
+        self.scratch.resize(entry.stored as usize, 0);
+        if let Err(e) = read_exact_at(file, &mut self.scratch, entry.offset) {
+            self.wipe_scratch();
+            return Err(e);
+        }
+
+        let scratch = std::mem::take(&mut self.scratch);
+        let decoded = match entry.codec {
+            CODEC_RAW if scratch.len() == buffer.len() => {
+                buffer.copy_from_slice(&scratch);
+                Ok(buffer.len())
+            }
+            CODEC_RAW => Ok(0),
+            id => {
+                let started = Instant::now();
+                let decoded = self
+                    .decoder(id)
+                    .and_then(|codec| codec.decompress(&scratch, buffer));
+                let timings = self.timings(id);
+                timings.decompressions += 1;
+                timings.decompress_time += started.elapsed();
+                decoded
+            }
+        };
+        self.scratch = scratch;
+        self.wipe_scratch();
+        let decoded = decoded?;
+
+        if decoded != buffer.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Page {} could not be decoded", page_id),
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Store a page, counting the syncs it takes in `stats` and reporting them
+    // to `events`
+    pub fn write_page(
+        &mut self,
+        file: &File,
+        page_id: u64,
+        data: &[u8],
+        stats: &Counters,
+        events: &Events,
+    ) -> std::io::Result<()> {
+        let address = page_id * data.len() as u64;
+        let compression = self
+            .ranges
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&address))
+            .map_or(self.compression, |(_, compression)| *compression);
+
+        // Cheaply estimate compressibility on a sample before spending CPU
+        // on the whole page; already-compressed payloads are stored raw.
+        let sample = &data[..data.len().min(SAMPLE_SIZE)];
+        let compressible = compression == Compression::None || {
+            let mut estimate = lz4_flex::block::compress(sample);
+            let compressible = ratio(sample.len(), estimate.len()) >= self.threshold;
+            if self.secure {
+                estimate.zeroize();
+            }
+            compressible
+        };
+        let compression = if !compressible {
+            Compression::None
+        } else {
+            compression
+        };
+
+        let mut scratch = std::mem::take(&mut self.scratch);
+        let codec = match compression {
+            Compression::None => Ok(CODEC_RAW),
+            compression => {
+                let started = Instant::now();
+                let codec = self.encoder(compression).and_then(|codec| {
+                    scratch.resize(codec.max_compressed_len(data.len()), 0);
+                    let len = codec.compress(data, &mut scratch)?;
+                    scratch.truncate(len);
+                    Ok(codec.id())
+                });
+                if let Ok(id) = codec {
+                    // Attributed even if the page ends up stored raw: the
+                    // CPU was spent either way.
+                    let timings = self.timings(id);
+                    timings.compressions += 1;
+                    timings.compress_time += started.elapsed();
+                }
+                codec
+            }
+        };
+        self.scratch = scratch;
+        let mut codec = match codec {
+            Ok(codec) => codec,
+            Err(e) => {
+                self.wipe_scratch();
+                return Err(e);
+            }
+        };
+
+        if codec == CODEC_RAW {
+            self.scratch.clear();
+            self.scratch.extend_from_slice(data);
+        }
+
+        if codec != CODEC_RAW && ratio(data.len(), self.scratch.len()) < self.threshold {
+            self.scratch.clear();
+            self.scratch.extend_from_slice(data);
+            codec = CODEC_RAW;
+        }
+
+        if self.entries.len() <= page_id as usize {
+            self.entries
+                .resize(page_id as usize + 1, PageEntry::default());
+        }
+
+        // The new payload always goes to space no index entry points into, so
+        // the old one stays intact until the index points past it; a crash at
+        // any point leaves the page decodable. The old slot is only freed for
+        // reuse once that has happened.
+        let len = self.scratch.len() as u64;
+        let extent = self
+            .free
+            .iter()
+            .position(|extent| extent.end - extent.start >= len);
+        let entry = PageEntry {
+            offset: extent.map_or(self.heap_end, |i| self.free[i].start),
+            allocated: self.scratch.len() as u32,
+            stored: self.scratch.len() as u32,
+            codec,
+        };
+        let written = write_all_at(file, &self.scratch, entry.offset);
+        self.wipe_scratch();
+        written?;
+        events.sync(file, page_id, stats)?;
+
+        let logical_size = self.logical_size.max((page_id + 1) * data.len() as u64);
+        let mut slot = [0; ENTRY_LEN];
+        slot[0..8].copy_from_slice(&entry.offset.to_le_bytes());
+        slot[8..12].copy_from_slice(&entry.allocated.to_le_bytes());
+        slot[12..16].copy_from_slice(&entry.stored.to_le_bytes());
+        slot[16..20].copy_from_slice(&entry.codec.to_le_bytes());
+        write_all_at(&self.index, &slot, (page_id + 1) * ENTRY_LEN as u64)?;
+        write_all_at(&self.index, &logical_size.to_le_bytes(), 0)?;
+        events.sync(&self.index, page_id, stats)?;
+
+        // Only published in memory once both writes are durable
+        let old = std::mem::replace(&mut self.entries[page_id as usize], entry);
+        match extent {
+            Some(i) if self.free[i].end - self.free[i].start == len => {
+                self.free.remove(i);
+            }
+            Some(i) => self.free[i].start += len,
+            None => self.heap_end += len,
+        }
+        if old.allocated > 0 {
+            self.release(old.offset..old.offset + old.allocated as u64);
+        }
+        self.logical_size = logical_size;
+        Ok(())
+    }
+
+    // Return an extent to the free list, merging it with its neighbours
+    fn release(&mut self, extent: Range<u64>) {
+        let i = self.free.partition_point(|free| free.start < extent.start);
+        let merges_next = self
+            .free
+            .get(i)
+            .is_some_and(|next| next.start == extent.end);
+        let merges_prev = i > 0 && self.free[i - 1].end == extent.start;
+        match (merges_prev, merges_next) {
+            (true, true) => {
+                self.free[i - 1].end = self.free[i].end;
+                self.free.remove(i);
+            }
+            (true, false) => self.free[i - 1].end = extent.end,
+            (false, true) => self.free[i].start = extent.start,
+            (false, false) => self.free.insert(i, extent),
+        }
+    }
+}
+
+impl Drop for CompressedStore {
+    fn drop(&mut self) {
+        self.wipe_scratch();
+    }
+}
+
+// Gaps in the heap between the payloads the index points to, including any
+// written by an interrupted write that never reached the index
+fn free_extents(entries: &[PageEntry], heap_end: u64) -> Vec<Range<u64>> {
+    let mut used = entries
+        .iter()
+        .filter(|entry| entry.allocated > 0)
+        .map(|entry| entry.offset..entry.offset + entry.allocated as u64)
+        .collect::<Vec<_>>();
+    used.sort_by_key(|extent| extent.start);
+
+    let mut free = Vec::new();
+    let mut end = 0;
+    for extent in used {
+        if extent.start > end {
+            free.push(end..extent.start);
+        }
+        end = end.max(extent.end);
+    }
+    if heap_end > end {
+        free.push(end..heap_end);
+    }
+    free
+}
+
+fn ratio(uncompressed: usize, compressed: usize) -> f64 {
+    uncompressed as f64 / compressed.max(1) as f64
+}
+
+fn codec_stats(codecs: &mut Vec<CodecStats>, id: u32) -> &mut CodecStats {
+    let index = match codecs.iter().position(|codec| codec.codec == id) {
+        Some(index) => index,
+        None => {
+            codecs.push(CodecStats {
+                codec: id,
+                ..CodecStats::default()
+            });
+            codecs.len() - 1
+        }
+    };
+    &mut codecs[index]
+}
@@ -0,0 +1,155 @@
+use std::fs::File;
+use std::io::Write;
+use std::net::TcpStream;
+
+use crate::pio::{copy_file_range, sendfile, write_all_at};
+use crate::{WriteThroughCache, MAX_IO_SIZE};
+
+impl WriteThroughCache {
+    /// Copy `len` bytes from `src` to `dst` within the file. Uncompressed
+    /// files without an audit log are copied in-kernel with
+    /// `copy_file_range`; everything else, and overlapping ranges, goes
+    /// through the cache as ordinary reads and writes.
+    pub fn copy_range(&mut self, src: u64, dst: u64, len: u64) -> std::io::Result<()> {
+        // Checked before any I/O, so a failed copy leaves the file untouched
+        self.check_in_file(src, len)?;
+        if dst.checked_add(len).is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Destination range overflows",
+            ));
+        }
+        if len == 0 || src == dst {
+            return Ok(());
+        }
+
+        let overlapping = src < dst + len && dst < src + len;
+        if self.compressed.is_none() && self.audit_log.is_none() && !overlapping {
+            // Copying straight from disk is only correct because every write
+            // has already reached it. The destination's LSNs go first, as for
+            // any write.
+            self.stamp_pages(dst, len)?;
+            if copy_file_range(&self.file, src, &self.file, dst, len)? {
+                self.stats.disk_write(len as usize);
+                return self.range_copied(dst, len);
+            }
+            #[cfg(feature = "log")]
+            log::debug!(
+                "copy_file_range unavailable, copying {} bytes through the cache",
+                len
+            );
+        }
+
+        // Chunks run back to front when the destination overlaps the end of
+        // the source, so nothing is overwritten before it is copied
+        let chunk = MAX_IO_SIZE as u64;
+        let chunks = len.div_ceil(chunk);
+        for index in 0..chunks {
+            let index = if dst > src { chunks - 1 - index } else { index };
+            let offset = index * chunk;
+            let size = std::cmp::min(chunk, len - offset) as usize;
+            let data = self.read(src + offset, size)?;
+            self.write(dst + offset, &data)?;
+        }
+        Ok(())
+    }
+
+    /// Copy `len` bytes starting at `address` into `dest` at `dest_offset`,
+    /// in-kernel where possible. `dest` is not synced.
+    pub fn export_range(
+        &mut self,
+        address: u64,
+        len: u64,
+        dest: &File,
+        dest_offset: u64,
+    ) -> std::io::Result<()> {
+        self.check_in_file(address, len)?;
+
+        if self.compressed.is_none()
+            && copy_file_range(&self.file, address, dest, dest_offset, len)?
+        {
+            self.stats.disk_read(len as usize);
+            return Ok(());
+        }
+
+        let chunk = MAX_IO_SIZE as u64;
+        let mut offset = 0;
+        while offset < len {
+            let size = std::cmp::min(chunk, len - offset) as usize;
+            let data = self.read(address + offset, size)?;
+            write_all_at(dest, &data, dest_offset + offset)?;
+            offset += size as u64;
+        }
+        Ok(())
+    }
+
+    /// Send `len` bytes starting at `address` to `stream` with `sendfile`,
+    /// so the data goes from the page cache to the socket without a copy
+    /// through userspace. Every write has already reached the file, so there
+    /// is nothing to flush first. Compressed files are sent through the
+    /// cache. `stream` must be in blocking mode.
+    pub fn send_range_to(
+        &mut self,
+        address: u64,
+        len: u64,
+        mut stream: &TcpStream,
+    ) -> std::io::Result<()> {
+        self.check_in_file(address, len)?;
+
+        if self.compressed.is_none() && sendfile(&self.file, address, stream, len)? {
+            self.stats.disk_read(len as usize);
+            return Ok(());
+        }
+
+        let chunk = MAX_IO_SIZE as u64;
+        let mut offset = 0;
+        while offset < len {
+            let size = std::cmp::min(chunk, len - offset) as usize;
+            let data = self.read(address + offset, size)?;
+            stream.write_all(&data)?;
+            offset += size as u64;
+        }
+        Ok(())
+    }
+
+    // Bring the cache in line with `dst..dst + len` having been rewritten on
+    // disk behind its back
+    fn range_copied(&mut self, dst: u64, len: u64) -> std::io::Result<()> {
+        let page_size = self.page_size as u64;
+        let first_page = dst / page_size;
+        let end_page = (dst + len).div_ceil(page_size);
+
+        // copy_file_range already extended the file to the end of the copy,
+        // and a partial last page is zero-filled when it is read
+        if self.file.metadata()?.len() < dst + len {
+            self.file.set_len(dst + len)?;
+        }
+        self.sync(first_page)?;
+
+        for page_id in first_page..end_page {
+            self.discard_page(page_id);
+            if let Some(fetcher) = &mut self.fetcher {
+                fetcher.forget(page_id);
+            }
+            if let Some(tier) = &mut self.tier {
+                tier.remove(page_id);
+            }
+        }
+        self.file_size = std::cmp::max(self.file_size, dst + len);
+        self.record_change(dst, len);
+        Ok(())
+    }
+
+    fn check_in_file(&self, address: u64, len: u64) -> std::io::Result<()> {
+        if address
+            .checked_add(len)
+            .is_none_or(|end| end > self.file_size)
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Range extends past the end of the file",
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use std::io::Write;
+
+use crate::WriteThroughCache;
+
+/// Where a page described by `CacheState` is held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageLocation {
+    /// The recently promoted segment of the main LRU.
+    Hot,
+    /// The segment of the main LRU that evictions are taken from.
+    Cold,
+    /// The compressed second-chance tier.
+    Tier,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageState {
+    pub page_id: u64,
+    pub location: PageLocation,
+    /// Bytes held for the page: the page size when resident, the compressed
+    /// size in the tier.
+    pub size: usize,
+}
+
+/// What the cache holds, for debugging and for assertions in tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheState {
+    pub page_size: usize,
+    pub file_size: u64,
+    /// Resident pages, next to be evicted first.
+    pub resident: Vec<PageState>,
+    /// Pages in the compressed tier, next to be discarded first.
+    pub tier: Vec<PageState>,
+}
+
+impl WriteThroughCache {
+    /// Describe the pages the cache holds and their eviction order. Every
+    /// write goes straight to disk, so no page is ever dirty, and there is
+    /// no pinning; neither is reported.
+    pub fn dump_state(&self) -> CacheState {
+        let resident = self
+            .cache
+            .iter_lru()
+            .map(|(page_id, hot)| PageState {
+                page_id,
+                location: if hot {
+                    PageLocation::Hot
+                } else {
+                    PageLocation::Cold
+                },
+                size: self.page_size,
+            })
+            .collect();
+        let tier = self
+            .tier
+            .iter()
+            .flat_map(|tier| tier.iter())
+            .map(|(page_id, size)| PageState {
+                page_id,
+                location: PageLocation::Tier,
+                size,
+            })
+            .collect();
+        CacheState {
+            page_size: self.page_size,
+            file_size: self.file_size,
+            resident,
+            tier,
+        }
+    }
+
+    /// Write `dump_state` to `out` as text, one page per line in eviction
+    /// order, so it can go straight into a log or an incident report.
+    pub fn write_state(&self, out: &mut impl Write) -> std::io::Result<()> {
+        let state = self.dump_state();
+        writeln!(
+            out,
+            "page size {}, file size {}, {} resident, {} in tier",
+            state.page_size,
+            state.file_size,
+            state.resident.len(),
+            state.tier.len()
+        )?;
+        for page in state.resident.iter().chain(&state.tier) {
+            writeln!(out, "{:?} {} {}", page.location, page.page_id, page.size)?;
+        }
+        Ok(())
+    }
+}
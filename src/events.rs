@@ -0,0 +1,97 @@
+use std::fs::File;
+use std::time::{Duration, Instant};
+
+use crate::stats::Counters;
+#[cfg(feature = "metrics")]
+use crate::MetricsFacade;
+#[cfg(feature = "prometheus")]
+use crate::PrometheusMetrics;
+
+/// Disk operation reported by `CacheEvents::on_slow_op`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskOp {
+    Read,
+    Write,
+    Fsync,
+}
+
+/// Callbacks for cache activity, registered with `CacheBuilder::events`.
+/// Every method defaults to doing nothing. They run inline on the calling
+/// thread, so they should be quick.
+pub trait CacheEvents: Send {
+    /// A page lookup by a read or partial write was served from memory.
+    fn on_hit(&self, _page_id: u64) {}
+
+    /// A page lookup had to go to the compressed tier or disk.
+    fn on_miss(&self, _page_id: u64) {}
+
+    /// A page was evicted to make room.
+    fn on_evict(&self, _page_id: u64) {}
+
+    /// The data file, or a compressed file's page index, was synced, taking
+    /// `elapsed`.
+    fn on_flush(&self, _elapsed: Duration) {}
+
+    /// A read or write failed with `error`, which is also returned to the
+    /// caller.
+    fn on_error(&self, _error: &std::io::Error) {}
+
+    /// A disk operation starting at `page_id` took longer than the
+    /// threshold set with `CacheBuilder::slow_op_threshold`.
+    fn on_slow_op(&self, _op: DiskOp, _page_id: u64, _elapsed: Duration) {}
+}
+
+// Where activity is reported to, beyond the built-in counters
+#[derive(Default)]
+pub(crate) struct Events {
+    pub hooks: Option<Box<dyn CacheEvents>>,
+    pub slow_op_threshold: Option<Duration>,
+    #[cfg(feature = "metrics")]
+    pub metrics: Option<MetricsFacade>,
+    #[cfg(feature = "prometheus")]
+    pub prometheus: Option<PrometheusMetrics>,
+}
+
+impl Events {
+    /// Sync `file` after a write starting at `page_id`, counting the sync in
+    /// `stats` and reporting it everywhere syncs are reported.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", name = "fsync", skip_all)
+    )]
+    pub fn sync(&self, file: &File, page_id: u64, stats: &Counters) -> std::io::Result<()> {
+        let started = Instant::now();
+        file.sync_all()?;
+        let elapsed = started.elapsed();
+        stats.fsyncs.incr();
+        stats.fsync_done(elapsed);
+        self.disk_op(DiskOp::Fsync, page_id, elapsed);
+        if let Some(hooks) = &self.hooks {
+            hooks.on_flush(elapsed);
+        }
+        #[cfg(feature = "prometheus")]
+        if let Some(metrics) = &self.prometheus {
+            metrics.observe_flush(elapsed);
+        }
+        Ok(())
+    }
+
+    /// Record how long `op` took, and report it if it was slow.
+    pub fn disk_op(&self, op: DiskOp, page_id: u64, elapsed: Duration) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.observe(op, elapsed);
+        }
+        if self
+            .slow_op_threshold
+            .is_none_or(|threshold| elapsed <= threshold)
+        {
+            return;
+        }
+        #[cfg(feature = "log")]
+        log::warn!("Slow disk {:?} at page {} took {:?}", op, page_id, elapsed);
+        if let Some(hooks) = &self.hooks {
+            hooks.on_slow_op(op, page_id, elapsed);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::{Compression, Partition};
+
+const MAGIC: [u8; 8] = *b"WTCACHE\0";
+pub const FORMAT_VERSION: u32 = 3;
+// Version 1: magic | version | page size | features | crc32
+// Version 2 appends a compression range table before the crc32:
+// count u32 | (start u64 | end u64 | codec u32 | level i32) * count
+// Version 3 appends a partition table after it:
+// count u32 | (name length u16 | name | start u64 | size u64) * count
+const FIXED_LEN: usize = 8 + 4 + 4 + 8;
+const RANGE_LEN: usize = 8 + 8 + 4 + 4;
+
+pub const FEATURE_PAGE_VERSIONS: u64 = 1 << 0;
+pub const FEATURE_COMPRESSED_PAGES: u64 = 1 << 1;
+
+// Feature flags understood by this version; anything else is rejected on open.
+pub const KNOWN_FEATURES: u64 = FEATURE_PAGE_VERSIONS | FEATURE_COMPRESSED_PAGES;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHeader {
+    pub version: u32,
+    pub page_size: usize,
+    pub features: u64,
+    pub compression_ranges: Vec<(Range<u64>, Compression)>,
+    pub partitions: Vec<Partition>,
+}
+
+impl FileHeader {
+    pub fn new(page_size: usize, features: u64) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            page_size,
+            features,
+            compression_ranges: Vec::new(),
+            partitions: Vec::new(),
+        }
+    }
+
+    pub fn path_for(file_path: &Path) -> PathBuf {
+        let mut path = file_path.as_os_str().to_owned();
+        path.push(".hdr");
+        PathBuf::from(path)
+    }
+
+    pub fn load(file_path: &Path) -> std::io::Result<Option<Self>> {
+        let mut file = match File::open(Self::path_for(file_path)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        Self::decode(&bytes).map(Some)
+    }
+
+    // Written to a temporary file and renamed over the old header, so a crash
+    // mid-update leaves either the old or the new header behind.
+    pub fn store(&self, file_path: &Path) -> std::io::Result<()> {
+        let path = Self::path_for(file_path);
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&self.encode())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, &path)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FIXED_LEN + 8);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.page_size as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.features.to_le_bytes());
+        bytes.extend_from_slice(&(self.compression_ranges.len() as u32).to_le_bytes());
+        for (range, compression) in &self.compression_ranges {
+            let (codec, level) = compression.encode();
+            bytes.extend_from_slice(&range.start.to_le_bytes());
+            bytes.extend_from_slice(&range.end.to_le_bytes());
+            bytes.extend_from_slice(&codec.to_le_bytes());
+            bytes.extend_from_slice(&level.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(self.partitions.len() as u32).to_le_bytes());
+        for partition in &self.partitions {
+            bytes.extend_from_slice(&(partition.name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(partition.name.as_bytes());
+            bytes.extend_from_slice(&partition.start.to_le_bytes());
+            bytes.extend_from_slice(&partition.size.to_le_bytes());
+        }
+        let crc = crc32fast::hash(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> std::io::Result<Self> {
+        if bytes.len() < FIXED_LEN + 4 || bytes[..8] != MAGIC {
+            return Err(invalid_data("Not a wt_cache file header"));
+        }
+
+        let (body, crc) = bytes.split_at(bytes.len() - 4);
+        if u32::from_le_bytes(crc.try_into().unwrap()) != crc32fast::hash(body) {
+            return Err(invalid_data("File header checksum mismatch"));
+        }
+
+        let mut header = Self {
+            version: u32::from_le_bytes(body[8..12].try_into().unwrap()),
+            page_size: u32::from_le_bytes(body[12..16].try_into().unwrap()) as usize,
+            features: u64::from_le_bytes(body[16..24].try_into().unwrap()),
+            compression_ranges: Vec::new(),
+            partitions: Vec::new(),
+        };
+
+        // Checked before anything past the fixed fields is parsed, since a
+        // newer format may lay them out differently
+        if header.version > FORMAT_VERSION {
+            return Err(invalid_data(&format!(
+                "Unsupported file format version {}",
+                header.version
+            )));
+        }
+
+        if header.version >= 2 {
+            let table = &body[FIXED_LEN..];
+            let count = table
+                .get(..4)
+                .map(|count| u32::from_le_bytes(count.try_into().unwrap()) as usize);
+            let table_len = count.map(|count| 4 + count * RANGE_LEN);
+            let table_len = match table_len {
+                Some(len) if len == table.len() && header.version == 2 => len,
+                Some(len) if len <= table.len() && header.version >= 3 => len,
+                _ => return Err(invalid_data("Malformed compression range table")),
+            };
+
+            if header.version >= 3 {
+                header.partitions = decode_partitions(&table[table_len..])
+                    .ok_or_else(|| invalid_data("Malformed partition table"))?;
+            }
+
+            for range in table[4..table_len].chunks_exact(RANGE_LEN) {
+                let start = u64::from_le_bytes(range[0..8].try_into().unwrap());
+                let end = u64::from_le_bytes(range[8..16].try_into().unwrap());
+                let codec = u32::from_le_bytes(range[16..20].try_into().unwrap());
+                let level = i32::from_le_bytes(range[20..24].try_into().unwrap());
+                let compression = Compression::decode(codec, level)
+                    .ok_or_else(|| invalid_data("Unknown codec in compression range table"))?;
+                header.compression_ranges.push((start..end, compression));
+            }
+        } else if body.len() != FIXED_LEN {
+            return Err(invalid_data("Malformed file header"));
+        }
+
+        if header.features & !KNOWN_FEATURES != 0 {
+            return Err(invalid_data(&format!(
+                "Unsupported file features {:#x}",
+                header.features & !KNOWN_FEATURES
+            )));
+        }
+
+        Ok(header)
+    }
+}
+
+fn decode_partitions(mut table: &[u8]) -> Option<Vec<Partition>> {
+    let count = u32::from_le_bytes(table.get(..4)?.try_into().ok()?);
+    table = &table[4..];
+    let mut partitions = Vec::new();
+    for _ in 0..count {
+        let name_len = u16::from_le_bytes(table.get(..2)?.try_into().ok()?) as usize;
+        let name = std::str::from_utf8(table.get(2..2 + name_len)?).ok()?;
+        let fields = table.get(2 + name_len..2 + name_len + 16)?;
+        partitions.push(Partition {
+            name: name.to_string(),
+            start: u64::from_le_bytes(fields[..8].try_into().ok()?),
+            size: u64::from_le_bytes(fields[8..].try_into().ok()?),
+        });
+        table = &table[2 + name_len + 16..];
+    }
+    table.is_empty().then_some(partitions)
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,86 @@
+use std::ops::Range;
+
+use crate::WriteThroughCache;
+
+/// Access counts over fixed-size regions of the file, as returned by
+/// `WriteThroughCache::export_heatmap`. Entry `i` of each array covers bytes
+/// `i * bucket_size..(i + 1) * bucket_size`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heatmap {
+    pub bucket_size: u64,
+    /// Successful read calls that touched each bucket.
+    pub reads: Vec<u32>,
+    /// Successful write calls that touched each bucket.
+    pub writes: Vec<u32>,
+}
+
+// Per-bucket counters, grown as accesses reach further into the file
+pub(crate) struct HeatmapCounters {
+    bucket_size: u64,
+    reads: Vec<u32>,
+    writes: Vec<u32>,
+}
+
+impl HeatmapCounters {
+    pub fn new(bucket_size: u64) -> Self {
+        Self {
+            bucket_size,
+            reads: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+
+    pub fn read(&mut self, address: u64, len: usize) {
+        let buckets = self.buckets(address, len);
+        bump(&mut self.reads, buckets);
+    }
+
+    pub fn write(&mut self, address: u64, len: usize) {
+        let buckets = self.buckets(address, len);
+        bump(&mut self.writes, buckets);
+    }
+
+    fn buckets(&self, address: u64, len: usize) -> Range<usize> {
+        if len == 0 {
+            return 0..0;
+        }
+        let first = address / self.bucket_size;
+        let end = (address + len as u64).div_ceil(self.bucket_size);
+        first as usize..end as usize
+    }
+}
+
+fn bump(counts: &mut Vec<u32>, buckets: Range<usize>) {
+    if counts.len() < buckets.end {
+        counts.resize(buckets.end, 0);
+    }
+    for count in &mut counts[buckets] {
+        *count = count.saturating_add(1);
+    }
+}
+
+impl WriteThroughCache {
+    /// Read and write counts per region of the file since open or the last
+    /// `reset_heatmap`, one entry per bucket up to the end of the file.
+    /// `None` unless enabled with `CacheBuilder::heatmap`.
+    pub fn export_heatmap(&self) -> Option<Heatmap> {
+        let counters = self.heatmap.as_ref()?;
+        let buckets = self.file_size.div_ceil(counters.bucket_size) as usize;
+        let fit = |counts: &[u32]| {
+            let mut counts = counts[..counts.len().min(buckets)].to_vec();
+            counts.resize(buckets, 0);
+            counts
+        };
+        Some(Heatmap {
+            bucket_size: counters.bucket_size,
+            reads: fit(&counters.reads),
+            writes: fit(&counters.writes),
+        })
+    }
+
+    pub fn reset_heatmap(&mut self) {
+        if let Some(counters) = &mut self.heatmap {
+            *counters = HeatmapCounters::new(counters.bucket_size);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::ops::Range;
+
+use crate::audit::AuditLog;
+use crate::header::{FileHeader, FEATURE_COMPRESSED_PAGES, FEATURE_PAGE_VERSIONS};
+use crate::pio::data_extents;
+use crate::{Compression, WriteThroughCache};
+
+/// Layout of a cache file and its side files, from `WriteThroughCache::info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+    /// Logical size in bytes, which for a compressed file is larger than the
+    /// data file.
+    pub file_size: u64,
+    /// Size of the data file itself.
+    pub disk_size: u64,
+    pub page_size: usize,
+    /// Pages up to the logical end of the file.
+    pub pages: u64,
+    /// Whether the file has a header. A file written before headers existed
+    /// has none until it is opened with an explicit page size, and until then
+    /// `page_size` is only assumed.
+    pub header_present: bool,
+    /// Format version of the header, or of the one the file would get.
+    pub header_version: u32,
+    pub page_versions: bool,
+    pub compressed: bool,
+    pub compression_ranges: Vec<(Range<u64>, Compression)>,
+    /// Ranges of the data file with storage allocated; the gaps between them
+    /// are holes.
+    pub data_extents: Vec<Range<u64>>,
+    /// Records in the audit log, or why its hash chain is broken; `None`
+    /// when the file has no audit log. Page data is not checksummed
+    /// otherwise, only the header is.
+    pub audit_log: Option<Result<u64, String>>,
+    /// Sequence number of the last write, for a file with page versions.
+    pub last_lsn: Option<u64>,
+}
+
+impl FileInfo {
+    /// Bytes of the data file with storage allocated.
+    pub fn allocated(&self) -> u64 {
+        self.data_extents
+            .iter()
+            .map(|extent| extent.end - extent.start)
+            .sum()
+    }
+}
+
+impl WriteThroughCache {
+    /// Describe the file as stored: sizes, header, holes and the state of
+    /// the audit log and page versions. Nothing is read through the cache.
+    pub fn info(&self) -> std::io::Result<FileInfo> {
+        let disk_size = self.file.metadata()?.len();
+        let audit_log = AuditLog::path_for(&self.file_path)
+            .exists()
+            .then(|| AuditLog::verify(&self.file_path).map_err(|e| e.to_string()));
+        Ok(FileInfo {
+            file_size: self.file_size,
+            disk_size,
+            page_size: self.page_size,
+            pages: self.file_size.div_ceil(self.page_size as u64),
+            header_present: FileHeader::path_for(&self.file_path).exists(),
+            header_version: self.header.version,
+            page_versions: self.header.features & FEATURE_PAGE_VERSIONS != 0,
+            compressed: self.header.features & FEATURE_COMPRESSED_PAGES != 0,
+            compression_ranges: self.header.compression_ranges.clone(),
+            data_extents: data_extents(&self.file, disk_size)?,
+            audit_log,
+            last_lsn: self.last_lsn(),
+        })
+    }
+}
@@ -0,0 +1,59 @@
+use crate::WriteThroughCache;
+
+// read_<t>_le/_be and write_<t>_le/_be for each numeric type, reading and
+// writing size_of::<t>() bytes at the address
+macro_rules! endian_accessors {
+    ($($ty:ident: $read_le:ident, $read_be:ident, $write_le:ident, $write_be:ident;)*) => {
+        impl WriteThroughCache {
+            $(
+                pub fn $read_le(&mut self, address: u64) -> std::io::Result<$ty> {
+                    let bytes = self.read(address, std::mem::size_of::<$ty>())?;
+                    Ok($ty::from_le_bytes(bytes.try_into().unwrap()))
+                }
+
+                pub fn $read_be(&mut self, address: u64) -> std::io::Result<$ty> {
+                    let bytes = self.read(address, std::mem::size_of::<$ty>())?;
+                    Ok($ty::from_be_bytes(bytes.try_into().unwrap()))
+                }
+
+                pub fn $write_le(&mut self, address: u64, value: $ty) -> std::io::Result<()> {
+                    self.write(address, &value.to_le_bytes())
+                }
+
+                pub fn $write_be(&mut self, address: u64, value: $ty) -> std::io::Result<()> {
+                    self.write(address, &value.to_be_bytes())
+                }
+            )*
+        }
+    };
+}
+
+endian_accessors! {
+    u16: read_u16_le, read_u16_be, write_u16_le, write_u16_be;
+    u32: read_u32_le, read_u32_be, write_u32_le, write_u32_be;
+    u64: read_u64_le, read_u64_be, write_u64_le, write_u64_be;
+    i16: read_i16_le, read_i16_be, write_i16_le, write_i16_be;
+    i32: read_i32_le, read_i32_be, write_i32_le, write_i32_be;
+    i64: read_i64_le, read_i64_be, write_i64_le, write_i64_be;
+    f32: read_f32_le, read_f32_be, write_f32_le, write_f32_be;
+    f64: read_f64_le, read_f64_be, write_f64_le, write_f64_be;
+}
+
+// Single bytes have no byte order
+impl WriteThroughCache {
+    pub fn read_u8(&mut self, address: u64) -> std::io::Result<u8> {
+        Ok(self.read(address, 1)?[0])
+    }
+
+    pub fn read_i8(&mut self, address: u64) -> std::io::Result<i8> {
+        Ok(self.read(address, 1)?[0] as i8)
+    }
+
+    pub fn write_u8(&mut self, address: u64, value: u8) -> std::io::Result<()> {
+        self.write(address, &[value])
+    }
+
+    pub fn write_i8(&mut self, address: u64, value: i8) -> std::io::Result<()> {
+        self.write(address, &value.to_le_bytes())
+    }
+}
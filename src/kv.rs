@@ -0,0 +1,323 @@
+//! A persistent key-value store kept in a cache-managed file.
+//!
+//! Puts and deletes are appended to the file as records, and an in-memory
+//! index of where each live value is stored is rebuilt by scanning them on
+//! open. Overwritten and deleted values stay in the file until `compact`
+//! rewrites the live ones into a new one.
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use std::ops::Bound;
+
+use crate::WriteThroughCache;
+
+// Record layout: kind u8 | key length u32 | value length u32 |
+// crc32 of everything else u32 | key | value. The file past the last record
+// is zero-filled, so a zero kind marks the end of the log.
+const HEADER_LEN: usize = 1 + 4 + 4 + 4;
+const KIND_PUT: u8 = 1;
+const KIND_DELETE: u8 = 2;
+
+struct Record {
+    kind: u8,
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+// Where a live value is stored
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    offset: u64,
+    len: u32,
+}
+
+pub struct KvStore {
+    cache: WriteThroughCache,
+    index: BTreeMap<Vec<u8>, Location>,
+    // Offset the next record is appended at
+    end: u64,
+}
+
+impl KvStore {
+    /// Open the store kept in `cache`'s file, which may be empty. A last
+    /// record that fails its checksum, or runs past the end of the file, is
+    /// taken as a write torn by a crash: it is zeroed and the log ends
+    /// before it. One followed by more of the log is corruption, reported
+    /// as `InvalidData` rather than dropping the records after it.
+    pub fn open(mut cache: WriteThroughCache) -> std::io::Result<Self> {
+        let mut index = BTreeMap::new();
+        let mut offset = 0;
+        while let Some(Record { kind, key, value }) = read_record(&mut cache, offset)? {
+            let value_offset = offset + (HEADER_LEN + key.len()) as u64;
+            offset = value_offset + value.len() as u64;
+            if kind == KIND_PUT {
+                let location = Location {
+                    offset: value_offset,
+                    len: value.len() as u32,
+                };
+                index.insert(key, location);
+            } else {
+                index.remove(&key);
+            }
+        }
+        Ok(Self {
+            cache,
+            index,
+            end: offset,
+        })
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+        match self.index.get(key) {
+            Some(location) => self.read_value(*location).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> std::io::Result<()> {
+        let value_offset = self.append(KIND_PUT, key, value)?;
+        let location = Location {
+            offset: value_offset,
+            len: value.len() as u32,
+        };
+        self.index.insert(key.to_vec(), location);
+        Ok(())
+    }
+
+    /// Remove `key`, returning whether it was present.
+    pub fn delete(&mut self, key: &[u8]) -> std::io::Result<bool> {
+        if !self.index.contains_key(key) {
+            return Ok(false);
+        }
+        self.append(KIND_DELETE, key, &[])?;
+        self.index.remove(key);
+        Ok(true)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.index.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Bytes of the file taken by the log, including superseded records.
+    pub fn log_size(&self) -> u64 {
+        self.end
+    }
+
+    /// Every entry in key order. Values are read as the iterator reaches
+    /// them.
+    pub fn iter(&mut self) -> Iter<'_> {
+        Iter {
+            store: self,
+            last: None,
+        }
+    }
+
+    pub fn into_inner(self) -> WriteThroughCache {
+        self.cache
+    }
+
+    /// Write the live entries into `dest`, which must be empty, as a fresh
+    /// log without superseded records, and return the store kept there.
+    /// This store is left as it was.
+    pub fn compact(&mut self, dest: WriteThroughCache) -> std::io::Result<KvStore> {
+        if dest.file_size() != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Compaction target is not empty",
+            ));
+        }
+        let mut compacted = KvStore {
+            cache: dest,
+            index: BTreeMap::new(),
+            end: 0,
+        };
+        for entry in self.iter() {
+            let (key, value) = entry?;
+            compacted.put(&key, &value)?;
+        }
+        Ok(compacted)
+    }
+
+    // Append a record in a single write and return the offset of its value
+    fn append(&mut self, kind: u8, key: &[u8], value: &[u8]) -> std::io::Result<u64> {
+        let too_large =
+            |what| Error::new(ErrorKind::InvalidInput, format!("{} is too large", what));
+        let key_len = u32::try_from(key.len()).map_err(|_| too_large("Key"))?;
+        let value_len = u32::try_from(value.len()).map_err(|_| too_large("Value"))?;
+
+        let mut record = Vec::with_capacity(HEADER_LEN + key.len() + value.len());
+        record.push(kind);
+        record.extend_from_slice(&key_len.to_le_bytes());
+        record.extend_from_slice(&value_len.to_le_bytes());
+        record.extend_from_slice(&[0; 4]);
+        record.extend_from_slice(key);
+        record.extend_from_slice(value);
+        let crc = record_crc(&record);
+        record[9..13].copy_from_slice(&crc.to_le_bytes());
+
+        self.cache.write(self.end, &record)?;
+        let value_offset = self.end + (HEADER_LEN + key.len()) as u64;
+        self.end = value_offset + value.len() as u64;
+        Ok(value_offset)
+    }
+
+    fn read_value(&mut self, location: Location) -> std::io::Result<Vec<u8>> {
+        self.cache.read(location.offset, location.len as usize)
+    }
+}
+
+/// Iterator over a `KvStore`'s entries in key order, from `KvStore::iter`.
+pub struct Iter<'a> {
+    store: &'a mut KvStore,
+    last: Option<Vec<u8>>,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = std::io::Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let lower = match &self.last {
+            Some(last) => Bound::Excluded(last.as_slice()),
+            None => Bound::Unbounded,
+        };
+        let (key, location) = self
+            .store
+            .index
+            .range::<[u8], _>((lower, Bound::Unbounded))
+            .next()
+            .map(|(key, location)| (key.clone(), *location))?;
+        self.last = Some(key.clone());
+        Some(self.store.read_value(location).map(|value| (key, value)))
+    }
+}
+
+// Read the record at `offset`, or None at the end of the log. A torn last
+// record is zeroed on the way, so a shorter one appended in its place
+// doesn't leave part of it behind.
+fn read_record(cache: &mut WriteThroughCache, offset: u64) -> std::io::Result<Option<Record>> {
+    let file_size = cache.file_size();
+    if offset + HEADER_LEN as u64 > file_size {
+        return Ok(None);
+    }
+    let header = cache.read(offset, HEADER_LEN)?;
+    let kind = header[0];
+    if kind == 0 {
+        return Ok(None);
+    }
+    let key_len = u32::from_le_bytes(header[1..5].try_into().unwrap()) as u64;
+    let value_len = u32::from_le_bytes(header[5..9].try_into().unwrap()) as u64;
+    let record_end = offset + HEADER_LEN as u64 + key_len + value_len;
+
+    let Some(mut record) = read_valid(cache, offset, file_size)? else {
+        // A damaged length field can make a record in the middle of the log
+        // look like it runs past the end, so the log is only taken to end
+        // here if no valid record starts after it
+        let torn_end = std::cmp::min(record_end, file_size);
+        if !is_zero(cache, torn_end, file_size)? || record_follows(cache, offset + 1, file_size)? {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Record at offset {} is damaged and more of the log follows",
+                    offset
+                ),
+            ));
+        }
+        zero(cache, offset, torn_end)?;
+        return Ok(None);
+    };
+    if kind != KIND_PUT && kind != KIND_DELETE {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Unknown record kind {} at offset {}", kind, offset),
+        ));
+    }
+    let value = record.split_off(HEADER_LEN + key_len as usize);
+    let key = record.split_off(HEADER_LEN);
+    Ok(Some(Record { kind, key, value }))
+}
+
+// The bytes of the record at `offset` if it lies within the file and passes
+// its checksum
+fn read_valid(
+    cache: &mut WriteThroughCache,
+    offset: u64,
+    file_size: u64,
+) -> std::io::Result<Option<Vec<u8>>> {
+    if offset + HEADER_LEN as u64 > file_size {
+        return Ok(None);
+    }
+    let header = cache.read(offset, HEADER_LEN)?;
+    let key_len = u32::from_le_bytes(header[1..5].try_into().unwrap()) as u64;
+    let value_len = u32::from_le_bytes(header[5..9].try_into().unwrap()) as u64;
+    let crc = u32::from_le_bytes(header[9..13].try_into().unwrap());
+    let body_offset = offset + HEADER_LEN as u64;
+    if body_offset + key_len + value_len > file_size {
+        return Ok(None);
+    }
+    let mut record = header;
+    record.extend(cache.read(body_offset, (key_len + value_len) as usize)?);
+    Ok((record_crc(&record) == crc).then_some(record))
+}
+
+// Whether a valid put or delete record starts anywhere in `start..end`
+fn record_follows(cache: &mut WriteThroughCache, start: u64, end: u64) -> std::io::Result<bool> {
+    let chunk = cache.page_size() as u64;
+    let mut offset = start;
+    while offset + HEADER_LEN as u64 <= end {
+        let len = std::cmp::min(chunk, end - offset);
+        let kinds = cache.read(offset, len as usize)?;
+        for (i, &kind) in kinds.iter().enumerate() {
+            if (kind == KIND_PUT || kind == KIND_DELETE)
+                && read_valid(cache, offset + i as u64, end)?.is_some()
+            {
+                return Ok(true);
+            }
+        }
+        offset += len;
+    }
+    Ok(false)
+}
+
+// Whether `start..end` of the file holds nothing but zeros
+fn is_zero(cache: &mut WriteThroughCache, start: u64, end: u64) -> std::io::Result<bool> {
+    let chunk = cache.page_size() as u64;
+    let mut offset = start;
+    while offset < end {
+        let len = std::cmp::min(chunk, end - offset);
+        if cache.read(offset, len as usize)?.iter().any(|&b| b != 0) {
+            return Ok(false);
+        }
+        offset += len;
+    }
+    Ok(true)
+}
+
+// Overwrite `start..end` of the file with zeros, a page at a time
+fn zero(cache: &mut WriteThroughCache, start: u64, end: u64) -> std::io::Result<()> {
+    let chunk = cache.page_size() as u64;
+    let zeros = vec![0; std::cmp::min(chunk, end.saturating_sub(start)) as usize];
+    let mut offset = start;
+    while offset < end {
+        let len = std::cmp::min(chunk, end - offset);
+        cache.write(offset, &zeros[..len as usize])?;
+        offset += len;
+    }
+    Ok(())
+}
+
+// Checksum of a record with its crc field taken as zero
+fn record_crc(record: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&record[..9]);
+    hasher.update(&[0; 4]);
+    hasher.update(&record[HEADER_LEN..]);
+    hasher.finalize()
+}
@@ -0,0 +1,162 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Each power of two is split into this many buckets, so a recorded value is
+// known to within 1/8th of itself; smaller values are kept exactly
+const SUB_BITS: u32 = 3;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+const BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_BUCKETS;
+
+fn bucket(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+    let shift = 63 - nanos.leading_zeros() - SUB_BITS;
+    (shift as usize + 1) * SUB_BUCKETS + (nanos >> shift) as usize - SUB_BUCKETS
+}
+
+// Largest value that falls into `bucket`
+fn bucket_high(bucket: usize) -> u64 {
+    if bucket < 2 * SUB_BUCKETS {
+        return bucket as u64;
+    }
+    let shift = bucket / SUB_BUCKETS - 1;
+    let low = ((SUB_BUCKETS + bucket % SUB_BUCKETS) as u64) << shift;
+    low + ((1 << shift) - 1)
+}
+
+// Histogram that can be recorded into through a shared reference
+pub(crate) struct AtomicHistogram {
+    counts: Box<[AtomicU64]>,
+    sum: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for AtomicHistogram {
+    fn default() -> Self {
+        Self {
+            counts: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl std::fmt::Debug for AtomicHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.snapshot().fmt(f)
+    }
+}
+
+impl AtomicHistogram {
+    pub fn record(&self, elapsed: Duration) {
+        let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        self.counts[bucket(nanos)].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(nanos, Ordering::Relaxed);
+        self.max.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> LatencyHistogram {
+        let mut counts: Vec<u64> = self
+            .counts
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect();
+        while counts.last() == Some(&0) {
+            counts.pop();
+        }
+        LatencyHistogram {
+            counts,
+            sum: self.sum.load(Ordering::Relaxed),
+            max: self.max.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset(&self) {
+        for count in self.counts.iter() {
+            count.store(0, Ordering::Relaxed);
+        }
+        self.sum.store(0, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Distribution of operation latencies in log-linear buckets: every value
+/// is resolved to within 12.5%, from nanoseconds up.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    sum: u64,
+    max: u64,
+}
+
+impl LatencyHistogram {
+    /// Number of operations recorded.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max)
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.count() {
+            0 => Duration::ZERO,
+            count => Duration::from_nanos(self.sum / count),
+        }
+    }
+
+    /// Latency that `percentile` percent of operations did not exceed, for
+    /// example `percentile(99.9)`. Reported as the top of its bucket, so it
+    /// errs high by at most 12.5%.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        let count = self.count();
+        if count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((percentile / 100.0 * count as f64).ceil() as u64).clamp(1, count);
+        let mut seen = 0;
+        for (bucket, &n) in self.counts.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Duration::from_nanos(bucket_high(bucket).min(self.max));
+            }
+        }
+        self.max()
+    }
+}
+
+/// Latencies of disk operations made on behalf of reads and writes.
+/// Readahead fetches run in the background and are not included.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub page_reads: LatencyHistogram,
+    /// Writes of page data. The sync that follows is timed separately,
+    /// except in compressed files, where it is part of the page write.
+    pub page_writes: LatencyHistogram,
+    pub fsyncs: LatencyHistogram,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Latencies {
+    pub page_reads: AtomicHistogram,
+    pub page_writes: AtomicHistogram,
+    pub fsyncs: AtomicHistogram,
+}
+
+impl Latencies {
+    pub fn snapshot(&self) -> LatencyStats {
+        LatencyStats {
+            page_reads: self.page_reads.snapshot(),
+            page_writes: self.page_writes.snapshot(),
+            fsyncs: self.fsyncs.snapshot(),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.page_reads.reset();
+        self.page_writes.reset();
+        self.fsyncs.reset();
+    }
+}
@@ -1,10 +1,122 @@
-use std::cell::RefCell;
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 use std::fs::File;
 use std::hash::BuildHasherDefault;
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
-use std::rc::Rc;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicU64;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+use zeroize::Zeroize;
+
+pub mod actor;
+pub mod allocator;
+mod audit;
+mod backup;
+pub mod bitmap;
+pub mod blob;
+pub mod btree;
+mod changes;
+mod codec;
+mod compact;
+mod compression;
+mod copy;
+mod dump;
+mod events;
+mod header;
+mod heatmap;
+mod info;
+mod ints;
+pub mod kv;
+#[cfg(feature = "latency")]
+mod latency;
+mod lock;
+mod manager;
+#[cfg(feature = "metrics")]
+mod metrics_facade;
+mod origin;
+mod partition;
+mod pio;
+#[cfg(feature = "zerocopy")]
+mod pod;
+mod pool;
+#[cfg(feature = "prometheus")]
+mod prometheus_metrics;
+#[cfg(feature = "epoch")]
+mod read_mostly;
+mod readahead;
+pub mod readthrough;
+#[cfg(feature = "postcard")]
+mod records;
+pub mod replication;
+pub mod ring;
+pub mod segmented;
+mod shared;
+#[cfg(unix)]
+pub mod shm;
+mod single_writer;
+mod slab;
+pub mod slotted;
+mod stats;
+pub mod superblock;
+mod sync;
+mod tier;
+pub mod tiered;
+mod verify;
+mod versions;
+#[cfg(feature = "rayon")]
+mod warm;
+pub mod workload;
+
+use audit::AuditLog;
+use changes::ChangeTracker;
+use compression::{CompressedStore, DEFAULT_COMPRESSION_THRESHOLD};
+use events::Events;
+use header::{FileHeader, FEATURE_COMPRESSED_PAGES, FEATURE_PAGE_VERSIONS};
+use heatmap::HeatmapCounters;
+use pio::{read_append_at, read_exact_at, write_all_at};
+use pool::{BufferPool, Frame};
+use readahead::{Fetcher, Readahead};
+use slab::{PageSlab, ENTRY_BYTES, HOT_SHARE};
+use stats::{Counters, Timer};
+use tier::CompressedTier;
+use versions::PageVersions;
+
+pub use backup::{Backup, BackupReport};
+pub use changes::{ChangeEvent, Epoch};
+#[cfg(feature = "snappy")]
+pub use codec::SnappyCodec;
+#[cfg(feature = "zstd")]
+pub use codec::ZstdCodec;
+pub use codec::{
+    Codec, Lz4Codec, CODEC_LZ4, CODEC_RAW, CODEC_SNAPPY, CODEC_ZSTD, CODEC_ZSTD_DICT,
+    CUSTOM_CODEC_BASE,
+};
+pub use compact::{CompactOptions, CompactReport};
+pub use compression::{CodecStats, Compression, CompressionStats};
+pub use dump::{CacheState, PageLocation, PageState};
+pub use events::{CacheEvents, DiskOp};
+pub use heatmap::Heatmap;
+pub use info::FileInfo;
+#[cfg(feature = "latency")]
+pub use latency::{LatencyHistogram, LatencyStats};
+pub use lock::FileLock;
+pub use manager::{CacheManager, ManagedCache};
+#[cfg(feature = "metrics")]
+pub use metrics_facade::MetricsFacade;
+pub use origin::{FileOrigin, Origin};
+pub use partition::{Partition, PartitionHandle};
+pub use pool::PoolStats;
+#[cfg(feature = "prometheus")]
+pub use prometheus_metrics::PrometheusMetrics;
+#[cfg(feature = "epoch")]
+pub use read_mostly::ReadMostlyCache;
+pub use shared::SyncCache;
+pub use single_writer::{CacheReader, CacheWriter};
+pub use stats::{CacheStats, StatsRates, StatsSnapshot};
+pub use verify::VerifyReport;
+#[cfg(feature = "zerocopy")]
+pub use zerocopy;
 
 const DEFAULT_PAGE_SIZE: usize = 64 * 1024; // 64KiB
 const MIN_PAGE_SIZE: usize = 512;
@@ -12,38 +124,312 @@ const MAX_PAGE_SIZE: usize = 1024 * 1024; // 1MiB
 const DEFAULT_CAPACITY: usize = 16 * 1024 * 1024; // 16MiB
 const MIN_CAPACITY: usize = MIN_PAGE_SIZE;
 const MAX_CAPACITY: usize = 1024 * 1024 * 1024; // 1GiB
+const DEFAULT_SECTOR_SIZE: usize = 4096;
+const DEFAULT_BUFFER_POOL: usize = 16;
+const MAX_IO_SIZE: usize = 4 * 1024 * 1024; // 4MiB
+const DEFAULT_FETCH_WORKERS: usize = 4;
 
-type LinkedListNode = Rc<RefCell<LinkedListNodeInner>>;
-pub type AHashMap<K, V> = HashMap<K, V, BuildHasherDefault<ahash::AHasher>>;
-
-struct LinkedListNodeInner {
-    data: Vec<u8>,
+// Largest power of two dividing `page_size`, capped at the default sector
+// size. Pages with no such divisor of at least `MIN_PAGE_SIZE` are always
+// rewritten whole.
+fn default_sector_size(page_size: usize) -> usize {
+    let divisor = std::cmp::min(page_size & page_size.wrapping_neg(), DEFAULT_SECTOR_SIZE);
+    if divisor < MIN_PAGE_SIZE {
+        page_size
+    } else {
+        divisor
+    }
 }
 
+pub type AHashMap<K, V> = HashMap<K, V, BuildHasherDefault<ahash::AHasher>>;
+
 pub struct WriteThroughCache {
     page_size: usize,
     capacity: usize,
-    cache: AHashMap<u64, LinkedListNode>,
-    usage_order: VecDeque<u64>,
+    cache: PageSlab,
     file: File,
+    // Handle holding the advisory lock, if one was asked for
+    _lock: Option<File>,
     file_size: u64,
+    secure: bool,
+    versions: Option<PageVersions>,
+    audit_log: Option<AuditLog>,
+    compressed: Option<CompressedStore>,
+    tier: Option<CompressedTier>,
+    sector_size: usize,
+    fetch_granularity: usize,
+    last_read_page: Option<u64>,
+    stream_end: u64,
+    stream_len: u64,
+    pool: BufferPool,
+    readahead: Option<Readahead>,
+    fetcher: Option<Fetcher>,
+    fetch_workers: usize,
+    stats: Counters,
+    events: Events,
+    heatmap: Option<HeatmapCounters>,
+    // Pages written since change tracking was first asked for
+    changes: Option<ChangeTracker>,
+    subscribers: Vec<Sender<ChangeEvent>>,
+    file_path: PathBuf,
+    header: FileHeader,
+}
+
+pub struct CacheBuilder {
+    file_path: PathBuf,
+    page_size: Option<usize>,
+    capacity: Option<usize>,
+    secure: bool,
+    page_versions: bool,
+    audit_log: bool,
+    compression: Compression,
+    compression_threshold: f64,
+    codecs: Vec<Box<dyn Codec>>,
+    compressed_tier: Option<usize>,
+    sector_size: Option<usize>,
+    fetch_granularity: Option<usize>,
+    buffer_pool: usize,
+    hugepages: bool,
+    readahead: usize,
+    fetch_workers: usize,
+    events: Option<Box<dyn CacheEvents>>,
+    slow_op_threshold: Option<std::time::Duration>,
+    heatmap: Option<u64>,
+    file_lock: Option<FileLock>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<MetricsFacade>,
+    #[cfg(feature = "prometheus")]
+    prometheus: Option<PrometheusMetrics>,
+    // Set by `CacheManager`, whose files share one budget: their indexes
+    // grow with the pages actually cached rather than being sized for the
+    // capacity up front, and their pages' recency is stamped from one clock
+    presize_index: bool,
+    recency_clock: Option<Arc<AtomicU64>>,
+}
+
+impl CacheBuilder {
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Wipe page buffers when they are evicted and when the cache is dropped.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Stamp every written page with a monotonically increasing LSN. Once
+    /// enabled for a file it stays enabled on every later open.
+    pub fn page_versions(mut self, page_versions: bool) -> Self {
+        self.page_versions = page_versions;
+        self
+    }
+
+    /// Append a hash-chained record of every write to a `.audit` sidecar.
+    /// Each record is synced before the write it describes, so every write
+    /// that reached the file is recorded; a write that failed or was
+    /// interrupted by a crash may be recorded without having landed.
+    pub fn audit_log(mut self, audit_log: bool) -> Self {
+        self.audit_log = audit_log;
+        self
+    }
+
+    /// Compress pages on disk. The data file then holds a heap of encoded
+    /// pages located through a `.idx` sidecar, so it can only be enabled on
+    /// an empty file; files already in this layout stay in it.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Store pages uncompressed when compression would shrink them by less
+    /// than `ratio` (uncompressed / compressed size).
+    pub fn compression_threshold(mut self, ratio: f64) -> Self {
+        self.compression_threshold = ratio;
+        self
+    }
+
+    /// Register a codec selectable with `Compression::Custom(codec.id())`.
+    /// It must be registered on every open of a file it has written pages to.
+    pub fn codec(mut self, codec: Box<dyn Codec>) -> Self {
+        self.codecs.push(codec);
+        self
+    }
+
+    /// Keep up to `capacity` bytes of evicted pages LZ4-compressed in memory,
+    /// so misses in the main cache can be served without going to disk.
+    pub fn compressed_tier(mut self, capacity: usize) -> Self {
+        self.compressed_tier = Some(capacity);
+        self
+    }
+
+    /// Granularity of partial page writes to uncompressed files: a write that
+    /// covers part of a page only rewrites the sectors it touches. A power of
+    /// two between 512 bytes and the page size. Defaults to 4KiB, or to the
+    /// largest power of two dividing a smaller or odd page size; pages with
+    /// no such divisor of at least 512 bytes are always rewritten whole.
+    pub fn sector_size(mut self, sector_size: usize) -> Self {
+        self.sector_size = Some(sector_size);
+        self
+    }
+
+    /// Serve small random read misses by reading only the `block_size`
+    /// aligned blocks they cover instead of the whole page. Such pages are
+    /// not cached, trading hit ratio for less read amplification on cold
+    /// random workloads. A power of two between 512 bytes and the page size;
+    /// only available for uncompressed files.
+    pub fn fetch_granularity(mut self, block_size: usize) -> Self {
+        self.fetch_granularity = Some(block_size);
+        self
+    }
+
+    /// Keep up to `max_buffers` freed page buffers around for reuse.
+    pub fn buffer_pool(mut self, max_buffers: usize) -> Self {
+        self.buffer_pool = max_buffers;
+        self
+    }
+
+    /// Carve page buffers out of 2MiB huge pages to cut TLB misses on large
+    /// caches. Falls back to transparent huge pages, then to the heap, when
+    /// none are available.
+    pub fn hugepages(mut self, hugepages: bool) -> Self {
+        self.hugepages = hugepages;
+        self
+    }
+
+    /// Fetch up to `max_pages` pages ahead of sequential and strided reads on
+    /// helper threads. Several interleaved streams are tracked; each window
+    /// grows while its stream keeps its stride, and random reads fetch
+    /// nothing ahead. Only available for uncompressed files.
+    pub fn readahead(mut self, max_pages: usize) -> Self {
+        self.readahead = max_pages;
+        self
+    }
+
+    /// Number of helper threads reading pages for readahead and `prefetch`.
+    pub fn fetch_workers(mut self, workers: usize) -> Self {
+        self.fetch_workers = workers;
+        self
+    }
+
+    /// Report hits, misses, evictions, syncs and errors to `events`.
+    pub fn events(mut self, events: Box<dyn CacheEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Report any single disk read, write or sync that takes longer than
+    /// `threshold`, to `CacheEvents::on_slow_op` and as a `log` warning.
+    pub fn slow_op_threshold(mut self, threshold: std::time::Duration) -> Self {
+        self.slow_op_threshold = Some(threshold);
+        self
+    }
+
+    /// Count reads and writes per `bucket_size` bytes of the file, for
+    /// `export_heatmap`. The bucket size must be a multiple of the page size.
+    pub fn heatmap(mut self, bucket_size: u64) -> Self {
+        self.heatmap = Some(bucket_size);
+        self
+    }
+
+    /// Take an exclusive advisory lock on the file while it is open, so
+    /// another process opening it with a lock fails or waits rather than
+    /// writing alongside this one.
+    pub fn file_lock(mut self, mode: FileLock) -> Self {
+        self.file_lock = Some(mode);
+        self
+    }
+
+    /// Report this cache's activity through the `metrics` facade.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: MetricsFacade) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Keep `metrics` up to date with this cache's activity.
+    #[cfg(feature = "prometheus")]
+    pub fn prometheus(mut self, metrics: PrometheusMetrics) -> Self {
+        self.prometheus = Some(metrics);
+        self
+    }
+
+    pub fn open(self) -> std::io::Result<WriteThroughCache> {
+        WriteThroughCache::open(self)
+    }
 }
 
 impl WriteThroughCache {
     pub fn new(
-        file_path: &PathBuf,
+        file_path: &Path,
         page_size: Option<usize>,
         capacity: Option<usize>,
     ) -> std::io::Result<Self> {
-        let file = File::options()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(file_path)?;
+        let mut options = Self::builder(file_path);
+        options.page_size = page_size;
+        options.capacity = capacity;
+        Self::open(options)
+    }
 
-        let file_size = file.metadata()?.len();
-        let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE);
-        let capacity = capacity.unwrap_or(DEFAULT_CAPACITY);
+    pub fn builder<P: Into<PathBuf>>(file_path: P) -> CacheBuilder {
+        CacheBuilder {
+            file_path: file_path.into(),
+            page_size: None,
+            capacity: None,
+            secure: false,
+            page_versions: false,
+            audit_log: false,
+            compression: Compression::None,
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            codecs: Vec::new(),
+            compressed_tier: None,
+            sector_size: None,
+            fetch_granularity: None,
+            buffer_pool: DEFAULT_BUFFER_POOL,
+            hugepages: false,
+            readahead: 0,
+            fetch_workers: DEFAULT_FETCH_WORKERS,
+            events: None,
+            slow_op_threshold: None,
+            heatmap: None,
+            file_lock: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "prometheus")]
+            prometheus: None,
+            presize_index: true,
+            recency_clock: None,
+        }
+    }
+
+    fn open(options: CacheBuilder) -> std::io::Result<Self> {
+        // Locked before anything is read, so a process waiting for the lock
+        // sees the holder's last state
+        let lock = match options.file_lock {
+            Some(mode) => Some(lock::acquire(&options.file_path, mode)?),
+            None => None,
+        };
+        let header = FileHeader::load(&options.file_path)?;
+        let page_size = match (&header, options.page_size) {
+            (Some(header), Some(page_size)) if header.page_size != page_size => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "File was written with a page size of {} bytes, not {} bytes",
+                        header.page_size, page_size
+                    ),
+                ));
+            }
+            (Some(header), _) => header.page_size,
+            (None, page_size) => page_size.unwrap_or(DEFAULT_PAGE_SIZE),
+        };
+        let capacity = options.capacity.unwrap_or(DEFAULT_CAPACITY);
 
         if page_size < MIN_PAGE_SIZE || capacity < MIN_CAPACITY {
             return Err(std::io::Error::new(
@@ -65,30 +451,289 @@ impl WriteThroughCache {
             ));
         }
 
+        let sector_size = match options.sector_size {
+            Some(sector_size)
+                if !sector_size.is_power_of_two()
+                    || sector_size < MIN_PAGE_SIZE
+                    || sector_size > page_size =>
+            {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "Sector size must be a power of two between {} bytes and the page size",
+                        MIN_PAGE_SIZE
+                    ),
+                ));
+            }
+            Some(sector_size) => sector_size,
+            None => default_sector_size(page_size),
+        };
+
+        if let Some(bucket_size) = options.heatmap {
+            if bucket_size == 0 || !bucket_size.is_multiple_of(page_size as u64) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Heatmap bucket size must be a multiple of the page size",
+                ));
+            }
+        }
+
+        // Whole pages are fetched unless the caller asks for smaller blocks
+        let fetch_granularity = match options.fetch_granularity {
+            Some(block_size)
+                if !block_size.is_power_of_two()
+                    || block_size < MIN_PAGE_SIZE
+                    || block_size > page_size =>
+            {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "Fetch granularity must be a power of two between {} bytes and the page size",
+                        MIN_PAGE_SIZE
+                    ),
+                ));
+            }
+            Some(block_size) => block_size,
+            None => page_size,
+        };
+
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&options.file_path)?;
+        let mut file_size = file.metadata()?.len();
+
+        let stored_features = header.as_ref().map(|header| header.features);
+        let mut header = header.unwrap_or_else(|| FileHeader::new(page_size, 0));
+        let mut features = header.features;
+        if options.page_versions {
+            features |= FEATURE_PAGE_VERSIONS;
+        }
+        if options.compression != Compression::None && features & FEATURE_COMPRESSED_PAGES == 0 {
+            if file_size > 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Compression can only be enabled on an empty file",
+                ));
+            }
+            features |= FEATURE_COMPRESSED_PAGES;
+        }
+        // A file written before headers existed only gets one, fixing its
+        // page size for good, once the caller has said what it is
+        let legacy = stored_features.is_none() && file_size > 0 && options.page_size.is_none();
+        if legacy && features != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Enabling features on a file without a header requires its page size",
+            ));
+        }
+        if stored_features != Some(features) && !legacy {
+            header.features = features;
+            header.store(&options.file_path)?;
+        }
+
+        let versions = if features & FEATURE_PAGE_VERSIONS != 0 {
+            Some(PageVersions::open(&options.file_path)?)
+        } else {
+            None
+        };
+
+        let compressed = if features & FEATURE_COMPRESSED_PAGES != 0 {
+            let mut store = CompressedStore::open(
+                &options.file_path,
+                file_size,
+                options.compression,
+                options.compression_threshold,
+                options.codecs,
+                options.secure,
+            )?;
+            store.set_ranges(header.compression_ranges.clone());
+            file_size = store.logical_size();
+            Some(store)
+        } else {
+            None
+        };
+
+        if fetch_granularity < page_size && compressed.is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Sub-page fetches require an uncompressed file",
+            ));
+        }
+
+        let readahead = if options.readahead == 0 {
+            None
+        } else if compressed.is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Readahead requires an uncompressed file",
+            ));
+        } else {
+            // Never fetch so far ahead that the window evicts itself
+            let max_depth = std::cmp::min(options.readahead, capacity / page_size / 2).max(1);
+            Some(Readahead::new(max_depth as u64))
+        };
+
+        let audit_log = if options.audit_log {
+            Some(AuditLog::open(&options.file_path)?)
+        } else {
+            None
+        };
+
+        #[cfg(feature = "log")]
+        log::debug!(
+            "Opened {} ({} bytes): page size {}, capacity {}, sector size {}, fetch granularity {}, \
+             compressed {}, page versions {}, audit log {}, compressed tier {:?}, readahead {}, \
+             secure {}",
+            options.file_path.display(),
+            file_size,
+            page_size,
+            capacity,
+            sector_size,
+            fetch_granularity,
+            compressed.is_some(),
+            versions.is_some(),
+            audit_log.is_some(),
+            options.compressed_tier,
+            options.readahead,
+            options.secure,
+        );
+
+        #[cfg_attr(not(feature = "metrics"), allow(unused_mut))]
+        let mut stats = Counters::default();
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &options.metrics {
+            metrics.mirror(&mut stats);
+        }
+
+        let mut cache = PageSlab::with_capacity(if options.presize_index {
+            capacity / (page_size + ENTRY_BYTES) + 1
+        } else {
+            0
+        });
+        if let Some(clock) = options.recency_clock {
+            cache.set_clock(clock);
+        }
+
         Ok(Self {
             page_size,
             capacity,
-            cache: AHashMap::default(),
-            usage_order: VecDeque::new(),
+            cache,
             file,
+            _lock: lock,
             file_size,
+            secure: options.secure,
+            versions,
+            audit_log,
+            compressed,
+            tier: options
+                .compressed_tier
+                .map(|capacity| CompressedTier::new(capacity, options.secure)),
+            sector_size,
+            fetch_granularity,
+            last_read_page: None,
+            stream_end: 0,
+            stream_len: 0,
+            pool: BufferPool::new(
+                page_size,
+                options.buffer_pool,
+                options.secure,
+                options.hugepages,
+            ),
+            readahead,
+            fetcher: None,
+            fetch_workers: options.fetch_workers,
+            stats,
+            events: Events {
+                hooks: options.events,
+                slow_op_threshold: options.slow_op_threshold,
+                #[cfg(feature = "metrics")]
+                metrics: options.metrics,
+                #[cfg(feature = "prometheus")]
+                prometheus: options.prometheus,
+            },
+            heatmap: options.heatmap.map(HeatmapCounters::new),
+            changes: None,
+            subscribers: Vec::new(),
+            file_path: options.file_path,
+            header,
         })
     }
 
     pub fn read(&mut self, address: u64, size: usize) -> std::io::Result<Vec<u8>> {
+        let result = self.read_range(address, size);
+        if let (Ok(_), Some(heatmap)) = (&result, &mut self.heatmap) {
+            heatmap.read(address, size);
+        }
+        self.observe(&result);
+        result
+    }
+
+    pub fn write(&mut self, address: u64, data: &[u8]) -> std::io::Result<()> {
+        let result = self.write_range(address, data);
+        if let (Ok(_), Some(heatmap)) = (&result, &mut self.heatmap) {
+            heatmap.write(address, data.len());
+        }
+        if result.is_ok() {
+            self.record_change(address, data.len() as u64);
+        }
+        self.observe(&result);
+        result
+    }
+
+    fn read_range(&mut self, address: u64, size: usize) -> std::io::Result<Vec<u8>> {
         let mut buffer = vec![0; size];
         let mut remaining_size = size;
         let mut current_address = address;
+        // Pages this read loaded ahead of itself still count as misses
+        let mut loaded = 0..0;
 
         while remaining_size > 0 {
             let page_id = current_address / self.page_size as u64;
             let offset = (current_address % self.page_size as u64) as usize;
             let read_size = std::cmp::min(remaining_size, self.page_size - offset);
 
-            let data = self.read_page(page_id)?;
+            self.readahead(page_id)?;
+            let random = !self
+                .last_read_page
+                .is_some_and(|last| page_id == last || page_id == last + 1);
+            self.last_read_page = Some(page_id);
+
             let buf_start = size - remaining_size;
-            buffer[buf_start..buf_start + read_size]
-                .copy_from_slice(&data[offset..offset + read_size]);
+            let blocks = if random && !self.cache.contains(page_id) {
+                self.read_blocks(page_id, offset, read_size)?
+            } else {
+                None
+            };
+            let mut copy = |data: &[u8]| {
+                buffer[buf_start..buf_start + read_size]
+                    .copy_from_slice(&data[offset..offset + read_size])
+            };
+            if let Some((start, mut blocks)) = blocks {
+                self.record_miss(page_id);
+                buffer[buf_start..buf_start + read_size]
+                    .copy_from_slice(&blocks[offset - start..offset - start + read_size]);
+                if self.secure {
+                    blocks.zeroize();
+                }
+            } else if let Some(slot) = self.cache.get(page_id) {
+                if loaded.contains(&page_id) {
+                    self.record_miss(page_id);
+                } else {
+                    self.record_hit(page_id);
+                }
+                copy(self.pool.frame(self.cache.frame(slot)));
+                self.cache.touch(slot);
+            } else {
+                self.record_miss(page_id);
+                if self.compressed.is_none() {
+                    let last_page = (address + size as u64 - 1) / self.page_size as u64;
+                    loaded = page_id..page_id + self.load_run(page_id, last_page)?;
+                }
+                self.with_page(page_id, copy)?;
+            }
 
             remaining_size -= read_size;
             current_address += read_size as u64;
@@ -97,24 +742,117 @@ impl WriteThroughCache {
         Ok(buffer)
     }
 
-    pub fn write(&mut self, address: u64, data: &[u8]) -> std::io::Result<()> {
+    fn write_range(&mut self, address: u64, data: &[u8]) -> std::io::Result<()> {
+        // The audit record is durable before the data, so a write can only
+        // reach the file with a record of it. A record may outlive a write
+        // that failed or was cut short by a crash.
+        if let Some(audit_log) = &mut self.audit_log {
+            audit_log.append(address, data)?;
+        }
+
+        // All pages touched by one write share its LSN, which is durable
+        // before any of them changes, so a crash part way through can only
+        // leave a page stamped newer than its data, never older
+        if !data.is_empty() {
+            self.stamp_pages(address, data.len() as u64)?;
+        }
+
+        // Once a sequential run outgrows the cache, keeping it resident would
+        // only evict everything else, so it is streamed straight to disk
+        if address == self.stream_end {
+            self.stream_len += data.len() as u64;
+        } else {
+            self.stream_len = data.len() as u64;
+        }
+        self.stream_end = address + data.len() as u64;
+
+        if self.compressed.is_none() && self.stream_len > self.capacity as u64 {
+            self.write_streaming(address, data)?;
+        } else {
+            self.write_cached(address, data)?;
+        }
+
+        Ok(())
+    }
+
+    // Report the outcome of a public read or write to the registered
+    // observers
+    fn observe<T>(&self, result: &std::io::Result<T>) {
+        if let (Err(e), Some(events)) = (result, &self.events.hooks) {
+            events.on_error(e);
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.events.metrics {
+            metrics.update(self);
+        }
+        #[cfg(feature = "prometheus")]
+        if let Some(metrics) = &self.events.prometheus {
+            metrics.update(self);
+        }
+    }
+
+    fn record_hit(&self, page_id: u64) {
+        self.stats.hits.incr();
+        if let Some(events) = &self.events.hooks {
+            events.on_hit(page_id);
+        }
+    }
+
+    fn record_miss(&self, page_id: u64) {
+        self.stats.misses.incr();
+        if let Some(events) = &self.events.hooks {
+            events.on_miss(page_id);
+        }
+    }
+
+    fn write_cached(&mut self, address: u64, data: &[u8]) -> std::io::Result<()> {
         let mut remaining_size = data.len();
         let mut current_address = address;
 
+        // Uncompressed pages are written a span at a time, with one syscall
+        // and one sync per span
+        let max_pages = if self.compressed.is_some() {
+            1
+        } else {
+            self.max_io_pages()
+        };
+
         while remaining_size > 0 {
             let page_id = current_address / self.page_size as u64;
             let offset = (current_address % self.page_size as u64) as usize;
-            let write_size = std::cmp::min(remaining_size, self.page_size - offset);
-
-            let mut page_data = match self.read_page(page_id) {
-                Ok(data) => data,
-                Err(_) => vec![0; self.page_size],
-            };
-            page_data[offset..offset + write_size].copy_from_slice(
-                &data[data.len() - remaining_size..data.len() - remaining_size + write_size],
+            let pages = std::cmp::min(
+                (offset + remaining_size).div_ceil(self.page_size),
+                max_pages,
             );
+            let write_size = std::cmp::min(remaining_size, pages * self.page_size - offset);
 
-            self.write_page(page_id, &page_data)?;
+            let data_start = data.len() - remaining_size;
+            let chunk = &data[data_start..data_start + write_size];
+            if pages == 1 {
+                self.write_within_page(page_id, offset, chunk)?;
+            } else {
+                // The span is assembled front to back so no byte is written
+                // twice: the head of the first page, the caller's data, then
+                // the tail of the last page. Only those two pages can be
+                // partially covered.
+                let mut span = Vec::with_capacity(pages * self.page_size);
+                let end = offset + write_size;
+                if offset > 0 {
+                    self.extend_from_page(page_id, 0..offset, &mut span);
+                }
+                span.extend_from_slice(chunk);
+                if !end.is_multiple_of(self.page_size) {
+                    let last_page = page_id + pages as u64 - 1;
+                    let tail = end % self.page_size..self.page_size;
+                    self.extend_from_page(last_page, tail, &mut span);
+                }
+
+                let result = self.write_pages(page_id, &span);
+                if self.secure {
+                    span.zeroize();
+                }
+                result?;
+            }
 
             remaining_size -= write_size;
             current_address += write_size as u64;
@@ -123,84 +861,821 @@ impl WriteThroughCache {
         Ok(())
     }
 
+    // Write the whole pages of `data` directly from the caller's buffer with
+    // one syscall and one sync, dropping any resident copies. Partial pages
+    // at either end go through the cache as usual.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", name = "disk_write", skip_all, fields(address, len = data.len()))
+    )]
+    fn write_streaming(&mut self, address: u64, data: &[u8]) -> std::io::Result<()> {
+        let page_size = self.page_size as u64;
+        let end = address + data.len() as u64;
+        let first_page = address.div_ceil(page_size);
+        let end_page = end / page_size;
+        if first_page >= end_page {
+            return self.write_cached(address, data);
+        }
+
+        let head = (first_page * page_size - address) as usize;
+        let body = ((end_page - first_page) * page_size) as usize;
+        if head > 0 {
+            self.write_cached(address, &data[..head])?;
+        }
+
+        for page_id in first_page..end_page {
+            self.discard_page(page_id);
+            if let Some(fetcher) = &mut self.fetcher {
+                fetcher.forget(page_id);
+            }
+            if let Some(tier) = &mut self.tier {
+                tier.remove(page_id);
+            }
+        }
+        let timer = Timer::start();
+        write_all_at(&self.file, &data[head..head + body], first_page * page_size)?;
+        let elapsed = self.stats.page_write_done(timer);
+        self.events.disk_op(DiskOp::Write, first_page, elapsed);
+        self.stats.disk_write(body);
+        self.sync(first_page)?;
+        self.file_size = std::cmp::max(self.file_size, end_page * page_size);
+
+        if head + body < data.len() {
+            self.write_cached(end_page * page_size, &data[head + body..])?;
+        }
+        Ok(())
+    }
+
+    /// Hash of the latest audit record, for anchoring the chain externally.
+    pub fn audit_head(&self) -> Option<[u8; 32]> {
+        self.audit_log.as_ref().map(|audit_log| audit_log.head())
+    }
+
+    /// Walk the audit log of `file_path` and return the number of records, or
+    /// an `InvalidData` error if the hash chain is broken.
+    pub fn verify_audit_log(file_path: &Path) -> std::io::Result<u64> {
+        AuditLog::verify(file_path)
+    }
+
+    /// Override the compression of pages starting inside `range`; later
+    /// overrides take precedence over earlier ones. The mapping is persisted
+    /// in the file header and applies to pages written from now on.
+    pub fn set_range_compression(
+        &mut self,
+        range: Range<u64>,
+        compression: Compression,
+    ) -> std::io::Result<()> {
+        let Some(store) = &mut self.compressed else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Range compression requires a compressed file",
+            ));
+        };
+
+        self.header.compression_ranges.push((range, compression));
+        self.header.store(&self.file_path)?;
+        store.set_ranges(self.header.compression_ranges.clone());
+        Ok(())
+    }
+
+    /// Logical size of the file in bytes, which for a compressed file is
+    /// larger than what it takes on disk.
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Pick up changes another process made to the file: re-read its size
+    /// and drop cached copies of the pages from `address` on, so the next
+    /// reads of them go to disk. Returns the new size. Only available for
+    /// uncompressed files.
+    pub fn reload(&mut self, address: u64) -> std::io::Result<u64> {
+        if self.compressed.is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Reload requires an uncompressed file",
+            ));
+        }
+        let page_size = self.page_size as u64;
+        for page_id in address / page_size..self.file_size.div_ceil(page_size) {
+            self.discard_page(page_id);
+            if let Some(fetcher) = &mut self.fetcher {
+                fetcher.forget(page_id);
+            }
+            if let Some(tier) = &mut self.tier {
+                tier.remove(page_id);
+            }
+        }
+        self.file_size = self.file.metadata()?.len();
+        Ok(self.file_size)
+    }
+
+    /// Drop every cached page, including those in the compressed tier, so
+    /// the next reads go to disk. Returns how many resident pages were
+    /// dropped; they count as evictions.
+    pub fn evict_all(&mut self) -> usize {
+        let mut evicted = 0;
+        while let Some((page_id, frame)) = self.cache.pop_lru() {
+            self.stats.evictions.incr();
+            if let Some(events) = &self.events.hooks {
+                events.on_evict(page_id);
+            }
+            self.pool.put(frame);
+            evicted += 1;
+        }
+        if let Some(tier) = &mut self.tier {
+            tier.clear();
+        }
+        evicted
+    }
+
+    /// Sync the data file. Every write is synced before it returns, so this
+    /// only matters for changes made to the file by other means.
+    pub fn flush(&self) -> std::io::Result<()> {
+        // Not tied to a write, so a slow sync is reported against page 0
+        self.sync(0)
+    }
+
+    /// Bytes of memory held for cached pages: every allocated page buffer,
+    /// including idle ones kept by the buffer pool, plus the bookkeeping for
+    /// resident pages. Pages in the compressed tier are not included.
+    pub fn memory_usage(&self) -> usize {
+        self.pool.allocated_bytes() + self.cache.heap_bytes()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats.snapshot()
+    }
+
+    /// `stats` stamped with the current time, to compute rates with
+    /// `StatsSnapshot::diff`.
+    pub fn stats_snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            taken_at: std::time::Instant::now(),
+            stats: self.stats(),
+        }
+    }
+
+    /// Zero the counters reported by `stats`.
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        self.pool.stats()
+    }
+
+    /// Space and CPU accounting for a compressed file; `None` otherwise.
+    pub fn compression_stats(&self) -> Option<CompressionStats> {
+        self.compressed
+            .as_ref()
+            .map(|store| store.stats(self.page_size))
+    }
+
+    pub fn range_compression(&self) -> &[(Range<u64>, Compression)] {
+        &self.header.compression_ranges
+    }
+
+    /// Train a zstd dictionary from the contents of `sample_pages`. Each page
+    /// is split into 4KiB samples, so a few dozen pages are usually enough.
+    #[cfg(feature = "zstd")]
+    pub fn train_zstd_dictionary(
+        &mut self,
+        sample_pages: &[u64],
+        max_size: usize,
+    ) -> std::io::Result<Vec<u8>> {
+        let mut samples = Vec::new();
+        for &page_id in sample_pages {
+            let page = self.read_page(page_id)?;
+            samples.extend(page.chunks(4096).map(|chunk| chunk.to_vec()));
+        }
+        zstd::dict::from_samples(&samples, max_size)
+    }
+
+    /// Persist `dictionary` alongside the file and use it for every page
+    /// compressed from now on. A file can only ever have one dictionary.
+    #[cfg(feature = "zstd")]
+    pub fn attach_zstd_dictionary(&mut self, dictionary: &[u8]) -> std::io::Result<()> {
+        match &mut self.compressed {
+            Some(store) => store.attach_dictionary(&self.file_path, dictionary),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Dictionaries require a compressed file",
+            )),
+        }
+    }
+
+    /// LSN of the last write to `page_id`, or 0 if it was never written.
+    pub fn page_version(&mut self, page_id: u64) -> std::io::Result<u64> {
+        match &mut self.versions {
+            Some(versions) => versions.get(page_id),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Page versions are not enabled for this file",
+            )),
+        }
+    }
+
+    /// LSN assigned to the most recent write.
+    pub fn last_lsn(&self) -> Option<u64> {
+        self.versions.as_ref().map(|versions| versions.last_lsn())
+    }
+
     fn read_page(&mut self, page_id: u64) -> std::io::Result<Vec<u8>> {
+        self.with_page(page_id, <[u8]>::to_vec)
+    }
+
+    // Run `f` on the cached copy of the page, loading it first on a miss, so
+    // callers can copy out just the bytes they need.
+    fn with_page<R>(&mut self, page_id: u64, f: impl FnOnce(&[u8]) -> R) -> std::io::Result<R> {
         // First check cache for the page
-        if let Some(node) = self.cache.get(&page_id) {
-            let data = node.borrow().data.clone();
-            self.promote(page_id);
-            return Ok(data);
-        }
+        let slot = match self.cache.get(page_id) {
+            Some(slot) => {
+                self.cache.touch(slot);
+                slot
+            }
+            None => self.load_page(page_id)?,
+        };
+        Ok(f(self.pool.frame(self.cache.frame(slot))))
+    }
 
-        if (page_id + 1) * self.page_size as u64 > self.file_size {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", name = "page_miss", skip_all, fields(page_id))
+    )]
+    fn load_page(&mut self, page_id: u64) -> std::io::Result<u32> {
+        // The last page may be partial, and is zero-filled past the end
+        if page_id * self.page_size as u64 >= self.file_size {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
                 "Page out of bounds",
             ));
         }
 
-        // Read the entire page from disk
-        self.file
-            .seek(SeekFrom::Start(page_id * self.page_size as u64))?;
+        // Frames are always initialized, so pages are read straight into
+        // them without zero-filling first
+        let frame = self.pool.take();
+        let page = self.pool.frame_mut(frame);
 
-        let file_size = self.file_size;
-        let read_size = if (page_id + 1) * self.page_size as u64 > file_size {
-            file_size - page_id * self.page_size as u64
+        let from_tier = match &mut self.tier {
+            Some(tier) => tier.take(page_id, page),
+            None => false,
+        };
+        let timer = Timer::start();
+        let result = if from_tier {
+            Ok(())
+        } else if let Some(store) = &mut self.compressed {
+            self.stats.disk_read(self.page_size);
+            let result = store.read_page(&self.file, page_id, page);
+            let elapsed = self.stats.page_read_done(timer);
+            self.events.disk_op(DiskOp::Read, page_id, elapsed);
+            result
         } else {
-            self.page_size as u64
-        } as usize;
+            // Read the entire page from disk
+            let file_size = self.file_size;
+            let read_size = if (page_id + 1) * self.page_size as u64 > file_size {
+                file_size - page_id * self.page_size as u64
+            } else {
+                self.page_size as u64
+            } as usize;
+
+            page[read_size..].fill(0);
+            self.stats.disk_read(read_size);
+            let result = read_exact_at(
+                &self.file,
+                &mut page[..read_size],
+                page_id * self.page_size as u64,
+            );
+            let elapsed = self.stats.page_read_done(timer);
+            self.events.disk_op(DiskOp::Read, page_id, elapsed);
+            result
+        };
+        if let Err(e) = result {
+            #[cfg(feature = "log")]
+            log::debug!("Reading page {} failed: {}", page_id, e);
+            self.pool.put(frame);
+            return Err(e);
+        }
 
-        let mut buffer = vec![0; self.page_size];
-        self.file.read_exact(&mut buffer[..read_size])?;
+        Ok(self.add_to_cache(page_id, frame))
+    }
 
-        self.add_to_cache(page_id, buffer.clone());
+    // Install pages the fetch workers have finished reading and start the
+    // next readahead if `page_id` continues a sequential run
+    fn readahead(&mut self, page_id: u64) -> std::io::Result<()> {
+        while self.install_fetched(false, |_| {}) {}
 
-        Ok(buffer)
+        let file_pages = self.file_size / self.page_size as u64;
+        let runs = match &mut self.readahead {
+            Some(readahead) => readahead.access(page_id, file_pages),
+            None => Vec::new(),
+        };
+        for (first_page, pages) in runs {
+            for (first_page, pages) in self.uncached_runs(first_page, first_page + pages, pages) {
+                self.fetcher()?.request(first_page, pages);
+                self.stats.disk_read(pages as usize * self.page_size);
+            }
+        }
+        Ok(())
+    }
+
+    // Runs of at most `max_pages` pages in first_page..end that aren't
+    // resident
+    fn uncached_runs(&self, first_page: u64, end: u64, max_pages: u64) -> Vec<(u64, u64)> {
+        let mut runs = Vec::new();
+        let mut page_id = first_page;
+        while page_id < end {
+            if self.cache.contains(page_id) {
+                page_id += 1;
+                continue;
+            }
+            let mut pages = 1;
+            while pages < max_pages
+                && page_id + pages < end
+                && !self.cache.contains(page_id + pages)
+            {
+                pages += 1;
+            }
+            runs.push((page_id, pages));
+            page_id += pages;
+        }
+        runs
     }
 
-    fn write_page(&mut self, page_id: u64, data: &[u8]) -> std::io::Result<()> {
-        if data.len() != self.page_size {
+    /// Read the pages covering `address..address + len` into the cache,
+    /// spreading the reads over the fetch workers so several are in flight
+    /// at once. Stops at the end of the file or once the cache is full.
+    pub fn prefetch(&mut self, address: u64, len: usize) -> std::io::Result<()> {
+        if self.compressed.is_some() {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
-                "Data size must match page size",
+                "Prefetch requires an uncompressed file",
             ));
         }
+        if len == 0 {
+            return Ok(());
+        }
+
+        let page_size = self.page_size as u64;
+        let first_page = address / page_size;
+        let cache_pages = self.cache_pages() as u64;
+        let end = (address + len as u64)
+            .div_ceil(page_size)
+            .min(self.file_size / page_size)
+            .min(first_page + cache_pages);
+        if first_page >= end {
+            return Ok(());
+        }
+
+        // Split the span into one chunk per worker, skipping cached pages
+        let chunk = (end - first_page)
+            .div_ceil(self.fetch_workers.max(1) as u64)
+            .min(self.max_io_pages() as u64);
+        let mut seqs = Vec::new();
+        for (page_id, pages) in self.uncached_runs(first_page, end, chunk) {
+            seqs.push(self.fetcher()?.request(page_id, pages));
+            self.stats.disk_read(pages as usize * self.page_size);
+        }
+
+        let mut result = Ok(());
+        while !seqs.is_empty() {
+            let done = self.install_fetched(true, |completed| {
+                seqs.retain(|&seq| seq != completed.seq);
+                if let Err(e) = &completed.pages {
+                    if result.is_ok() {
+                        result = Err(std::io::Error::new(e.kind(), e.to_string()));
+                    }
+                }
+            });
+            if !done {
+                break;
+            }
+        }
+        result
+    }
+
+    // Install the next finished fetch into the cache, calling `on_completed`
+    // for it. Returns false if there was none.
+    fn install_fetched(
+        &mut self,
+        wait: bool,
+        on_completed: impl FnOnce(&readahead::Completed),
+    ) -> bool {
+        let Some(fetcher) = &mut self.fetcher else {
+            return false;
+        };
+        let Some(completed) = fetcher.completed(wait) else {
+            return false;
+        };
+        on_completed(&completed);
+
+        match completed.pages {
+            Ok(pages) => {
+                for (page_id, mut data) in pages {
+                    if !self.cache.contains(page_id) {
+                        if let Some(tier) = &mut self.tier {
+                            tier.remove(page_id);
+                        }
+                        let frame = self.pool.take();
+                        self.pool.frame_mut(frame).copy_from_slice(&data);
+                        self.add_to_cache(page_id, frame);
+                    }
+                    if self.secure {
+                        data.zeroize();
+                    }
+                }
+            }
+            // The pages are simply read on demand instead
+            Err(_e) => {
+                #[cfg(feature = "log")]
+                log::debug!("Fetch {} failed: {}", completed.seq, _e);
+            }
+        }
+
+        true
+    }
+
+    fn fetcher(&mut self) -> std::io::Result<&mut Fetcher> {
+        if self.fetcher.is_none() {
+            self.fetcher = Some(Fetcher::new(
+                self.file.try_clone()?,
+                self.page_size,
+                self.fetch_workers,
+                self.secure,
+            )?);
+        }
+        Ok(self.fetcher.as_mut().unwrap())
+    }
+
+    // Append `range` of the current contents of `page_id` to `out`, or zeros
+    // if the page is past the end of the file
+    fn extend_from_page(&mut self, page_id: u64, range: Range<usize>, out: &mut Vec<u8>) {
+        let len = range.len();
+        if self
+            .with_page(page_id, |data| out.extend_from_slice(&data[range]))
+            .is_err()
+        {
+            out.resize(out.len() + len, 0);
+        }
+    }
+
+    // Read the run of uncached pages starting at `first_page` with a single
+    // syscall and split it into the cache. The run stops at `last_page`, at
+    // the first page already cached or in the tier, or once it would no
+    // longer fit in the cache. Returns the number of pages loaded.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", name = "disk_read", skip_all, fields(first_page))
+    )]
+    fn load_run(&mut self, first_page: u64, last_page: u64) -> std::io::Result<u64> {
+        let max_pages = self.max_io_pages() as u64;
+        let mut pages = 0;
+        while pages < max_pages && first_page + pages <= last_page {
+            let page_id = first_page + pages;
+            if (page_id + 1) * self.page_size as u64 > self.file_size
+                || self.cache.contains(page_id)
+                || self
+                    .tier
+                    .as_ref()
+                    .is_some_and(|tier| tier.contains(page_id))
+            {
+                break;
+            }
+            pages += 1;
+        }
+        if pages < 2 {
+            return Ok(0);
+        }
+
+        let mut span = Vec::new();
+        let timer = Timer::start();
+        read_append_at(
+            &self.file,
+            &mut span,
+            pages as usize * self.page_size,
+            first_page * self.page_size as u64,
+        )?;
+        let elapsed = self.stats.page_read_done(timer);
+        self.events.disk_op(DiskOp::Read, first_page, elapsed);
+        self.stats.disk_read(span.len());
+        for (page_id, page) in (first_page..).zip(span.chunks_exact(self.page_size)) {
+            let frame = self.pool.take();
+            self.pool.frame_mut(frame).copy_from_slice(page);
+            self.add_to_cache(page_id, frame);
+        }
+        if self.secure {
+            span.zeroize();
+        }
+
+        Ok(pages)
+    }
+
+    // Read just the blocks of `page_id` covering `offset..offset + len`, when
+    // sub-page fetches are enabled and that is less than the whole page.
+    // Returns the page offset the blocks start at along with their contents.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            name = "disk_read",
+            skip_all,
+            fields(page_id, offset, len)
+        )
+    )]
+    fn read_blocks(
+        &mut self,
+        page_id: u64,
+        offset: usize,
+        len: usize,
+    ) -> std::io::Result<Option<(usize, Vec<u8>)>> {
+        let start = offset / self.fetch_granularity * self.fetch_granularity;
+        let end = (offset + len).next_multiple_of(self.fetch_granularity);
+        if end - start >= self.page_size
+            || (page_id + 1) * self.page_size as u64 > self.file_size
+            || self
+                .tier
+                .as_ref()
+                .is_some_and(|tier| tier.contains(page_id))
+        {
+            return Ok(None);
+        }
+
+        let mut blocks = vec![0; end - start];
+        self.stats.disk_read(blocks.len());
+        let timer = Timer::start();
+        read_exact_at(
+            &self.file,
+            &mut blocks,
+            page_id * self.page_size as u64 + start as u64,
+        )?;
+        let elapsed = self.stats.page_read_done(timer);
+        self.events.disk_op(DiskOp::Read, page_id, elapsed);
+        Ok(Some((start, blocks)))
+    }
+
+    // Largest span moved in one syscall: bounded so it never evicts its own
+    // pages from the cache, which happens once it outgrows the pages outside
+    // the hot segment
+    fn max_io_pages(&self) -> usize {
+        let cache_pages = self.cache_pages();
+        std::cmp::min(
+            MAX_IO_SIZE / self.page_size,
+            cache_pages - cache_pages / HOT_SHARE,
+        )
+        .max(1)
+    }
+
+    // Pages the cache holds at most once its bookkeeping is paid for
+    fn cache_pages(&self) -> usize {
+        let budget = self.capacity.saturating_sub(self.cache.heap_bytes());
+        std::cmp::max(budget / (self.page_size + ENTRY_BYTES), 1)
+    }
+
+    // Write `chunk` at `offset` within one page. The caller's bytes are copied
+    // once, into the resident page, and that same frame goes to disk.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", name = "disk_write", skip_all, fields(page_id, len = chunk.len()))
+    )]
+    fn write_within_page(
+        &mut self,
+        page_id: u64,
+        offset: usize,
+        chunk: &[u8],
+    ) -> std::io::Result<()> {
+        let range = offset..offset + chunk.len();
+        let partial = chunk.len() < self.page_size;
+        let in_bounds = page_id * (self.page_size as u64) < self.file_size;
+        let resident = match self.cache.get(page_id) {
+            Some(slot) => {
+                self.record_hit(page_id);
+                Some(slot)
+            }
+            // A page that cannot be read back is rewritten from zeros
+            None if partial && in_bounds => {
+                self.record_miss(page_id);
+                self.load_page(page_id)
+                    .inspect_err(|_e| {
+                        #[cfg(feature = "log")]
+                        log::debug!("Rewriting page {} from zeros: {}", page_id, _e);
+                    })
+                    .ok()
+            }
+            None => None,
+        };
+
+        let slot = match resident {
+            Some(slot) => slot,
+            None => {
+                let frame = self.pool.take();
+                let page = self.pool.frame_mut(frame);
+                page[..range.start].fill(0);
+                page[range.end..].fill(0);
+                self.add_to_cache(page_id, frame)
+            }
+        };
+
+        let page = self.pool.frame_mut(self.cache.frame(slot));
+        page[range.clone()].copy_from_slice(chunk);
+        let (result, written) = match &mut self.compressed {
+            Some(store) => {
+                let timer = Timer::start();
+                let result = store.write_page(&self.file, page_id, page, &self.stats, &self.events);
+                let elapsed = self.stats.page_write_done(timer);
+                self.events.disk_op(DiskOp::Write, page_id, elapsed);
+                (result, self.page_size)
+            }
+            // Only the touched sectors differ from what is already on disk.
+            // A partial last page is written through to its end, so the file
+            // on disk grows to the size recorded by `page_written`.
+            None if resident.is_some() => {
+                let start = range.start / self.sector_size * self.sector_size;
+                let end = if (page_id + 1) * self.page_size as u64 > self.file_size {
+                    self.page_size
+                } else {
+                    std::cmp::min(
+                        range.end.div_ceil(self.sector_size) * self.sector_size,
+                        self.page_size,
+                    )
+                };
+                let timer = Timer::start();
+                let result = write_all_at(
+                    &self.file,
+                    &page[start..end],
+                    page_id * self.page_size as u64 + start as u64,
+                );
+                let elapsed = self.stats.page_write_done(timer);
+                self.events.disk_op(DiskOp::Write, page_id, elapsed);
+                (result.and_then(|()| self.sync(page_id)), end - start)
+            }
+            None => {
+                let timer = Timer::start();
+                let result = write_all_at(&self.file, page, page_id * self.page_size as u64);
+                let elapsed = self.stats.page_write_done(timer);
+                self.events.disk_op(DiskOp::Write, page_id, elapsed);
+                (result.and_then(|()| self.sync(page_id)), self.page_size)
+            }
+        };
+        if result.is_ok() {
+            self.stats.disk_write(written);
+        }
+        if let Err(_e) = &result {
+            #[cfg(feature = "log")]
+            log::debug!(
+                "Writing page {} at offset {} failed: {}",
+                page_id,
+                offset,
+                _e
+            );
+            // The resident copy no longer matches the disk
+            self.discard_page(page_id);
+        }
+        result?;
 
-        self.file
-            .seek(SeekFrom::Start(page_id * self.page_size as u64))?;
-        self.file.write_all(data)?;
-        self.file.sync_all()?;
+        self.page_written(page_id, slot)
+    }
 
-        if let Some(node) = self.cache.get_mut(&page_id) {
-            let mut node_data = node.borrow_mut();
-            node_data.data.copy_from_slice(data);
+    // Write consecutive whole pages starting at `first_page`
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", name = "disk_write", skip_all, fields(first_page, len = data.len()))
+    )]
+    fn write_pages(&mut self, first_page: u64, data: &[u8]) -> std::io::Result<()> {
+        if data.is_empty() || !data.len().is_multiple_of(self.page_size) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Data size must be a multiple of the page size",
+            ));
+        }
+
+        if let Some(store) = &mut self.compressed {
+            for (page_id, page) in (first_page..).zip(data.chunks_exact(self.page_size)) {
+                let timer = Timer::start();
+                store.write_page(&self.file, page_id, page, &self.stats, &self.events)?;
+                let elapsed = self.stats.page_write_done(timer);
+                self.events.disk_op(DiskOp::Write, page_id, elapsed);
+                self.stats.disk_write(page.len());
+            }
         } else {
-            self.add_to_cache(page_id, data.to_vec());
+            let timer = Timer::start();
+            write_all_at(&self.file, data, first_page * self.page_size as u64)?;
+            let elapsed = self.stats.page_write_done(timer);
+            self.events.disk_op(DiskOp::Write, first_page, elapsed);
+            self.stats.disk_write(data.len());
+            self.sync(first_page)?;
         }
 
-        self.file_size = std::cmp::max(
-            self.file_size,
-            page_id * self.page_size as u64 + data.len() as u64,
-        );
+        // The span is copied into the resident pages, the price of moving it
+        // to disk in one syscall
+        for (page_id, page) in (first_page..).zip(data.chunks_exact(self.page_size)) {
+            let slot = match self.cache.get(page_id) {
+                Some(slot) => slot,
+                None => {
+                    let frame = self.pool.take();
+                    self.add_to_cache(page_id, frame)
+                }
+            };
+            self.pool
+                .frame_mut(self.cache.frame(slot))
+                .copy_from_slice(page);
+            self.page_written(page_id, slot)?;
+        }
 
-        self.promote(page_id);
+        Ok(())
+    }
 
+    // Bookkeeping once `page_id` is on disk and its new contents are resident
+    fn page_written(&mut self, page_id: u64, slot: u32) -> std::io::Result<()> {
+        if let Some(fetcher) = &mut self.fetcher {
+            fetcher.forget(page_id);
+        }
+
+        if let Some(tier) = &mut self.tier {
+            tier.remove(page_id);
+        }
+
+        self.file_size = std::cmp::max(self.file_size, (page_id + 1) * self.page_size as u64);
+
+        self.cache.touch(slot);
+
+        Ok(())
+    }
+
+    // Assign the next LSN to the pages covering `len` bytes at `address`,
+    // with a single write and sync of the sidecar
+    fn stamp_pages(&mut self, address: u64, len: u64) -> std::io::Result<()> {
+        if let Some(versions) = &mut self.versions {
+            let page_size = self.page_size as u64;
+            let first_page = address / page_size;
+            let end_page = (address + len).div_ceil(page_size);
+            let lsn = versions.next_lsn();
+            versions.set_range(first_page, end_page - first_page, lsn)?;
+        }
         Ok(())
     }
 
-    fn add_to_cache(&mut self, page_id: u64, data: Vec<u8>) {
-        if self.cache.len() * self.page_size >= self.capacity {
-            if let Some(oldest_page) = self.usage_order.pop_front() {
-                self.cache.remove(&oldest_page);
+    fn discard_page(&mut self, page_id: u64) {
+        if let Some(frame) = self.cache.remove(page_id) {
+            self.pool.put(frame);
+        }
+    }
+
+    // Sync the data file after a write starting at `page_id`
+    fn sync(&self, page_id: u64) -> std::io::Result<()> {
+        self.events.sync(&self.file, page_id, &self.stats)
+    }
+
+    // Bytes charged against `capacity`: resident page frames plus the slab and
+    // index that track them
+    fn resident_bytes(&self) -> usize {
+        self.cache.len() * self.page_size + self.cache.heap_bytes()
+    }
+
+    // Insert a page that is not resident yet, evicting the oldest page if the
+    // cache is full
+    fn add_to_cache(&mut self, page_id: u64, frame: Frame) -> u32 {
+        // Evict until the new page and its bookkeeping fit, always keeping
+        // room for at least the one page being inserted
+        while self.resident_bytes() + self.page_size + ENTRY_BYTES > self.capacity {
+            if !self.evict_lru() {
+                break;
             }
         }
 
-        let node = Rc::new(RefCell::new(LinkedListNodeInner { data }));
-        self.cache.insert(page_id, node);
-        self.usage_order.push_back(page_id);
+        self.cache.insert(page_id, frame)
+    }
+
+    // Bytes of page data resident in memory, without bookkeeping
+    pub(crate) fn resident_page_bytes(&self) -> usize {
+        self.cache.len() * self.page_size
+    }
+
+    // Recency stamp of the page `evict_lru` would evict next, if any
+    pub(crate) fn lru_last_used(&self) -> Option<u64> {
+        self.cache.lru_last_used()
     }
 
-    fn promote(&mut self, page_id: u64) {
-        self.usage_order.retain(|&x| x != page_id);
-        self.usage_order.push_back(page_id);
+    // Evict the least recently used resident page, returning false if there
+    // is none
+    pub(crate) fn evict_lru(&mut self) -> bool {
+        let Some((oldest_page, oldest)) = self.cache.pop_lru() else {
+            return false;
+        };
+        self.stats.evictions.incr();
+        if let Some(events) = &self.events.hooks {
+            events.on_evict(oldest_page);
+        }
+        #[cfg(feature = "log")]
+        log::trace!("Evicted page {}", oldest_page);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(page_id = oldest_page, "evict");
+        if let Some(tier) = &mut self.tier {
+            tier.insert(oldest_page, self.pool.frame(oldest));
+        }
+        self.pool.put(oldest);
+        true
     }
 }
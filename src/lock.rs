@@ -0,0 +1,37 @@
+use std::fs::{File, TryLockError};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// What to do on open when another process holds the file's lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileLock {
+    /// Fail with `ErrorKind::WouldBlock`.
+    Fail,
+    /// Block until the other process releases it.
+    Wait,
+}
+
+// Take an exclusive advisory lock on the data file, held until the returned
+// handle is dropped. Only other opens asking for the lock are kept out.
+pub(crate) fn acquire(file_path: &Path, mode: FileLock) -> std::io::Result<File> {
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(file_path)?;
+    match mode {
+        FileLock::Wait => file.lock()?,
+        FileLock::Fail => match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(Error::new(
+                    ErrorKind::WouldBlock,
+                    format!("{} is locked by another process", file_path.display()),
+                ))
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        },
+    }
+    Ok(file)
+}
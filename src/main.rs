@@ -1,17 +1,1435 @@
-use std::path::PathBuf;
+use std::io::{BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-use wt_cache::WriteThroughCache;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use wt_cache::workload::Workload;
+use wt_cache::{
+    CacheReader, CacheStats, CompactOptions, CompactReport, Compression, FileInfo, VerifyReport,
+    WriteThroughCache,
+};
 
-fn main() -> std::io::Result<()> {
-    let mut cache = WriteThroughCache::new(&PathBuf::from("cache.dat"), None, None)?;
+// Bytes shown per line of a dump
+const DUMP_WIDTH: usize = 16;
+// Bytes moved through the cache per call when streaming a range, a whole
+// number of dump lines
+const CHUNK_SIZE: usize = 1024 * 1024;
+// zstd's own default
+const ZSTD_DEFAULT_LEVEL: i32 = 3;
 
-    let address = 0;
-    let data = vec![1; 1024]; // Write 1024 bytes
+/// Read and write files through the write-through page cache.
+#[derive(Parser)]
+#[command(name = "wt_cache", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Print results of info, verify, bench, stress and the shell's stats as JSON
+    #[arg(long, global = true)]
+    json: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Read a range of the file, printed as hex unless written to a file
+    Read {
+        #[command(flatten)]
+        cache: CacheArgs,
+        #[arg(long, value_parser = parse_number)]
+        offset: u64,
+        #[arg(long, value_parser = parse_number)]
+        len: u64,
+        /// Write the raw bytes to this file instead
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Write bytes given as hex or read from a file
+    Write {
+        #[command(flatten)]
+        cache: CacheArgs,
+        #[arg(long, value_parser = parse_number)]
+        offset: u64,
+        /// Bytes to write, as hex
+        #[arg(
+            long,
+            value_parser = parse_hex,
+            conflicts_with = "input",
+            required_unless_present = "input"
+        )]
+        data: Option<HexData>,
+        /// File whose contents to write
+        #[arg(long)]
+        input: Option<PathBuf>,
+    },
+    /// Print a range of the file in xxd style: offsets, hex and ASCII
+    Dump {
+        #[command(flatten)]
+        cache: CacheArgs,
+        #[arg(long, value_parser = parse_number, default_value = "0")]
+        offset: u64,
+        /// Bytes to print; defaults to the rest of the file
+        #[arg(long, value_parser = parse_number)]
+        len: Option<u64>,
+    },
+    /// Stream a range of the file to stdout
+    Export {
+        #[command(flatten)]
+        cache: CacheArgs,
+        #[arg(long, value_parser = parse_number, default_value = "0")]
+        offset: u64,
+        /// Bytes to export; defaults to the rest of the file
+        #[arg(long, value_parser = parse_number)]
+        len: Option<u64>,
+        /// Block size for --skip and --count
+        #[arg(long, value_parser = parse_number, default_value = "512")]
+        bs: u64,
+        /// Blocks of the file to skip, as with dd
+        #[arg(long, value_parser = parse_number, conflicts_with = "offset")]
+        skip: Option<u64>,
+        /// Blocks to export, as with dd
+        #[arg(long, value_parser = parse_number, conflicts_with = "len")]
+        count: Option<u64>,
+    },
+    /// Write everything read from stdin into the file at an offset
+    Import {
+        #[command(flatten)]
+        cache: CacheArgs,
+        #[arg(long, value_parser = parse_number, default_value = "0")]
+        offset: u64,
+        /// Block size for --seek, --skip and --count
+        #[arg(long, value_parser = parse_number, default_value = "512")]
+        bs: u64,
+        /// Blocks of the file to skip before writing, as with dd
+        #[arg(long, value_parser = parse_number, conflicts_with = "offset")]
+        seek: Option<u64>,
+        /// Blocks of stdin to discard first, as with dd
+        #[arg(long, value_parser = parse_number)]
+        skip: Option<u64>,
+        /// Blocks to import; defaults to everything on stdin
+        #[arg(long, value_parser = parse_number)]
+        count: Option<u64>,
+    },
+    /// Time a workload against the file and report throughput and latency
+    Bench {
+        #[command(flatten)]
+        cache: CacheArgs,
+        #[arg(long, value_enum, default_value_t = BenchWorkload::RandRead)]
+        workload: BenchWorkload,
+        /// Bytes of the file to work over; it is filled first if shorter
+        #[arg(long, value_parser = parse_number, default_value = "64m")]
+        size: u64,
+        /// Bytes per operation
+        #[arg(long, value_parser = parse_number, default_value = "4k")]
+        io_size: u64,
+        /// Seconds to run for
+        #[arg(long, default_value_t = 10.0)]
+        duration: f64,
+        /// Share of reads in the mixed workload
+        #[arg(long, default_value_t = 0.7)]
+        read_ratio: f64,
+        #[arg(long, default_value_t = 1)]
+        seed: u64,
+    },
+    /// Run commands against one open cache, read from stdin a line at a time;
+    /// `help` lists them
+    Shell {
+        #[command(flatten)]
+        cache: CacheArgs,
+    },
+    /// Follow the end of the file, streaming bytes to stdout as they are
+    /// written. Zero bytes at the end of the last page count as not written
+    /// yet, since the file grows a zero-filled page at a time.
+    Tail {
+        #[command(flatten)]
+        cache: CacheArgs,
+        /// Where to start; defaults to the end of the data already written
+        #[arg(long, value_parser = parse_number)]
+        offset: Option<u64>,
+        /// Bytes to follow before exiting; by default runs until interrupted
+        #[arg(long, value_parser = parse_number)]
+        len: Option<u64>,
+        /// Milliseconds between checks for new data
+        #[arg(long, default_value_t = 250)]
+        interval: u64,
+    },
+    /// Rewrite the file into a new one, leaving all-zero pages as holes and
+    /// optionally changing its compression, and report the space saved
+    Compact {
+        #[command(flatten)]
+        cache: CacheArgs,
+        /// File to create
+        dest: PathBuf,
+        /// none, lz4, snappy, zstd or zstd=LEVEL; a compressed file is
+        /// decompressed by default
+        #[arg(long, value_parser = parse_compression, default_value = "none")]
+        compression: Compression,
+        /// Write all-zero pages out instead of leaving holes
+        #[arg(long)]
+        keep_zero_pages: bool,
+    },
+    /// Rewrite the file into a new one with a different page size
+    Convert {
+        /// File to convert
+        file: PathBuf,
+        /// File to create
+        dest: PathBuf,
+        /// Page size of the new file
+        #[arg(long, value_parser = parse_number)]
+        page_size: u64,
+        /// none, lz4, snappy, zstd or zstd=LEVEL; a compressed file is
+        /// decompressed by default
+        #[arg(long, value_parser = parse_compression, default_value = "none")]
+        compression: Compression,
+    },
+    /// Run random reads, writes and evictions from several threads, checking
+    /// every read against an in-memory copy of the data; exits with status 1
+    /// at the first difference
+    Stress {
+        #[command(flatten)]
+        cache: CacheArgs,
+        /// Bytes of the file to work over; they are overwritten first
+        #[arg(long, value_parser = parse_number, default_value = "16m")]
+        size: u64,
+        #[arg(long, default_value_t = 4)]
+        threads: usize,
+        /// Seconds to run for
+        #[arg(long, default_value_t = 10.0)]
+        duration: f64,
+        /// Relative weights of reads, writes and evictions, as R:W:E
+        #[arg(long, value_parser = parse_mix, default_value = "70:28:2")]
+        mix: Mix,
+        #[arg(long, default_value_t = 1)]
+        seed: u64,
+    },
+    /// Write a reproducible pattern over the start of the file
+    Fill {
+        #[command(flatten)]
+        cache: CacheArgs,
+        /// Bytes to write from offset 0
+        #[arg(long, value_parser = parse_number)]
+        size: u64,
+        /// zero, random, seq (each byte is its offset mod 256) or byte=N
+        #[arg(long, value_parser = parse_pattern, default_value = "zero")]
+        pattern: Pattern,
+        /// Seed for the random pattern
+        #[arg(long, default_value_t = 1)]
+        seed: u64,
+    },
+    /// Describe the file: sizes, header, holes, audit log and page versions
+    Info {
+        #[command(flatten)]
+        cache: CacheArgs,
+    },
+    /// Check the file's integrity; exits with status 1 if anything is wrong
+    Verify {
+        #[command(flatten)]
+        cache: CacheArgs,
+        /// List every page, not just the bad ones
+        #[arg(long)]
+        verbose: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BenchWorkload {
+    SeqRead,
+    SeqWrite,
+    RandRead,
+    RandWrite,
+    Mixed,
+}
+
+#[derive(Args)]
+struct CacheArgs {
+    /// File managed by the cache
+    file: PathBuf,
+    /// Page size for a new file; existing files keep their own
+    #[arg(long, value_parser = parse_number)]
+    page_size: Option<u64>,
+    /// Memory for cached pages, in bytes
+    #[arg(long, value_parser = parse_number)]
+    capacity: Option<u64>,
+}
+
+impl CacheArgs {
+    fn open(&self) -> std::io::Result<WriteThroughCache> {
+        let mut builder = WriteThroughCache::builder(&self.file);
+        if let Some(page_size) = self.page_size {
+            builder = builder.page_size(page_size as usize);
+        }
+        if let Some(capacity) = self.capacity {
+            builder = builder.capacity(capacity as usize);
+        }
+        builder.open()
+    }
+
+    // Open the file only if it exists, for commands that inspect it
+    fn open_existing(&self) -> std::io::Result<WriteThroughCache> {
+        require_file(&self.file)?;
+        self.open()
+    }
+}
+
+// Fail without creating `path`, or its sidecars, if it doesn't exist
+fn require_file(path: &Path) -> std::io::Result<()> {
+    if path.try_exists()? {
+        return Ok(());
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("{} does not exist", path.display()),
+    ))
+}
+
+// Decimal or 0x-prefixed hex, optionally followed by a binary k, m or g
+// suffix
+fn parse_number(s: &str) -> Result<u64, String> {
+    let lower = s.to_ascii_lowercase();
+    let (digits, shift) = match lower.as_bytes().last() {
+        Some(b'k') => (&lower[..lower.len() - 1], 10),
+        Some(b'm') => (&lower[..lower.len() - 1], 20),
+        Some(b'g') => (&lower[..lower.len() - 1], 30),
+        _ => (lower.as_str(), 0),
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    }
+    .map_err(|e| format!("invalid number {:?}: {}", s, e))?;
+    value
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("{:?} is too large", s))
+}
 
-    cache.write(address, &data)?;
-    let read_data = cache.read(address, 1024)?;
+#[derive(Clone)]
+struct HexData(Vec<u8>);
 
-    assert_eq!(data, read_data);
+fn parse_hex(s: &str) -> Result<HexData, String> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    if !digits.len().is_multiple_of(2) {
+        return Err("hex data must have an even number of digits".to_string());
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("invalid hex data {:?}", s))
+        })
+        .collect::<Result<_, _>>()
+        .map(HexData)
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
 
+// Read `len` bytes from `offset`, or up to the end of the file, a chunk at a
+// time, handing each chunk and its address to `sink`
+fn stream_range(
+    cache: &mut WriteThroughCache,
+    offset: u64,
+    len: Option<u64>,
+    mut sink: impl FnMut(u64, &[u8]) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let end = match len {
+        Some(len) => offset + len,
+        None => cache.file_size(),
+    };
+    let mut address = offset;
+    while address < end {
+        let size = std::cmp::min(CHUNK_SIZE as u64, end - address);
+        sink(address, &cache.read(address, size as usize)?)?;
+        address += size;
+    }
     Ok(())
 }
+
+// `count` blocks of `bs` bytes, in bytes
+fn blocks(count: Option<u64>, bs: u64) -> std::io::Result<Option<u64>> {
+    count
+        .map(|count| {
+            count.checked_mul(bs).ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "Block range is too large")
+            })
+        })
+        .transpose()
+}
+
+// Write everything `input` yields to the file from `offset`, in whole chunks
+// so a slow pipe doesn't turn into many small synced writes
+fn import(
+    cache: &mut WriteThroughCache,
+    offset: u64,
+    input: &mut impl Read,
+) -> std::io::Result<()> {
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut address = offset;
+    loop {
+        let mut len = 0;
+        while len < buffer.len() {
+            match input.read(&mut buffer[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        cache.write(address, &buffer[..len])?;
+        address += len as u64;
+    }
+}
+
+const SHELL_HELP: &str = "\
+r <offset> <len>  print a range of the file
+w <offset> <hex>  write bytes given as hex
+stats             print the cache counters
+evict             drop every cached page
+flush             sync the data file
+quit              exit";
+
+// Read commands from `input` until it ends or says quit, printing results to
+// `out` and errors to stderr. `prompt` is shown before each command.
+fn shell(
+    cache: &mut WriteThroughCache,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    prompt: bool,
+    json: bool,
+) -> std::io::Result<()> {
+    let mut line = String::new();
+    loop {
+        if prompt {
+            write!(out, "> ")?;
+            out.flush()?;
+        }
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match shell_command(cache, &words, out, json) {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(e) => eprintln!("error: {}", e),
+        }
+    }
+}
+
+// Run one shell command, returning whether to carry on
+fn shell_command(
+    cache: &mut WriteThroughCache,
+    words: &[&str],
+    out: &mut impl Write,
+    json: bool,
+) -> std::io::Result<bool> {
+    let invalid = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, e);
+    match words {
+        [] => {}
+        ["r", offset, len] => {
+            let offset = parse_number(offset).map_err(invalid)?;
+            let len = parse_number(len).map_err(invalid)?;
+            hexdump(out, offset, &cache.read(offset, len as usize)?)?;
+        }
+        ["w", offset, data] => {
+            let offset = parse_number(offset).map_err(invalid)?;
+            let HexData(data) = parse_hex(data).map_err(invalid)?;
+            cache.write(offset, &data)?;
+        }
+        ["stats"] if json => writeln!(out, "{}", stats_json(&cache.stats()))?,
+        ["stats"] => {
+            let stats = cache.stats();
+            writeln!(
+                out,
+                "{} hits, {} misses, {} evictions, {} disk reads, {} disk writes, {} fsyncs",
+                stats.hits,
+                stats.misses,
+                stats.evictions,
+                stats.disk_reads,
+                stats.disk_writes,
+                stats.fsyncs
+            )?;
+        }
+        ["evict"] => writeln!(out, "evicted {} pages", cache.evict_all())?,
+        ["flush"] => cache.flush()?,
+        ["help"] => writeln!(out, "{}", SHELL_HELP)?,
+        ["quit"] | ["exit"] => return Ok(false),
+        _ => {
+            return Err(invalid(format!(
+                "unknown command {:?}; try help",
+                words.join(" ")
+            )))
+        }
+    }
+    Ok(true)
+}
+
+// Where the data written to the file ends: its size less the zero bytes the
+// last page is padded with
+fn data_end(cache: &mut WriteThroughCache) -> std::io::Result<u64> {
+    let size = cache.file_size();
+    let last_page = size.saturating_sub(cache.page_size() as u64);
+    let data = cache.read(last_page, (size - last_page) as usize)?;
+    Ok(last_page
+        + data
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .map_or(0, |i| i as u64 + 1))
+}
+
+// Copy bytes to `out` as they are written to the file, from `offset` (or the
+// current end of the data) until `len` bytes have been copied
+fn tail(
+    cache: &mut WriteThroughCache,
+    offset: Option<u64>,
+    len: Option<u64>,
+    interval: Duration,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    let mut address = match offset {
+        Some(offset) => offset,
+        None => data_end(cache)?,
+    };
+    let end = len.map_or(u64::MAX, |len| address.saturating_add(len));
+    let page_size = cache.page_size() as u64;
+    while address < end {
+        let size = cache.reload(address)?;
+        let available = std::cmp::min(size, end);
+        let mut data = Vec::new();
+        if address < available {
+            let chunk = std::cmp::min(CHUNK_SIZE as u64, available - address);
+            data = cache.read(address, chunk as usize)?;
+            if address + chunk > size.saturating_sub(page_size) {
+                let written = data
+                    .iter()
+                    .rposition(|&byte| byte != 0)
+                    .map_or(0, |i| i + 1);
+                data.truncate(written);
+            }
+        }
+        if data.is_empty() {
+            std::thread::sleep(interval);
+            continue;
+        }
+        out.write_all(&data)?;
+        out.flush()?;
+        address += data.len() as u64;
+    }
+    Ok(())
+}
+
+// Print `data`, read from `address`, 16 bytes to a line
+fn hexdump(out: &mut impl Write, address: u64, data: &[u8]) -> std::io::Result<()> {
+    for (line, bytes) in (address..).step_by(DUMP_WIDTH).zip(data.chunks(DUMP_WIDTH)) {
+        let hex: Vec<String> = bytes.chunks(2).map(to_hex).collect();
+        let text: String = bytes
+            .iter()
+            .map(|&byte| match byte {
+                0x20..=0x7e => byte as char,
+                _ => '.',
+            })
+            .collect();
+        writeln!(
+            out,
+            "{:08x}: {:<width$}  {}",
+            line,
+            hex.join(" "),
+            text,
+            width = DUMP_WIDTH / 2 * 5 - 1
+        )?;
+    }
+    Ok(())
+}
+
+fn parse_compression(s: &str) -> Result<Compression, String> {
+    match s {
+        "none" => Ok(Compression::None),
+        "lz4" => Ok(Compression::Lz4),
+        "snappy" => Ok(Compression::Snappy),
+        "zstd" => Ok(Compression::Zstd {
+            level: ZSTD_DEFAULT_LEVEL,
+        }),
+        _ => match s.strip_prefix("zstd=") {
+            Some(level) => level
+                .parse()
+                .map(|level| Compression::Zstd { level })
+                .map_err(|e| format!("invalid zstd level {:?}: {}", level, e)),
+            None => Err(format!(
+                "unknown compression {:?}; expected none, lz4, snappy, zstd or zstd=LEVEL",
+                s
+            )),
+        },
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Pattern {
+    Zero,
+    Random,
+    Seq,
+    Byte(u8),
+}
+
+fn parse_pattern(s: &str) -> Result<Pattern, String> {
+    match s {
+        "zero" => Ok(Pattern::Zero),
+        "random" => Ok(Pattern::Random),
+        "seq" => Ok(Pattern::Seq),
+        _ => match s.strip_prefix("byte=") {
+            Some(byte) => parse_number(byte)?
+                .try_into()
+                .map(Pattern::Byte)
+                .map_err(|_| format!("byte {:?} does not fit in a byte", byte)),
+            None => Err(format!(
+                "unknown pattern {:?}; expected zero, random, seq or byte=N",
+                s
+            )),
+        },
+    }
+}
+
+impl Pattern {
+    // Fill `buffer` with the pattern's bytes for offset `address` onwards
+    fn generate(self, address: u64, buffer: &mut [u8], seed: u64) {
+        match self {
+            Pattern::Zero => buffer.fill(0),
+            Pattern::Byte(byte) => buffer.fill(byte),
+            Pattern::Seq => {
+                for (offset, byte) in (address..).zip(buffer.iter_mut()) {
+                    *byte = offset as u8;
+                }
+            }
+            // Each byte depends only on the seed and its offset, so the data
+            // does not depend on how it was split into writes
+            Pattern::Random => {
+                for (offset, byte) in (address..).zip(buffer.iter_mut()) {
+                    let word = mix(seed ^ mix(offset / 8));
+                    *byte = (word >> (offset % 8 * 8)) as u8;
+                }
+            }
+        }
+    }
+}
+
+// SplitMix64 finalizer
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn fill(
+    cache: &mut WriteThroughCache,
+    size: u64,
+    pattern: Pattern,
+    seed: u64,
+) -> std::io::Result<()> {
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut address = 0;
+    while address < size {
+        let len = std::cmp::min(CHUNK_SIZE as u64, size - address) as usize;
+        pattern.generate(address, &mut buffer[..len], seed);
+        cache.write(address, &buffer[..len])?;
+        address += len as u64;
+    }
+    Ok(())
+}
+
+fn print_compact_report(out: &mut impl Write, report: &CompactReport) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "{} pages, {} all-zero left as holes",
+        report.pages, report.zero_pages
+    )?;
+    let saved = report.bytes_before as i64 - report.bytes_after as i64;
+    writeln!(
+        out,
+        "{} bytes on disk before, {} after: saved {} bytes ({:.1}%)",
+        report.bytes_before,
+        report.bytes_after,
+        saved,
+        match report.bytes_before {
+            0 => 0.0,
+            before => saved as f64 * 100.0 / before as f64,
+        }
+    )
+}
+
+fn print_info(out: &mut impl Write, info: &FileInfo) -> std::io::Result<()> {
+    writeln!(out, "file size:   {} bytes", info.file_size)?;
+    writeln!(
+        out,
+        "on disk:     {} bytes, {} allocated",
+        info.disk_size,
+        info.allocated()
+    )?;
+    writeln!(out, "page size:   {} bytes", info.page_size)?;
+    writeln!(out, "pages:       {}", info.pages)?;
+    let mut features = Vec::new();
+    if info.page_versions {
+        features.push("page versions");
+    }
+    if info.compressed {
+        features.push("compressed pages");
+    }
+    if features.is_empty() {
+        features.push("none");
+    }
+    if info.header_present {
+        writeln!(
+            out,
+            "header:      version {}, features: {}",
+            info.header_version,
+            features.join(", ")
+        )?;
+    } else {
+        writeln!(out, "header:      no header (page size assumed)")?;
+    }
+    for (range, compression) in &info.compression_ranges {
+        writeln!(out, "compression: {:?} for {:?}", compression, range)?;
+    }
+    match info.data_extents.as_slice() {
+        [] => writeln!(out, "extents:     none")?,
+        [extent] if *extent == (0..info.disk_size) => writeln!(out, "extents:     not sparse")?,
+        extents => {
+            let extents: Vec<String> = extents.iter().map(|e| format!("{:?}", e)).collect();
+            writeln!(out, "extents:     {}", extents.join(", "))?
+        }
+    }
+    match &info.audit_log {
+        None => writeln!(out, "checksums:   header only, no audit log")?,
+        Some(Ok(records)) => writeln!(
+            out,
+            "checksums:   header, and writes in the audit log ({} records, chain intact)",
+            records
+        )?,
+        Some(Err(e)) => writeln!(
+            out,
+            "checksums:   header, and an audit log that is broken: {}",
+            e
+        )?,
+    }
+    match info.last_lsn {
+        Some(lsn) => writeln!(out, "last lsn:    {}", lsn),
+        None => writeln!(out, "last lsn:    no page versions"),
+    }
+}
+
+// `s` as a JSON string literal
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+// An object from field names and their already encoded values
+fn json_object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| format!("{}:{}", json_string(name), value))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn json_array(items: impl IntoIterator<Item = String>) -> String {
+    format!("[{}]", items.into_iter().collect::<Vec<_>>().join(","))
+}
+
+fn json_option(value: Option<impl ToString>) -> String {
+    value.map_or("null".to_string(), |value| value.to_string())
+}
+
+// The audit log fields shared by info and verify
+fn json_audit_log(audit_log: &Option<Result<u64, String>>) -> [(&'static str, String); 3] {
+    let (records, error) = match audit_log {
+        None => (None, None),
+        Some(Ok(records)) => (Some(*records), None),
+        Some(Err(e)) => (None, Some(json_string(e))),
+    };
+    [
+        ("audit_log", audit_log.is_some().to_string()),
+        ("audit_records", json_option(records)),
+        ("audit_error", json_option(error)),
+    ]
+}
+
+fn print_info_json(out: &mut impl Write, info: &FileInfo) -> std::io::Result<()> {
+    let ranges = info.compression_ranges.iter().map(|(range, compression)| {
+        json_object(&[
+            ("start", range.start.to_string()),
+            ("end", range.end.to_string()),
+            ("compression", json_string(&format!("{:?}", compression))),
+        ])
+    });
+    let extents = info.data_extents.iter().map(|extent| {
+        json_object(&[
+            ("start", extent.start.to_string()),
+            ("end", extent.end.to_string()),
+        ])
+    });
+    let mut fields = vec![
+        ("file_size", info.file_size.to_string()),
+        ("disk_size", info.disk_size.to_string()),
+        ("allocated", info.allocated().to_string()),
+        ("page_size", info.page_size.to_string()),
+        ("pages", info.pages.to_string()),
+        ("header_present", info.header_present.to_string()),
+        (
+            "header_version",
+            json_option(info.header_present.then_some(info.header_version)),
+        ),
+        ("page_versions", info.page_versions.to_string()),
+        ("compressed", info.compressed.to_string()),
+        ("compression_ranges", json_array(ranges)),
+        ("data_extents", json_array(extents)),
+    ];
+    fields.extend(json_audit_log(&info.audit_log));
+    fields.push(("last_lsn", json_option(info.last_lsn)));
+    writeln!(out, "{}", json_object(&fields))
+}
+
+fn print_report(out: &mut impl Write, report: &VerifyReport, verbose: bool) -> std::io::Result<()> {
+    let mut bad_pages = report.bad_pages.iter().peekable();
+    for page_id in 0..report.pages {
+        match bad_pages.next_if(|(bad, _)| *bad == page_id) {
+            Some((_, reason)) => writeln!(out, "page {}: {}", page_id, reason)?,
+            None if verbose => writeln!(out, "page {}: ok", page_id)?,
+            None => {}
+        }
+    }
+    match &report.header_error {
+        Some(reason) => writeln!(out, "header: {}", reason)?,
+        None if !report.header_present => writeln!(out, "header: no header (page size assumed)")?,
+        None => writeln!(out, "header: ok")?,
+    }
+    match &report.audit_log {
+        Some(Ok(records)) => writeln!(out, "audit log: ok, {} records", records)?,
+        Some(Err(reason)) => writeln!(out, "audit log: {}", reason)?,
+        None => {}
+    }
+    writeln!(
+        out,
+        "{} pages checked, {} bad",
+        report.pages,
+        report.bad_pages.len()
+    )
+}
+
+fn print_report_json(out: &mut impl Write, report: &VerifyReport) -> std::io::Result<()> {
+    let bad_pages = report.bad_pages.iter().map(|(page_id, reason)| {
+        json_object(&[
+            ("page", page_id.to_string()),
+            ("error", json_string(reason)),
+        ])
+    });
+    let mut fields = vec![
+        ("ok", report.is_ok().to_string()),
+        ("pages", report.pages.to_string()),
+        ("bad_pages", json_array(bad_pages)),
+        ("header_present", report.header_present.to_string()),
+        (
+            "header_error",
+            json_option(report.header_error.as_deref().map(json_string)),
+        ),
+    ];
+    fields.extend(json_audit_log(&report.audit_log));
+    writeln!(out, "{}", json_object(&fields))
+}
+
+fn stats_json(stats: &CacheStats) -> String {
+    json_object(&[
+        ("hits", stats.hits.to_string()),
+        ("misses", stats.misses.to_string()),
+        ("evictions", stats.evictions.to_string()),
+        ("disk_reads", stats.disk_reads.to_string()),
+        ("disk_writes", stats.disk_writes.to_string()),
+        ("fsyncs", stats.fsyncs.to_string()),
+        ("bytes_read", stats.bytes_read.to_string()),
+        ("bytes_written", stats.bytes_written.to_string()),
+    ])
+}
+
+struct BenchArgs {
+    workload: BenchWorkload,
+    size: u64,
+    io_size: usize,
+    duration: Duration,
+    read_ratio: f64,
+    seed: u64,
+}
+
+fn bench(cache: &mut WriteThroughCache, args: BenchArgs) -> std::io::Result<BenchReport> {
+    if cache.file_size() < args.size {
+        let fill_size = std::cmp::max(args.io_size, CHUNK_SIZE);
+        for op in Workload::fill(args.size, fill_size) {
+            op.apply(cache, 1)?;
+        }
+    }
+    let (size, io_size, seed) = (args.size, args.io_size, args.seed);
+    let workload = match args.workload {
+        BenchWorkload::SeqRead => Workload::sequential_reads(size, io_size),
+        BenchWorkload::SeqWrite => Workload::sequential_writes(size, io_size),
+        BenchWorkload::RandRead => Workload::random_reads(size, io_size, seed),
+        BenchWorkload::RandWrite => Workload::random_writes(size, io_size, seed),
+        BenchWorkload::Mixed => Workload::random_mixed(size, io_size, args.read_ratio, seed),
+    };
+
+    cache.reset_stats();
+    let mut latencies = Vec::new();
+    let started = Instant::now();
+    for (index, op) in workload.enumerate() {
+        let op_started = Instant::now();
+        op.apply(cache, index as u8)?;
+        let now = Instant::now();
+        latencies.push(now - op_started);
+        if now - started >= args.duration {
+            break;
+        }
+    }
+    let elapsed = started.elapsed();
+
+    latencies.sort_unstable();
+    let ops = latencies.len();
+    let percentile =
+        |p: f64| latencies[((p / 100.0 * ops as f64).ceil() as usize).clamp(1, ops) - 1];
+    Ok(BenchReport {
+        ops,
+        elapsed,
+        io_size,
+        mean: latencies.iter().sum::<Duration>() / ops as u32,
+        p50: percentile(50.0),
+        p99: percentile(99.0),
+        p999: percentile(99.9),
+        max: latencies[ops - 1],
+        stats: cache.stats(),
+    })
+}
+
+struct BenchReport {
+    ops: usize,
+    elapsed: Duration,
+    io_size: usize,
+    mean: Duration,
+    p50: Duration,
+    p99: Duration,
+    p999: Duration,
+    max: Duration,
+    stats: CacheStats,
+}
+
+impl BenchReport {
+    fn ops_per_sec(&self) -> f64 {
+        self.ops as f64 / self.elapsed.as_secs_f64()
+    }
+
+    fn mib_per_sec(&self) -> f64 {
+        (self.ops * self.io_size) as f64 / self.elapsed.as_secs_f64() / (1024.0 * 1024.0)
+    }
+}
+
+fn print_bench(out: &mut impl Write, report: &BenchReport) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "{} ops in {:.2?}: {:.0} ops/s, {:.1} MiB/s",
+        report.ops,
+        report.elapsed,
+        report.ops_per_sec(),
+        report.mib_per_sec()
+    )?;
+    writeln!(
+        out,
+        "latency: mean {:.1?}, p50 {:.1?}, p99 {:.1?}, p99.9 {:.1?}, max {:.1?}",
+        report.mean, report.p50, report.p99, report.p999, report.max
+    )?;
+    let stats = &report.stats;
+    writeln!(
+        out,
+        "cache: {} hits, {} misses, {} disk reads, {} disk writes, {} fsyncs",
+        stats.hits, stats.misses, stats.disk_reads, stats.disk_writes, stats.fsyncs
+    )
+}
+
+fn print_bench_json(out: &mut impl Write, report: &BenchReport) -> std::io::Result<()> {
+    let nanos = |duration: Duration| duration.as_nanos().to_string();
+    let latency = json_object(&[
+        ("mean", nanos(report.mean)),
+        ("p50", nanos(report.p50)),
+        ("p99", nanos(report.p99)),
+        ("p99.9", nanos(report.p999)),
+        ("max", nanos(report.max)),
+    ]);
+    let fields = [
+        ("ops", report.ops.to_string()),
+        ("elapsed_ns", nanos(report.elapsed)),
+        ("ops_per_sec", report.ops_per_sec().to_string()),
+        ("mib_per_sec", report.mib_per_sec().to_string()),
+        ("latency_ns", latency),
+        ("cache", stats_json(&report.stats)),
+    ];
+    writeln!(out, "{}", json_object(&fields))
+}
+
+#[derive(Clone, Copy)]
+struct Mix {
+    read: u64,
+    write: u64,
+    evict: u64,
+}
+
+fn parse_mix(s: &str) -> Result<Mix, String> {
+    let weights: Vec<u64> = s
+        .split(':')
+        .map(|weight| weight.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("invalid mix {:?}: {}", s, e))?;
+    match weights[..] {
+        [read, write, evict] if read + write + evict > 0 => Ok(Mix { read, write, evict }),
+        _ => Err(format!(
+            "invalid mix {:?}; expected three weights R:W:E, not all zero",
+            s
+        )),
+    }
+}
+
+struct StressArgs {
+    size: u64,
+    threads: usize,
+    duration: Duration,
+    mix: Mix,
+    seed: u64,
+}
+
+#[derive(Default)]
+struct StressReport {
+    reads: u64,
+    writes: u64,
+    evictions: u64,
+    full_checks: u64,
+    // Where the cache first disagreed with the model
+    divergence: Option<String>,
+}
+
+// Compare data read from `address` with the model, describing the first
+// difference
+fn compare(address: u64, actual: &[u8], expected: &[u8]) -> Option<String> {
+    let index = actual.iter().zip(expected).position(|(a, e)| a != e)?;
+    Some(format!(
+        "byte {} read back as {:#04x}, expected {:#04x}",
+        address + index as u64,
+        actual[index],
+        expected[index]
+    ))
+}
+
+// Time between full comparisons of the file with the model
+const STRESS_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+fn stress(mut cache: WriteThroughCache, args: StressArgs) -> std::io::Result<StressReport> {
+    let StressArgs {
+        size,
+        threads,
+        duration,
+        mix: weights,
+        seed,
+    } = args;
+    let mut model = vec![0; size as usize];
+    Pattern::Random.generate(0, &mut model, seed);
+    fill(&mut cache, size, Pattern::Random, seed)?;
+
+    // The model is locked a page at a time, always in ascending order, and
+    // each operation holds the pages of its range while it runs, so the
+    // cache and the model see operations on a range in the same order while
+    // operations on other ranges go ahead
+    let page_size = cache.page_size();
+    let model: Vec<Mutex<Vec<u8>>> = model
+        .chunks(page_size)
+        .map(|page| Mutex::new(page.to_vec()))
+        .collect();
+    let lock_range = |range: std::ops::Range<usize>| {
+        let pages = range.start / page_size..range.end.div_ceil(page_size);
+        model[pages]
+            .iter()
+            .map(|page| page.lock().unwrap())
+            .collect::<Vec<_>>()
+    };
+    let (writer, reader) = cache.into_split();
+    let reads = AtomicU64::new(0);
+    let writes = AtomicU64::new(0);
+    let evictions = AtomicU64::new(0);
+    let divergence = Mutex::new(None);
+    let stop = AtomicBool::new(false);
+    let deadline = Instant::now() + duration;
+    let max_len = 3 * page_size as u64;
+
+    let run = |thread: usize, reader: CacheReader| -> std::io::Result<()> {
+        let mut rng = mix(seed ^ mix(thread as u64 + 1));
+        let mut next = || {
+            rng = mix(rng);
+            rng
+        };
+        while !stop.load(Ordering::Relaxed) && Instant::now() < deadline {
+            let pick = next() % (weights.read + weights.write + weights.evict);
+            let address = next() % size;
+            let len = 1 + next() % std::cmp::min(max_len, size - address);
+            let range = address as usize..(address + len) as usize;
+            // Offset of the range within its first model page
+            let offset = range.start % page_size;
+            if pick < weights.read {
+                let pages = lock_range(range);
+                let data = reader.read(address, len as usize)?;
+                reads.fetch_add(1, Ordering::Relaxed);
+                let expected: Vec<u8> =
+                    pages.iter().flat_map(|page| page.iter().copied()).collect();
+                if let Some(found) = compare(address, &data, &expected[offset..offset + data.len()])
+                {
+                    divergence.lock().unwrap().get_or_insert(found);
+                    stop.store(true, Ordering::Relaxed);
+                }
+            } else if pick < weights.read + weights.write {
+                let data: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+                let mut pages = lock_range(range);
+                writer.write(address, &data)?;
+                let mut rest = data.as_slice();
+                let mut start = offset;
+                for page in &mut pages {
+                    let n = std::cmp::min(rest.len(), page.len() - start);
+                    page[start..start + n].copy_from_slice(&rest[..n]);
+                    rest = &rest[n..];
+                    start = 0;
+                }
+                writes.fetch_add(1, Ordering::Relaxed);
+            } else {
+                writer.lock()?.evict_all();
+                evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    };
+    // A worker that fails stops the others too
+    let worker = |thread: usize, reader: CacheReader| {
+        let result = run(thread, reader);
+        if result.is_err() {
+            stop.store(true, Ordering::Relaxed);
+        }
+        result
+    };
+
+    let mut full_checks = 0;
+    std::thread::scope(|scope| -> std::io::Result<()> {
+        let handles: Vec<_> = (0..threads)
+            .map(|thread| {
+                let reader = reader.clone();
+                scope.spawn(move || worker(thread, reader))
+            })
+            .collect();
+        // Meanwhile compare the whole file with the model now and then, a
+        // chunk at a time
+        let mut result = Ok(());
+        'checks: while !stop.load(Ordering::Relaxed) && Instant::now() < deadline {
+            std::thread::sleep(std::cmp::min(
+                STRESS_CHECK_INTERVAL,
+                deadline.saturating_duration_since(Instant::now()),
+            ));
+            let mut address = 0;
+            while address < size {
+                let len = std::cmp::min(CHUNK_SIZE as u64, size - address);
+                let pages = lock_range(address as usize..(address + len) as usize);
+                let data = match reader.read(address, len as usize) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        result = Err(e);
+                        stop.store(true, Ordering::Relaxed);
+                        break 'checks;
+                    }
+                };
+                let expected: Vec<u8> =
+                    pages.iter().flat_map(|page| page.iter().copied()).collect();
+                if let Some(found) = compare(address, &data, &expected[..data.len()]) {
+                    divergence.lock().unwrap().get_or_insert(found);
+                    stop.store(true, Ordering::Relaxed);
+                }
+                address += len;
+            }
+            full_checks += 1;
+        }
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+        result
+    })?;
+
+    Ok(StressReport {
+        reads: reads.into_inner(),
+        writes: writes.into_inner(),
+        evictions: evictions.into_inner(),
+        full_checks,
+        divergence: divergence.into_inner().unwrap(),
+    })
+}
+
+fn print_stress(out: &mut impl Write, report: &StressReport) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "{} reads, {} writes, {} evictions, {} full checks",
+        report.reads, report.writes, report.evictions, report.full_checks
+    )?;
+    match &report.divergence {
+        Some(divergence) => writeln!(out, "diverged: {}", divergence),
+        None => writeln!(out, "no divergence"),
+    }
+}
+
+fn print_stress_json(out: &mut impl Write, report: &StressReport) -> std::io::Result<()> {
+    let fields = [
+        ("reads", report.reads.to_string()),
+        ("writes", report.writes.to_string()),
+        ("evictions", report.evictions.to_string()),
+        ("full_checks", report.full_checks.to_string()),
+        (
+            "divergence",
+            json_option(report.divergence.as_deref().map(json_string)),
+        ),
+    ];
+    writeln!(out, "{}", json_object(&fields))
+}
+
+fn run(command: Command, json: bool) -> std::io::Result<()> {
+    match command {
+        Command::Read {
+            cache,
+            offset,
+            len,
+            output,
+        } => {
+            let data = cache.open()?.read(offset, len as usize)?;
+            match output {
+                Some(path) => std::fs::write(path, data),
+                None => writeln!(std::io::stdout(), "{}", to_hex(&data)),
+            }
+        }
+        Command::Write {
+            cache,
+            offset,
+            data,
+            input,
+        } => {
+            let data = match input {
+                Some(path) => std::fs::read(path)?,
+                None => data.map(|HexData(data)| data).unwrap_or_default(),
+            };
+            cache.open()?.write(offset, &data)
+        }
+        Command::Dump { cache, offset, len } => {
+            let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+            stream_range(&mut cache.open()?, offset, len, |address, data| {
+                hexdump(&mut out, address, data)
+            })?;
+            out.flush()
+        }
+        Command::Export {
+            cache,
+            offset,
+            len,
+            bs,
+            skip,
+            count,
+        } => {
+            let offset = blocks(skip, bs)?.unwrap_or(offset);
+            let len = blocks(count, bs)?.or(len);
+            let mut out = std::io::stdout().lock();
+            stream_range(&mut cache.open()?, offset, len, |_, data| {
+                out.write_all(data)
+            })?;
+            out.flush()
+        }
+        Command::Import {
+            cache,
+            offset,
+            bs,
+            seek,
+            skip,
+            count,
+        } => {
+            let offset = blocks(seek, bs)?.unwrap_or(offset);
+            let mut input = std::io::stdin().lock();
+            if let Some(skip) = blocks(skip, bs)? {
+                let skipped = std::io::copy(&mut (&mut input).take(skip), &mut std::io::sink())?;
+                if skipped < skip {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "stdin ended before the blocks to skip",
+                    ));
+                }
+            }
+            let limit = blocks(count, bs)?.unwrap_or(u64::MAX);
+            import(&mut cache.open()?, offset, &mut input.take(limit))
+        }
+        Command::Bench {
+            cache,
+            workload,
+            size,
+            io_size,
+            duration,
+            read_ratio,
+            seed,
+        } => {
+            if io_size == 0 || size < io_size {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "I/O size must be non-zero and at most the file size",
+                ));
+            }
+            let args = BenchArgs {
+                workload,
+                size,
+                io_size: io_size as usize,
+                duration: Duration::try_from_secs_f64(duration)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+                read_ratio,
+                seed,
+            };
+            let report = bench(&mut cache.open()?, args)?;
+            let mut out = std::io::stdout().lock();
+            if json {
+                print_bench_json(&mut out, &report)
+            } else {
+                print_bench(&mut out, &report)
+            }
+        }
+        Command::Stress {
+            cache,
+            size,
+            threads,
+            duration,
+            mix,
+            seed,
+        } => {
+            if size == 0 || threads == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Size and thread count must be non-zero",
+                ));
+            }
+            let args = StressArgs {
+                size,
+                threads,
+                duration: Duration::try_from_secs_f64(duration)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+                mix,
+                seed,
+            };
+            let report = stress(cache.open()?, args)?;
+            let mut out = std::io::stdout().lock();
+            if json {
+                print_stress_json(&mut out, &report)?;
+            } else {
+                print_stress(&mut out, &report)?;
+            }
+            if report.divergence.is_some() {
+                out.flush()?;
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Shell { cache } => {
+            let prompt = std::io::stdin().is_terminal();
+            shell(
+                &mut cache.open()?,
+                &mut std::io::stdin().lock(),
+                &mut std::io::stdout().lock(),
+                prompt,
+                json,
+            )
+        }
+        Command::Tail {
+            cache,
+            offset,
+            len,
+            interval,
+        } => tail(
+            &mut cache.open()?,
+            offset,
+            len,
+            Duration::from_millis(interval),
+            &mut std::io::stdout().lock(),
+        ),
+        Command::Compact {
+            cache,
+            dest,
+            compression,
+            keep_zero_pages,
+        } => {
+            let options = CompactOptions {
+                compression,
+                skip_zero_pages: !keep_zero_pages,
+                ..CompactOptions::default()
+            };
+            let report = cache.open()?.compact_to(&dest, options)?;
+            print_compact_report(&mut std::io::stdout().lock(), &report)
+        }
+        Command::Convert {
+            file,
+            dest,
+            page_size,
+            compression,
+        } => {
+            let options = CompactOptions {
+                compression,
+                page_size: Some(page_size as usize),
+                ..CompactOptions::default()
+            };
+            require_file(&file)?;
+            let report = WriteThroughCache::new(&file, None, None)?.compact_to(&dest, options)?;
+            print_compact_report(&mut std::io::stdout().lock(), &report)
+        }
+        Command::Fill {
+            cache,
+            size,
+            pattern,
+            seed,
+        } => fill(&mut cache.open()?, size, pattern, seed),
+        Command::Info { cache } => {
+            let info = cache.open_existing()?.info()?;
+            let mut out = std::io::stdout().lock();
+            if json {
+                print_info_json(&mut out, &info)
+            } else {
+                print_info(&mut out, &info)
+            }
+        }
+        Command::Verify { cache, verbose } => {
+            let report = cache.open_existing()?.verify()?;
+            let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+            if json {
+                print_report_json(&mut out, &report)?;
+            } else {
+                print_report(&mut out, &report, verbose)?;
+            }
+            out.flush()?;
+            if !report.is_ok() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli.command, cli.json) {
+        eprintln!("wt_cache: {}", e);
+        std::process::exit(1);
+    }
+}
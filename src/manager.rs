@@ -0,0 +1,218 @@
+//! Many cached files sharing one memory budget.
+//!
+//! Each file gets its own `WriteThroughCache`, opened on first use with a
+//! capacity of its own, a quarter of the budget unless set otherwise, no
+//! pooled buffers, so evicted pages give their memory back, and an index
+//! that grows with the pages it holds. After every use of a file through a
+//! handle, pages are evicted until the memory used by all files together
+//! fits the budget, least recently used first across all files: every
+//! file's pages are stamped from one clock. Files are known by their
+//! canonical paths, so every spelling of one path, symlinks included, gets
+//! the same cache.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+use crate::{AHashMap, CacheStats, WriteThroughCache, MAX_CAPACITY, MIN_CAPACITY};
+
+struct ManagedFile {
+    path: PathBuf,
+    cache: WriteThroughCache,
+}
+
+pub struct CacheManager {
+    budget: usize,
+    page_size: Option<usize>,
+    file_capacity: usize,
+    files: Vec<ManagedFile>,
+    index: AHashMap<PathBuf, usize>,
+    // Shared by all files' caches to stamp page uses
+    clock: Arc<AtomicU64>,
+}
+
+/// Access to one file's cache, from `CacheManager::cache`. The manager
+/// brings memory use back within its budget when the handle is dropped.
+pub struct ManagedCache<'a> {
+    manager: &'a mut CacheManager,
+    file: usize,
+}
+
+impl CacheManager {
+    pub fn new(budget: usize) -> std::io::Result<Self> {
+        if !(MIN_CAPACITY..=MAX_CAPACITY).contains(&budget) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "Memory budget must be between {} and {} bytes",
+                    MIN_CAPACITY, MAX_CAPACITY
+                ),
+            ));
+        }
+        Ok(Self {
+            budget,
+            page_size: None,
+            file_capacity: std::cmp::max(budget / 4, MIN_CAPACITY),
+            files: Vec::new(),
+            index: AHashMap::default(),
+            clock: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Page size for files created from now on. Existing files keep the
+    /// page size they were written with.
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Capacity of each file's cache opened from now on, which bounds how
+    /// much of the budget one file can take.
+    pub fn file_capacity(mut self, capacity: usize) -> Self {
+        self.file_capacity = capacity;
+        self
+    }
+
+    /// The cache for `path`, opening it if it isn't open yet.
+    pub fn cache<P: AsRef<Path>>(&mut self, path: P) -> std::io::Result<ManagedCache<'_>> {
+        let path = &canonical(path.as_ref())?;
+        let file = match self.index.get(path) {
+            Some(&file) => file,
+            None => {
+                let mut builder = WriteThroughCache::builder(path)
+                    .capacity(self.file_capacity)
+                    .buffer_pool(0);
+                builder.presize_index = false;
+                builder.recency_clock = Some(self.clock.clone());
+                if let Some(page_size) = self.page_size {
+                    builder = builder.page_size(page_size);
+                }
+                let cache = builder.open()?;
+                self.files.push(ManagedFile {
+                    path: path.to_path_buf(),
+                    cache,
+                });
+                self.index.insert(path.to_path_buf(), self.files.len() - 1);
+                self.files.len() - 1
+            }
+        };
+        Ok(ManagedCache {
+            manager: self,
+            file,
+        })
+    }
+
+    /// Drop `path`'s cache, returning whether it was open.
+    pub fn close<P: AsRef<Path>>(&mut self, path: P) -> bool {
+        let Some(file) = canonical(path.as_ref())
+            .ok()
+            .and_then(|path| self.index.remove(&path))
+        else {
+            return false;
+        };
+        self.files.swap_remove(file);
+        if let Some(moved) = self.files.get(file) {
+            self.index.insert(moved.path.clone(), file);
+        }
+        true
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Bytes of pages cached for all open files.
+    pub fn resident_bytes(&self) -> usize {
+        self.files
+            .iter()
+            .map(|file| file.cache.resident_page_bytes())
+            .sum()
+    }
+
+    /// Memory used by the caches of all open files, including their
+    /// indexes, which is what the budget limits.
+    pub fn memory_usage(&self) -> usize {
+        self.files
+            .iter()
+            .map(|file| file.cache.memory_usage())
+            .sum()
+    }
+
+    /// Canonical paths of the open files, in no particular order.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> + '_ {
+        self.files.iter().map(|file| file.path.as_path())
+    }
+
+    pub fn stats<P: AsRef<Path>>(&self, path: P) -> Option<CacheStats> {
+        let file = *self.index.get(&canonical(path.as_ref()).ok()?)?;
+        Some(self.files[file].cache.stats())
+    }
+
+    fn enforce_budget(&mut self) {
+        let mut usage = self.memory_usage();
+        if usage <= self.budget {
+            return;
+        }
+        // Each file keyed by when the page it would evict next was last
+        // used, so pages go in global LRU order
+        let mut next: BinaryHeap<Reverse<(u64, usize)>> = self
+            .files
+            .iter()
+            .enumerate()
+            .filter_map(|(file, managed)| Some(Reverse((managed.cache.lru_last_used()?, file))))
+            .collect();
+        while usage > self.budget {
+            let Some(Reverse((_, file))) = next.pop() else {
+                break;
+            };
+            let cache = &mut self.files[file].cache;
+            let before = cache.memory_usage();
+            cache.evict_lru();
+            usage = usage - before + cache.memory_usage();
+            if let Some(last_used) = cache.lru_last_used() {
+                next.push(Reverse((last_used, file)));
+            }
+        }
+    }
+}
+
+// The path `path` is known by, the same for every spelling of it. A file
+// not created yet is named within the canonical path of its directory.
+fn canonical(path: &Path) -> std::io::Result<PathBuf> {
+    match path.canonicalize() {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let Some(name) = path.file_name() else {
+                return Err(e);
+            };
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            Ok(dir.canonicalize()?.join(name))
+        }
+        result => result,
+    }
+}
+
+impl Deref for ManagedCache<'_> {
+    type Target = WriteThroughCache;
+
+    fn deref(&self) -> &WriteThroughCache {
+        &self.manager.files[self.file].cache
+    }
+}
+
+impl DerefMut for ManagedCache<'_> {
+    fn deref_mut(&mut self) -> &mut WriteThroughCache {
+        &mut self.manager.files[self.file].cache
+    }
+}
+
+impl Drop for ManagedCache<'_> {
+    fn drop(&mut self) {
+        self.manager.enforce_budget();
+    }
+}
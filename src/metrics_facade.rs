@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use ::metrics::{counter, gauge, histogram, Counter, Gauge, Histogram, Label};
+
+use crate::stats::Counters;
+use crate::{DiskOp, WriteThroughCache};
+
+/// Cache metrics reported through the `metrics` facade, for whichever
+/// exporter the application installs. Hand one to `CacheBuilder::metrics`
+/// and the cache keeps it current: counters as they are bumped, gauges
+/// after every read and write, the latency histograms on every disk
+/// operation. Counters only go up, even across `reset_stats`. Handles are bound
+/// to the recorder installed when `new` is called, so install it first.
+#[derive(Clone)]
+pub struct MetricsFacade {
+    hits: Counter,
+    misses: Counter,
+    evictions: Counter,
+    disk_reads: Counter,
+    disk_writes: Counter,
+    fsyncs: Counter,
+    bytes_read: Counter,
+    bytes_written: Counter,
+    hit_ratio: Gauge,
+    resident_bytes: Gauge,
+    read_seconds: Histogram,
+    write_seconds: Histogram,
+    fsync_seconds: Histogram,
+}
+
+impl MetricsFacade {
+    /// Register the metrics, named `<prefix>_hits` and so on, with `labels`
+    /// attached to every one of them.
+    pub fn new(prefix: &str, labels: &[(&str, &str)]) -> Self {
+        let labels: Vec<Label> = labels
+            .iter()
+            .map(|&(key, value)| Label::new(key.to_string(), value.to_string()))
+            .collect();
+        let name = |metric: &str| format!("{}_{}", prefix, metric);
+        Self {
+            hits: counter!(name("hits"), labels.iter()),
+            misses: counter!(name("misses"), labels.iter()),
+            evictions: counter!(name("evictions"), labels.iter()),
+            disk_reads: counter!(name("disk_reads"), labels.iter()),
+            disk_writes: counter!(name("disk_writes"), labels.iter()),
+            fsyncs: counter!(name("fsyncs"), labels.iter()),
+            bytes_read: counter!(name("bytes_read"), labels.iter()),
+            bytes_written: counter!(name("bytes_written"), labels.iter()),
+            hit_ratio: gauge!(name("hit_ratio"), labels.iter()),
+            resident_bytes: gauge!(name("resident_bytes"), labels.iter()),
+            read_seconds: histogram!(name("disk_read_seconds"), labels.iter()),
+            write_seconds: histogram!(name("disk_write_seconds"), labels.iter()),
+            fsync_seconds: histogram!(name("fsync_seconds"), labels.iter()),
+        }
+    }
+
+    pub(crate) fn observe(&self, op: DiskOp, elapsed: Duration) {
+        let histogram = match op {
+            DiskOp::Read => &self.read_seconds,
+            DiskOp::Write => &self.write_seconds,
+            DiskOp::Fsync => &self.fsync_seconds,
+        };
+        histogram.record(elapsed.as_secs_f64());
+    }
+
+    // Have `stats` bump the facade's counters along with its own
+    pub(crate) fn mirror(&self, stats: &mut Counters) {
+        stats.hits.mirror(self.hits.clone());
+        stats.misses.mirror(self.misses.clone());
+        stats.evictions.mirror(self.evictions.clone());
+        stats.disk_reads.mirror(self.disk_reads.clone());
+        stats.disk_writes.mirror(self.disk_writes.clone());
+        stats.fsyncs.mirror(self.fsyncs.clone());
+        stats.bytes_read.mirror(self.bytes_read.clone());
+        stats.bytes_written.mirror(self.bytes_written.clone());
+    }
+
+    pub(crate) fn update(&self, cache: &WriteThroughCache) {
+        let stats = cache.stats();
+        let lookups = stats.hits + stats.misses;
+        if lookups > 0 {
+            self.hit_ratio.set(stats.hits as f64 / lookups as f64);
+        }
+        self.resident_bytes.set(cache.memory_usage() as f64);
+    }
+}
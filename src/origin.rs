@@ -0,0 +1,47 @@
+use std::fs::File;
+use std::path::Path;
+
+use crate::pio;
+
+/// Slow, authoritative source of data that a local cache sits in front of,
+/// such as an object in remote storage.
+pub trait Origin: Send {
+    /// Size of the object in bytes.
+    fn size(&mut self) -> std::io::Result<u64>;
+
+    /// Read `len` bytes at `offset`, which lie within the object.
+    fn read_at(&mut self, offset: u64, len: usize) -> std::io::Result<Vec<u8>>;
+
+    /// Tag that changes whenever the object's content does, such as an
+    /// etag or an epoch number, used to tell whether data cached from an
+    /// earlier version is still good. `None` if the origin has no such tag,
+    /// leaving only its size to compare.
+    fn version(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+}
+
+/// A local file used as an origin, mostly for testing.
+pub struct FileOrigin {
+    file: File,
+}
+
+impl FileOrigin {
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Ok(Self {
+            file: File::open(path)?,
+        })
+    }
+}
+
+impl Origin for FileOrigin {
+    fn size(&mut self) -> std::io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    fn read_at(&mut self, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        let mut data = vec![0; len];
+        pio::read_exact_at(&self.file, &mut data, offset)?;
+        Ok(data)
+    }
+}
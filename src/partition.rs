@@ -0,0 +1,116 @@
+use std::io::{Error, ErrorKind};
+
+use crate::WriteThroughCache;
+
+/// A named region of the file with its own addresses starting at 0,
+/// recorded in the file header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    pub name: String,
+    /// File address of the partition's address 0, a multiple of the page
+    /// size.
+    pub start: u64,
+    pub size: u64,
+}
+
+/// Reads and writes within one partition, from
+/// `WriteThroughCache::partition`.
+pub struct PartitionHandle<'a> {
+    cache: &'a mut WriteThroughCache,
+    start: u64,
+    size: u64,
+}
+
+impl WriteThroughCache {
+    /// Reserve `size` bytes as partition `name`, after every existing
+    /// partition. A file divided into partitions should only be written
+    /// through them.
+    pub fn create_partition(&mut self, name: &str, size: u64) -> std::io::Result<Partition> {
+        if name.is_empty() || name.len() > u16::MAX as usize {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Partition name must be between 1 and 65535 bytes",
+            ));
+        }
+        if self.header.partitions.iter().any(|p| p.name == name) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("Partition {} already exists", name),
+            ));
+        }
+        let end = self
+            .header
+            .partitions
+            .iter()
+            .map(|p| p.start + p.size)
+            .max()
+            .unwrap_or(0);
+        let partition = Partition {
+            name: name.to_string(),
+            start: end.next_multiple_of(self.page_size as u64),
+            size,
+        };
+        self.header.partitions.push(partition.clone());
+        if let Err(e) = self.header.store(&self.file_path) {
+            self.header.partitions.pop();
+            return Err(e);
+        }
+        Ok(partition)
+    }
+
+    /// Access to partition `name`.
+    pub fn partition(&mut self, name: &str) -> std::io::Result<PartitionHandle<'_>> {
+        let Some(partition) = self.header.partitions.iter().find(|p| p.name == name) else {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("No partition {}", name),
+            ));
+        };
+        Ok(PartitionHandle {
+            start: partition.start,
+            size: partition.size,
+            cache: self,
+        })
+    }
+
+    /// Partitions in the order they were created.
+    pub fn partitions(&self) -> &[Partition] {
+        &self.header.partitions
+    }
+}
+
+impl PartitionHandle<'_> {
+    pub fn read(&mut self, address: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        self.check(address, len as u64)?;
+        self.cache.read(self.start + address, len)
+    }
+
+    pub fn write(&mut self, address: u64, data: &[u8]) -> std::io::Result<()> {
+        self.check(address, data.len() as u64)?;
+        self.cache.write(self.start + address, data)
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// File address of the partition's address 0.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    fn check(&self, address: u64, len: u64) -> std::io::Result<()> {
+        if address.saturating_add(len) > self.size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Range {}..{} is past the end of the partition, {}",
+                    address,
+                    address.saturating_add(len),
+                    self.size
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+// Positional I/O: one syscall per transfer and no shared file cursor.
+use std::fs::File;
+
+#[cfg(unix)]
+pub fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(unix)]
+pub fn write_all_at(file: &File, buf: &[u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+
+/// Append `len` bytes read at `offset` to `buf`, without zero-filling the
+/// space first. On error `buf` is left as it was.
+#[cfg(unix)]
+pub fn read_append_at(
+    file: &File,
+    buf: &mut Vec<u8>,
+    len: usize,
+    offset: u64,
+) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    buf.reserve(len);
+    let mut filled = 0;
+    while filled < len {
+        let spare = buf.spare_capacity_mut();
+        // SAFETY: `reserve` left at least `len` bytes of spare capacity, so the
+        // destination holds `len - filled` writable bytes. pread only writes
+        // to it and reports how many bytes it wrote.
+        let read = unsafe {
+            libc::pread(
+                file.as_raw_fd(),
+                spare.as_mut_ptr().add(filled).cast(),
+                len - filled,
+                (offset + filled as u64) as libc::off_t,
+            )
+        };
+        match read {
+            0 => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "failed to fill whole buffer",
+                ))
+            }
+            n if n > 0 => filled += n as usize,
+            _ => {
+                let e = std::io::Error::last_os_error();
+                if e.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+        }
+    }
+    // SAFETY: the `len` bytes past the old length were all written by pread
+    unsafe { buf.set_len(buf.len() + len) };
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn read_append_at(
+    file: &File,
+    buf: &mut Vec<u8>,
+    len: usize,
+    offset: u64,
+) -> std::io::Result<()> {
+    let start = buf.len();
+    buf.resize(start + len, 0);
+    let result = read_exact_at(file, &mut buf[start..], offset);
+    if result.is_err() {
+        buf.truncate(start);
+    }
+    result
+}
+
+#[cfg(windows)]
+pub fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "failed to fill whole buffer",
+                ))
+            }
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn write_all_at(file: &File, mut buf: &[u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        match file.seek_write(buf, offset) {
+            Ok(0) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::WriteZero,
+                    "failed to write whole buffer",
+                ))
+            }
+            Ok(n) => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Copy `len` bytes between files inside the kernel. Returns `Ok(false)`,
+/// having copied nothing, when the kernel or filesystem cannot do it, so the
+/// caller can fall back to copying through userspace.
+#[cfg(target_os = "linux")]
+pub fn copy_file_range(
+    src: &File,
+    src_offset: u64,
+    dst: &File,
+    dst_offset: u64,
+    len: u64,
+) -> std::io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut copied = 0;
+    while copied < len {
+        let mut src_pos = (src_offset + copied) as libc::loff_t;
+        let mut dst_pos = (dst_offset + copied) as libc::loff_t;
+        let chunk = std::cmp::min(len - copied, isize::MAX as u64) as usize;
+        // SAFETY: both descriptors stay open for the call and the offsets
+        // point to live locals
+        let n = unsafe {
+            libc::copy_file_range(
+                src.as_raw_fd(),
+                &mut src_pos,
+                dst.as_raw_fd(),
+                &mut dst_pos,
+                chunk,
+                0,
+            )
+        };
+        match n {
+            0 => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "Source range extends past the end of the file",
+                ))
+            }
+            n if n > 0 => copied += n as u64,
+            _ => {
+                let e = std::io::Error::last_os_error();
+                match e.raw_os_error() {
+                    Some(libc::EINTR) => {}
+                    Some(libc::ENOSYS | libc::EXDEV | libc::EOPNOTSUPP | libc::EINVAL)
+                        if copied == 0 =>
+                    {
+                        return Ok(false)
+                    }
+                    _ => return Err(e),
+                }
+            }
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn copy_file_range(
+    _src: &File,
+    _src_offset: u64,
+    _dst: &File,
+    _dst_offset: u64,
+    _len: u64,
+) -> std::io::Result<bool> {
+    Ok(false)
+}
+
+/// Send `len` bytes of `file` starting at `offset` to `socket` inside the
+/// kernel. Returns `Ok(false)`, having sent nothing, when `sendfile` is not
+/// available so the caller can fall back to copying through userspace.
+#[cfg(target_os = "linux")]
+pub fn sendfile(
+    file: &File,
+    offset: u64,
+    socket: &std::net::TcpStream,
+    len: u64,
+) -> std::io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut sent = 0;
+    while sent < len {
+        let mut pos = (offset + sent) as libc::off_t;
+        let chunk = std::cmp::min(len - sent, isize::MAX as u64) as usize;
+        // SAFETY: both descriptors stay open for the call and the offset
+        // points to a live local
+        let n = unsafe { libc::sendfile(socket.as_raw_fd(), file.as_raw_fd(), &mut pos, chunk) };
+        match n {
+            0 => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "Range extends past the end of the file",
+                ))
+            }
+            n if n > 0 => sent += n as u64,
+            _ => {
+                let e = std::io::Error::last_os_error();
+                match e.raw_os_error() {
+                    Some(libc::EINTR) => {}
+                    Some(libc::ENOSYS | libc::EINVAL) if sent == 0 => return Ok(false),
+                    _ => return Err(e),
+                }
+            }
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn sendfile(
+    _file: &File,
+    _offset: u64,
+    _socket: &std::net::TcpStream,
+    _len: u64,
+) -> std::io::Result<bool> {
+    Ok(false)
+}
+
+/// Ranges of the first `len` bytes of `file` that have storage allocated;
+/// the gaps between them are holes. The whole range counts as allocated
+/// where holes cannot be detected.
+#[cfg(target_os = "linux")]
+pub fn data_extents(file: &File, len: u64) -> std::io::Result<Vec<std::ops::Range<u64>>> {
+    use std::os::unix::io::AsRawFd;
+
+    let seek = |offset: u64, whence| {
+        // SAFETY: the descriptor stays open for the call. Only the file
+        // cursor moves, which positional I/O does not use.
+        match unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, whence) } {
+            n if n >= 0 => Ok(Some(n as u64)),
+            _ => {
+                let e = std::io::Error::last_os_error();
+                match e.raw_os_error() {
+                    // No data past `offset`
+                    Some(libc::ENXIO) => Ok(None),
+                    _ => Err(e),
+                }
+            }
+        }
+    };
+
+    let mut extents = Vec::new();
+    let mut offset = 0;
+    while offset < len {
+        let start = match seek(offset, libc::SEEK_DATA) {
+            Ok(Some(start)) if start < len => start,
+            Ok(_) => break,
+            Err(e) if offset == 0 && e.raw_os_error() == Some(libc::EINVAL) => {
+                return Ok(vec![std::ops::Range { start: 0, end: len }])
+            }
+            Err(e) => return Err(e),
+        };
+        let end = seek(start, libc::SEEK_HOLE)?.map_or(len, |end| end.min(len));
+        extents.push(start..end);
+        offset = end;
+    }
+    Ok(extents)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn data_extents(_file: &File, len: u64) -> std::io::Result<Vec<std::ops::Range<u64>>> {
+    Ok(if len == 0 {
+        Vec::new()
+    } else {
+        vec![std::ops::Range { start: 0, end: len }]
+    })
+}
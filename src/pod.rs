@@ -0,0 +1,24 @@
+use zerocopy::{FromBytes, Immutable, IntoBytes};
+
+use crate::WriteThroughCache;
+
+impl WriteThroughCache {
+    /// Read a `T` from its `size_of::<T>()` bytes at `address`. Any bytes
+    /// are a valid `T`, which zerocopy's derives check when `T` is
+    /// compiled.
+    pub fn read_as<T: FromBytes>(&mut self, address: u64) -> std::io::Result<T> {
+        let bytes = self.read(address, std::mem::size_of::<T>())?;
+        // The length always matches, and FromBytes types have no alignment
+        // to meet when read by value
+        Ok(T::read_from_bytes(&bytes).unwrap())
+    }
+
+    /// Write `value`'s bytes at `address`.
+    pub fn write_as<T: IntoBytes + Immutable>(
+        &mut self,
+        address: u64,
+        value: &T,
+    ) -> std::io::Result<()> {
+        self.write(address, value.as_bytes())
+    }
+}
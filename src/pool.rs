@@ -0,0 +1,285 @@
+use zeroize::Zeroize;
+
+use crate::stats::{snapshot, Counter};
+
+/// Counters for the page buffer pool.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Buffers currently waiting in the pool.
+    pub buffers: usize,
+    pub max_buffers: usize,
+    /// Buffers handed out from the pool.
+    pub hits: u64,
+    /// Buffers that had to be allocated because the pool was empty.
+    pub misses: u64,
+}
+
+/// Handle to one page-sized frame owned by the pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Frame(u32);
+
+const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024; // 2MiB
+
+enum Chunk {
+    Heap(Box<[u8]>),
+    #[cfg(target_os = "linux")]
+    Mapped(hugepage::Mapping),
+    // A heap frame whose memory was handed back to the allocator
+    Released,
+}
+
+impl Chunk {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Chunk::Heap(bytes) => bytes,
+            #[cfg(target_os = "linux")]
+            Chunk::Mapped(mapping) => mapping.bytes(),
+            Chunk::Released => &[],
+        }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        match self {
+            Chunk::Heap(bytes) => bytes,
+            #[cfg(target_os = "linux")]
+            Chunk::Mapped(mapping) => mapping.bytes_mut(),
+            Chunk::Released => &mut [],
+        }
+    }
+}
+
+// Page frames for the cache. Frames freed by evicted pages come back here and
+// misses draw from the free list before allocating. By default every frame is
+// its own heap allocation; with huge pages they are carved out of 2MiB chunks
+// mapped with MAP_HUGETLB, or with transparent huge page advice when no huge
+// pages are reserved, or from the heap when neither works.
+pub(crate) struct BufferPool {
+    page_size: usize,
+    frames_per_chunk: usize,
+    chunks: Vec<Chunk>,
+    frames: u32,
+    free: Vec<Frame>,
+    released: Vec<Frame>,
+    // Bytes of frame memory currently allocated, in use or idle
+    allocated: usize,
+    hugepages: bool,
+    max_buffers: usize,
+    secure: bool,
+    hits: Counter,
+    misses: Counter,
+}
+
+impl BufferPool {
+    pub fn new(page_size: usize, max_buffers: usize, secure: bool, hugepages: bool) -> Self {
+        Self {
+            page_size,
+            frames_per_chunk: if hugepages {
+                std::cmp::max(HUGE_PAGE_SIZE / page_size, 1)
+            } else {
+                1
+            },
+            chunks: Vec::new(),
+            frames: 0,
+            free: Vec::new(),
+            released: Vec::new(),
+            allocated: 0,
+            hugepages,
+            max_buffers,
+            secure,
+            hits: Counter::default(),
+            misses: Counter::default(),
+        }
+    }
+
+    /// A frame with unspecified contents.
+    pub fn take(&mut self) -> Frame {
+        if let Some(frame) = self.free.pop() {
+            self.hits.incr();
+            return frame;
+        }
+
+        self.misses.incr();
+        if let Some(frame) = self.released.pop() {
+            self.chunks[frame.0 as usize] = Chunk::Heap(vec![0; self.page_size].into());
+            self.allocated += self.page_size;
+            return frame;
+        }
+
+        let frame = Frame(self.frames);
+        self.frames += 1;
+        if (frame.0 as usize).is_multiple_of(self.frames_per_chunk) {
+            let chunk = self.allocate_chunk();
+            self.allocated += chunk.bytes().len();
+            self.chunks.push(chunk);
+        }
+        frame
+    }
+
+    /// Return a frame that is no longer in use. Heap frames beyond
+    /// `max_buffers` are released; frames carved from huge pages stay.
+    pub fn put(&mut self, frame: Frame) {
+        if self.secure {
+            self.frame_mut(frame).zeroize();
+        }
+        if self.hugepages || self.free.len() < self.max_buffers {
+            self.free.push(frame);
+        } else {
+            self.chunks[frame.0 as usize] = Chunk::Released;
+            self.allocated -= self.page_size;
+            self.released.push(frame);
+        }
+    }
+
+    pub fn frame(&self, frame: Frame) -> &[u8] {
+        let (chunk, offset) = self.locate(frame);
+        &self.chunks[chunk].bytes()[offset..offset + self.page_size]
+    }
+
+    pub fn frame_mut(&mut self, frame: Frame) -> &mut [u8] {
+        let (chunk, offset) = self.locate(frame);
+        &mut self.chunks[chunk].bytes_mut()[offset..offset + self.page_size]
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let [hits, misses] = snapshot([&self.hits, &self.misses]);
+        PoolStats {
+            buffers: self.free.len(),
+            max_buffers: self.max_buffers,
+            hits,
+            misses,
+        }
+    }
+
+    /// Bytes of frame memory allocated, whether holding pages or idle.
+    pub fn allocated_bytes(&self) -> usize {
+        self.allocated
+    }
+
+    fn locate(&self, frame: Frame) -> (usize, usize) {
+        let index = frame.0 as usize;
+        (
+            index / self.frames_per_chunk,
+            index % self.frames_per_chunk * self.page_size,
+        )
+    }
+
+    fn allocate_chunk(&self) -> Chunk {
+        #[cfg(target_os = "linux")]
+        if self.hugepages {
+            if let Some(mapping) = hugepage::Mapping::new(HUGE_PAGE_SIZE) {
+                return Chunk::Mapped(mapping);
+            }
+        }
+        Chunk::Heap(vec![0; self.frames_per_chunk * self.page_size].into())
+    }
+}
+
+impl Drop for BufferPool {
+    fn drop(&mut self) {
+        if self.secure {
+            for chunk in &mut self.chunks {
+                chunk.bytes_mut().zeroize();
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod hugepage {
+    use super::HUGE_PAGE_SIZE;
+
+    // An anonymous private mapping backed by huge pages where possible
+    pub struct Mapping {
+        ptr: *mut u8,
+        len: usize,
+    }
+
+    // SAFETY: the mapping is owned exclusively and only reached through
+    // `&self`/`&mut self`
+    unsafe impl Send for Mapping {}
+
+    impl Mapping {
+        pub fn new(len: usize) -> Option<Self> {
+            Self::hugetlb(len).or_else(|| Self::transparent(len))
+        }
+
+        fn hugetlb(len: usize) -> Option<Self> {
+            // SAFETY: a fresh anonymous mapping; no existing memory is touched
+            let ptr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_HUGETLB,
+                    -1,
+                    0,
+                )
+            };
+            (ptr != libc::MAP_FAILED).then_some(Self {
+                ptr: ptr.cast(),
+                len,
+            })
+        }
+
+        // Transparent huge pages only back 2MiB-aligned ranges, so map an
+        // extra huge page and trim the mapping to an aligned window
+        fn transparent(len: usize) -> Option<Self> {
+            let mapped_len = len + HUGE_PAGE_SIZE;
+            // SAFETY: a fresh anonymous mapping; no existing memory is touched
+            let ptr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    mapped_len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return None;
+            }
+
+            let start = ptr as usize;
+            let aligned = start.next_multiple_of(HUGE_PAGE_SIZE);
+            let head = aligned - start;
+            let tail = mapped_len - head - len;
+            // SAFETY: both ranges lie inside the mapping created above and
+            // outside the window that is kept
+            unsafe {
+                if head > 0 {
+                    libc::munmap(ptr, head);
+                }
+                if tail > 0 {
+                    libc::munmap((aligned + len) as *mut libc::c_void, tail);
+                }
+                // Advice only; the mapping works without it
+                libc::madvise(aligned as *mut libc::c_void, len, libc::MADV_HUGEPAGE);
+            }
+            Some(Self {
+                ptr: aligned as *mut u8,
+                len,
+            })
+        }
+
+        pub fn bytes(&self) -> &[u8] {
+            // SAFETY: `ptr` points to `len` readable, initialized (zero-filled
+            // on first touch) bytes for as long as the mapping lives
+            unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+        }
+
+        pub fn bytes_mut(&mut self) -> &mut [u8] {
+            // SAFETY: as for `bytes`, and `&mut self` makes the access unique
+            unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            // SAFETY: unmaps exactly the range this mapping owns
+            unsafe {
+                libc::munmap(self.ptr.cast(), self.len);
+            }
+        }
+    }
+}
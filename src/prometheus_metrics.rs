@@ -0,0 +1,58 @@
+use ::prometheus::{Gauge, Histogram, HistogramOpts, IntGauge, Opts, Registry};
+
+use crate::WriteThroughCache;
+
+/// Cache metrics registered with a Prometheus registry. Hand a clone to
+/// `CacheBuilder::prometheus` and the cache keeps them current: the gauges
+/// after every read and write, the flush latency histogram on every sync.
+#[derive(Clone)]
+pub struct PrometheusMetrics {
+    hit_ratio: Gauge,
+    resident_bytes: IntGauge,
+    dirty_pages: IntGauge,
+    flush_seconds: Histogram,
+}
+
+impl PrometheusMetrics {
+    /// Create the metrics, named `<prefix>_hit_ratio` and so on, and register
+    /// them with `registry`.
+    pub fn register(registry: &Registry, prefix: &str) -> ::prometheus::Result<Self> {
+        let metrics = Self {
+            hit_ratio: Gauge::with_opts(Opts::new(
+                format!("{}_hit_ratio", prefix),
+                "Share of page lookups served from memory",
+            ))?,
+            resident_bytes: IntGauge::with_opts(Opts::new(
+                format!("{}_resident_bytes", prefix),
+                "Bytes held for cached pages and their bookkeeping",
+            ))?,
+            dirty_pages: IntGauge::with_opts(Opts::new(
+                format!("{}_dirty_pages", prefix),
+                "Pages written to the cache but not yet to disk; always 0 for a write-through cache",
+            ))?,
+            flush_seconds: Histogram::with_opts(HistogramOpts::new(
+                format!("{}_flush_seconds", prefix),
+                "Latency of syncing the data file",
+            ))?,
+        };
+        registry.register(Box::new(metrics.hit_ratio.clone()))?;
+        registry.register(Box::new(metrics.resident_bytes.clone()))?;
+        registry.register(Box::new(metrics.dirty_pages.clone()))?;
+        registry.register(Box::new(metrics.flush_seconds.clone()))?;
+        Ok(metrics)
+    }
+
+    pub(crate) fn observe_flush(&self, elapsed: std::time::Duration) {
+        self.flush_seconds.observe(elapsed.as_secs_f64());
+    }
+
+    pub(crate) fn update(&self, cache: &WriteThroughCache) {
+        let stats = cache.stats();
+        let lookups = stats.hits + stats.misses;
+        if lookups > 0 {
+            self.hit_ratio.set(stats.hits as f64 / lookups as f64);
+        }
+        self.resident_bytes.set(cache.memory_usage() as i64);
+        self.dirty_pages.set(0);
+    }
+}
@@ -0,0 +1,221 @@
+//! A cache for workloads that almost only read, where reads take no locks.
+//!
+//! Cached pages are kept in a page table that is never changed once
+//! published. Readers load the current table with an atomic pointer read
+//! and copy from its pages. Writes, and reads that miss, lock the
+//! underlying cache, build a new table with the change applied and publish
+//! it in one atomic swap; epoch-based reclamation frees the old table once
+//! no reader can still hold it. Building a table copies the index of cached
+//! pages, though not their contents, so writes cost more as more pages are
+//! cached. In secure mode a page is wiped once the last table holding it is
+//! freed.
+
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crossbeam_epoch::{self as epoch, Atomic, Owned};
+use zeroize::Zeroize;
+
+use crate::{AHashMap, WriteThroughCache};
+
+#[derive(Clone)]
+struct PageTable {
+    pages: AHashMap<u64, Arc<Page>>,
+    file_size: u64,
+}
+
+// A page's bytes, shared by every table holding it
+struct Page {
+    data: Vec<u8>,
+    secure: bool,
+}
+
+struct Writer {
+    cache: WriteThroughCache,
+    // Cached pages oldest first. Readers leave no trace, so pages are
+    // evicted in the order they were loaded.
+    order: VecDeque<u64>,
+}
+
+pub struct ReadMostlyCache {
+    table: Atomic<PageTable>,
+    writer: Mutex<Writer>,
+    page_size: u64,
+    max_pages: usize,
+    secure: bool,
+}
+
+impl ReadMostlyCache {
+    /// Serve reads of `cache`'s file from a published table of up to
+    /// `max_pages` pages.
+    pub fn new(cache: WriteThroughCache, max_pages: usize) -> std::io::Result<Self> {
+        if max_pages == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "A read-mostly cache must hold at least one page",
+            ));
+        }
+        let table = PageTable {
+            pages: AHashMap::default(),
+            file_size: cache.file_size(),
+        };
+        Ok(Self {
+            table: Atomic::new(table),
+            page_size: cache.page_size() as u64,
+            secure: cache.secure,
+            writer: Mutex::new(Writer {
+                cache,
+                order: VecDeque::new(),
+            }),
+            max_pages,
+        })
+    }
+
+    pub fn read(&self, address: u64, size: usize) -> std::io::Result<Vec<u8>> {
+        {
+            let guard = epoch::pin();
+            // SAFETY: the table is only freed after every thread pinned
+            // before it was replaced has unpinned
+            let table = unsafe { self.table.load(Ordering::Acquire, &guard).deref() };
+            if let Some(data) = self.read_table(table, address, size)? {
+                return Ok(data);
+            }
+        }
+
+        let mut writer = self.lock()?;
+        let data = writer.cache.read(address, size)?;
+        self.publish(&mut writer, address, address + size as u64, false)?;
+        Ok(data)
+    }
+
+    pub fn write(&self, address: u64, data: &[u8]) -> std::io::Result<()> {
+        let mut writer = self.lock()?;
+        writer.cache.write(address, data)?;
+        self.publish(&mut writer, address, address + data.len() as u64, true)
+    }
+
+    pub fn file_size(&self) -> u64 {
+        let guard = epoch::pin();
+        // SAFETY: as in `read`
+        unsafe { self.table.load(Ordering::Acquire, &guard).deref() }.file_size
+    }
+
+    /// Pages in the published table.
+    pub fn cached_pages(&self) -> usize {
+        let guard = epoch::pin();
+        // SAFETY: as in `read`
+        unsafe { self.table.load(Ordering::Acquire, &guard).deref() }
+            .pages
+            .len()
+    }
+
+    // The range's bytes if every page of it is in `table`
+    fn read_table(
+        &self,
+        table: &PageTable,
+        address: u64,
+        size: usize,
+    ) -> std::io::Result<Option<Vec<u8>>> {
+        let end = address.saturating_add(size as u64);
+        if end > table.file_size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Range {}..{} is past the end of the file, {}",
+                    address, end, table.file_size
+                ),
+            ));
+        }
+        let mut data = Vec::with_capacity(size);
+        for page_id in address / self.page_size..end.div_ceil(self.page_size) {
+            let Some(page) = table.pages.get(&page_id) else {
+                return Ok(None);
+            };
+            let base = page_id * self.page_size;
+            let start = (address.max(base) - base) as usize;
+            let stop = (end.min(base + self.page_size) - base) as usize;
+            data.extend_from_slice(&page.data[start..stop]);
+        }
+        Ok(Some(data))
+    }
+
+    // Publish a table with the pages covering address..end loaded from the
+    // cache. Pages already in the table are reloaded only if `changed`.
+    fn publish(
+        &self,
+        writer: &mut Writer,
+        address: u64,
+        end: u64,
+        changed: bool,
+    ) -> std::io::Result<()> {
+        let guard = epoch::pin();
+        let current = self.table.load(Ordering::Acquire, &guard);
+        // SAFETY: as in `read`
+        let mut table = unsafe { current.deref() }.clone();
+        let file_size = writer.cache.file_size();
+        if file_size != table.file_size {
+            // The old last page was cut short by the old end of the file
+            let last = table.file_size / self.page_size;
+            if table.pages.remove(&last).is_some() {
+                writer.order.retain(|&page_id| page_id != last);
+            }
+            table.file_size = file_size;
+        }
+
+        for page_id in address / self.page_size..end.div_ceil(self.page_size) {
+            let present = table.pages.contains_key(&page_id);
+            if present && !changed {
+                continue;
+            }
+            let base = page_id * self.page_size;
+            let len = self.page_size.min(file_size - base) as usize;
+            let page = Page {
+                data: writer.cache.read(base, len)?,
+                secure: self.secure,
+            };
+            table.pages.insert(page_id, Arc::new(page));
+            if !present {
+                writer.order.push_back(page_id);
+            }
+        }
+        while writer.order.len() > self.max_pages {
+            let oldest = writer.order.pop_front().unwrap();
+            table.pages.remove(&oldest);
+        }
+
+        let old = self.table.swap(Owned::new(table), Ordering::AcqRel, &guard);
+        // SAFETY: `old` is no longer reachable from `self.table`, and is
+        // only freed once readers pinned before the swap are done
+        unsafe { guard.defer_destroy(old) };
+        Ok(())
+    }
+
+    fn lock(&self) -> std::io::Result<MutexGuard<'_, Writer>> {
+        self.writer
+            .lock()
+            .map_err(|_| Error::other("Cache lock poisoned by a panic"))
+    }
+}
+
+impl Drop for ReadMostlyCache {
+    fn drop(&mut self) {
+        // SAFETY: `&mut self` means no reader holds the table
+        unsafe {
+            drop(
+                self.table
+                    .load(Ordering::Relaxed, epoch::unprotected())
+                    .into_owned(),
+            );
+        }
+    }
+}
+
+impl Drop for Page {
+    fn drop(&mut self) {
+        if self.secure {
+            self.data.zeroize();
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::BuildHasherDefault;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use zeroize::Zeroize;
+
+use crate::pio::read_append_at;
+
+struct Request {
+    seq: u64,
+    first_page: u64,
+    pages: u64,
+}
+
+struct Fetched {
+    seq: u64,
+    first_page: u64,
+    pages: std::io::Result<Vec<Vec<u8>>>,
+}
+
+/// Pages fetched by one request that are still current.
+pub(crate) struct Completed {
+    pub seq: u64,
+    pub pages: std::io::Result<Vec<(u64, Vec<u8>)>>,
+}
+
+// Reads page spans on a pool of helper threads. Each request gets a sequence
+// number that is recorded for every page it covers; a write to a page forgets
+// it, so data fetched before the write is never installed.
+pub(crate) struct Fetcher {
+    requests: Option<Sender<Request>>,
+    results: Receiver<Fetched>,
+    workers: Vec<JoinHandle<()>>,
+    pending: HashMap<u64, u64, BuildHasherDefault<ahash::AHasher>>,
+    seq: u64,
+    in_flight: usize,
+    secure: bool,
+}
+
+impl Fetcher {
+    pub fn new(
+        file: File,
+        page_size: usize,
+        workers: usize,
+        secure: bool,
+    ) -> std::io::Result<Self> {
+        let (requests, worker_requests) = channel::<Request>();
+        let (worker_results, results) = channel();
+        let worker_requests = Arc::new(Mutex::new(worker_requests));
+        let file = Arc::new(file);
+
+        let workers = (0..workers.max(1))
+            .map(|index| {
+                let requests = Arc::clone(&worker_requests);
+                let results = worker_results.clone();
+                let file = Arc::clone(&file);
+                std::thread::Builder::new()
+                    .name(format!("wt_cache-fetch-{}", index))
+                    .spawn(move || loop {
+                        let request = match requests.lock().unwrap().recv() {
+                            Ok(request) => request,
+                            Err(_) => break,
+                        };
+                        let fetched = Fetched {
+                            seq: request.seq,
+                            first_page: request.first_page,
+                            pages: fetch(&file, page_size, &request, secure),
+                        };
+                        if results.send(fetched).is_err() {
+                            break;
+                        }
+                    })
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            requests: Some(requests),
+            results,
+            workers,
+            pending: HashMap::default(),
+            seq: 0,
+            in_flight: 0,
+            secure,
+        })
+    }
+
+    /// Queue a fetch of `pages` pages starting at `first_page` and return its
+    /// sequence number.
+    pub fn request(&mut self, first_page: u64, pages: u64) -> u64 {
+        self.seq += 1;
+        for page_id in first_page..first_page + pages {
+            self.pending.insert(page_id, self.seq);
+        }
+
+        let request = Request {
+            seq: self.seq,
+            first_page,
+            pages,
+        };
+        if let Some(requests) = &self.requests {
+            if requests.send(request).is_ok() {
+                self.in_flight += 1;
+            }
+        }
+        self.seq
+    }
+
+    /// The next finished request. With `wait` this blocks while requests are
+    /// still in flight; otherwise it only returns what is already done.
+    pub fn completed(&mut self, wait: bool) -> Option<Completed> {
+        if self.in_flight == 0 {
+            return None;
+        }
+        let fetched = if wait {
+            self.results.recv().ok()?
+        } else {
+            self.results.try_recv().ok()?
+        };
+        self.in_flight -= 1;
+
+        let pages = fetched.pages.map(|pages| {
+            let mut current = Vec::with_capacity(pages.len());
+            for (page_id, mut data) in (fetched.first_page..).zip(pages) {
+                if self.pending.get(&page_id) == Some(&fetched.seq) {
+                    self.pending.remove(&page_id);
+                    current.push((page_id, data));
+                } else if self.secure {
+                    data.zeroize();
+                }
+            }
+            current
+        });
+        Some(Completed {
+            seq: fetched.seq,
+            pages,
+        })
+    }
+
+    /// Drop any in-flight fetch of `page_id`, which is about to change.
+    pub fn forget(&mut self, page_id: u64) {
+        self.pending.remove(&page_id);
+    }
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "trace",
+        name = "disk_read",
+        skip_all,
+        fields(first_page = request.first_page, pages = request.pages)
+    )
+)]
+fn fetch(
+    file: &File,
+    page_size: usize,
+    request: &Request,
+    secure: bool,
+) -> std::io::Result<Vec<Vec<u8>>> {
+    let mut span = Vec::new();
+    let len = request.pages as usize * page_size;
+    let result = read_append_at(file, &mut span, len, request.first_page * page_size as u64)
+        .map(|()| span.chunks_exact(page_size).map(<[u8]>::to_vec).collect());
+    if secure {
+        span.zeroize();
+    }
+    result
+}
+
+impl Drop for Fetcher {
+    fn drop(&mut self) {
+        // Closing the request channel stops each worker once it is idle
+        self.requests = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        if self.secure {
+            while let Ok(fetched) = self.results.try_recv() {
+                for mut data in fetched.pages.into_iter().flatten() {
+                    data.zeroize();
+                }
+            }
+        }
+    }
+}
+
+// Streams tracked at once, so interleaved scans each keep their pattern
+const MAX_STREAMS: usize = 8;
+// Largest gap between pages, in pages, still taken as a stride
+const MAX_STRIDE: i64 = 64;
+
+struct Stream {
+    last_page: u64,
+    stride: i64,
+    // Consecutive accesses that followed `stride`
+    matches: u32,
+    depth: u64,
+    // Accesses past `last_page` whose pages have already been requested
+    ahead: u64,
+    last_used: u64,
+}
+
+impl Stream {
+    fn new(last_page: u64, stride: i64, matches: u32, last_used: u64) -> Self {
+        Self {
+            last_page,
+            stride,
+            matches,
+            depth: 0,
+            ahead: 0,
+            last_used,
+        }
+    }
+}
+
+// Detects sequential and strided access streams in recent history and decides
+// how far to read ahead of each. A stream's depth doubles while it keeps its
+// stride; accesses matching no stream start a new one in place of the least
+// recently used, so random access never triggers readahead.
+pub(crate) struct Readahead {
+    max_depth: u64,
+    streams: Vec<Stream>,
+    clock: u64,
+}
+
+impl Readahead {
+    pub fn new(max_depth: u64) -> Self {
+        Self {
+            max_depth,
+            streams: Vec::with_capacity(MAX_STREAMS),
+            clock: 0,
+        }
+    }
+
+    /// Record an access to `page_id` and return the runs of pages, as
+    /// `(first_page, pages)`, to fetch next.
+    pub fn access(&mut self, page_id: u64, file_pages: u64) -> Vec<(u64, u64)> {
+        self.clock += 1;
+        let delta = |stream: &Stream| page_id as i64 - stream.last_page as i64;
+
+        let index = match self
+            .streams
+            .iter()
+            .position(|stream| stream.stride != 0 && delta(stream) == stream.stride)
+        {
+            Some(index) => {
+                let stream = &mut self.streams[index];
+                stream.matches += 1;
+                stream.ahead = stream.ahead.saturating_sub(1);
+                index
+            }
+            None => {
+                if let Some(stream) = self.streams.iter_mut().find(|stream| delta(stream) == 0) {
+                    stream.last_used = self.clock;
+                    return Vec::new();
+                }
+                // The gap from the nearest stream is taken as a candidate
+                // stride for a new stream; the old one keeps its own pattern
+                let stride = self
+                    .streams
+                    .iter()
+                    .map(delta)
+                    .filter(|delta| delta.abs() <= MAX_STRIDE)
+                    .min_by_key(|delta| delta.abs())
+                    .unwrap_or(0);
+                let stream = Stream::new(page_id, stride, (stride != 0) as u32, self.clock);
+                let index = if self.streams.len() < MAX_STREAMS {
+                    self.streams.push(stream);
+                    self.streams.len() - 1
+                } else {
+                    let oldest = (0..self.streams.len())
+                        .min_by_key(|&index| self.streams[index].last_used)
+                        .unwrap();
+                    self.streams[oldest] = stream;
+                    oldest
+                };
+                if stride == 0 {
+                    return Vec::new();
+                }
+                index
+            }
+        };
+
+        let max_depth = self.max_depth;
+        let stream = &mut self.streams[index];
+        stream.last_page = page_id;
+        stream.last_used = self.clock;
+
+        // Sequential streams are trusted at once; other strides must repeat
+        // before they are acted on
+        if stream.stride != 1 && stream.matches < 2 {
+            return Vec::new();
+        }
+        // Wait until half of the previous window has been consumed
+        if stream.ahead > stream.depth / 2 {
+            return Vec::new();
+        }
+
+        stream.depth = (stream.depth * 2).max(2).min(max_depth);
+        let mut runs: Vec<(u64, u64)> = Vec::new();
+        for step in stream.ahead + 1..=stream.depth {
+            let target = page_id as i64 + step as i64 * stream.stride;
+            if target < 0 || target as u64 >= file_pages {
+                break;
+            }
+            let target = target as u64;
+            match runs.last_mut() {
+                Some((first, pages)) if *first + *pages == target => *pages += 1,
+                _ => runs.push((target, 1)),
+            }
+        }
+        stream.ahead = stream.depth;
+        runs
+    }
+}
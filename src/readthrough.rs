@@ -0,0 +1,174 @@
+//! A local file kept as a persistent cache of an `Origin`.
+//!
+//! The local file mirrors the origin's addresses. Reads are served from
+//! the local file's cache in memory, then from the local file itself, and
+//! only pages never fetched before go to the origin, being written to the
+//! local file on the way. Which pages have been fetched is kept in a
+//! bitmap in a side file, along with the origin's size and version, so the
+//! local copy survives restarts and is dropped when the origin changes.
+
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::bitmap::Bitmap;
+use crate::{Origin, WriteThroughCache};
+
+const MAGIC: [u8; 8] = *b"WTRDTHRU";
+// magic | origin size u64 | version length u32 | version | crc32, in page 0
+// of the side file; the bitmap starts at page 1
+const HEADER_LEN: usize = 8 + 8 + 4;
+const SIDE_PAGE_SIZE: usize = 4096;
+const MAX_VERSION_LEN: usize = SIDE_PAGE_SIZE - HEADER_LEN - 4;
+
+pub struct ReadThroughCache {
+    origin: Box<dyn Origin>,
+    origin_size: u64,
+    local: WriteThroughCache,
+    side: WriteThroughCache,
+    fetched: Bitmap,
+    origin_reads: u64,
+}
+
+impl ReadThroughCache {
+    /// Side file holding the fetched pages of the local file at
+    /// `file_path`.
+    pub fn path_for(file_path: &Path) -> PathBuf {
+        let mut path = file_path.as_os_str().to_owned();
+        path.push(".fetched");
+        PathBuf::from(path)
+    }
+
+    /// Cache `origin` in `local`'s file. Pages fetched by an earlier run
+    /// are used if the origin still has the same size and version.
+    pub fn open(mut origin: Box<dyn Origin>, local: WriteThroughCache) -> std::io::Result<Self> {
+        let mut side = WriteThroughCache::builder(Self::path_for(&local.file_path))
+            .page_size(SIDE_PAGE_SIZE)
+            .open()?;
+        let origin_size = origin.size()?;
+        let version = origin.version()?.unwrap_or_default();
+        let fetched = match load_header(&mut side)? {
+            Some(stored) if stored == (origin_size, version.clone()) => Bitmap::open(&mut side, 1)?,
+            _ => reset(&mut side, origin_size, local.page_size(), &version)?,
+        };
+        Ok(Self {
+            origin,
+            origin_size,
+            local,
+            side,
+            fetched,
+            origin_reads: 0,
+        })
+    }
+
+    pub fn read(&mut self, address: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        let end = address.saturating_add(len as u64);
+        if end > self.origin_size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Range {}..{} is past the end of the origin, {}",
+                    address, end, self.origin_size
+                ),
+            ));
+        }
+        if len > 0 {
+            let page_size = self.local.page_size() as u64;
+            for page_id in address / page_size..end.div_ceil(page_size) {
+                self.fetch(page_id)?;
+            }
+        }
+        self.local.read(address, len)
+    }
+
+    /// Check the origin's size and version again, dropping the local copy
+    /// if either changed. Returns whether it was dropped.
+    pub fn revalidate(&mut self) -> std::io::Result<bool> {
+        let size = self.origin.size()?;
+        let version = self.origin.version()?.unwrap_or_default();
+        if load_header(&mut self.side)? == Some((size, version.clone())) {
+            return Ok(false);
+        }
+        self.origin_size = size;
+        self.fetched = reset(&mut self.side, size, self.local.page_size(), &version)?;
+        Ok(true)
+    }
+
+    /// Size of the origin when last validated.
+    pub fn size(&self) -> u64 {
+        self.origin_size
+    }
+
+    /// Pages read from the origin since opening.
+    pub fn origin_reads(&self) -> u64 {
+        self.origin_reads
+    }
+
+    pub fn into_inner(self) -> WriteThroughCache {
+        self.local
+    }
+
+    fn fetch(&mut self, page_id: u64) -> std::io::Result<()> {
+        if self.fetched.test(&mut self.side, page_id)? {
+            return Ok(());
+        }
+        let page_size = self.local.page_size() as u64;
+        let start = page_id * page_size;
+        let len = std::cmp::min(page_size, self.origin_size - start);
+        let data = self.origin.read_at(start, len as usize)?;
+        self.origin_reads += 1;
+        // The page is marked fetched only once it is in the local file
+        self.local.write(start, &data)?;
+        self.fetched.set(&mut self.side, page_id)
+    }
+}
+
+// Set up `side` with nothing fetched for an origin of `size` bytes at
+// `version`, returning the bitmap of fetched pages
+fn reset(
+    side: &mut WriteThroughCache,
+    size: u64,
+    page_size: usize,
+    version: &[u8],
+) -> std::io::Result<Bitmap> {
+    if version.len() > MAX_VERSION_LEN {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Origin version is longer than {} bytes", MAX_VERSION_LEN),
+        ));
+    }
+    // Invalidate the old header first, so a crash part way through leaves
+    // nothing that looks fetched
+    side.write(0, &[0; HEADER_LEN])?;
+    let fetched = Bitmap::create(side, 1, size.div_ceil(page_size as u64))?;
+
+    let mut header = Vec::with_capacity(HEADER_LEN + version.len() + 4);
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&size.to_le_bytes());
+    header.extend_from_slice(&(version.len() as u32).to_le_bytes());
+    header.extend_from_slice(version);
+    header.extend_from_slice(&crc32fast::hash(&header).to_le_bytes());
+    side.write(0, &header)?;
+    Ok(fetched)
+}
+
+// Origin size and version `side` was set up for, or None if it isn't set up
+fn load_header(side: &mut WriteThroughCache) -> std::io::Result<Option<(u64, Vec<u8>)>> {
+    if side.file_size() < HEADER_LEN as u64 {
+        return Ok(None);
+    }
+    let fixed = side.read(0, HEADER_LEN)?;
+    if fixed[..8] != MAGIC {
+        return Ok(None);
+    }
+    let size = u64::from_le_bytes(fixed[8..16].try_into().unwrap());
+    let version_len = u32::from_le_bytes(fixed[16..20].try_into().unwrap()) as usize;
+    if version_len > MAX_VERSION_LEN {
+        return Ok(None);
+    }
+    let header = side.read(0, HEADER_LEN + version_len + 4)?;
+    let (body, crc) = header.split_at(HEADER_LEN + version_len);
+    if u32::from_le_bytes(crc.try_into().unwrap()) != crc32fast::hash(body) {
+        return Ok(None);
+    }
+    Ok(Some((size, body[HEADER_LEN..].to_vec())))
+}
@@ -0,0 +1,56 @@
+use std::io::{Error, ErrorKind};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::WriteThroughCache;
+
+// body length u32 | crc32 of the body | postcard-encoded body
+const RECORD_HEADER_LEN: usize = 4 + 4;
+
+impl WriteThroughCache {
+    /// Encode `value` with postcard and write it at `address` along with
+    /// its length and checksum, returning the bytes written.
+    pub fn write_serde<T: Serialize>(&mut self, address: u64, value: &T) -> std::io::Result<usize> {
+        let body =
+            postcard::to_allocvec(value).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        let len = u32::try_from(body.len())
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "Record is over 4GiB encoded"))?;
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + body.len());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+        record.extend_from_slice(&body);
+        self.write(address, &record)?;
+        Ok(record.len())
+    }
+
+    /// Read a record written by `write_serde` at `address`, taking up to
+    /// `max_len` bytes in all.
+    pub fn read_serde<T: DeserializeOwned>(
+        &mut self,
+        address: u64,
+        max_len: usize,
+    ) -> std::io::Result<T> {
+        let header = self.read(address, RECORD_HEADER_LEN)?;
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+        if RECORD_HEADER_LEN + len > max_len {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Record of {} bytes is longer than the limit of {}",
+                    RECORD_HEADER_LEN + len,
+                    max_len
+                ),
+            ));
+        }
+        let body = self.read(address + RECORD_HEADER_LEN as u64, len)?;
+        if crc32fast::hash(&body) != crc {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Record checksum mismatch",
+            ));
+        }
+        postcard::from_bytes(&body).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
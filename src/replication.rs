@@ -0,0 +1,261 @@
+//! Streaming a cache's writes to warm standbys over TCP.
+//!
+//! A `Primary` wraps the cache taking writes and listens for followers.
+//! Each follower that connects is first sent a snapshot of the whole file,
+//! then every write made through the primary, tagged with its LSN. A
+//! `Follower` applies what it receives through its own cache, so its file
+//! tracks the primary's. Followers are only taken on by `accept_followers`,
+//! which the caller runs between writes, so writes never wait on a
+//! snapshot. Sends to a follower that stop making progress for longer than
+//! the write timeout drop it.
+//!
+//! Every message is a frame of kind u8 | lsn u64 | address u64 | length u32
+//! | data. A snapshot is a `SNAPSHOT` frame carrying the file size in its
+//! address, page frames for the file's contents, then an `END` frame. No
+//! frame carries more than `MAX_FRAME_DATA` bytes, so a follower never
+//! allocates more than that for a length it is sent.
+
+use std::io::{BufReader, Error, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::{WriteThroughCache, MAX_IO_SIZE};
+
+const SNAPSHOT: u8 = 1;
+const PAGE: u8 = 2;
+const END: u8 = 3;
+const FRAME_HEADER_LEN: usize = 1 + 8 + 8 + 4;
+// The most data one frame carries: a whole write, or a page of a snapshot
+const MAX_FRAME_DATA: usize = MAX_IO_SIZE;
+const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The cache taking writes, sending them on to connected followers.
+pub struct Primary {
+    cache: WriteThroughCache,
+    listener: TcpListener,
+    followers: Vec<TcpStream>,
+    lsn: u64,
+    write_timeout: Duration,
+}
+
+impl Primary {
+    /// Serve `cache` to followers connecting to `addr`.
+    pub fn bind<A: ToSocketAddrs>(cache: WriteThroughCache, addr: A) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        // Followers are picked up between writes rather than waited for
+        listener.set_nonblocking(true)?;
+        let lsn = cache.last_lsn().unwrap_or(0);
+        Ok(Self {
+            cache,
+            listener,
+            followers: Vec::new(),
+            lsn,
+            write_timeout: DEFAULT_WRITE_TIMEOUT,
+        })
+    }
+
+    /// How long a send to a follower may block before the follower is
+    /// dropped, for followers accepted from now on. Defaults to 5 seconds.
+    pub fn set_write_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+        if timeout.is_zero() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Write timeout must be non-zero",
+            ));
+        }
+        self.write_timeout = timeout;
+        Ok(())
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Take on followers waiting to connect, sending each a snapshot.
+    /// Returns how many were added.
+    pub fn accept_followers(&mut self) -> std::io::Result<usize> {
+        let mut added = 0;
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(added),
+                Err(e) => return Err(e),
+            };
+            stream.set_nonblocking(false)?;
+            stream.set_nodelay(true)?;
+            stream.set_write_timeout(Some(self.write_timeout))?;
+            // A follower that can't take its snapshot is left to reconnect
+            if self.send_snapshot(&stream).is_ok() {
+                self.followers.push(stream);
+                added += 1;
+            }
+        }
+    }
+
+    pub fn read(&mut self, address: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        self.cache.read(address, len)
+    }
+
+    /// Write through the primary's cache, then send the write to every
+    /// follower. Followers that can't be sent it are dropped. Writes are
+    /// limited to 4 MiB, the most a frame carries.
+    pub fn write(&mut self, address: u64, data: &[u8]) -> std::io::Result<()> {
+        if data.len() > MAX_FRAME_DATA {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Replicated writes are limited to {} bytes", MAX_FRAME_DATA),
+            ));
+        }
+        self.cache.write(address, data)?;
+        self.lsn = match self.cache.last_lsn() {
+            Some(lsn) => lsn,
+            None => self.lsn + 1,
+        };
+        let frame = encode_frame(PAGE, self.lsn, address, data)?;
+        self.followers
+            .retain_mut(|follower| follower.write_all(&frame).is_ok());
+        Ok(())
+    }
+
+    /// LSN of the last write sent to followers.
+    pub fn lsn(&self) -> u64 {
+        self.lsn
+    }
+
+    /// Followers currently connected.
+    pub fn followers(&self) -> usize {
+        self.followers.len()
+    }
+
+    pub fn cache(&self) -> &WriteThroughCache {
+        &self.cache
+    }
+
+    pub fn into_inner(self) -> WriteThroughCache {
+        self.cache
+    }
+
+    fn send_snapshot(&mut self, mut stream: &TcpStream) -> std::io::Result<()> {
+        let file_size = self.cache.file_size();
+        stream.write_all(&encode_frame(SNAPSHOT, self.lsn, file_size, &[])?)?;
+        let page_size = self.cache.page_size() as u64;
+        for page_id in 0..file_size.div_ceil(page_size) {
+            let address = page_id * page_size;
+            let len = std::cmp::min(page_size, file_size - address);
+            let page = self.cache.read_range(address, len as usize)?;
+            stream.write_all(&encode_frame(PAGE, self.lsn, address, &page)?)?;
+        }
+        stream.write_all(&encode_frame(END, self.lsn, 0, &[])?)
+    }
+}
+
+/// A standby applying a primary's writes to its own cache.
+pub struct Follower {
+    cache: WriteThroughCache,
+    stream: BufReader<TcpStream>,
+    lsn: u64,
+    in_sync: bool,
+}
+
+impl Follower {
+    /// Follow the primary at `addr` with `cache`, whose file is brought up
+    /// to date by the snapshot the primary sends first. The file must be
+    /// no larger than the primary's.
+    pub fn connect<A: ToSocketAddrs>(cache: WriteThroughCache, addr: A) -> std::io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            cache,
+            stream: BufReader::new(stream),
+            lsn: 0,
+            in_sync: false,
+        })
+    }
+
+    /// Wait for the next message from the primary and apply it. Returns
+    /// false once the primary has closed the connection.
+    pub fn apply_next(&mut self) -> std::io::Result<bool> {
+        let mut header = [0; FRAME_HEADER_LEN];
+        match self.stream.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+        }
+        let kind = header[0];
+        let lsn = u64::from_le_bytes(header[1..9].try_into().unwrap());
+        let address = u64::from_le_bytes(header[9..17].try_into().unwrap());
+        let len = u32::from_le_bytes(header[17..21].try_into().unwrap()) as usize;
+        // Checked before allocating, as the length comes off the wire
+        if len > MAX_FRAME_DATA {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Replication frame of {} bytes is too large", len),
+            ));
+        }
+        let mut data = vec![0; len];
+        self.stream.read_exact(&mut data)?;
+
+        match kind {
+            SNAPSHOT => {
+                if self.cache.file_size() > address {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "Follower file is {} bytes, larger than the primary's {}",
+                            self.cache.file_size(),
+                            address
+                        ),
+                    ));
+                }
+                self.in_sync = false;
+            }
+            PAGE => self.cache.write(address, &data)?,
+            END => self.in_sync = true,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Invalid replication frame kind {}", kind),
+                ))
+            }
+        }
+        self.lsn = lsn;
+        Ok(true)
+    }
+
+    /// Apply messages until the primary closes the connection.
+    pub fn run(&mut self) -> std::io::Result<()> {
+        while self.apply_next()? {}
+        Ok(())
+    }
+
+    /// LSN of the primary's last write applied here.
+    pub fn lsn(&self) -> u64 {
+        self.lsn
+    }
+
+    /// Whether the snapshot has been applied, so the file matches the
+    /// primary's as of `lsn`.
+    pub fn in_sync(&self) -> bool {
+        self.in_sync
+    }
+
+    pub fn read(&mut self, address: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        self.cache.read(address, len)
+    }
+
+    pub fn into_inner(self) -> WriteThroughCache {
+        self.cache
+    }
+}
+
+fn encode_frame(kind: u8, lsn: u64, address: u64, data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let len = u32::try_from(data.len())
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "Frame data is too large"))?;
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + data.len());
+    frame.push(kind);
+    frame.extend_from_slice(&lsn.to_le_bytes());
+    frame.extend_from_slice(&address.to_le_bytes());
+    frame.extend_from_slice(&len.to_le_bytes());
+    frame.extend_from_slice(data);
+    Ok(frame)
+}
@@ -0,0 +1,176 @@
+//! A fixed-size circular byte log kept in a cache-managed file.
+//!
+//! Data is addressed by logical position, which only grows. Position `p`
+//! is stored at `p % capacity` past the header page, so once the log is
+//! full each append overwrites the oldest data. The range still held, from
+//! `tail` to `head`, is persisted in the header page.
+
+use std::io::{Error, ErrorKind};
+
+use crate::WriteThroughCache;
+
+const MAGIC: [u8; 8] = *b"WTRING\0\0";
+// magic | capacity u64 | head u64 | tail u64 | crc32
+const HEADER_LEN: usize = 8 + 8 + 8 + 8 + 4;
+
+pub struct RingCache {
+    cache: WriteThroughCache,
+    capacity: u64,
+    head: u64,
+    tail: u64,
+}
+
+impl RingCache {
+    /// Open the ring in `cache`'s file, creating it with room for
+    /// `capacity` bytes if the file is empty. An existing ring must have
+    /// been created with the same capacity.
+    pub fn open(mut cache: WriteThroughCache, capacity: u64) -> std::io::Result<Self> {
+        if capacity == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Ring capacity must be non-zero",
+            ));
+        }
+        if cache.file_size() == 0 {
+            let mut ring = Self {
+                cache,
+                capacity,
+                head: 0,
+                tail: 0,
+            };
+            ring.store_header(0, 0)?;
+            return Ok(ring);
+        }
+
+        let header = cache.read(0, HEADER_LEN)?;
+        let (body, crc) = header.split_at(HEADER_LEN - 4);
+        if body[..8] != MAGIC
+            || u32::from_le_bytes(crc.try_into().unwrap()) != crc32fast::hash(body)
+        {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid ring header"));
+        }
+        let field =
+            |index: usize| u64::from_le_bytes(body[8 * index..8 * (index + 1)].try_into().unwrap());
+        let (stored_capacity, head, tail) = (field(1), field(2), field(3));
+        if stored_capacity != capacity {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Ring was created with capacity {}", stored_capacity),
+            ));
+        }
+        Ok(Self {
+            cache,
+            capacity,
+            head,
+            tail,
+        })
+    }
+
+    /// Append `data`, overwriting the oldest data if the ring is full, and
+    /// return the position it was written at.
+    pub fn append(&mut self, data: &[u8]) -> std::io::Result<u64> {
+        let len = data.len() as u64;
+        if len > self.capacity {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Data is larger than the ring",
+            ));
+        }
+
+        let position = self.head;
+        let head = position + len;
+        // Give up the space being overwritten before writing over it, so a
+        // crash in between never leaves the header claiming torn data
+        let tail = std::cmp::max(self.tail, head.saturating_sub(self.capacity));
+        if tail != self.tail {
+            self.store_header(self.head, tail)?;
+        }
+        let (first, second) = self.split(position, len);
+        self.cache
+            .write(self.offset(position), &data[..first as usize])?;
+        if second > 0 {
+            self.cache
+                .write(self.offset(position + first), &data[first as usize..])?;
+        }
+        self.store_header(head, tail)?;
+        Ok(position)
+    }
+
+    /// Read `len` bytes from `position`, which must still be held.
+    pub fn read(&mut self, position: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        let len = len as u64;
+        if position < self.tail || position.saturating_add(len) > self.head {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Range {}..{} is outside the data held, {}..{}",
+                    position,
+                    position.saturating_add(len),
+                    self.tail,
+                    self.head
+                ),
+            ));
+        }
+        let (first, second) = self.split(position, len);
+        let mut data = self.cache.read(self.offset(position), first as usize)?;
+        if second > 0 {
+            data.extend(
+                self.cache
+                    .read(self.offset(position + first), second as usize)?,
+            );
+        }
+        Ok(data)
+    }
+
+    /// Position the next append is written at.
+    pub fn head(&self) -> u64 {
+        self.head
+    }
+
+    /// Position of the oldest data still held.
+    pub fn tail(&self) -> u64 {
+        self.tail
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Bytes held, at most the capacity.
+    pub fn len(&self) -> u64 {
+        self.head - self.tail
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head == self.tail
+    }
+
+    pub fn into_inner(self) -> WriteThroughCache {
+        self.cache
+    }
+
+    // File offset of `position`
+    fn offset(&self, position: u64) -> u64 {
+        self.cache.page_size() as u64 + position % self.capacity
+    }
+
+    // Split `len` bytes from `position` into the parts before and after the
+    // end of the file
+    fn split(&self, position: u64, len: u64) -> (u64, u64) {
+        let first = std::cmp::min(len, self.capacity - position % self.capacity);
+        (first, len - first)
+    }
+
+    fn store_header(&mut self, head: u64, tail: u64) -> std::io::Result<()> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&self.capacity.to_le_bytes());
+        header.extend_from_slice(&head.to_le_bytes());
+        header.extend_from_slice(&tail.to_le_bytes());
+        header.extend_from_slice(&crc32fast::hash(&header).to_le_bytes());
+        self.cache.write(0, &header)?;
+        self.head = head;
+        self.tail = tail;
+        Ok(())
+    }
+}
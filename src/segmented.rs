@@ -0,0 +1,238 @@
+//! One address space split across segment files of a fixed size.
+//!
+//! Address `a` lives in segment `a / segment_size`, stored in the file
+//! named after the base path with the segment number appended. Segments
+//! are created when first written and can be dropped as a whole, so old
+//! data is retired by deleting files. The segments share one memory budget
+//! through a `CacheManager`. The segment size is recorded in a manifest
+//! next to them, so they are never read back with a different one.
+
+use std::collections::BTreeSet;
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::audit::AuditLog;
+use crate::compression::CompressedStore;
+use crate::header::FileHeader;
+use crate::versions::PageVersions;
+use crate::CacheManager;
+
+const MAGIC: [u8; 8] = *b"WTSEGMNT";
+// magic | segment size u64 | crc32
+const MANIFEST_LEN: usize = 8 + 8 + 4;
+
+pub struct SegmentedCache {
+    manager: CacheManager,
+    base: PathBuf,
+    segment_size: u64,
+    segments: BTreeSet<u64>,
+}
+
+impl SegmentedCache {
+    /// Open the segments of `base` found on disk. `segment_size` must be
+    /// the one recorded in the manifest, which is written by the first
+    /// open; segments that predate the manifest are taken to have it.
+    pub fn open<P: Into<PathBuf>>(
+        base: P,
+        segment_size: u64,
+        budget: usize,
+    ) -> std::io::Result<Self> {
+        if segment_size == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Segment size must be non-zero",
+            ));
+        }
+        let base = base.into();
+        let manifest_path = Self::manifest_path_for(&base);
+        match std::fs::read(&manifest_path) {
+            Ok(manifest) => {
+                let recorded = decode_manifest(&manifest).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidData, "Segment manifest is corrupt")
+                })?;
+                if recorded != segment_size {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "Segments were written with segment size {}, not {}",
+                            recorded, segment_size
+                        ),
+                    ));
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                write_manifest(&manifest_path, segment_size)?;
+            }
+            Err(e) => return Err(e),
+        }
+        let manager = CacheManager::new(budget)?;
+        let mut segments = BTreeSet::new();
+        let (Some(dir), Some(name)) = (base.parent(), base.file_name()) else {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Base path must name a file",
+            ));
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let prefix = format!("{}.", name.to_string_lossy());
+        for entry in std::fs::read_dir(dir)? {
+            let file_name = entry?.file_name();
+            let Some(index) = file_name
+                .to_str()
+                .and_then(|file_name| file_name.strip_prefix(&prefix))
+                .filter(|suffix| suffix.len() == 6 || !suffix.starts_with('0'))
+                .and_then(|suffix| suffix.parse().ok())
+            else {
+                continue;
+            };
+            segments.insert(index);
+        }
+        Ok(Self {
+            manager,
+            base,
+            segment_size,
+            segments,
+        })
+    }
+
+    /// Read `len` bytes from `address`. Every segment in the range must
+    /// exist.
+    pub fn read(&mut self, address: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(len);
+        for (index, offset, part) in self.split(address, len as u64) {
+            if !self.segments.contains(&index) {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!(
+                        "Address {} is in segment {}, which doesn't exist",
+                        index * self.segment_size + offset,
+                        index
+                    ),
+                ));
+            }
+            let path = self.segment_path(index);
+            data.extend(self.manager.cache(path)?.read(offset, part as usize)?);
+        }
+        Ok(data)
+    }
+
+    /// Write `data` at `address`, creating segments as needed.
+    pub fn write(&mut self, address: u64, data: &[u8]) -> std::io::Result<()> {
+        let mut written = 0;
+        for (index, offset, part) in self.split(address, data.len() as u64) {
+            let path = self.segment_path(index);
+            let part = &data[written..written + part as usize];
+            self.manager.cache(path)?.write(offset, part)?;
+            self.segments.insert(index);
+            written += part.len();
+        }
+        Ok(())
+    }
+
+    /// Delete segment `index` and its sidecar files, returning whether it
+    /// existed.
+    pub fn drop_segment(&mut self, index: u64) -> std::io::Result<bool> {
+        if !self.segments.remove(&index) {
+            return Ok(false);
+        }
+        let path = self.segment_path(index);
+        self.manager.close(&path);
+        std::fs::remove_file(&path)?;
+        let sidecars = [
+            FileHeader::path_for(&path),
+            CompressedStore::path_for(&path),
+            CompressedStore::dictionary_path_for(&path),
+            PageVersions::path_for(&path),
+            AuditLog::path_for(&path),
+        ];
+        for sidecar in sidecars {
+            match std::fs::remove_file(sidecar) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(true)
+    }
+
+    /// Delete every segment that lies entirely below `address`, returning
+    /// how many there were.
+    pub fn drop_before(&mut self, address: u64) -> std::io::Result<usize> {
+        let doomed: Vec<u64> = self
+            .segments
+            .range(..address / self.segment_size)
+            .copied()
+            .collect();
+        for &index in &doomed {
+            self.drop_segment(index)?;
+        }
+        Ok(doomed.len())
+    }
+
+    /// Numbers of the segments that exist, in order.
+    pub fn segments(&self) -> impl Iterator<Item = u64> + '_ {
+        self.segments.iter().copied()
+    }
+
+    pub fn segment_size(&self) -> u64 {
+        self.segment_size
+    }
+
+    /// Manifest recording the segment size of the segments of `base`.
+    pub fn manifest_path_for(base: &Path) -> PathBuf {
+        let mut path = base.as_os_str().to_owned();
+        path.push(".manifest");
+        PathBuf::from(path)
+    }
+
+    /// File holding segment `index`.
+    pub fn segment_path(&self, index: u64) -> PathBuf {
+        let mut path = self.base.as_os_str().to_owned();
+        path.push(format!(".{:06}", index));
+        PathBuf::from(path)
+    }
+
+    /// The manager holding the segments' caches, for their stats.
+    pub fn manager(&self) -> &CacheManager {
+        &self.manager
+    }
+
+    // Split `len` bytes from `address` into (segment, offset, len) parts
+    fn split(&self, address: u64, len: u64) -> Vec<(u64, u64, u64)> {
+        let mut parts = Vec::new();
+        let mut address = address;
+        let end = address + len;
+        while address < end {
+            let offset = address % self.segment_size;
+            let part = std::cmp::min(end - address, self.segment_size - offset);
+            parts.push((address / self.segment_size, offset, part));
+            address += part;
+        }
+        parts
+    }
+}
+
+fn write_manifest(path: &Path, segment_size: u64) -> std::io::Result<()> {
+    let mut manifest = Vec::with_capacity(MANIFEST_LEN);
+    manifest.extend_from_slice(&MAGIC);
+    manifest.extend_from_slice(&segment_size.to_le_bytes());
+    manifest.extend_from_slice(&crc32fast::hash(&manifest).to_le_bytes());
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(&manifest)?;
+    file.sync_all()
+}
+
+// Segment size recorded in `manifest`, or None if it isn't a valid one
+fn decode_manifest(manifest: &[u8]) -> Option<u64> {
+    if manifest.len() != MANIFEST_LEN || manifest[..8] != MAGIC {
+        return None;
+    }
+    let (body, crc) = manifest.split_at(MANIFEST_LEN - 4);
+    if u32::from_le_bytes(crc.try_into().unwrap()) != crc32fast::hash(body) {
+        return None;
+    }
+    Some(u64::from_le_bytes(body[8..16].try_into().unwrap()))
+}
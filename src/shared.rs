@@ -0,0 +1,58 @@
+use std::io::Error;
+
+use crate::sync::{Mutex, MutexGuard};
+use crate::{CacheStats, WriteThroughCache};
+
+/// A cache that can be shared between threads, usually in an `Arc`.
+///
+/// Each call holds a lock on the cache for its whole length, so operations
+/// are applied one at a time in the order they take the lock. Two writes
+/// to overlapping ranges never mix: a read sees the overlap as entirely one
+/// or entirely the other, whichever came last. Use `lock` to make several
+/// operations atomic together.
+pub struct SyncCache {
+    cache: Mutex<WriteThroughCache>,
+}
+
+impl SyncCache {
+    pub fn new(cache: WriteThroughCache) -> Self {
+        Self {
+            cache: Mutex::new(cache),
+        }
+    }
+
+    pub fn read(&self, address: u64, size: usize) -> std::io::Result<Vec<u8>> {
+        self.lock()?.read(address, size)
+    }
+
+    pub fn write(&self, address: u64, data: &[u8]) -> std::io::Result<()> {
+        self.lock()?.write(address, data)
+    }
+
+    pub fn file_size(&self) -> std::io::Result<u64> {
+        Ok(self.lock()?.file_size())
+    }
+
+    pub fn stats(&self) -> std::io::Result<CacheStats> {
+        Ok(self.lock()?.stats())
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        self.lock()?.flush()
+    }
+
+    /// Exclusive access to the cache until the guard is dropped. Fails if
+    /// a thread panicked while holding it, as the cache may have been left
+    /// part way through an operation.
+    pub fn lock(&self) -> std::io::Result<MutexGuard<'_, WriteThroughCache>> {
+        self.cache
+            .lock()
+            .map_err(|_| Error::other("Cache lock poisoned by a panic"))
+    }
+
+    pub fn into_inner(self) -> std::io::Result<WriteThroughCache> {
+        self.cache
+            .into_inner()
+            .map_err(|_| Error::other("Cache lock poisoned by a panic"))
+    }
+}
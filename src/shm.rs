@@ -0,0 +1,416 @@
+//! Experimental: a page cache in shared memory, used by several processes
+//! at once.
+//!
+//! The cache is a segment file, normally under /dev/shm, mapped by every
+//! process using the data file. Pages are cached in sets of `WAYS` slots
+//! picked by page id, with second-chance eviction within a set. An
+//! advisory lock on the segment file orders processes: reads served
+//! entirely from the segment share the lock, while misses and writes take
+//! it exclusively, so a page is only loaded or changed while no other
+//! process is reading it. Writes go to the data file and are synced before
+//! the lock is released, keeping cached copies equal to the file.
+//!
+//! Every process must open the file through a `ShmCache` with the same
+//! segment; writes made any other way leave cached pages stale. The segment
+//! records the device and inode of the data file, so it can't be opened
+//! with a different one.
+
+use std::fs::File;
+use std::io::{Error, ErrorKind};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::pio::{read_exact_at, write_all_at};
+
+const MAGIC: [u8; 8] = *b"WTSHMSEG";
+// magic | page size u64 | slots u64 | data file device u64 | data file
+// inode u64, padded to HEADER_LEN
+const HEADER_LEN: usize = 64;
+// page id u64 | referenced u64
+const SLOT_LEN: usize = 16;
+const EMPTY: u64 = u64::MAX;
+/// Slots in each set a page can be cached in.
+pub const WAYS: usize = 8;
+
+pub struct ShmCache {
+    file: File,
+    segment: File,
+    map: Mapping,
+    page_size: usize,
+    slots: usize,
+    data_start: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl ShmCache {
+    /// Open `file_path` with its pages cached in the segment at
+    /// `segment_path`, creating the segment with `slots` pages of
+    /// `page_size` bytes if it doesn't exist. Processes opening an existing
+    /// segment must ask for the same sizes.
+    pub fn open(
+        file_path: &Path,
+        segment_path: &Path,
+        page_size: usize,
+        slots: usize,
+    ) -> std::io::Result<Self> {
+        if page_size == 0 || slots == 0 || !slots.is_multiple_of(WAYS) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Page size must be non-zero and slots a non-zero multiple of {}",
+                    WAYS
+                ),
+            ));
+        }
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(file_path)?;
+        let segment = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(segment_path)?;
+
+        let data_start = (HEADER_LEN + slots * SLOT_LEN).next_multiple_of(4096);
+        let len = data_start + slots * page_size;
+        // Whoever finds the segment empty sets it up, while the others wait
+        segment.lock()?;
+        let result = Self::map_segment(&segment, &file, len, page_size, slots);
+        segment.unlock()?;
+        Ok(Self {
+            file,
+            segment,
+            map: result?,
+            page_size,
+            slots,
+            data_start,
+            hits: 0,
+            misses: 0,
+        })
+    }
+
+    pub fn read(&mut self, address: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        self.segment.lock_shared()?;
+        let result = self.read_resident(address, len);
+        self.segment.unlock()?;
+        if let Some(data) = result? {
+            return Ok(data);
+        }
+
+        self.segment.lock()?;
+        let result = self.read_loading(address, len);
+        self.segment.unlock()?;
+        result
+    }
+
+    /// Write `data` to the file at `address`, updating any cached copies.
+    pub fn write(&mut self, address: u64, data: &[u8]) -> std::io::Result<()> {
+        self.segment.lock()?;
+        let result = self.write_locked(address, data);
+        self.segment.unlock()?;
+        result
+    }
+
+    pub fn file_size(&self) -> std::io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Pages this process found in the segment.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Pages this process loaded into the segment, or read from the file
+    /// for a partial last page.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    fn map_segment(
+        segment: &File,
+        file: &File,
+        len: usize,
+        page_size: usize,
+        slots: usize,
+    ) -> std::io::Result<Mapping> {
+        let existing = segment.metadata()?.len();
+        if existing == 0 {
+            segment.set_len(len as u64)?;
+        } else if existing != len as u64 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Shared memory segment was created with different sizes",
+            ));
+        }
+        let metadata = file.metadata()?;
+        let mut map = Mapping::new(segment, len)?;
+        let bytes = map.slice_mut(0, HEADER_LEN + slots * SLOT_LEN);
+        if existing == 0 {
+            for slot in 0..slots {
+                let at = HEADER_LEN + slot * SLOT_LEN;
+                bytes[at..at + 8].copy_from_slice(&EMPTY.to_le_bytes());
+            }
+            bytes[8..16].copy_from_slice(&(page_size as u64).to_le_bytes());
+            bytes[16..24].copy_from_slice(&(slots as u64).to_le_bytes());
+            bytes[24..32].copy_from_slice(&metadata.dev().to_le_bytes());
+            bytes[32..40].copy_from_slice(&metadata.ino().to_le_bytes());
+            // The magic goes last, marking the segment as set up
+            bytes[..8].copy_from_slice(&MAGIC);
+        } else if bytes[..8] != MAGIC
+            || bytes[8..16] != (page_size as u64).to_le_bytes()
+            || bytes[16..24] != (slots as u64).to_le_bytes()
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Invalid shared memory segment header",
+            ));
+        } else if bytes[24..32] != metadata.dev().to_le_bytes()
+            || bytes[32..40] != metadata.ino().to_le_bytes()
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Shared memory segment caches a different file",
+            ));
+        }
+        Ok(map)
+    }
+
+    // The range's bytes if every page of it is cached. Called under the
+    // shared lock.
+    fn read_resident(&mut self, address: u64, len: usize) -> std::io::Result<Option<Vec<u8>>> {
+        let end = self.check_range(address, len)?;
+        let page_size = self.page_size as u64;
+        let mut slots = Vec::new();
+        for page_id in address / page_size..end.div_ceil(page_size) {
+            match self.find(page_id) {
+                Some(slot) => slots.push(slot),
+                None => return Ok(None),
+            }
+        }
+        let mut data = Vec::with_capacity(len);
+        for (page_id, slot) in (address / page_size..).zip(slots) {
+            self.copy_out(slot, page_id, address, end, &mut data);
+            self.hits += 1;
+        }
+        Ok(Some(data))
+    }
+
+    // Read the range, loading pages that aren't cached. Called under the
+    // exclusive lock.
+    fn read_loading(&mut self, address: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        let end = self.check_range(address, len)?;
+        let file_size = self.file_size()?;
+        let page_size = self.page_size as u64;
+        let mut data = Vec::with_capacity(len);
+        for page_id in address / page_size..end.div_ceil(page_size) {
+            let slot = match self.find(page_id) {
+                Some(slot) => {
+                    self.hits += 1;
+                    slot
+                }
+                // A partial last page could still grow, so it isn't cached
+                None if (page_id + 1) * page_size > file_size => {
+                    self.misses += 1;
+                    let start = address.max(page_id * page_size);
+                    let stop = end.min((page_id + 1) * page_size);
+                    let mut partial = vec![0; (stop - start) as usize];
+                    read_exact_at(&self.file, &mut partial, start)?;
+                    data.extend_from_slice(&partial);
+                    continue;
+                }
+                None => {
+                    self.misses += 1;
+                    self.load(page_id)?
+                }
+            };
+            self.copy_out(slot, page_id, address, end, &mut data);
+        }
+        Ok(data)
+    }
+
+    fn write_locked(&mut self, address: u64, data: &[u8]) -> std::io::Result<()> {
+        let end = address.checked_add(data.len() as u64).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "Write range overflows the address space",
+            )
+        })?;
+        let page_size = self.page_size as u64;
+        // Cached copies are emptied while the file changes under them, so a
+        // process dying mid-write leaves no page that differs from the file
+        let mut cached = Vec::new();
+        for page_id in address / page_size..end.div_ceil(page_size) {
+            if let Some(slot) = self.find(page_id) {
+                self.set_slot_page(slot, EMPTY);
+                cached.push((page_id, slot));
+            }
+        }
+        write_all_at(&self.file, data, address)?;
+        self.file.sync_data()?;
+        for (page_id, slot) in cached {
+            let start = address.max(page_id * page_size);
+            let stop = end.min((page_id + 1) * page_size);
+            let at =
+                self.data_start + slot * self.page_size + (start - page_id * page_size) as usize;
+            self.map
+                .slice_mut(at, (stop - start) as usize)
+                .copy_from_slice(&data[(start - address) as usize..(stop - address) as usize]);
+            self.set_slot_page(slot, page_id);
+        }
+        Ok(())
+    }
+
+    fn check_range(&self, address: u64, len: usize) -> std::io::Result<u64> {
+        let file_size = self.file_size()?;
+        match address.checked_add(len as u64) {
+            Some(end) if end <= file_size => Ok(end),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Range at {} of {} bytes is past the end of the file, {}",
+                    address, len, file_size
+                ),
+            )),
+        }
+    }
+
+    fn find(&self, page_id: u64) -> Option<usize> {
+        let set = (page_id % (self.slots / WAYS) as u64) as usize;
+        let slot = (set * WAYS..(set + 1) * WAYS).find(|&slot| self.slot_page(slot) == page_id)?;
+        self.referenced(slot).store(1, Ordering::Relaxed);
+        Some(slot)
+    }
+
+    // Read `page_id` from the file into a slot of its set, evicting the
+    // first page not referenced since the set was last scanned
+    fn load(&mut self, page_id: u64) -> std::io::Result<usize> {
+        let set = (page_id % (self.slots / WAYS) as u64) as usize;
+        let ways = set * WAYS..(set + 1) * WAYS;
+        let slot = match ways.clone().find(|&slot| self.slot_page(slot) == EMPTY) {
+            Some(slot) => slot,
+            None => loop {
+                let victim = ways
+                    .clone()
+                    .find(|&slot| self.referenced(slot).swap(0, Ordering::Relaxed) == 0);
+                if let Some(slot) = victim {
+                    break slot;
+                }
+            },
+        };
+        let at = self.data_start + slot * self.page_size;
+        let page_size = self.page_size;
+        // Mark the slot empty while it is filled, so a failed read leaves
+        // nothing behind
+        self.set_slot_page(slot, EMPTY);
+        read_exact_at(
+            &self.file,
+            self.map.slice_mut(at, page_size),
+            page_id * page_size as u64,
+        )?;
+        self.set_slot_page(slot, page_id);
+        self.referenced(slot).store(1, Ordering::Relaxed);
+        Ok(slot)
+    }
+
+    // Append the part of `page_id`, cached in `slot`, inside address..end
+    fn copy_out(&self, slot: usize, page_id: u64, address: u64, end: u64, data: &mut Vec<u8>) {
+        let page_size = self.page_size as u64;
+        let start = address.max(page_id * page_size) - page_id * page_size;
+        let stop = end.min((page_id + 1) * page_size) - page_id * page_size;
+        let at = self.data_start + slot * self.page_size;
+        data.extend_from_slice(self.map.slice(at + start as usize, (stop - start) as usize));
+    }
+
+    fn slot_page(&self, slot: usize) -> u64 {
+        let at = HEADER_LEN + slot * SLOT_LEN;
+        u64::from_le_bytes(self.map.slice(at, 8).try_into().unwrap())
+    }
+
+    fn set_slot_page(&mut self, slot: usize, page_id: u64) {
+        let at = HEADER_LEN + slot * SLOT_LEN;
+        self.map
+            .slice_mut(at, 8)
+            .copy_from_slice(&page_id.to_le_bytes());
+    }
+
+    // Set on hits by processes holding only the shared lock, so accessed
+    // atomically
+    fn referenced(&self, slot: usize) -> &AtomicU64 {
+        let at = HEADER_LEN + slot * SLOT_LEN + 8;
+        // SAFETY: `at` is within the mapping and 8-byte aligned, as the
+        // mapping is page aligned and HEADER_LEN and SLOT_LEN are multiples
+        // of 8. The mapping outlives the returned reference.
+        unsafe { &*(self.map.ptr.add(at) as *const AtomicU64) }
+    }
+}
+
+// A shared mapping of the segment file
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// SAFETY: the mapping is only reached through `&self`/`&mut self`, and
+// other processes only change it under the segment lock
+unsafe impl Send for Mapping {}
+
+impl Mapping {
+    fn new(segment: &File, len: usize) -> std::io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        // SAFETY: a fresh mapping of the segment file, which is at least
+        // `len` bytes long; no existing memory is touched
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                segment.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(Error::last_os_error());
+        }
+        Ok(Self {
+            ptr: ptr.cast(),
+            len,
+        })
+    }
+
+    // Slices cover only the bytes asked for, never the referenced bits
+    // other processes may be setting at the same time
+    fn slice(&self, at: usize, len: usize) -> &[u8] {
+        assert!(at + len <= self.len);
+        // SAFETY: the range lies within the `len` mapped bytes, which stay
+        // mapped for as long as the mapping lives
+        unsafe { std::slice::from_raw_parts(self.ptr.add(at), len) }
+    }
+
+    fn slice_mut(&mut self, at: usize, len: usize) -> &mut [u8] {
+        assert!(at + len <= self.len);
+        // SAFETY: as for `slice`, and `&mut self` makes the access unique
+        // within this process
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.add(at), len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: unmaps exactly the range this mapping owns
+        unsafe {
+            libc::munmap(self.ptr.cast(), self.len);
+        }
+    }
+}
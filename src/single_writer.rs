@@ -0,0 +1,135 @@
+//! A cache split into one writer and any number of readers.
+//!
+//! Reads of pages already in the cache are served under a shared lock, so
+//! readers on different threads run at the same time. A read that misses,
+//! and every write, takes the lock exclusively.
+
+use std::io::Error;
+
+use crate::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use crate::WriteThroughCache;
+
+struct Shared(RwLock<WriteThroughCache>);
+
+// SAFETY: the cache is only reached through the lock. Under the shared lock
+// only `read_resident` is called, which reads the page index and page
+// buffers without changing them, counts hits with atomics and sets the
+// metrics gauges, which are `Sync`. It never runs the `CacheEvents` hooks,
+// which are only `Send`, or touches the heatmap; everything else takes the
+// lock exclusively.
+unsafe impl Sync for Shared {}
+
+/// The one handle that writes, from `WriteThroughCache::into_split`.
+pub struct CacheWriter {
+    shared: Arc<Shared>,
+}
+
+/// A handle for reading, from `WriteThroughCache::into_split`. Clone it to
+/// read from more threads.
+#[derive(Clone)]
+pub struct CacheReader {
+    shared: Arc<Shared>,
+}
+
+impl WriteThroughCache {
+    /// Split the cache into a writer and a reader that can be shared
+    /// between threads. Hits served to readers concurrently don't refresh
+    /// the pages' places in the eviction order. With `CacheEvents` hooks or
+    /// a heatmap registered every read takes the lock exclusively, so each
+    /// hit still reaches them.
+    pub fn into_split(self) -> (CacheWriter, CacheReader) {
+        let shared = Arc::new(Shared(RwLock::new(self)));
+        (
+            CacheWriter {
+                shared: shared.clone(),
+            },
+            CacheReader { shared },
+        )
+    }
+
+    // The range's bytes if every page it touches is cached, without
+    // changing anything but the hit counters and metrics. `None` when hits
+    // have to be reported to hooks or the heatmap, which needs `&mut self`.
+    pub(crate) fn read_resident(&self, address: u64, size: usize) -> Option<Vec<u8>> {
+        if self.events.hooks.is_some() || self.heatmap.is_some() {
+            return None;
+        }
+        let end = address.checked_add(size as u64)?;
+        if end > self.file_size {
+            return None;
+        }
+        let page_size = self.page_size as u64;
+        let pages = address / page_size..end.div_ceil(page_size);
+        let slots = pages
+            .clone()
+            .map(|page_id| self.cache.get(page_id))
+            .collect::<Option<Vec<_>>>()?;
+
+        let mut buffer = Vec::with_capacity(size);
+        for (page_id, slot) in pages.zip(slots) {
+            let page = self.pool.frame(self.cache.frame(slot));
+            let start = address.max(page_id * page_size) - page_id * page_size;
+            let stop = end.min((page_id + 1) * page_size) - page_id * page_size;
+            buffer.extend_from_slice(&page[start as usize..stop as usize]);
+            self.stats.hits.incr();
+        }
+        let result = Ok(buffer);
+        self.observe(&result);
+        result.ok()
+    }
+}
+
+impl CacheWriter {
+    pub fn write(&self, address: u64, data: &[u8]) -> std::io::Result<()> {
+        self.shared.write()?.write(address, data)
+    }
+
+    pub fn read(&self, address: u64, size: usize) -> std::io::Result<Vec<u8>> {
+        self.shared.read_range(address, size)
+    }
+
+    /// Exclusive access to the cache until the guard is dropped, holding
+    /// off readers.
+    pub fn lock(&self) -> std::io::Result<RwLockWriteGuard<'_, WriteThroughCache>> {
+        self.shared.write()
+    }
+
+    pub fn reader(&self) -> CacheReader {
+        CacheReader {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl CacheReader {
+    pub fn read(&self, address: u64, size: usize) -> std::io::Result<Vec<u8>> {
+        self.shared.read_range(address, size)
+    }
+
+    pub fn file_size(&self) -> std::io::Result<u64> {
+        Ok(self.shared.read()?.file_size())
+    }
+}
+
+impl Shared {
+    fn read_range(&self, address: u64, size: usize) -> std::io::Result<Vec<u8>> {
+        if let Some(data) = self.read()?.read_resident(address, size) {
+            return Ok(data);
+        }
+        // Loading pages changes the cache, so a miss waits for the writer
+        // and other readers
+        self.write()?.read(address, size)
+    }
+
+    fn read(&self) -> std::io::Result<RwLockReadGuard<'_, WriteThroughCache>> {
+        self.0
+            .read()
+            .map_err(|_| Error::other("Cache lock poisoned by a panic"))
+    }
+
+    fn write(&self) -> std::io::Result<RwLockWriteGuard<'_, WriteThroughCache>> {
+        self.0
+            .write()
+            .map_err(|_| Error::other("Cache lock poisoned by a panic"))
+    }
+}
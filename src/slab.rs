@@ -0,0 +1,243 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::pool::Frame;
+use crate::AHashMap;
+
+const NIL: u32 = u32::MAX;
+
+/// Bookkeeping bytes added by one more resident page.
+pub(crate) const ENTRY_BYTES: usize =
+    std::mem::size_of::<Slot>() + std::mem::size_of::<(u64, u32)>() + 1;
+
+// One resident page in four is kept in the hot segment
+pub(crate) const HOT_SHARE: usize = 4;
+
+const COLD: usize = 0;
+const HOT: usize = 1;
+
+struct Slot {
+    page_id: u64,
+    frame: Frame,
+    prev: u32,
+    next: u32,
+    segment: usize,
+    referenced: bool,
+    // Tick of the slab's clock at the page's last use
+    last_used: u64,
+}
+
+#[derive(Clone, Copy)]
+struct List {
+    head: u32,
+    tail: u32,
+    len: usize,
+}
+
+impl List {
+    const EMPTY: List = List {
+        head: NIL,
+        tail: NIL,
+        len: 0,
+    };
+}
+
+// Resident pages in one contiguous slab, addressed by slot handles; the page
+// bytes live in buffer pool frames. Recency is tracked by two doubly linked
+// lists threaded through the slots, least recently used at the head. New and
+// promoted pages enter the small hot segment, where a hit only sets a
+// referenced bit, so very hot pages cost no list manipulation. Pages leaving
+// the hot segment get a second chance if they were referenced, otherwise they
+// move to the cold segment, where a hit promotes them back and eviction
+// happens. Freed slots are reused before the slab grows.
+pub(crate) struct PageSlab {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    index: AHashMap<u64, u32>,
+    lists: [List; 2],
+    clock: Option<Arc<AtomicU64>>,
+}
+
+impl PageSlab {
+    /// A slab sized up front for `pages` resident pages, so a cache that
+    /// stays within that many pages never allocates for its bookkeeping.
+    pub fn with_capacity(pages: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(pages),
+            free: Vec::with_capacity(pages),
+            index: AHashMap::with_capacity_and_hasher(pages, Default::default()),
+            lists: [List::EMPTY; 2],
+            clock: None,
+        }
+    }
+
+    /// Stamp every use of a page with a tick of `clock`, which other slabs
+    /// may share so their pages' recency can be compared.
+    pub fn set_clock(&mut self, clock: Arc<AtomicU64>) {
+        self.clock = Some(clock);
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Heap bytes held by the slab and its index, excluding page frames.
+    pub fn heap_bytes(&self) -> usize {
+        // Each index bucket carries one control byte alongside the entry
+        self.slots.capacity() * std::mem::size_of::<Slot>()
+            + self.free.capacity() * std::mem::size_of::<u32>()
+            + self.index.capacity() * (std::mem::size_of::<(u64, u32)>() + 1)
+    }
+
+    pub fn get(&self, page_id: u64) -> Option<u32> {
+        self.index.get(&page_id).copied()
+    }
+
+    pub fn contains(&self, page_id: u64) -> bool {
+        self.index.contains_key(&page_id)
+    }
+
+    pub fn frame(&self, slot: u32) -> Frame {
+        self.slots[slot as usize].frame
+    }
+
+    /// Record a hit on `slot`.
+    pub fn touch(&mut self, slot: u32) {
+        self.slots[slot as usize].last_used = self.tick();
+        if self.slots[slot as usize].segment == HOT {
+            self.slots[slot as usize].referenced = true;
+            return;
+        }
+        self.unlink(slot);
+        self.link_tail(HOT, slot);
+        self.balance();
+    }
+
+    /// Insert a page that is not resident yet as the most recently used.
+    pub fn insert(&mut self, page_id: u64, frame: Frame) -> u32 {
+        let last_used = self.tick();
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                let entry = &mut self.slots[slot as usize];
+                entry.page_id = page_id;
+                entry.frame = frame;
+                entry.last_used = last_used;
+                slot
+            }
+            None => {
+                self.slots.push(Slot {
+                    page_id,
+                    frame,
+                    prev: NIL,
+                    next: NIL,
+                    segment: HOT,
+                    referenced: false,
+                    last_used,
+                });
+                (self.slots.len() - 1) as u32
+            }
+        };
+        self.index.insert(page_id, slot);
+        self.link_tail(HOT, slot);
+        self.balance();
+        slot
+    }
+
+    /// Resident pages from the next to be evicted to the most recently used,
+    /// each with whether it is in the hot segment.
+    pub fn iter_lru(&self) -> impl Iterator<Item = (u64, bool)> + '_ {
+        [COLD, HOT].into_iter().flat_map(move |segment| {
+            let mut slot = self.lists[segment].head;
+            std::iter::from_fn(move || {
+                let entry = self.slots.get(slot as usize)?;
+                slot = entry.next;
+                Some((entry.page_id, segment == HOT))
+            })
+        })
+    }
+
+    /// Remove the least recently used page.
+    pub fn pop_lru(&mut self) -> Option<(u64, Frame)> {
+        let page_id = self.slots.get(self.lru_slot() as usize)?.page_id;
+        self.remove(page_id).map(|frame| (page_id, frame))
+    }
+
+    /// Clock tick at the last use of the page `pop_lru` would remove; always
+    /// 0 without a clock.
+    pub fn lru_last_used(&self) -> Option<u64> {
+        Some(self.slots.get(self.lru_slot() as usize)?.last_used)
+    }
+
+    fn lru_slot(&self) -> u32 {
+        match self.lists[COLD].head {
+            NIL => self.lists[HOT].head,
+            head => head,
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock
+            .as_ref()
+            .map_or(0, |clock| clock.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn remove(&mut self, page_id: u64) -> Option<Frame> {
+        let slot = self.index.remove(&page_id)?;
+        self.unlink(slot);
+        self.free.push(slot);
+        Some(self.slots[slot as usize].frame)
+    }
+
+    // Move pages from the head of the hot segment to the cold one until the
+    // hot segment is back to its share
+    fn balance(&mut self) {
+        let limit = std::cmp::max(self.len() / HOT_SHARE, 1);
+        while self.lists[HOT].len > limit {
+            let slot = self.lists[HOT].head;
+            self.unlink(slot);
+            if std::mem::take(&mut self.slots[slot as usize].referenced) {
+                self.link_tail(HOT, slot);
+            } else {
+                self.link_tail(COLD, slot);
+            }
+        }
+    }
+
+    fn link_tail(&mut self, segment: usize, slot: u32) {
+        let list = &mut self.lists[segment];
+        let tail = list.tail;
+        list.tail = slot;
+        list.len += 1;
+        if tail == NIL {
+            list.head = slot;
+        }
+
+        let entry = &mut self.slots[slot as usize];
+        entry.prev = tail;
+        entry.next = NIL;
+        entry.segment = segment;
+        entry.referenced = false;
+        if tail != NIL {
+            self.slots[tail as usize].next = slot;
+        }
+    }
+
+    fn unlink(&mut self, slot: u32) {
+        let Slot {
+            prev,
+            next,
+            segment,
+            ..
+        } = self.slots[slot as usize];
+        let list = &mut self.lists[segment];
+        list.len -= 1;
+        match prev {
+            NIL => list.head = next,
+            prev => self.slots[prev as usize].next = next,
+        }
+        match next {
+            NIL => list.tail = prev,
+            next => self.slots[next as usize].prev = prev,
+        }
+    }
+}
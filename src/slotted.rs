@@ -0,0 +1,231 @@
+//! Variable-length records packed into a single page.
+//!
+//! A slot directory grows from the start of the page and record data from
+//! the end. Records are addressed by slot number, which stays the same when
+//! the page is compacted, so other pages can refer to a record by page id
+//! and slot.
+
+use std::io::{Error, ErrorKind};
+
+use crate::WriteThroughCache;
+
+// slot count u32 | start of record data u32
+const HEADER_LEN: usize = 4 + 4;
+// Per slot: record offset u32 | record length u32. Offset 0 marks a free
+// slot, since records never start inside the header.
+const SLOT_LEN: usize = 4 + 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlottedPage {
+    data: Vec<u8>,
+}
+
+impl SlottedPage {
+    pub fn new(page_size: usize) -> Self {
+        let mut page = Self {
+            data: vec![0; page_size],
+        };
+        page.set_data_start(page_size);
+        page
+    }
+
+    /// Interpret `data` as a slotted page. An all-zero page is an empty
+    /// one, so freshly allocated pages need no setting up.
+    pub fn from_bytes(data: Vec<u8>) -> std::io::Result<Self> {
+        let mut page = Self { data };
+        let len = page.data.len();
+        if len < HEADER_LEN || len > u32::MAX as usize {
+            return Err(invalid_page("Page size out of range"));
+        }
+        if page.slot_count() == 0 && page.data_start() == 0 {
+            page.set_data_start(len);
+        }
+        let directory_end = page.directory_end();
+        if directory_end > page.data_start() || page.data_start() > len {
+            return Err(invalid_page("Slot directory overlaps record data"));
+        }
+        for slot in 0..page.slot_count() {
+            let (offset, record_len) = page.slot_entry(slot);
+            if offset != 0 && (offset < page.data_start() || offset + record_len > len) {
+                return Err(invalid_page("Slot points outside record data"));
+            }
+        }
+        Ok(page)
+    }
+
+    /// Read page `page_id` from `cache`.
+    pub fn load(cache: &mut WriteThroughCache, page_id: u64) -> std::io::Result<Self> {
+        let page_size = cache.page_size();
+        Self::from_bytes(cache.read(page_id * page_size as u64, page_size)?)
+    }
+
+    /// Write the page to `cache` as page `page_id`.
+    pub fn store(&self, cache: &mut WriteThroughCache, page_id: u64) -> std::io::Result<()> {
+        cache.write(page_id * self.data.len() as u64, &self.data)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Store `record`, returning its slot, or `None` if it doesn't fit even
+    /// after compaction. Free slots are reused before the directory grows.
+    pub fn insert(&mut self, record: &[u8]) -> Option<u32> {
+        let free_slot = (0..self.slot_count()).find(|&slot| self.slot_entry(slot).0 == 0);
+        let directory_growth = if free_slot.is_some() { 0 } else { SLOT_LEN };
+        if record.len() + directory_growth > self.free_space() {
+            return None;
+        }
+        if record.len() + directory_growth > self.contiguous_free_space() {
+            self.compact();
+        }
+
+        let slot = match free_slot {
+            Some(slot) => slot,
+            None => {
+                let slot = self.slot_count();
+                self.set_slot_count(slot + 1);
+                slot
+            }
+        };
+        let offset = self.data_start() - record.len();
+        self.data[offset..offset + record.len()].copy_from_slice(record);
+        self.set_data_start(offset);
+        self.set_slot_entry(slot, offset, record.len());
+        Some(slot)
+    }
+
+    pub fn get(&self, slot: u32) -> Option<&[u8]> {
+        if slot >= self.slot_count() {
+            return None;
+        }
+        match self.slot_entry(slot) {
+            (0, _) => None,
+            (offset, len) => Some(&self.data[offset..offset + len]),
+        }
+    }
+
+    /// Replace the record in `slot`, keeping its slot number. Returns false
+    /// if the slot is free or the new record doesn't fit; the old record is
+    /// kept in that case.
+    pub fn update(&mut self, slot: u32, record: &[u8]) -> bool {
+        let Some(old_len) = self.get(slot).map(<[u8]>::len) else {
+            return false;
+        };
+        if record.len() <= old_len {
+            let offset = self.slot_entry(slot).0;
+            self.data[offset..offset + record.len()].copy_from_slice(record);
+            self.set_slot_entry(slot, offset, record.len());
+            return true;
+        }
+        if record.len() > self.free_space() + old_len {
+            return false;
+        }
+        // Free the old copy first so compaction can reclaim it
+        self.set_slot_entry(slot, 0, 0);
+        if record.len() > self.contiguous_free_space() {
+            self.compact();
+        }
+        let offset = self.data_start() - record.len();
+        self.data[offset..offset + record.len()].copy_from_slice(record);
+        self.set_data_start(offset);
+        self.set_slot_entry(slot, offset, record.len());
+        true
+    }
+
+    /// Free `slot`, returning whether it held a record. Trailing free slots
+    /// are dropped from the directory.
+    pub fn delete(&mut self, slot: u32) -> bool {
+        if self.get(slot).is_none() {
+            return false;
+        }
+        self.set_slot_entry(slot, 0, 0);
+        let mut count = self.slot_count();
+        while count > 0 && self.slot_entry(count - 1).0 == 0 {
+            count -= 1;
+        }
+        self.set_slot_count(count);
+        true
+    }
+
+    /// Move the records to the end of the page, so all free space is in one
+    /// run between them and the slot directory.
+    pub fn compact(&mut self) {
+        let mut records: Vec<(u32, usize, usize)> = (0..self.slot_count())
+            .map(|slot| (slot, self.slot_entry(slot)))
+            .filter(|(_, (offset, _))| *offset != 0)
+            .map(|(slot, (offset, len))| (slot, offset, len))
+            .collect();
+        // Highest first, so each record only ever moves towards the end
+        // without overwriting one not yet moved
+        records.sort_unstable_by_key(|&(_, offset, _)| std::cmp::Reverse(offset));
+        let mut end = self.data.len();
+        for (slot, offset, len) in records {
+            let new_offset = end - len;
+            self.data.copy_within(offset..offset + len, new_offset);
+            self.set_slot_entry(slot, new_offset, len);
+            end = new_offset;
+        }
+        self.set_data_start(end);
+    }
+
+    /// Slots in the directory, free ones included.
+    pub fn slot_count(&self) -> u32 {
+        self.field(0)
+    }
+
+    /// Live records with their slots, in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &[u8])> + '_ {
+        (0..self.slot_count()).filter_map(|slot| self.get(slot).map(|record| (slot, record)))
+    }
+
+    /// Bytes available for new records and their slots, counting space
+    /// that compaction would reclaim.
+    pub fn free_space(&self) -> usize {
+        let used: usize = self.iter().map(|(_, record)| record.len()).sum();
+        self.data.len() - self.directory_end() - used
+    }
+
+    fn contiguous_free_space(&self) -> usize {
+        self.data_start() - self.directory_end()
+    }
+
+    fn directory_end(&self) -> usize {
+        HEADER_LEN + self.slot_count() as usize * SLOT_LEN
+    }
+
+    fn data_start(&self) -> usize {
+        self.field(4) as usize
+    }
+
+    fn set_slot_count(&mut self, count: u32) {
+        self.set_field(0, count);
+    }
+
+    fn set_data_start(&mut self, offset: usize) {
+        self.set_field(4, offset as u32);
+    }
+
+    fn slot_entry(&self, slot: u32) -> (usize, usize) {
+        let at = HEADER_LEN + slot as usize * SLOT_LEN;
+        (self.field(at) as usize, self.field(at + 4) as usize)
+    }
+
+    fn set_slot_entry(&mut self, slot: u32, offset: usize, len: usize) {
+        let at = HEADER_LEN + slot as usize * SLOT_LEN;
+        self.set_field(at, offset as u32);
+        self.set_field(at + 4, len as u32);
+    }
+
+    fn field(&self, at: usize) -> u32 {
+        u32::from_le_bytes(self.data[at..at + 4].try_into().unwrap())
+    }
+
+    fn set_field(&mut self, at: usize, value: u32) {
+        self.data[at..at + 4].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn invalid_page(reason: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, reason)
+}
@@ -0,0 +1,243 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "latency")]
+use crate::latency::{Latencies, LatencyStats};
+
+// Double-collect attempts before a snapshot settles for the last pass
+const SNAPSHOT_ATTEMPTS: usize = 4;
+
+// A statistics counter that can be bumped through a shared reference. Counters
+// only ever feed reporting, so relaxed ordering is enough and the hot path
+// never waits on them.
+#[derive(Debug, Default)]
+pub(crate) struct Counter {
+    value: AtomicU64,
+    // Facade counter bumped along with this one. It is never reset, so it
+    // keeps counting up across `reset`.
+    #[cfg(feature = "metrics")]
+    mirror: Option<::metrics::Counter>,
+}
+
+impl Counter {
+    pub fn incr(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.value.fetch_add(n, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        if let Some(mirror) = &self.mirror {
+            mirror.increment(n);
+        }
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.value.store(0, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn mirror(&mut self, counter: ::metrics::Counter) {
+        self.mirror = Some(counter);
+    }
+}
+
+/// Read a group of counters so that related values agree with each other:
+/// the group is read until two passes in a row return the same values.
+/// Under constant updates it gives up after a few passes and returns the
+/// last one.
+pub(crate) fn snapshot<const N: usize>(counters: [&Counter; N]) -> [u64; N] {
+    let read = || counters.map(Counter::get);
+    let mut values = read();
+    for _ in 0..SNAPSHOT_ATTEMPTS {
+        let again = read();
+        if again == values {
+            break;
+        }
+        values = again;
+    }
+    values
+}
+
+// Start of a timed disk operation
+#[derive(Clone, Copy)]
+pub(crate) struct Timer(Instant);
+
+impl Timer {
+    pub fn start() -> Self {
+        Self(Instant::now())
+    }
+}
+
+/// Counters for cache activity since open or the last `reset_stats`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Page lookups by reads and partial writes served from memory.
+    pub hits: u64,
+    /// Page lookups that had to go to the compressed tier or disk.
+    pub misses: u64,
+    pub evictions: u64,
+    /// Read calls against the data file, including readahead fetches.
+    pub disk_reads: u64,
+    /// Write calls against the data file.
+    pub disk_writes: u64,
+    /// Syncs of the data file and, for a compressed file, of its
+    /// compressed-page index.
+    pub fsyncs: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    #[cfg(feature = "latency")]
+    pub latency: LatencyStats,
+}
+
+/// `CacheStats` along with when they were taken, from
+/// `WriteThroughCache::stats_snapshot`.
+#[derive(Debug, Clone)]
+pub struct StatsSnapshot {
+    pub taken_at: Instant,
+    pub stats: CacheStats,
+}
+
+impl StatsSnapshot {
+    /// Activity between `older` and this snapshot, as rates. Counters reset
+    /// in between count from zero.
+    pub fn diff(&self, older: &StatsSnapshot) -> StatsRates {
+        let interval = self.taken_at.saturating_duration_since(older.taken_at);
+        let secs = interval.as_secs_f64();
+        let rate = |new: u64, old: u64| {
+            if secs == 0.0 {
+                0.0
+            } else {
+                new.saturating_sub(old) as f64 / secs
+            }
+        };
+        let (new, old) = (&self.stats, &older.stats);
+        let hits = new.hits.saturating_sub(old.hits);
+        let misses = new.misses.saturating_sub(old.misses);
+        StatsRates {
+            interval,
+            hits_per_sec: rate(new.hits, old.hits),
+            misses_per_sec: rate(new.misses, old.misses),
+            miss_ratio: match hits + misses {
+                0 => 0.0,
+                lookups => misses as f64 / lookups as f64,
+            },
+            evictions_per_sec: rate(new.evictions, old.evictions),
+            disk_reads_per_sec: rate(new.disk_reads, old.disk_reads),
+            disk_writes_per_sec: rate(new.disk_writes, old.disk_writes),
+            fsyncs_per_sec: rate(new.fsyncs, old.fsyncs),
+            bytes_read_per_sec: rate(new.bytes_read, old.bytes_read),
+            bytes_written_per_sec: rate(new.bytes_written, old.bytes_written),
+        }
+    }
+}
+
+/// Cache activity over an interval, from `StatsSnapshot::diff`. Rates are 0
+/// over an empty interval.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct StatsRates {
+    pub interval: Duration,
+    pub hits_per_sec: f64,
+    pub misses_per_sec: f64,
+    /// Share of page lookups in the interval that missed; 0 if there were
+    /// none.
+    pub miss_ratio: f64,
+    pub evictions_per_sec: f64,
+    pub disk_reads_per_sec: f64,
+    pub disk_writes_per_sec: f64,
+    pub fsyncs_per_sec: f64,
+    pub bytes_read_per_sec: f64,
+    pub bytes_written_per_sec: f64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    pub hits: Counter,
+    pub misses: Counter,
+    pub evictions: Counter,
+    pub disk_reads: Counter,
+    pub disk_writes: Counter,
+    pub fsyncs: Counter,
+    pub bytes_read: Counter,
+    pub bytes_written: Counter,
+    #[cfg(feature = "latency")]
+    pub latency: Latencies,
+}
+
+impl Counters {
+    pub fn disk_read(&self, bytes: usize) {
+        self.disk_reads.incr();
+        self.bytes_read.add(bytes as u64);
+    }
+
+    pub fn disk_write(&self, bytes: usize) {
+        self.disk_writes.incr();
+        self.bytes_written.add(bytes as u64);
+    }
+
+    pub fn page_read_done(&self, timer: Timer) -> Duration {
+        let elapsed = timer.0.elapsed();
+        #[cfg(feature = "latency")]
+        self.latency.page_reads.record(elapsed);
+        elapsed
+    }
+
+    pub fn page_write_done(&self, timer: Timer) -> Duration {
+        let elapsed = timer.0.elapsed();
+        #[cfg(feature = "latency")]
+        self.latency.page_writes.record(elapsed);
+        elapsed
+    }
+
+    pub fn fsync_done(&self, _elapsed: Duration) {
+        #[cfg(feature = "latency")]
+        self.latency.fsyncs.record(_elapsed);
+    }
+
+    pub fn snapshot(&self) -> CacheStats {
+        let [hits, misses, evictions, disk_reads, disk_writes, fsyncs, bytes_read, bytes_written] =
+            snapshot([
+                &self.hits,
+                &self.misses,
+                &self.evictions,
+                &self.disk_reads,
+                &self.disk_writes,
+                &self.fsyncs,
+                &self.bytes_read,
+                &self.bytes_written,
+            ]);
+        CacheStats {
+            hits,
+            misses,
+            evictions,
+            disk_reads,
+            disk_writes,
+            fsyncs,
+            bytes_read,
+            bytes_written,
+            #[cfg(feature = "latency")]
+            latency: self.latency.snapshot(),
+        }
+    }
+
+    pub fn reset(&self) {
+        for counter in [
+            &self.hits,
+            &self.misses,
+            &self.evictions,
+            &self.disk_reads,
+            &self.disk_writes,
+            &self.fsyncs,
+            &self.bytes_read,
+            &self.bytes_written,
+        ] {
+            counter.reset();
+        }
+        #[cfg(feature = "latency")]
+        self.latency.reset();
+    }
+}
@@ -0,0 +1,185 @@
+//! Application metadata kept in a reserved region of the file.
+//!
+//! The superblock lives in its own partition of two pages, each holding a
+//! full copy of the metadata stamped with a generation number. A commit
+//! writes the page not holding the current copy, so a crash part way
+//! through leaves the previous copy intact, and opening takes the valid
+//! copy with the highest generation.
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+
+use crate::WriteThroughCache;
+
+const PARTITION: &str = "superblock";
+const MAGIC: [u8; 8] = *b"WTSUPER\0";
+// magic | generation u64 | entry count u32 | entries | crc32, where each
+// entry is key length u16 | key | value length u32 | value
+const HEADER_LEN: usize = 8 + 8 + 4;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Superblock {
+    generation: u64,
+    entries: BTreeMap<String, Vec<u8>>,
+}
+
+impl Superblock {
+    /// Load the superblock from `cache`'s file, reserving its partition if
+    /// the file doesn't have one yet. A file holding data but no
+    /// partitions is refused, as the partition would land on that data.
+    pub fn open(cache: &mut WriteThroughCache) -> std::io::Result<Self> {
+        let page_size = cache.page_size();
+        if cache.partitions().iter().all(|p| p.name != PARTITION) {
+            if cache.partitions().is_empty() && cache.file_size() > 0 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "File has data outside any partition, where the superblock would go",
+                ));
+            }
+            cache.create_partition(PARTITION, 2 * page_size as u64)?;
+            return Ok(Self::default());
+        }
+        let file_size = cache.file_size();
+        let mut partition = cache.partition(PARTITION)?;
+        let mut newest: Option<Self> = None;
+        for slot in 0..2 {
+            // Copies are only as long as they need to be, so the file may
+            // end part way into the last one written, or before a slot
+            let offset = slot * page_size as u64;
+            let len = std::cmp::min(
+                page_size as u64,
+                file_size.saturating_sub(partition.start() + offset),
+            );
+            let copy = partition.read(offset, len as usize)?;
+            if let Some(copy) = Self::decode(&copy) {
+                if newest
+                    .as_ref()
+                    .is_none_or(|newest| copy.generation > newest.generation)
+                {
+                    newest = Some(copy);
+                }
+            }
+        }
+        Ok(newest.unwrap_or_default())
+    }
+
+    /// Number of commits made, 0 for a superblock never committed.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    /// Set `key` for the next commit.
+    pub fn set(&mut self, key: &str, value: &[u8]) {
+        self.entries.insert(key.to_string(), value.to_vec());
+    }
+
+    /// Remove `key` at the next commit, returning whether it was set.
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.entries.remove(key).is_some()
+    }
+
+    /// `key` as a little-endian u64. `None` if it isn't set or isn't 8
+    /// bytes long.
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        Some(u64::from_le_bytes(self.get(key)?.try_into().ok()?))
+    }
+
+    pub fn set_u64(&mut self, key: &str, value: u64) {
+        self.set(key, &value.to_le_bytes());
+    }
+
+    /// Add one to the counter `key`, taken as 0 if unset, and return the
+    /// new value.
+    pub fn increment(&mut self, key: &str) -> u64 {
+        let value = self.get_u64(key).unwrap_or(0) + 1;
+        self.set_u64(key, value);
+        value
+    }
+
+    /// `key` as a string. `None` if it isn't set or isn't UTF-8.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        std::str::from_utf8(self.get(key)?).ok()
+    }
+
+    pub fn set_str(&mut self, key: &str, value: &str) {
+        self.set(key, value.as_bytes());
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> + '_ {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// Write the metadata as the next generation, returning it. Nothing set
+    /// since the last commit is kept on disk until this returns.
+    pub fn commit(&mut self, cache: &mut WriteThroughCache) -> std::io::Result<u64> {
+        if self.entries.keys().any(|key| key.len() > u16::MAX as usize) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Superblock keys must be at most 65535 bytes",
+            ));
+        }
+        let page_size = cache.page_size();
+        let generation = self.generation + 1;
+        let copy = self.encode(generation);
+        if copy.len() > page_size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Superblock takes {} bytes, more than a page of {}",
+                    copy.len(),
+                    page_size
+                ),
+            ));
+        }
+        // Generations alternate between the two pages
+        let slot = generation % 2;
+        cache
+            .partition(PARTITION)?
+            .write(slot * page_size as u64, &copy)?;
+        self.generation = generation;
+        Ok(generation)
+    }
+
+    fn encode(&self, generation: u64) -> Vec<u8> {
+        let mut copy = Vec::new();
+        copy.extend_from_slice(&MAGIC);
+        copy.extend_from_slice(&generation.to_le_bytes());
+        copy.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for (key, value) in &self.entries {
+            copy.extend_from_slice(&(key.len() as u16).to_le_bytes());
+            copy.extend_from_slice(key.as_bytes());
+            copy.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            copy.extend_from_slice(value);
+        }
+        copy.extend_from_slice(&crc32fast::hash(&copy).to_le_bytes());
+        copy
+    }
+
+    fn decode(page: &[u8]) -> Option<Self> {
+        if page.get(..8)? != MAGIC {
+            return None;
+        }
+        let generation = u64::from_le_bytes(page.get(8..16)?.try_into().ok()?);
+        let count = u32::from_le_bytes(page.get(16..20)?.try_into().ok()?);
+        let mut at = HEADER_LEN;
+        let mut entries = BTreeMap::new();
+        for _ in 0..count {
+            let key_len = u16::from_le_bytes(page.get(at..at + 2)?.try_into().ok()?) as usize;
+            let key = std::str::from_utf8(page.get(at + 2..at + 2 + key_len)?).ok()?;
+            at += 2 + key_len;
+            let value_len = u32::from_le_bytes(page.get(at..at + 4)?.try_into().ok()?) as usize;
+            let value = page.get(at + 4..at + 4 + value_len)?;
+            at += 4 + value_len;
+            entries.insert(key.to_string(), value.to_vec());
+        }
+        let crc = u32::from_le_bytes(page.get(at..at + 4)?.try_into().ok()?);
+        (crc == crc32fast::hash(&page[..at])).then_some(Self {
+            generation,
+            entries,
+        })
+    }
+}
@@ -0,0 +1,7 @@
+// Locks used by the thread-safe wrappers. Building with `RUSTFLAGS="--cfg
+// loom"` swaps in loom's versions, so loom models, in tests/tests_loom.rs or
+// in a downstream crate, explore every order the wrappers can take them in.
+#[cfg(loom)]
+pub(crate) use loom::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(loom))]
+pub(crate) use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
@@ -0,0 +1,107 @@
+use std::collections::VecDeque;
+
+use zeroize::Zeroize;
+
+use crate::AHashMap;
+
+// Second-chance tier for pages evicted from the main LRU, held LZ4-compressed.
+// Capacity is accounted in compressed bytes.
+pub(crate) struct CompressedTier {
+    capacity: usize,
+    used: usize,
+    pages: AHashMap<u64, Vec<u8>>,
+    usage_order: VecDeque<u64>,
+    secure: bool,
+}
+
+impl CompressedTier {
+    pub fn new(capacity: usize, secure: bool) -> Self {
+        Self {
+            capacity,
+            used: 0,
+            pages: AHashMap::default(),
+            usage_order: VecDeque::new(),
+            secure,
+        }
+    }
+
+    pub fn insert(&mut self, page_id: u64, data: &[u8]) {
+        self.remove(page_id);
+
+        let compressed = lz4_flex::block::compress(data);
+        if compressed.len() > self.capacity {
+            return;
+        }
+
+        while self.used + compressed.len() > self.capacity {
+            match self.usage_order.pop_front() {
+                Some(oldest_page) => self.discard(oldest_page),
+                None => break,
+            }
+        }
+
+        self.used += compressed.len();
+        self.pages.insert(page_id, compressed);
+        self.usage_order.push_back(page_id);
+    }
+
+    /// Move `page_id` out of the tier, decompressing it into `buffer`.
+    pub fn take(&mut self, page_id: u64, buffer: &mut [u8]) -> bool {
+        let Some(compressed) = self.pages.get(&page_id) else {
+            return false;
+        };
+
+        let decoded = lz4_flex::block::decompress_into(compressed, buffer);
+        self.remove(page_id);
+        matches!(decoded, Ok(len) if len == buffer.len())
+    }
+
+    /// Pages from the next to be discarded to the newest, with their
+    /// compressed sizes.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, usize)> + '_ {
+        self.usage_order
+            .iter()
+            .map(|&page_id| (page_id, self.pages[&page_id].len()))
+    }
+
+    pub fn contains(&self, page_id: u64) -> bool {
+        self.pages.contains_key(&page_id)
+    }
+
+    pub fn remove(&mut self, page_id: u64) {
+        if self.pages.contains_key(&page_id) {
+            self.usage_order.retain(|&x| x != page_id);
+            self.discard(page_id);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        if self.secure {
+            for compressed in self.pages.values_mut() {
+                compressed.zeroize();
+            }
+        }
+        self.pages.clear();
+        self.usage_order.clear();
+        self.used = 0;
+    }
+
+    fn discard(&mut self, page_id: u64) {
+        if let Some(mut compressed) = self.pages.remove(&page_id) {
+            self.used -= compressed.len();
+            if self.secure {
+                compressed.zeroize();
+            }
+        }
+    }
+}
+
+impl Drop for CompressedTier {
+    fn drop(&mut self) {
+        if self.secure {
+            for compressed in self.pages.values_mut() {
+                compressed.zeroize();
+            }
+        }
+    }
+}
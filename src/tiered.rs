@@ -0,0 +1,254 @@
+//! A read cache in front of an `Origin`, with a memory tier and a local
+//! disk tier.
+//!
+//! Pages are read from the origin into memory. Pages evicted from memory
+//! spill to slots in a local file, and are moved back to memory when read
+//! again; pages evicted from the file are dropped and read from the origin
+//! next time. Each tier evicts its least recently used page. Which page is
+//! in which slot is only kept in memory, so the local file starts out cold
+//! every time.
+
+use std::collections::BTreeMap;
+use std::hash::Hash;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+
+use crate::{AHashMap, Origin, WriteThroughCache, DEFAULT_PAGE_SIZE};
+
+const DEFAULT_RAM_CAPACITY: usize = 16 * 1024 * 1024;
+const DEFAULT_DISK_CAPACITY: u64 = 256 * 1024 * 1024;
+
+/// Counters for a `TieredCache` since it was opened.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TieredStats {
+    pub ram_hits: u64,
+    pub disk_hits: u64,
+    /// Pages read from the origin.
+    pub origin_reads: u64,
+    /// Pages written to the disk tier on eviction from memory.
+    pub spills: u64,
+}
+
+pub struct TieredBuilder {
+    origin: Box<dyn Origin>,
+    spill_path: PathBuf,
+    page_size: usize,
+    ram_capacity: usize,
+    disk_capacity: u64,
+}
+
+pub struct TieredCache {
+    origin: Box<dyn Origin>,
+    origin_size: u64,
+    page_size: usize,
+    ram: Lru<u64, Vec<u8>>,
+    ram_pages: usize,
+    disk: WriteThroughCache,
+    // Slot and length of each page in the disk tier
+    disk_index: Lru<u64, (u64, usize)>,
+    disk_slots: u64,
+    free_slots: Vec<u64>,
+    stats: TieredStats,
+}
+
+// Least recently used ordering over a map
+struct Lru<K, V> {
+    entries: AHashMap<K, (V, u64)>,
+    order: BTreeMap<u64, K>,
+    clock: u64,
+}
+
+impl TieredBuilder {
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Bytes of pages to keep in memory.
+    pub fn ram_capacity(mut self, capacity: usize) -> Self {
+        self.ram_capacity = capacity;
+        self
+    }
+
+    /// Bytes of pages to keep in the local file.
+    pub fn disk_capacity(mut self, capacity: u64) -> Self {
+        self.disk_capacity = capacity;
+        self
+    }
+
+    pub fn open(mut self) -> std::io::Result<TieredCache> {
+        if self.page_size == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Page size must be non-zero",
+            ));
+        }
+        let ram_pages = self.ram_capacity / self.page_size;
+        let disk_slots = self.disk_capacity / self.page_size as u64;
+        if ram_pages == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Memory tier must hold at least one page",
+            ));
+        }
+        // The spill file's own cache is kept small, the memory tier being
+        // the one meant to hold pages
+        let disk = WriteThroughCache::builder(&self.spill_path)
+            .page_size(self.page_size)
+            .capacity(self.page_size)
+            .open()?;
+        Ok(TieredCache {
+            origin_size: self.origin.size()?,
+            origin: self.origin,
+            page_size: self.page_size,
+            ram: Lru::new(),
+            ram_pages,
+            disk,
+            disk_index: Lru::new(),
+            disk_slots,
+            free_slots: (0..disk_slots).rev().collect(),
+            stats: TieredStats::default(),
+        })
+    }
+}
+
+impl TieredCache {
+    /// Cache `origin`, spilling pages evicted from memory to `spill_path`.
+    pub fn builder<P: Into<PathBuf>>(origin: Box<dyn Origin>, spill_path: P) -> TieredBuilder {
+        TieredBuilder {
+            origin,
+            spill_path: spill_path.into(),
+            page_size: DEFAULT_PAGE_SIZE,
+            ram_capacity: DEFAULT_RAM_CAPACITY,
+            disk_capacity: DEFAULT_DISK_CAPACITY,
+        }
+    }
+
+    pub fn read(&mut self, address: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        let end = address.saturating_add(len as u64);
+        if end > self.origin_size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Range {}..{} is past the end of the origin, {}",
+                    address, end, self.origin_size
+                ),
+            ));
+        }
+        let page_size = self.page_size as u64;
+        let mut data = Vec::with_capacity(len);
+        let mut position = address;
+        while position < end {
+            let page_id = position / page_size;
+            let offset = (position % page_size) as usize;
+            let part = std::cmp::min(end - position, page_size - offset as u64) as usize;
+            let page = self.page(page_id)?;
+            data.extend_from_slice(&page[offset..offset + part]);
+            position += part as u64;
+        }
+        Ok(data)
+    }
+
+    /// Size of the origin when the cache was opened.
+    pub fn size(&self) -> u64 {
+        self.origin_size
+    }
+
+    pub fn stats(&self) -> TieredStats {
+        self.stats
+    }
+
+    fn page(&mut self, page_id: u64) -> std::io::Result<&[u8]> {
+        if self.ram.touch(&page_id) {
+            self.stats.ram_hits += 1;
+        } else {
+            let data = match self.disk_index.remove(&page_id) {
+                Some((slot, len)) => {
+                    self.free_slots.push(slot);
+                    self.stats.disk_hits += 1;
+                    self.disk.read(slot * self.page_size as u64, len)?
+                }
+                None => {
+                    let start = page_id * self.page_size as u64;
+                    let len = std::cmp::min(self.page_size as u64, self.origin_size - start);
+                    self.stats.origin_reads += 1;
+                    self.origin.read_at(start, len as usize)?
+                }
+            };
+            if self.ram.len() >= self.ram_pages {
+                if let Some((evicted, data)) = self.ram.pop() {
+                    self.spill(evicted, data)?;
+                }
+            }
+            self.ram.insert(page_id, data);
+        }
+        Ok(self.ram.get(&page_id).expect("page was just made resident"))
+    }
+
+    // Move a page evicted from memory to the disk tier
+    fn spill(&mut self, page_id: u64, data: Vec<u8>) -> std::io::Result<()> {
+        if self.disk_slots == 0 {
+            return Ok(());
+        }
+        if self.free_slots.is_empty() {
+            if let Some((_, (slot, _))) = self.disk_index.pop() {
+                self.free_slots.push(slot);
+            }
+        }
+        let slot = self.free_slots.pop().expect("a slot was just freed");
+        self.disk.write(slot * self.page_size as u64, &data)?;
+        self.disk_index.insert(page_id, (slot, data.len()));
+        self.stats.spills += 1;
+        Ok(())
+    }
+}
+
+impl<K: Copy + Eq + Hash, V> Lru<K, V> {
+    fn new() -> Self {
+        Self {
+            entries: AHashMap::default(),
+            order: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    // Mark `key` as just used, returning whether it is present
+    fn touch(&mut self, key: &K) -> bool {
+        let Some((_, used)) = self.entries.get_mut(key) else {
+            return false;
+        };
+        self.order.remove(used);
+        self.clock += 1;
+        *used = self.clock;
+        self.order.insert(self.clock, *key);
+        true
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        self.remove(&key);
+        self.clock += 1;
+        self.entries.insert(key, (value, self.clock));
+        self.order.insert(self.clock, key);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let (value, used) = self.entries.remove(key)?;
+        self.order.remove(&used);
+        Some(value)
+    }
+
+    // Remove and return the least recently used entry
+    fn pop(&mut self) -> Option<(K, V)> {
+        let (_, key) = self.order.pop_first()?;
+        let (value, _) = self.entries.remove(&key)?;
+        Some((key, value))
+    }
+}
@@ -0,0 +1,78 @@
+use crate::audit::AuditLog;
+use crate::header::FileHeader;
+use crate::pio::read_exact_at;
+use crate::WriteThroughCache;
+
+/// Outcome of `WriteThroughCache::verify`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Pages checked: every page up to the end of the file.
+    pub pages: u64,
+    /// Pages that failed, with the reason.
+    pub bad_pages: Vec<(u64, String)>,
+    /// Whether the file has a header; one written before headers existed
+    /// has none, and its page size is assumed.
+    pub header_present: bool,
+    /// Why the header on disk failed validation, if it did.
+    pub header_error: Option<String>,
+    /// Records in the audit log, or why its hash chain is broken; `None`
+    /// when the file has no audit log.
+    pub audit_log: Option<Result<u64, String>>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.bad_pages.is_empty()
+            && self.header_error.is_none()
+            && !matches!(self.audit_log, Some(Err(_)))
+    }
+}
+
+impl WriteThroughCache {
+    /// Check the file on disk: the header checksum, that every page can be
+    /// read back (and decoded, in a compressed file), that resident pages
+    /// match what is on disk, and the audit log's hash chain. Pages are read
+    /// straight from disk without being cached.
+    pub fn verify(&mut self) -> std::io::Result<VerifyReport> {
+        let mut report = VerifyReport {
+            pages: self.file_size.div_ceil(self.page_size as u64),
+            ..Default::default()
+        };
+
+        match FileHeader::load(&self.file_path) {
+            Ok(header) => report.header_present = header.is_some(),
+            Err(e) => {
+                report.header_present = true;
+                report.header_error = Some(e.to_string());
+            }
+        }
+
+        let mut page = vec![0; self.page_size];
+        for page_id in 0..report.pages {
+            let offset = page_id * self.page_size as u64;
+            let len = std::cmp::min(self.page_size as u64, self.file_size - offset) as usize;
+            page[len..].fill(0);
+            self.stats.disk_read(len);
+            let result = match &mut self.compressed {
+                Some(store) => store.read_page(&self.file, page_id, &mut page),
+                None => read_exact_at(&self.file, &mut page[..len], offset),
+            };
+            let resident = self.cache.get(page_id).map(|slot| self.cache.frame(slot));
+            match result {
+                Err(e) => report.bad_pages.push((page_id, e.to_string())),
+                Ok(()) if resident.is_some_and(|frame| self.pool.frame(frame) != page) => {
+                    report
+                        .bad_pages
+                        .push((page_id, "Resident copy differs from disk".to_string()));
+                }
+                Ok(()) => {}
+            }
+        }
+
+        if AuditLog::path_for(&self.file_path).exists() {
+            report.audit_log = Some(AuditLog::verify(&self.file_path).map_err(|e| e.to_string()));
+        }
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,67 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::pio::{read_exact_at, write_all_at};
+
+// Sidecar layout: slot 0 holds the last assigned LSN, slot `page_id + 1` the
+// LSN of the last write to that page. Unwritten pages read back as 0.
+pub struct PageVersions {
+    file: File,
+    last_lsn: u64,
+}
+
+impl PageVersions {
+    pub fn path_for(file_path: &Path) -> PathBuf {
+        let mut path = file_path.as_os_str().to_owned();
+        path.push(".lsn");
+        PathBuf::from(path)
+    }
+
+    pub fn open(file_path: &Path) -> std::io::Result<Self> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(Self::path_for(file_path))?;
+
+        let mut versions = Self { file, last_lsn: 0 };
+        versions.last_lsn = versions.read_slot(0)?;
+        Ok(versions)
+    }
+
+    pub fn last_lsn(&self) -> u64 {
+        self.last_lsn
+    }
+
+    pub fn next_lsn(&mut self) -> u64 {
+        self.last_lsn += 1;
+        self.last_lsn
+    }
+
+    pub fn get(&mut self, page_id: u64) -> std::io::Result<u64> {
+        self.read_slot(page_id + 1)
+    }
+
+    /// Stamp `pages` consecutive pages with `lsn` in a single write and sync.
+    pub fn set_range(&mut self, first_page: u64, pages: u64, lsn: u64) -> std::io::Result<()> {
+        let slots: Vec<u8> = (0..pages).flat_map(|_| lsn.to_le_bytes()).collect();
+        write_all_at(&self.file, &slots, (first_page + 1) * 8)?;
+        self.write_slot(0, self.last_lsn)?;
+        self.file.sync_data()
+    }
+
+    fn read_slot(&mut self, slot: u64) -> std::io::Result<u64> {
+        if (slot + 1) * 8 > self.file.metadata()?.len() {
+            return Ok(0);
+        }
+
+        let mut bytes = [0; 8];
+        read_exact_at(&self.file, &mut bytes, slot * 8)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn write_slot(&mut self, slot: u64, value: u64) -> std::io::Result<()> {
+        write_all_at(&self.file, &value.to_le_bytes(), slot * 8)
+    }
+}
@@ -0,0 +1,62 @@
+use rayon::prelude::*;
+use zeroize::Zeroize;
+
+use crate::pio::read_exact_at;
+use crate::WriteThroughCache;
+
+// Pages read by one parallel batch before they are installed, bounding the
+// memory held outside the cache
+const BATCH_PAGES_PER_THREAD: usize = 64;
+
+impl WriteThroughCache {
+    /// Load the pages covering each `(address, len)` range into the cache,
+    /// reading them from disk on rayon's thread pool. Stops once the cache
+    /// is full and skips pages already cached or past the last whole page.
+    /// Returns the number of pages loaded.
+    pub fn warm(&mut self, ranges: &[(u64, u64)]) -> std::io::Result<u64> {
+        let page_size = self.page_size as u64;
+        let file_pages = self.file_size / page_size;
+        let mut pages: Vec<u64> = ranges
+            .iter()
+            .filter(|&&(_, len)| len > 0)
+            .flat_map(|&(address, len)| {
+                address / page_size..address.saturating_add(len).div_ceil(page_size)
+            })
+            .filter(|&page_id| page_id < file_pages && !self.cache.contains(page_id))
+            .collect();
+        pages.sort_unstable();
+        pages.dedup();
+        pages.truncate(self.cache_pages());
+
+        // Compressed files, and caches whose compressed tier may hold some
+        // of the pages, load through the usual path, which decodes them
+        if self.compressed.is_some() || self.tier.is_some() {
+            for &page_id in &pages {
+                self.load_page(page_id)?;
+            }
+            return Ok(pages.len() as u64);
+        }
+
+        let batch = rayon::current_num_threads() * BATCH_PAGES_PER_THREAD;
+        for chunk in pages.chunks(batch) {
+            let file = &self.file;
+            let read: Vec<Vec<u8>> = chunk
+                .par_iter()
+                .map(|&page_id| {
+                    let mut page = vec![0; page_size as usize];
+                    read_exact_at(file, &mut page, page_id * page_size).map(|_| page)
+                })
+                .collect::<std::io::Result<_>>()?;
+            self.stats.disk_read(read.len() * self.page_size);
+            for (&page_id, mut page) in chunk.iter().zip(read) {
+                let frame = self.pool.take();
+                self.pool.frame_mut(frame).copy_from_slice(&page);
+                self.add_to_cache(page_id, frame);
+                if self.secure {
+                    page.zeroize();
+                }
+            }
+        }
+        Ok(pages.len() as u64)
+    }
+}
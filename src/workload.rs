@@ -0,0 +1,199 @@
+//! Synthetic access patterns for exercising a cache, used by the benchmarks
+//! and usable from user code to compare configurations.
+
+use crate::WriteThroughCache;
+
+/// One operation of a workload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Read { address: u64, len: usize },
+    Write { address: u64, len: usize },
+}
+
+impl Op {
+    /// Run the operation against `cache`. Writes store `len` bytes of `fill`.
+    pub fn apply(&self, cache: &mut WriteThroughCache, fill: u8) -> std::io::Result<()> {
+        match *self {
+            Op::Read { address, len } => cache.read(address, len).map(drop),
+            Op::Write { address, len } => cache.write(address, &vec![fill; len]),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    Random,
+    Sequential { next: u64 },
+    Zipfian(Zipf),
+}
+
+/// An endless, reproducible stream of operations over a file of `file_size`
+/// bytes, each `io_size` bytes long and aligned to `io_size`.
+#[derive(Debug, Clone)]
+pub struct Workload {
+    slots: u64,
+    io_size: usize,
+    // Share of operations that are reads, in parts per million
+    read_ppm: u32,
+    pattern: Pattern,
+    rng: SplitMix64,
+}
+
+impl Workload {
+    /// Reads spread uniformly over the file.
+    pub fn random_reads(file_size: u64, io_size: usize, seed: u64) -> Self {
+        Self::new(file_size, io_size, 1.0, Pattern::Random, seed)
+    }
+
+    /// Writes spread uniformly over the file.
+    pub fn random_writes(file_size: u64, io_size: usize, seed: u64) -> Self {
+        Self::new(file_size, io_size, 0.0, Pattern::Random, seed)
+    }
+
+    /// Reads and writes spread uniformly over the file; `read_ratio` is the
+    /// share of reads.
+    pub fn random_mixed(file_size: u64, io_size: usize, read_ratio: f64, seed: u64) -> Self {
+        Self::new(file_size, io_size, read_ratio, Pattern::Random, seed)
+    }
+
+    /// Reads covering the file front to back, wrapping at the end.
+    pub fn sequential_reads(file_size: u64, io_size: usize) -> Self {
+        Self::new(file_size, io_size, 1.0, Pattern::Sequential { next: 0 }, 0)
+    }
+
+    /// Writes covering the file front to back, wrapping at the end.
+    pub fn sequential_writes(file_size: u64, io_size: usize) -> Self {
+        Self::new(file_size, io_size, 0.0, Pattern::Sequential { next: 0 }, 0)
+    }
+
+    /// Reads and writes skewed towards a few hot offsets with a Zipf
+    /// distribution of exponent `theta` (0 < theta < 1; 0.99 is the usual
+    /// YCSB setting). `read_ratio` is the share of reads.
+    pub fn zipfian(file_size: u64, io_size: usize, theta: f64, read_ratio: f64, seed: u64) -> Self {
+        let slots = Self::slots(file_size, io_size);
+        Self::new(
+            file_size,
+            io_size,
+            read_ratio,
+            Pattern::Zipfian(Zipf::new(slots, theta)),
+            seed,
+        )
+    }
+
+    /// Operations needed to write every byte of the file once, so a cache
+    /// can be populated before reads are timed.
+    pub fn fill(file_size: u64, io_size: usize) -> impl Iterator<Item = Op> {
+        Self::sequential_writes(file_size, io_size).take(Self::slots(file_size, io_size) as usize)
+    }
+
+    /// Apply the next `ops` operations to `cache`.
+    pub fn run(&mut self, cache: &mut WriteThroughCache, ops: usize) -> std::io::Result<()> {
+        for (index, op) in self.take(ops).enumerate() {
+            op.apply(cache, index as u8)?;
+        }
+        Ok(())
+    }
+
+    fn new(file_size: u64, io_size: usize, read_ratio: f64, pattern: Pattern, seed: u64) -> Self {
+        Self {
+            slots: Self::slots(file_size, io_size),
+            io_size,
+            read_ppm: (read_ratio.clamp(0.0, 1.0) * 1_000_000.0) as u32,
+            pattern,
+            rng: SplitMix64(seed),
+        }
+    }
+
+    fn slots(file_size: u64, io_size: usize) -> u64 {
+        assert!(io_size > 0, "io_size must be non-zero");
+        std::cmp::max(file_size / io_size as u64, 1)
+    }
+}
+
+impl Iterator for Workload {
+    type Item = Op;
+
+    fn next(&mut self) -> Option<Op> {
+        let slot = match &mut self.pattern {
+            Pattern::Random => self.rng.next_u64() % self.slots,
+            Pattern::Sequential { next } => {
+                let slot = *next;
+                *next = (slot + 1) % self.slots;
+                slot
+            }
+            Pattern::Zipfian(zipf) => {
+                // Scatter the hot ranks so they are not all at the start of
+                // the file
+                let rank = zipf.sample(self.rng.next_f64());
+                rank.wrapping_mul(0x9E37_79B9_7F4A_7C15) % self.slots
+            }
+        };
+
+        let address = slot * self.io_size as u64;
+        let len = self.io_size;
+        let read = match self.read_ppm {
+            0 => false,
+            1_000_000 => true,
+            ppm => (self.rng.next_u64() % 1_000_000) < ppm as u64,
+        };
+        Some(if read {
+            Op::Read { address, len }
+        } else {
+            Op::Write { address, len }
+        })
+    }
+}
+
+// Gray et al., "Quickly Generating Billion-Record Synthetic Databases"
+#[derive(Debug, Clone)]
+struct Zipf {
+    items: u64,
+    theta: f64,
+    alpha: f64,
+    zeta_n: f64,
+    eta: f64,
+}
+
+impl Zipf {
+    fn new(items: u64, theta: f64) -> Self {
+        let zeta = |n: u64| (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum::<f64>();
+        let zeta_n = zeta(items);
+        let zeta_2 = zeta(std::cmp::min(items, 2));
+        Self {
+            items,
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zeta_n,
+            eta: (1.0 - (2.0 / items as f64).powf(1.0 - theta)) / (1.0 - zeta_2 / zeta_n),
+        }
+    }
+
+    fn sample(&self, u: f64) -> u64 {
+        let uz = u * self.zeta_n;
+        if uz < 1.0 {
+            return 0;
+        }
+        if uz < 1.0 + 0.5f64.powf(self.theta) {
+            return 1;
+        }
+        let rank = (self.items as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha)) as u64;
+        std::cmp::min(rank, self.items - 1)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,641 @@
+#![cfg(feature = "cli")]
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+use tempfile::{tempdir, NamedTempFile, TempDir};
+
+// A fresh path in `dir`, which removes the file and its sidecars when the
+// test ends
+fn tmp_file(dir: &TempDir) -> PathBuf {
+    NamedTempFile::new_in(dir).unwrap().path().to_path_buf()
+}
+
+fn wt_cache(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_wt_cache"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: Output) -> String {
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_cli_read_write_hex() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+    stdout(wt_cache(&[
+        "write",
+        file,
+        "--page-size",
+        "4k",
+        "--offset",
+        "0x1000",
+        "--data",
+        "48656c6c6f",
+    ]));
+    assert_eq!(
+        stdout(wt_cache(&["read", file, "--offset", "4096", "--len", "5"])),
+        "48656c6c6f\n"
+    );
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 8 * 1024);
+}
+
+#[test]
+fn test_cli_read_write_files() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+    let input = tmp_file(&dir);
+    let output = tmp_file(&dir);
+    std::fs::write(&input, [7; 100]).unwrap();
+
+    stdout(wt_cache(&[
+        "write",
+        file,
+        "--offset",
+        "10",
+        "--input",
+        input.to_str().unwrap(),
+    ]));
+    stdout(wt_cache(&[
+        "read",
+        file,
+        "--offset",
+        "0",
+        "--len",
+        "110",
+        "--output",
+        output.to_str().unwrap(),
+    ]));
+    let data = std::fs::read(&output).unwrap();
+    assert_eq!(data[..10], [0; 10]);
+    assert_eq!(data[10..], [7; 100]);
+}
+
+#[test]
+fn test_cli_errors() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+
+    // Bad arguments are rejected before touching the file
+    let output = wt_cache(&["write", file, "--offset", "0", "--data", "abc"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!path.exists());
+
+    let output = wt_cache(&["read", file, "--offset", "1g", "--len", "1"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("wt_cache: "));
+}
+
+#[test]
+fn test_cli_dump() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+    stdout(wt_cache(&[
+        "write",
+        file,
+        "--page-size",
+        "4k",
+        "--offset",
+        "3",
+        "--data",
+        "48656c6c6f20776f726c640a0102",
+    ]));
+
+    assert_eq!(
+        stdout(wt_cache(&["dump", file, "--len", "20"])),
+        "00000000: 0000 0048 656c 6c6f 2077 6f72 6c64 0a01  ...Hello world..\n\
+         00000010: 0200 0000                                ....\n"
+    );
+    assert_eq!(
+        stdout(wt_cache(&["dump", file, "--offset", "4", "--len", "4"])),
+        "00000004: 656c 6c6f                                ello\n"
+    );
+    // The whole file by default
+    assert_eq!(stdout(wt_cache(&["dump", file])).lines().count(), 256);
+}
+
+#[test]
+fn test_cli_bench() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+    for workload in ["seq-read", "seq-write", "rand-read", "rand-write", "mixed"] {
+        let report = stdout(wt_cache(&[
+            "bench",
+            file,
+            "--workload",
+            workload,
+            "--size",
+            "1m",
+            "--duration",
+            "0.05",
+        ]));
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", report);
+        assert!(lines[0].contains(" ops in "), "{}", report);
+        assert!(lines[1].starts_with("latency: mean "), "{}", report);
+        assert!(lines[2].starts_with("cache: "), "{}", report);
+    }
+    // Filled once, up to the requested size
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 1024 * 1024);
+
+    let output = wt_cache(&["bench", file, "--io-size", "2m", "--size", "1m"]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_cli_fill() {
+    let dir = tempdir().unwrap();
+    let fill = |pattern: &str, seed: &str| {
+        let path = tmp_file(&dir);
+        stdout(wt_cache(&[
+            "fill",
+            path.to_str().unwrap(),
+            "--page-size",
+            "4k",
+            "--size",
+            "3m",
+            "--pattern",
+            pattern,
+            "--seed",
+            seed,
+        ]));
+        let data = std::fs::read(&path).unwrap();
+        assert_eq!(data.len(), 3 * 1024 * 1024);
+        data
+    };
+
+    assert!(fill("zero", "1").iter().all(|&byte| byte == 0));
+    assert!(fill("byte=0x41", "1").iter().all(|&byte| byte == b'A'));
+    let seq = fill("seq", "1");
+    assert!(seq
+        .iter()
+        .enumerate()
+        .all(|(offset, &byte)| byte == offset as u8));
+
+    // Random data is reproducible for a seed and differs between seeds
+    let random = fill("random", "1");
+    assert_eq!(random, fill("random", "1"));
+    assert_ne!(random, fill("random", "2"));
+    let zeros = random.iter().filter(|&&byte| byte == 0).count();
+    assert!(zeros < random.len() / 128);
+
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+    for pattern in ["byte=256", "ones"] {
+        let output = wt_cache(&["fill", file, "--size", "1k", "--pattern", pattern]);
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+#[test]
+fn test_cli_verify() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+    stdout(wt_cache(&[
+        "fill",
+        file,
+        "--page-size",
+        "4k",
+        "--size",
+        "8k",
+    ]));
+    assert_eq!(
+        stdout(wt_cache(&["verify", file, "--verbose"])),
+        "page 0: ok\npage 1: ok\nheader: ok\n2 pages checked, 0 bad\n"
+    );
+
+    // Corruption is reported with a failing exit status
+    let mut header_path = path.clone().into_os_string();
+    header_path.push(".hdr");
+    let mut header = std::fs::read(&header_path).unwrap();
+    header[0] ^= 1;
+    std::fs::write(&header_path, header).unwrap();
+    let output = wt_cache(&["verify", file]);
+    assert_eq!(output.status.code(), Some(1));
+
+    // A missing file is an error, and is not created
+    let missing = tmp_file(&dir);
+    let output = wt_cache(&["verify", missing.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .ends_with("does not exist\n"));
+    let mut missing_header = missing.clone().into_os_string();
+    missing_header.push(".hdr");
+    assert!(!missing.exists());
+    assert!(!PathBuf::from(missing_header).exists());
+}
+
+#[test]
+fn test_cli_export_import() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+    // Larger than one chunk, and not a whole number of pages
+    let data: Vec<u8> = (0..3 * 1024 * 1024 + 7).map(|i| (i % 251) as u8).collect();
+
+    let mut import = Command::new(env!("CARGO_BIN_EXE_wt_cache"))
+        .args(["import", file, "--page-size", "4k", "--offset", "100"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    import.stdin.take().unwrap().write_all(&data).unwrap();
+    assert!(import.wait().unwrap().success());
+
+    let output = wt_cache(&[
+        "export",
+        file,
+        "--offset",
+        "100",
+        "--len",
+        &data.len().to_string(),
+    ]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, data);
+
+    // The whole file by default, up to its last page
+    let output = wt_cache(&["export", file]);
+    assert_eq!(
+        output.stdout.len(),
+        (100 + data.len()).div_ceil(4096) * 4096
+    );
+    assert_eq!(output.stdout[100..100 + data.len()], data);
+}
+
+#[test]
+fn test_cli_tail() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+    stdout(wt_cache(&[
+        "write",
+        file,
+        "--page-size",
+        "4k",
+        "--offset",
+        "0",
+        "--data",
+        "68656c6c6f",
+    ]));
+
+    let tail = Command::new(env!("CARGO_BIN_EXE_wt_cache"))
+        .args([
+            "tail",
+            file,
+            "--offset",
+            "5",
+            "--len",
+            "4102",
+            "--interval",
+            "10",
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Appends within the last page, then one that grows the file
+    stdout(wt_cache(&[
+        "write",
+        file,
+        "--offset",
+        "5",
+        "--data",
+        "20776f726c64",
+    ]));
+    let more = "ab".repeat(4096);
+    stdout(wt_cache(&[
+        "write", file, "--offset", "11", "--data", &more,
+    ]));
+
+    let output = tail.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let mut expected = b" world".to_vec();
+    expected.extend([0xab; 4096]);
+    assert_eq!(output.stdout, expected);
+}
+
+#[test]
+fn test_cli_shell() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let mut shell = Command::new(env!("CARGO_BIN_EXE_wt_cache"))
+        .args(["shell", path.to_str().unwrap(), "--page-size", "4k"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    shell
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(
+            b"w 0x10 48656c6c6f\nr 16 5\n\nr 16 5\nevict\nbogus\nflush\nstats\nquit\nr 0 1\n",
+        )
+        .unwrap();
+    let output = shell.wait_with_output().unwrap();
+    assert!(output.status.success());
+    // The cache stays open between commands, so the second read is a hit
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "00000010: 4865 6c6c 6f                             Hello\n\
+         00000010: 4865 6c6c 6f                             Hello\n\
+         evicted 1 pages\n\
+         2 hits, 0 misses, 1 evictions, 0 disk reads, 1 disk writes, 2 fsyncs\n"
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "error: unknown command \"bogus\"; try help\n"
+    );
+}
+
+#[test]
+fn test_cli_info() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+    stdout(wt_cache(&[
+        "fill",
+        file,
+        "--page-size",
+        "4k",
+        "--size",
+        "8k",
+    ]));
+
+    let info = stdout(wt_cache(&["info", file]));
+    assert!(info.contains("file size:   8192 bytes\n"), "{}", info);
+    assert!(info.contains("pages:       2\n"), "{}", info);
+    assert!(
+        info.contains("header:      version 3, features: none\n"),
+        "{}",
+        info
+    );
+
+    let json = stdout(wt_cache(&["info", file, "--json"]));
+    assert!(
+        json.starts_with("{\"file_size\":8192,\"disk_size\":8192,"),
+        "{}",
+        json
+    );
+    assert!(json.contains("\"page_size\":4096,\"pages\":2,"), "{}", json);
+    assert!(
+        json.ends_with(
+            "\"audit_log\":false,\"audit_records\":null,\"audit_error\":null,\"last_lsn\":null}\n"
+        ),
+        "{}",
+        json
+    );
+
+    // A file written before headers existed only has an assumed page size
+    let legacy = tmp_file(&dir);
+    std::fs::write(&legacy, [1; 100]).unwrap();
+    let legacy = legacy.to_str().unwrap();
+    let info = stdout(wt_cache(&["info", legacy]));
+    assert!(
+        info.contains("header:      no header (page size assumed)\n"),
+        "{}",
+        info
+    );
+    let json = stdout(wt_cache(&["info", legacy, "--json"]));
+    assert!(
+        json.contains("\"header_present\":false,\"header_version\":null,"),
+        "{}",
+        json
+    );
+    let report = stdout(wt_cache(&["verify", legacy]));
+    assert!(
+        report.contains("header: no header (page size assumed)\n"),
+        "{}",
+        report
+    );
+
+    let missing = tmp_file(&dir);
+    let output = wt_cache(&["info", missing.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!missing.exists());
+}
+
+#[test]
+fn test_cli_compact() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+    stdout(wt_cache(&[
+        "fill",
+        file,
+        "--page-size",
+        "4k",
+        "--size",
+        "64k",
+    ]));
+    stdout(wt_cache(&[
+        "fill",
+        file,
+        "--size",
+        "4k",
+        "--pattern",
+        "seq",
+    ]));
+
+    let dest = tmp_file(&dir);
+    let report = stdout(wt_cache(&[
+        "compact",
+        file,
+        dest.to_str().unwrap(),
+        "--compression",
+        "lz4",
+    ]));
+    // The last page is kept so the size carries over
+    assert!(
+        report.starts_with("16 pages, 14 all-zero left as holes\n"),
+        "{}",
+        report
+    );
+    assert!(report.contains("saved"), "{}", report);
+    let compacted = wt_cache(&["export", dest.to_str().unwrap()]);
+    assert!(compacted.status.success());
+    assert_eq!(compacted.stdout, wt_cache(&["export", file]).stdout);
+
+    let output = wt_cache(&["compact", file, dest.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let output = wt_cache(&["compact", file, "x", "--compression", "brotli"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cli_convert() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+    stdout(wt_cache(&[
+        "fill",
+        file,
+        "--page-size",
+        "4k",
+        "--size",
+        "32k",
+    ]));
+    stdout(wt_cache(&[
+        "fill",
+        file,
+        "--size",
+        "12k",
+        "--pattern",
+        "seq",
+    ]));
+
+    let dest = tmp_file(&dir);
+    let dest = dest.to_str().unwrap();
+    let report = stdout(wt_cache(&["convert", file, dest, "--page-size", "16k"]));
+    assert!(
+        report.starts_with("2 pages, 0 all-zero left as holes\n"),
+        "{}",
+        report
+    );
+    assert!(stdout(wt_cache(&["info", dest])).contains("page size:   16384 bytes\n"));
+    let converted = wt_cache(&["export", dest]);
+    assert!(converted.status.success());
+    assert_eq!(converted.stdout, wt_cache(&["export", file]).stdout);
+
+    let output = wt_cache(&["convert", file, "x", "--page-size", "100"]);
+    assert!(!output.status.success());
+
+    let missing = tmp_file(&dir);
+    let output = wt_cache(&[
+        "convert",
+        missing.to_str().unwrap(),
+        &format!("{}.new", dest),
+        "--page-size",
+        "4k",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!missing.exists());
+}
+
+#[test]
+fn test_cli_dd_options() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+    let data: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+
+    // Skip the first block of stdin, then write two blocks from block 3
+    let mut import = Command::new(env!("CARGO_BIN_EXE_wt_cache"))
+        .args(["import", file, "--page-size", "4k", "--bs", "1k"])
+        .args(["--skip", "1", "--seek", "3", "--count", "2"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    import.stdin.take().unwrap().write_all(&data).unwrap();
+    assert!(import.wait().unwrap().success());
+
+    let output = wt_cache(&["export", file, "--bs", "1k", "--skip", "3", "--count", "2"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, data[1024..3072]);
+    let output = wt_cache(&["export", file, "--skip", "6", "--count", "4"]);
+    assert_eq!(output.stdout, data[1024..3072]);
+
+    let output = wt_cache(&["export", file, "--offset", "0", "--skip", "1"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cli_json() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+    stdout(wt_cache(&[
+        "fill",
+        file,
+        "--page-size",
+        "4k",
+        "--size",
+        "16k",
+    ]));
+
+    // The flag goes before or after the subcommand
+    assert_eq!(
+        stdout(wt_cache(&["--json", "verify", file])),
+        "{\"ok\":true,\"pages\":4,\"bad_pages\":[],\"header_present\":true,\"header_error\":null,\
+         \"audit_log\":false,\"audit_records\":null,\"audit_error\":null}\n"
+    );
+    assert!(stdout(wt_cache(&["info", file, "--json"])).starts_with("{\"file_size\":16384,"));
+
+    let bench = stdout(wt_cache(&[
+        "bench",
+        file,
+        "--json",
+        "--size",
+        "16k",
+        "--duration",
+        "0.05",
+    ]));
+    assert!(bench.starts_with("{\"ops\":"), "{}", bench);
+    assert!(bench.contains(",\"latency_ns\":{\"mean\":"), "{}", bench);
+    assert!(bench.contains(",\"cache\":{\"hits\":"), "{}", bench);
+
+    let mut shell = Command::new(env!("CARGO_BIN_EXE_wt_cache"))
+        .args(["--json", "shell", file])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    shell
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"r 0 1\nstats\n")
+        .unwrap();
+    let output = shell.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().ends_with(
+        "{\"hits\":0,\"misses\":1,\"evictions\":0,\"disk_reads\":1,\"disk_writes\":0,\
+         \"fsyncs\":0,\"bytes_read\":4096,\"bytes_written\":0}\n"
+    ));
+}
+
+#[test]
+fn test_cli_stress() {
+    let dir = tempdir().unwrap();
+    let path = tmp_file(&dir);
+    let file = path.to_str().unwrap();
+    let report = stdout(wt_cache(&[
+        "stress",
+        file,
+        "--page-size",
+        "4k",
+        "--capacity",
+        "32k",
+        "--size",
+        "256k",
+        "--threads",
+        "3",
+        "--duration",
+        "0.3",
+    ]));
+    assert!(
+        report.ends_with(" full checks\nno divergence\n"),
+        "{}",
+        report
+    );
+    assert!(!report.starts_with("0 reads"), "{}", report);
+
+    let output = wt_cache(&["stress", file, "--mix", "0:0:0"]);
+    assert!(!output.status.success());
+    let output = wt_cache(&["stress", file, "--threads", "0"]);
+    assert!(!output.status.success());
+}
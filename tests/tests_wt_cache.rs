@@ -1,9 +1,41 @@
+use std::cell::RefCell;
 use std::{io::ErrorKind, path::PathBuf};
-use tempfile::NamedTempFile;
-use wt_cache::WriteThroughCache;
+use tempfile::{tempdir, NamedTempFile, TempDir};
+use wt_cache::actor;
+use wt_cache::allocator::PageAllocator;
+use wt_cache::bitmap::Bitmap;
+use wt_cache::blob::{BlobId, BlobStore};
+use wt_cache::btree::BTree;
+use wt_cache::kv::KvStore;
+use wt_cache::readthrough::ReadThroughCache;
+use wt_cache::replication::{Follower, Primary};
+use wt_cache::ring::RingCache;
+use wt_cache::segmented::SegmentedCache;
+#[cfg(unix)]
+use wt_cache::shm::ShmCache;
+use wt_cache::slotted::SlottedPage;
+use wt_cache::superblock::Superblock;
+use wt_cache::tiered::{TieredCache, TieredStats};
+#[cfg(feature = "epoch")]
+use wt_cache::ReadMostlyCache;
+use wt_cache::{
+    Backup, BackupReport, CacheEvents, CacheManager, CacheReader, CacheState, CacheStats,
+    ChangeEvent, Codec, CompactOptions, Compression, DiskOp, Epoch, FileLock, FileOrigin, Heatmap,
+    Lz4Codec, Origin, PageLocation, PageState, Partition, StatsRates, StatsSnapshot, SyncCache,
+    VerifyReport, WriteThroughCache, CODEC_LZ4, CODEC_RAW, CUSTOM_CODEC_BASE,
+};
+
+thread_local! {
+    // Each test runs on its own thread, so its directories, and with them
+    // every file and sidecar it created, are removed when it finishes
+    static TMP_DIRS: RefCell<Vec<TempDir>> = const { RefCell::new(Vec::new()) };
+}
 
 fn tmp_file() -> PathBuf {
-    NamedTempFile::new().unwrap().path().to_path_buf()
+    let dir = tempdir().unwrap();
+    let path = NamedTempFile::new_in(&dir).unwrap().path().to_path_buf();
+    TMP_DIRS.with(|dirs| dirs.borrow_mut().push(dir));
+    path
 }
 
 #[test]
@@ -451,3 +483,3925 @@ fn test_write_read_non_aligned_address() {
 
     assert_eq!(data, read_data);
 }
+
+#[test]
+fn test_builder_secure_eviction() {
+    let page_size = 64 * 1024;
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .capacity(2 * page_size) // Only enough capacity for two pages
+        .secure(true)
+        .open()
+        .unwrap();
+
+    let data1 = vec![1; page_size];
+    let data2 = vec![2; page_size];
+    let data3 = vec![3; page_size];
+
+    cache.write(0, &data1).unwrap();
+    cache.write(page_size as u64, &data2).unwrap();
+    cache.write(2 * page_size as u64, &data3).unwrap(); // Evicts (and wipes) the first page
+
+    assert_eq!(data1, cache.read(0, page_size).unwrap());
+    assert_eq!(data2, cache.read(page_size as u64, page_size).unwrap());
+    assert_eq!(data3, cache.read(2 * page_size as u64, page_size).unwrap());
+}
+
+#[test]
+fn test_builder_secure_compressed() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .capacity(page_size)
+        .compression(Compression::Lz4)
+        .secure(true)
+        .open()
+        .unwrap();
+
+    // One compressible page and one stored raw, each read back from disk
+    // after the other evicts it
+    let data1 = vec![1; page_size];
+    let data2 = (0..page_size)
+        .map(|i| (i * 7919 % 251) as u8 ^ (i >> 5) as u8)
+        .collect::<Vec<_>>();
+    cache.write(0, &data1).unwrap();
+    cache.write(page_size as u64, &data2).unwrap();
+    assert_eq!(data1, cache.read(0, page_size).unwrap());
+    assert_eq!(data2, cache.read(page_size as u64, page_size).unwrap());
+    drop(cache);
+
+    let mut cache = WriteThroughCache::builder(&path)
+        .compression(Compression::Lz4)
+        .secure(true)
+        .open()
+        .unwrap();
+    assert_eq!(data1, cache.read(0, page_size).unwrap());
+    assert_eq!(data2, cache.read(page_size as u64, page_size).unwrap());
+}
+
+#[test]
+fn test_header_page_size_mismatch() {
+    let path = tmp_file();
+    let mut cache = WriteThroughCache::new(&path, Some(4 * 1024), None).unwrap();
+    cache.write(0, &[7; 100]).unwrap();
+    drop(cache);
+
+    let result = WriteThroughCache::new(&path, Some(64 * 1024), None);
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+
+    // Without an explicit page size the one recorded in the header is used
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    assert_eq!(cache.read(0, 100).unwrap(), vec![7; 100]);
+}
+
+#[test]
+fn test_header_legacy_file() {
+    let path = tmp_file();
+    std::fs::write(&path, [5; 100]).unwrap();
+    let mut header_path = path.clone().into_os_string();
+    header_path.push(".hdr");
+
+    // The page size of a file without a header is only a guess, so it is
+    // not recorded
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    assert_eq!(cache.read(0, 100).unwrap(), vec![5; 100]);
+    drop(cache);
+    assert!(!PathBuf::from(&header_path).exists());
+    let result = WriteThroughCache::builder(&path).page_versions(true).open();
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+    assert!(!PathBuf::from(&header_path).exists());
+
+    drop(WriteThroughCache::new(&path, Some(8 * 1024), None).unwrap());
+    assert!(PathBuf::from(&header_path).exists());
+    let cache = WriteThroughCache::new(&path, None, None).unwrap();
+    assert_eq!(cache.page_size(), 8 * 1024);
+}
+
+#[test]
+fn test_header_newer_version() {
+    let path = tmp_file();
+    let mut header_path = path.clone().into_os_string();
+    header_path.push(".hdr");
+    // A future layout the tables of this version can't be parsed from
+    let mut bytes = b"WTCACHE\0".to_vec();
+    bytes.extend_from_slice(&4u32.to_le_bytes());
+    bytes.extend_from_slice(&4096u32.to_le_bytes());
+    bytes.extend_from_slice(&0u64.to_le_bytes());
+    bytes.extend_from_slice(&[0xff; 3]);
+    bytes.extend_from_slice(&crc32fast::hash(&bytes).to_le_bytes());
+    std::fs::write(&header_path, bytes).unwrap();
+
+    let err = WriteThroughCache::new(&path, None, None).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "Unsupported file format version 4");
+}
+
+#[test]
+fn test_header_corrupted() {
+    let path = tmp_file();
+    drop(WriteThroughCache::new(&path, None, None).unwrap());
+
+    let mut header_path = path.clone().into_os_string();
+    header_path.push(".hdr");
+    let mut bytes = std::fs::read(&header_path).unwrap();
+    bytes[12] ^= 0xff; // Flip bits in the page size field
+    std::fs::write(&header_path, bytes).unwrap();
+
+    let result = WriteThroughCache::new(&path, None, None);
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_page_versions() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .page_versions(true)
+        .open()
+        .unwrap();
+
+    cache.write(0, &[1; 10]).unwrap();
+    cache.write(page_size as u64 - 5, &[2; 10]).unwrap(); // Straddles pages 0 and 1
+    cache.write(3 * page_size as u64, &[3; 10]).unwrap();
+
+    assert_eq!(cache.page_version(0).unwrap(), 2);
+    assert_eq!(cache.page_version(1).unwrap(), 2);
+    assert_eq!(cache.page_version(2).unwrap(), 0);
+    assert_eq!(cache.page_version(3).unwrap(), 3);
+    drop(cache);
+
+    // Versions stay enabled and keep increasing after reopening
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    assert_eq!(cache.last_lsn(), Some(3));
+    cache.write(page_size as u64, &[4; 10]).unwrap();
+    assert_eq!(cache.page_version(1).unwrap(), 4);
+    assert_eq!(cache.page_version(0).unwrap(), 2);
+}
+
+#[test]
+fn test_page_versions_disabled() {
+    let mut cache = WriteThroughCache::new(&tmp_file(), None, None).unwrap();
+    cache.write(0, &[1; 10]).unwrap();
+
+    assert_eq!(cache.last_lsn(), None);
+    assert_eq!(
+        cache.page_version(0).unwrap_err().kind(),
+        ErrorKind::Unsupported
+    );
+}
+
+#[test]
+fn test_audit_log() {
+    let path = tmp_file();
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(4 * 1024)
+        .audit_log(true)
+        .open()
+        .unwrap();
+
+    cache.write(0, &[1; 100]).unwrap();
+    cache.write(5000, &[2; 10000]).unwrap();
+    let head = cache.audit_head().unwrap();
+    drop(cache);
+
+    // The chain continues across reopening
+    let mut cache = WriteThroughCache::builder(&path)
+        .audit_log(true)
+        .open()
+        .unwrap();
+    assert_eq!(cache.audit_head(), Some(head));
+    cache.write(10, &[3; 10]).unwrap();
+    drop(cache);
+
+    assert_eq!(WriteThroughCache::verify_audit_log(&path).unwrap(), 3);
+
+    let mut audit_path = path.clone().into_os_string();
+    audit_path.push(".audit");
+    let mut bytes = std::fs::read(&audit_path).unwrap();
+    bytes[88] ^= 1; // Tamper with the offset of the second record
+    std::fs::write(&audit_path, bytes).unwrap();
+
+    let result = WriteThroughCache::verify_audit_log(&path);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+}
+
+// Size of the audit log each time the data file is synced
+struct AuditAtFlush(PathBuf, std::sync::Arc<std::sync::Mutex<Vec<u64>>>);
+
+impl CacheEvents for AuditAtFlush {
+    fn on_flush(&self, _elapsed: std::time::Duration) {
+        let len = std::fs::metadata(&self.0).map_or(0, |metadata| metadata.len());
+        self.1.lock().unwrap().push(len);
+    }
+}
+
+#[test]
+fn test_audit_log_precedes_data() {
+    let path = tmp_file();
+    let mut audit_path = path.clone().into_os_string();
+    audit_path.push(".audit");
+    let sizes = std::sync::Arc::default();
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(4 * 1024)
+        .audit_log(true)
+        .events(Box::new(AuditAtFlush(
+            audit_path.into(),
+            std::sync::Arc::clone(&sizes),
+        )))
+        .open()
+        .unwrap();
+
+    // Each write's record is on disk by the time its data is synced
+    cache.write(0, &[1; 100]).unwrap();
+    cache.write(5000, &[2; 10]).unwrap();
+    assert_eq!(*sizes.lock().unwrap(), [88, 176]);
+}
+
+#[test]
+fn test_lz4_compression() {
+    let path = tmp_file();
+    let page_size = 64 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .compression(Compression::Lz4)
+        .open()
+        .unwrap();
+
+    let data: Vec<u8> = (0..4 * page_size).map(|i| (i / 1000) as u8).collect();
+    cache.write(100, &data).unwrap();
+    cache.write(7 * page_size as u64, &[9; 10]).unwrap();
+    drop(cache);
+
+    // Highly compressible data takes a fraction of its logical size on disk
+    assert!(std::fs::metadata(&path).unwrap().len() < page_size as u64);
+
+    let mut cache = WriteThroughCache::new(&path, None, Some(2 * page_size)).unwrap();
+    assert_eq!(cache.read(100, data.len()).unwrap(), data);
+    assert_eq!(cache.read(6 * page_size as u64, 10).unwrap(), vec![0; 10]);
+    assert_eq!(cache.read(7 * page_size as u64, 10).unwrap(), vec![9; 10]);
+
+    let result = cache.read(8 * page_size as u64, 10);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_compressed_rewrite_keeps_old_payload() {
+    let path = tmp_file();
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(4096)
+        .compression(Compression::Lz4)
+        .open()
+        .unwrap();
+    cache.write(0, &[1; 4096]).unwrap();
+    let before = std::fs::read(&path).unwrap();
+
+    // A payload that would fit in the old slot still goes to new space, so
+    // a crash before the index is updated leaves the old page readable
+    cache.write(0, &[2; 4096]).unwrap();
+    let after = std::fs::read(&path).unwrap();
+    assert!(after.len() > before.len());
+    assert_eq!(after[..before.len()], before[..]);
+    drop(cache);
+
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    assert_eq!(cache.read(0, 4096).unwrap(), vec![2; 4096]);
+}
+
+#[test]
+fn test_compressed_rewrites_reuse_space() {
+    let path = tmp_file();
+    let page_size = 4096;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .compression(Compression::Lz4)
+        .open()
+        .unwrap();
+    cache.write(page_size as u64, &vec![9; page_size]).unwrap();
+
+    // Payloads of varying size, so freed slots have to be split and merged
+    let mut page = vec![0; page_size];
+    for i in 0..200usize {
+        let noisy = (i % 5) * 512;
+        for (j, byte) in page[..noisy].iter_mut().enumerate() {
+            *byte = (j * 31 + i * 7) as u8 ^ (j >> 3) as u8;
+        }
+        page[noisy..].fill(i as u8);
+        cache.write(0, &page).unwrap();
+    }
+    assert!(std::fs::metadata(&path).unwrap().len() < 3 * page_size as u64);
+    drop(cache);
+
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    assert_eq!(cache.read(0, page_size).unwrap(), page);
+    cache.write(0, &vec![1; page_size]).unwrap();
+    assert_eq!(
+        cache.read(page_size as u64, page_size).unwrap(),
+        vec![9; page_size]
+    );
+    assert!(std::fs::metadata(&path).unwrap().len() < 3 * page_size as u64);
+}
+
+#[test]
+fn test_compression_requires_empty_file() {
+    let path = tmp_file();
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    cache.write(0, &[1; 10]).unwrap();
+    drop(cache);
+
+    let result = WriteThroughCache::builder(&path)
+        .compression(Compression::Lz4)
+        .open();
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn test_zstd_dictionary() {
+    let path = tmp_file();
+    let page_size = 64 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .compression(Compression::Zstd { level: 3 })
+        .open()
+        .unwrap();
+
+    // Small structured records with a shared layout
+    let record = |i: usize| format!("{{\"id\":{},\"kind\":\"sensor\",\"value\":{}}}\n", i, i * 7);
+    let page = |base: usize| -> Vec<u8> {
+        let mut page = Vec::new();
+        let mut i = base;
+        while page.len() < page_size {
+            page.extend_from_slice(record(i).as_bytes());
+            i += 1;
+        }
+        page.truncate(page_size);
+        page
+    };
+
+    for page_id in 0..8 {
+        cache
+            .write(page_id * page_size as u64, &page(page_id as usize * 10_000))
+            .unwrap();
+    }
+
+    let dictionary = cache
+        .train_zstd_dictionary(&[0, 1, 2, 3, 4, 5, 6, 7], 16 * 1024)
+        .unwrap();
+    cache.attach_zstd_dictionary(&dictionary).unwrap();
+    let result = cache.attach_zstd_dictionary(&dictionary);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+
+    cache.write(8 * page_size as u64, &page(80_000)).unwrap();
+    drop(cache);
+
+    // Pages written before and after attaching the dictionary both read back
+    let mut cache = WriteThroughCache::builder(&path)
+        .compression(Compression::Zstd { level: 3 })
+        .open()
+        .unwrap();
+    assert_eq!(cache.read(0, page_size).unwrap(), page(0));
+    assert_eq!(
+        cache.read(8 * page_size as u64, page_size).unwrap(),
+        page(80_000)
+    );
+}
+
+#[test]
+fn test_compressed_tier() {
+    let path = tmp_file();
+    let page_size = 64 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .capacity(2 * page_size) // Only enough capacity for two pages
+        .compressed_tier(page_size)
+        .open()
+        .unwrap();
+
+    for page_id in 0..4u64 {
+        cache
+            .write(
+                page_id * page_size as u64,
+                &vec![page_id as u8 + 1; page_size],
+            )
+            .unwrap();
+    }
+
+    // Evicted pages come back from the compressed tier, even once the file
+    // behind them has been changed out from under the cache.
+    std::fs::write(&path, vec![0xee; 4 * page_size]).unwrap();
+    assert_eq!(cache.read(0, page_size).unwrap(), vec![1; page_size]);
+    assert_eq!(
+        cache.read(page_size as u64, page_size).unwrap(),
+        vec![2; page_size]
+    );
+}
+
+#[test]
+fn test_incompressible_pages_stored_raw() {
+    let path = tmp_file();
+    let page_size = 64 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .compression(Compression::Lz4)
+        .open()
+        .unwrap();
+
+    // xorshift noise does not compress
+    let mut state = 0x2545f4914f6cdd1du64;
+    let noise: Vec<u8> = (0..page_size)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+
+    cache.write(0, &noise).unwrap();
+    cache.write(page_size as u64, &vec![5; page_size]).unwrap();
+
+    // The noise page is stored as-is rather than LZ4's slightly larger output
+    let file_len = std::fs::metadata(&path).unwrap().len();
+    assert!(file_len >= page_size as u64);
+    assert!(file_len < page_size as u64 + 1024);
+    drop(cache);
+
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    assert_eq!(cache.read(0, page_size).unwrap(), noise);
+    assert_eq!(
+        cache.read(page_size as u64, page_size).unwrap(),
+        vec![5; page_size]
+    );
+}
+
+#[test]
+fn test_range_compression() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .compression(Compression::Lz4)
+        .open()
+        .unwrap();
+
+    // Keep the first four pages uncompressed
+    cache
+        .set_range_compression(0..4 * page_size as u64, Compression::None)
+        .unwrap();
+    cache.write(0, &vec![1; 8 * page_size]).unwrap();
+
+    let file_len = std::fs::metadata(&path).unwrap().len();
+    assert!(file_len >= 4 * page_size as u64);
+    assert!(file_len < 5 * page_size as u64);
+    drop(cache);
+
+    // The mapping survives reopening
+    let mut cache = WriteThroughCache::builder(&path)
+        .compression(Compression::Lz4)
+        .open()
+        .unwrap();
+    assert_eq!(
+        cache.range_compression(),
+        &[(0..4 * page_size as u64, Compression::None)]
+    );
+    assert_eq!(
+        cache.read(0, 8 * page_size).unwrap(),
+        vec![1; 8 * page_size]
+    );
+
+    let mut plain = WriteThroughCache::new(&tmp_file(), None, None).unwrap();
+    let result = plain.set_range_compression(0..10, Compression::None);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_compact_to() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .compression(Compression::Lz4)
+        .open()
+        .unwrap();
+
+    let noise: Vec<u8> = (0..page_size as u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+        .collect();
+    cache.write(0, &vec![1; 4 * page_size]).unwrap();
+    cache.write(9 * page_size as u64, &[2; 10]).unwrap();
+    // Grow pages 0 and 1 past their slots, leaving dead space in the heap
+    cache.write(0, &noise).unwrap();
+    cache.write(page_size as u64, &noise).unwrap();
+
+    let dest = tmp_file();
+    let report = cache
+        .compact_to(
+            &dest,
+            CompactOptions {
+                compression: Compression::Lz4,
+                ..CompactOptions::default()
+            },
+        )
+        .unwrap();
+
+    assert_eq!(report.pages, 10);
+    assert_eq!(report.zero_pages, 5);
+    assert!(std::fs::metadata(&dest).unwrap().len() < std::fs::metadata(&path).unwrap().len());
+
+    let expected = cache.read(0, 10 * page_size).unwrap();
+    let mut compacted = WriteThroughCache::new(&dest, None, None).unwrap();
+    assert_eq!(compacted.read(0, 10 * page_size).unwrap(), expected);
+
+    let result = cache.compact_to(&dest, CompactOptions::default());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+}
+
+// Run-length encoding as (count, byte) pairs
+struct RleCodec;
+
+impl Codec for RleCodec {
+    fn id(&self) -> u32 {
+        CUSTOM_CODEC_BASE + 7
+    }
+
+    fn max_compressed_len(&self, len: usize) -> usize {
+        2 * len
+    }
+
+    fn compress(&mut self, input: &[u8], output: &mut [u8]) -> std::io::Result<usize> {
+        let mut len = 0;
+        for run in input.chunk_by(|a, b| a == b) {
+            for chunk in run.chunks(255) {
+                output[len] = chunk.len() as u8;
+                output[len + 1] = chunk[0];
+                len += 2;
+            }
+        }
+        Ok(len)
+    }
+
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> std::io::Result<usize> {
+        let mut len = 0;
+        for pair in input.chunks_exact(2) {
+            let count = pair[0] as usize;
+            output[len..len + count].fill(pair[1]);
+            len += count;
+        }
+        Ok(len)
+    }
+}
+
+#[test]
+fn test_custom_codec() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .compression(Compression::Custom(CUSTOM_CODEC_BASE + 7))
+        .codec(Box::new(RleCodec))
+        .open()
+        .unwrap();
+
+    cache.write(0, &vec![3; 2 * page_size]).unwrap();
+    assert!(std::fs::metadata(&path).unwrap().len() < 256);
+    drop(cache);
+
+    // Pages written by the codec can't be read without it
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    assert_eq!(
+        cache.read(0, 10).unwrap_err().kind(),
+        ErrorKind::Unsupported
+    );
+
+    let mut cache = WriteThroughCache::builder(&path)
+        .codec(Box::new(RleCodec))
+        .open()
+        .unwrap();
+    assert_eq!(
+        cache.read(0, 2 * page_size).unwrap(),
+        vec![3; 2 * page_size]
+    );
+}
+
+#[test]
+fn test_custom_codec_reserved_id() {
+    struct Reserved;
+
+    impl Codec for Reserved {
+        fn id(&self) -> u32 {
+            1
+        }
+
+        fn max_compressed_len(&self, len: usize) -> usize {
+            len
+        }
+
+        fn compress(&mut self, input: &[u8], output: &mut [u8]) -> std::io::Result<usize> {
+            output[..input.len()].copy_from_slice(input);
+            Ok(input.len())
+        }
+
+        fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> std::io::Result<usize> {
+            output[..input.len()].copy_from_slice(input);
+            Ok(input.len())
+        }
+    }
+
+    let result = WriteThroughCache::builder(tmp_file())
+        .compression(Compression::Lz4)
+        .codec(Box::new(Reserved))
+        .open();
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+}
+
+#[cfg(feature = "snappy")]
+#[test]
+fn test_snappy_compression() {
+    let path = tmp_file();
+    let page_size = 64 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .compression(Compression::Snappy)
+        .open()
+        .unwrap();
+
+    cache.write(10, &vec![4; 3 * page_size]).unwrap();
+    assert!(std::fs::metadata(&path).unwrap().len() < page_size as u64);
+    drop(cache);
+
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    assert_eq!(
+        cache.read(10, 3 * page_size).unwrap(),
+        vec![4; 3 * page_size]
+    );
+}
+
+#[test]
+fn test_compression_stats() {
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .capacity(page_size)
+        .compression(Compression::Lz4)
+        .open()
+        .unwrap();
+
+    cache
+        .set_range_compression(0..page_size as u64, Compression::None)
+        .unwrap();
+    cache.write(0, &vec![1; 4 * page_size]).unwrap();
+    cache.read(page_size as u64, 10).unwrap(); // Evicted, so decompressed from disk
+
+    let stats = cache.compression_stats().unwrap();
+    assert_eq!(stats.logical_bytes, 4 * page_size as u64);
+    assert_eq!(stats.physical_bytes, stats.stored_bytes);
+    assert!(stats.stored_bytes < 2 * page_size as u64);
+
+    let raw = &stats.codecs[0];
+    assert_eq!((raw.codec, raw.pages), (CODEC_RAW, 1));
+    assert_eq!(raw.ratio(), 1.0);
+
+    let lz4 = &stats.codecs[1];
+    assert_eq!((lz4.codec, lz4.pages), (CODEC_LZ4, 3));
+    assert_eq!((lz4.compressions, lz4.decompressions), (3, 1));
+    assert!(lz4.ratio() > 10.0);
+
+    let plain = WriteThroughCache::new(&tmp_file(), None, None).unwrap();
+    assert!(plain.compression_stats().is_none());
+}
+
+#[test]
+fn test_buffer_pool() {
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .capacity(2 * page_size)
+        .buffer_pool(4)
+        .open()
+        .unwrap();
+
+    for page_id in 0..8u64 {
+        cache
+            .write(page_id * page_size as u64, &vec![page_id as u8; page_size])
+            .unwrap();
+    }
+    for page_id in 0..8u64 {
+        assert_eq!(
+            cache.read(page_id * page_size as u64 + 1, 2).unwrap(),
+            vec![page_id as u8; 2]
+        );
+    }
+
+    let stats = cache.pool_stats();
+    assert_eq!(stats.max_buffers, 4);
+    assert!(stats.buffers <= 4);
+    assert!(stats.hits > stats.misses);
+}
+
+#[test]
+fn test_multi_page_spans() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    // Large enough that neither write is streamed past the cache
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .capacity(16 * page_size)
+        .open()
+        .unwrap();
+
+    cache.write(0, &vec![1; 12 * page_size]).unwrap();
+    // Unaligned at both ends, so the first and last pages are merged
+    let data: Vec<u8> = (0..7 * page_size).map(|i| (i % 251) as u8).collect();
+    cache.write(page_size as u64 + 100, &data).unwrap();
+    drop(cache);
+
+    let mut cache = WriteThroughCache::builder(&path)
+        .capacity(4 * page_size)
+        .open()
+        .unwrap();
+    let contents = cache.read(0, 12 * page_size).unwrap();
+    let start = page_size + 100;
+    assert_eq!(contents[..start], vec![1; start]);
+    assert_eq!(contents[start..start + data.len()], data);
+    assert_eq!(
+        contents[start + data.len()..],
+        vec![1; 12 * page_size - start - data.len()]
+    );
+}
+
+#[test]
+fn test_readahead() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::new(&path, Some(page_size), None).unwrap();
+    for page_id in 0..64u64 {
+        cache
+            .write(page_id * page_size as u64, &vec![page_id as u8; page_size])
+            .unwrap();
+    }
+    drop(cache);
+
+    let mut cache = WriteThroughCache::builder(&path)
+        .capacity(32 * page_size)
+        .readahead(8)
+        .open()
+        .unwrap();
+    for page_id in 0..32u64 {
+        assert_eq!(
+            cache.read(page_id * page_size as u64, 16).unwrap(),
+            vec![page_id as u8; 16]
+        );
+    }
+
+    // A write must win over a fetch that is already in flight
+    cache
+        .write(33 * page_size as u64, &vec![0xff; page_size])
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    for page_id in 32..64u64 {
+        let expected = if page_id == 33 { 0xff } else { page_id as u8 };
+        assert_eq!(
+            cache.read(page_id * page_size as u64, 16).unwrap(),
+            vec![expected; 16]
+        );
+    }
+
+    // Resident pages aren't fetched again
+    drop(cache);
+    let mut cache = WriteThroughCache::builder(&path)
+        .capacity(32 * page_size)
+        .readahead(8)
+        .open()
+        .unwrap();
+    cache.write(0, &vec![1; 16 * page_size]).unwrap();
+    for page_id in 0..16u64 {
+        cache.read(page_id * page_size as u64, 16).unwrap();
+    }
+    let stats = cache.stats();
+    assert_eq!(stats.misses, 0);
+    assert!(
+        stats.bytes_read <= 8 * page_size as u64,
+        "{}",
+        stats.bytes_read
+    );
+
+    let compressed = WriteThroughCache::builder(tmp_file())
+        .compression(Compression::Lz4)
+        .readahead(8)
+        .open();
+    assert_eq!(compressed.err().unwrap().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_prefetch() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::new(&path, Some(page_size), None).unwrap();
+    for page_id in 0..40u64 {
+        cache
+            .write(page_id * page_size as u64, &vec![page_id as u8; page_size])
+            .unwrap();
+    }
+    drop(cache);
+
+    let mut cache = WriteThroughCache::builder(&path)
+        .capacity(32 * page_size)
+        .fetch_workers(3)
+        .open()
+        .unwrap();
+    cache.prefetch(0, 40 * page_size).unwrap();
+    for page_id in 0..32u64 {
+        assert_eq!(
+            cache.read(page_id * page_size as u64, 16).unwrap(),
+            vec![page_id as u8; 16]
+        );
+    }
+
+    // Past the end of the file is not an error
+    cache.prefetch(100 * page_size as u64, page_size).unwrap();
+}
+
+#[test]
+fn test_cache_is_send() {
+    fn assert_send<T: Send>(_: &T) {}
+
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .capacity(4 * page_size)
+        .open()
+        .unwrap();
+    assert_send(&cache);
+
+    for page_id in 0..8u64 {
+        cache
+            .write(page_id * page_size as u64, &vec![page_id as u8; page_size])
+            .unwrap();
+    }
+    let reader = std::thread::spawn(move || {
+        (0..8u64)
+            .map(|page_id| cache.read(page_id * page_size as u64, 1).unwrap()[0])
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(reader.join().unwrap(), (0..8).collect::<Vec<u8>>());
+}
+
+#[test]
+fn test_sector_writes() {
+    let path = tmp_file();
+    let page_size = 16 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .sector_size(4096)
+        .open()
+        .unwrap();
+    cache.write(0, &vec![1; page_size]).unwrap();
+
+    // Change the last sector behind the cache's back; a small write to the
+    // first sector must not rewrite it
+    let mut contents = std::fs::read(&path).unwrap();
+    contents[page_size - 10..].fill(9);
+    std::fs::write(&path, &contents).unwrap();
+
+    cache.write(100, &[2; 100]).unwrap();
+    drop(cache);
+
+    let contents = std::fs::read(&path).unwrap();
+    assert_eq!(contents[100..200], [2; 100]);
+    assert_eq!(contents[page_size - 10..], [9; 10]);
+
+    for sector_size in [256, 3000, 2 * page_size] {
+        let result = WriteThroughCache::builder(tmp_file())
+            .page_size(page_size)
+            .sector_size(sector_size)
+            .open();
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn test_odd_page_size() {
+    let path = tmp_file();
+    for page_size in [1000, 5000, 6000] {
+        let mut cache = WriteThroughCache::new(&tmp_file(), Some(page_size), None).unwrap();
+        cache.write(0, &vec![1; 3 * page_size]).unwrap();
+        cache.write(page_size as u64 + 10, &[2; 100]).unwrap();
+        let data = cache.read(0, 3 * page_size).unwrap();
+        assert_eq!(data[page_size + 10..page_size + 110], [2; 100]);
+        assert_eq!(data[page_size + 110..], vec![1; 2 * page_size - 110]);
+    }
+
+    // An explicit sector size still has to be a power of two
+    let result = WriteThroughCache::builder(&path)
+        .page_size(1000)
+        .sector_size(1000)
+        .open();
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_hugepages() {
+    let path = tmp_file();
+    let page_size = 64 * 1024;
+    // Works whether or not huge pages are available
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .capacity(64 * page_size)
+        .hugepages(true)
+        .secure(true)
+        .open()
+        .unwrap();
+
+    for page_id in 0..80u64 {
+        cache
+            .write(page_id * page_size as u64 + 7, &[page_id as u8; 100])
+            .unwrap();
+    }
+    for page_id in 0..80u64 {
+        assert_eq!(
+            cache.read(page_id * page_size as u64 + 7, 100).unwrap(),
+            vec![page_id as u8; 100]
+        );
+    }
+}
+
+#[test]
+fn test_sequential_write_streaming() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .capacity(4 * page_size)
+        .page_versions(true)
+        .open()
+        .unwrap();
+
+    cache.write(0, &vec![1; 16 * page_size]).unwrap();
+    assert_eq!(cache.read(5 * page_size as u64, 4).unwrap(), vec![1; 4]);
+
+    // A sequential run, unaligned at both ends, that outgrows the cache
+    let start = 100u64;
+    for chunk in 0..6u64 {
+        let address = start + chunk * 2 * page_size as u64;
+        cache.write(address, &vec![2; 2 * page_size]).unwrap();
+    }
+    let end = start + 12 * page_size as u64;
+
+    // The page read before the run must not be served stale
+    assert_eq!(cache.read(5 * page_size as u64, 4).unwrap(), vec![2; 4]);
+    // Page 7 is covered whole by the fourth write of the run, which is streamed
+    assert_eq!(cache.page_version(7).unwrap(), 5);
+    drop(cache);
+
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    let contents = cache.read(0, 16 * page_size).unwrap();
+    assert_eq!(contents[..100], [1; 100]);
+    assert!(contents[100..end as usize].iter().all(|&b| b == 2));
+    assert!(contents[end as usize..].iter().all(|&b| b == 1));
+}
+
+#[test]
+fn test_copy_range() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .capacity(16 * page_size)
+        .page_versions(true)
+        .open()
+        .unwrap();
+
+    let data: Vec<u8> = (0..4 * page_size).map(|i| (i % 251) as u8).collect();
+    cache.write(0, &data).unwrap();
+    cache
+        .write(8 * page_size as u64, &vec![9; page_size])
+        .unwrap();
+    // Page 8 is resident and must not be served stale after the copy
+    assert_eq!(cache.read(8 * page_size as u64, 4).unwrap(), vec![9; 4]);
+
+    cache
+        .copy_range(100, 8 * page_size as u64 + 10, 2 * page_size as u64)
+        .unwrap();
+    assert_eq!(
+        cache
+            .read(8 * page_size as u64 + 10, 2 * page_size)
+            .unwrap(),
+        data[100..100 + 2 * page_size]
+    );
+    assert_eq!(cache.read(8 * page_size as u64, 10).unwrap(), vec![9; 10]);
+    assert!(cache.page_version(9).unwrap() > cache.page_version(3).unwrap());
+
+    // Overlapping ranges behave like memmove
+    cache.copy_range(0, 1000, 3 * page_size as u64).unwrap();
+    assert_eq!(
+        cache.read(1000, 3 * page_size).unwrap(),
+        data[..3 * page_size]
+    );
+
+    let export = tmp_file();
+    let dest = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&export)
+        .unwrap();
+    cache
+        .export_range(1000, 3 * page_size as u64, &dest, 5)
+        .unwrap();
+    assert_eq!(std::fs::read(&export).unwrap()[5..], data[..3 * page_size]);
+
+    let err = cache
+        .export_range(0, 100 * page_size as u64, &dest, 0)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    drop(cache);
+
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    assert_eq!(
+        cache
+            .read(8 * page_size as u64 + 10, 2 * page_size)
+            .unwrap(),
+        data[100..100 + 2 * page_size]
+    );
+}
+
+#[test]
+fn test_copy_range_keeps_partial_last_page() {
+    let path = tmp_file();
+    let data: Vec<u8> = (0..200).map(|i| i as u8).collect();
+    std::fs::write(&path, &data).unwrap();
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+
+    cache.copy_range(0, 100, 50).unwrap();
+    assert_eq!(cache.file_size(), 200);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 200);
+    assert_eq!(cache.read(100, 50).unwrap(), data[..50]);
+    assert_eq!(cache.read(150, 50).unwrap(), data[150..]);
+
+    // Copies past the end grow the file only as far as they reach
+    cache.copy_range(0, 180, 50).unwrap();
+    assert_eq!(cache.file_size(), 230);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 230);
+    assert_eq!(cache.read(180, 50).unwrap(), data[..50]);
+
+    // A source running past the end is refused before anything is written
+    for (src, dst, len) in [(200, 300, 50), (1, u64::MAX, 10), (u64::MAX, 0, 2)] {
+        assert_eq!(
+            cache.copy_range(src, dst, len).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+    }
+    assert_eq!(cache.file_size(), 230);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 230);
+}
+
+#[test]
+fn test_write_into_partial_last_page() {
+    let path = tmp_file();
+    let data: Vec<u8> = (0..200).map(|i| i as u8).collect();
+    std::fs::write(&path, &data).unwrap();
+    let page_size = 64 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .open()
+        .unwrap();
+
+    // The page is loaded, and the write reaches the page end on disk
+    cache.write(0, &[1; 10]).unwrap();
+    assert_eq!(cache.file_size(), page_size as u64);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), page_size as u64);
+
+    cache.evict_all();
+    let mut expected = data[..16].to_vec();
+    expected[..10].fill(1);
+    assert_eq!(cache.read(0, 16).unwrap(), expected);
+    assert_eq!(cache.read(190, 20).unwrap()[..10], data[190..]);
+}
+
+#[test]
+fn test_send_range_to() {
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .capacity(16 * page_size)
+        .open()
+        .unwrap();
+    let data: Vec<u8> = (0..3 * page_size).map(|i| (i % 251) as u8).collect();
+    cache.write(0, &data).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+
+    cache
+        .send_range_to(10, 2 * page_size as u64, &server)
+        .unwrap();
+    let err = cache
+        .send_range_to(0, 4 * page_size as u64, &server)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    drop(server);
+
+    let mut received = Vec::new();
+    let mut client = client;
+    client.read_to_end(&mut received).unwrap();
+    assert_eq!(received, data[10..10 + 2 * page_size]);
+}
+
+#[test]
+fn test_workloads() {
+    use wt_cache::workload::{Op, Workload};
+
+    let file_size = 1024 * 1024;
+    let io_size = 4096;
+    let address = |op: Op| match op {
+        Op::Read { address, len } | Op::Write { address, len } => {
+            assert_eq!(len, io_size);
+            address
+        }
+    };
+
+    // Seeded workloads are reproducible and stay inside the file
+    let ops: Vec<Op> = Workload::random_reads(file_size, io_size, 7)
+        .take(1000)
+        .collect();
+    assert_eq!(
+        ops,
+        Workload::random_reads(file_size, io_size, 7)
+            .take(1000)
+            .collect::<Vec<_>>()
+    );
+    assert!(ops.iter().all(|op| matches!(op, Op::Read { .. })));
+    assert!(ops
+        .iter()
+        .all(|&op| address(op) + io_size as u64 <= file_size));
+
+    let addresses: Vec<u64> = Workload::sequential_writes(file_size, io_size)
+        .take(258)
+        .map(address)
+        .collect();
+    assert_eq!(addresses[..3], [0, 4096, 8192]);
+    assert_eq!(addresses[256..], [0, 4096]);
+    assert!(Workload::sequential_reads(file_size, io_size)
+        .take(3)
+        .eq([0, 4096, 8192].map(|address| Op::Read {
+            address,
+            len: io_size
+        })));
+    assert!(Workload::random_writes(file_size, io_size, 7)
+        .take(100)
+        .all(|op| matches!(op, Op::Write { .. })));
+    let reads = Workload::random_mixed(file_size, io_size, 0.7, 7)
+        .take(10_000)
+        .filter(|op| matches!(op, Op::Read { .. }))
+        .count();
+    assert!((6000..8000).contains(&reads));
+
+    // A skewed workload keeps returning to a few hot offsets
+    let ops: Vec<Op> = Workload::zipfian(file_size, io_size, 0.99, 0.5, 7)
+        .take(10_000)
+        .collect();
+    let reads = ops
+        .iter()
+        .filter(|op| matches!(op, Op::Read { .. }))
+        .count();
+    assert!((4000..6000).contains(&reads));
+    let mut counts = std::collections::HashMap::new();
+    for &op in &ops {
+        *counts.entry(address(op)).or_insert(0) += 1;
+    }
+    assert!(counts.values().max().unwrap() * 100 > ops.len());
+
+    let mut cache = WriteThroughCache::new(&tmp_file(), Some(64 * 1024), Some(256 * 1024)).unwrap();
+    for op in Workload::fill(file_size, io_size) {
+        op.apply(&mut cache, 1).unwrap();
+    }
+    assert_eq!(cache.read(file_size - 1, 1).unwrap(), vec![1]);
+    Workload::zipfian(file_size, io_size, 0.99, 0.5, 7)
+        .run(&mut cache, 500)
+        .unwrap();
+}
+
+#[test]
+fn test_memory_usage() {
+    let page_size = 4 * 1024;
+    let capacity = 4 * page_size + page_size / 2;
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .capacity(capacity)
+        .buffer_pool(0)
+        .open()
+        .unwrap();
+    // Only the bookkeeping, sized up front, before any page is cached
+    assert!(cache.memory_usage() < page_size);
+
+    for page_id in 0..32u64 {
+        cache.write(page_id * page_size as u64, &[1; 100]).unwrap();
+        // Neither the half page nor the bookkeeping may push usage past the
+        // capacity
+        assert!(cache.memory_usage() <= capacity, "{}", cache.memory_usage());
+    }
+    assert!(cache.memory_usage() > 3 * page_size);
+
+    // A single page is always kept, however small the capacity
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .capacity(page_size)
+        .open()
+        .unwrap();
+    cache.write(0, &vec![2; page_size]).unwrap();
+    cache.write(page_size as u64, &vec![3; page_size]).unwrap();
+    assert_eq!(
+        cache.read(0, 2 * page_size).unwrap()[page_size - 1..page_size + 1],
+        [2, 3]
+    );
+}
+
+#[test]
+fn test_fetch_granularity() {
+    let path = tmp_file();
+    let page_size = 64 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .open()
+        .unwrap();
+    cache.write(0, &vec![1; 8 * page_size]).unwrap();
+    drop(cache);
+
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .fetch_granularity(4096)
+        .open()
+        .unwrap();
+    let address = 5 * page_size as u64 + 4000;
+    assert_eq!(cache.read(address, 200).unwrap(), vec![1; 200]);
+
+    // The page was not cached, so a change behind the cache's back shows
+    let mut contents = std::fs::read(&path).unwrap();
+    contents[address as usize..address as usize + 200].fill(7);
+    std::fs::write(&path, &contents).unwrap();
+    assert_eq!(cache.read(2 * page_size as u64, 10).unwrap(), vec![1; 10]);
+    assert_eq!(cache.read(address, 200).unwrap(), vec![7; 200]);
+
+    // Sequential reads still fill whole pages
+    assert_eq!(
+        cache.read(address + page_size as u64, 10).unwrap(),
+        vec![1; 10]
+    );
+    assert_eq!(
+        cache.read(address + 2 * page_size as u64, 10).unwrap(),
+        vec![1; 10]
+    );
+
+    for granularity in [256, 3000, 2 * page_size] {
+        let result = WriteThroughCache::builder(tmp_file())
+            .page_size(page_size)
+            .fetch_granularity(granularity)
+            .open();
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+    }
+    let result = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .compression(Compression::Lz4)
+        .fetch_granularity(4096)
+        .open();
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_strided_readahead() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::new(&path, Some(page_size), None).unwrap();
+    for page_id in 0..64u64 {
+        cache
+            .write(page_id * page_size as u64, &vec![page_id as u8; page_size])
+            .unwrap();
+    }
+    drop(cache);
+
+    let mut cache = WriteThroughCache::builder(&path)
+        .capacity(32 * page_size)
+        .readahead(8)
+        .open()
+        .unwrap();
+    // Two interleaved streams: every third page forwards, and a random
+    // access in between that must not disturb it
+    for page_id in [0u64, 50, 3, 6, 9] {
+        assert_eq!(
+            cache.read(page_id * page_size as u64, 16).unwrap(),
+            vec![page_id as u8; 16]
+        );
+    }
+    std::thread::sleep(std::time::Duration::from_millis(50));
+
+    // Pages ahead of the stride were fetched before this change on disk
+    let mut contents = std::fs::read(&path).unwrap();
+    for page_id in [12, 15, 18, 21] {
+        let start = page_id * page_size;
+        contents[start..start + page_size].fill(0xee);
+    }
+    std::fs::write(&path, &contents).unwrap();
+    for page_id in [12u64, 15, 18, 21] {
+        assert_eq!(
+            cache.read(page_id * page_size as u64, 16).unwrap(),
+            vec![page_id as u8; 16]
+        );
+    }
+}
+
+#[test]
+fn test_cache_stats() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::new(&path, Some(page_size), None).unwrap();
+    cache.write(0, &vec![1; 32 * page_size]).unwrap();
+    let stats = cache.stats();
+    assert_eq!(stats.disk_writes, 1);
+    assert_eq!(stats.fsyncs, 1);
+    assert_eq!(stats.bytes_written, 32 * page_size as u64);
+    drop(cache);
+
+    let mut cache = WriteThroughCache::builder(&path)
+        .capacity(8 * page_size)
+        .open()
+        .unwrap();
+    cache.read(0, 10).unwrap();
+    cache.read(0, 10).unwrap();
+    cache.write(100, &[2; 100]).unwrap();
+    let stats = cache.stats();
+    assert_eq!(
+        stats,
+        CacheStats {
+            hits: 2,
+            misses: 1,
+            evictions: 0,
+            disk_reads: 1,
+            disk_writes: 1,
+            fsyncs: 1,
+            bytes_read: page_size as u64,
+            bytes_written: page_size as u64,
+            #[cfg(feature = "latency")]
+            latency: stats.latency.clone(),
+        }
+    );
+
+    cache.read(page_size as u64, 16 * page_size).unwrap();
+    let stats = cache.stats();
+    assert_eq!(stats.misses, 17);
+    assert!(stats.evictions > 0);
+    assert_eq!(stats.bytes_read, 17 * page_size as u64);
+
+    cache.reset_stats();
+    assert_eq!(cache.stats(), CacheStats::default());
+
+    // A compressed page is synced twice, once for its payload and once for
+    // its index entry
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .compression(Compression::Lz4)
+        .open()
+        .unwrap();
+    cache.write(0, &vec![3; 2 * page_size]).unwrap();
+    let stats = cache.stats();
+    assert_eq!((stats.disk_writes, stats.fsyncs), (2, 4));
+    cache.write(100, &[4; 10]).unwrap();
+    let stats = cache.stats();
+    assert_eq!((stats.disk_writes, stats.fsyncs), (3, 6));
+}
+
+#[cfg(feature = "prometheus")]
+#[test]
+fn test_prometheus_metrics() {
+    use wt_cache::PrometheusMetrics;
+
+    let registry = prometheus::Registry::new();
+    let metrics = PrometheusMetrics::register(&registry, "wt_cache").unwrap();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .prometheus(metrics)
+        .open()
+        .unwrap();
+    cache.write(0, &vec![1; 2 * page_size]).unwrap();
+    cache.write(100, &[2; 10]).unwrap();
+    cache.read(0, 10).unwrap();
+
+    let families = registry.gather();
+    let family = |name: &str| {
+        families
+            .iter()
+            .find(|family| family.get_name() == name)
+            .unwrap()
+            .get_metric()[0]
+            .clone()
+    };
+    assert_eq!(family("wt_cache_hit_ratio").get_gauge().get_value(), 1.0);
+    assert_eq!(
+        family("wt_cache_resident_bytes").get_gauge().get_value() as usize,
+        cache.memory_usage()
+    );
+    assert_eq!(family("wt_cache_dirty_pages").get_gauge().get_value(), 0.0);
+    let flushes = family("wt_cache_flush_seconds");
+    assert_eq!(flushes.get_histogram().get_sample_count(), 2);
+
+    // Registering the same names twice is refused
+    assert!(PrometheusMetrics::register(&registry, "wt_cache").is_err());
+}
+
+#[cfg(feature = "latency")]
+#[test]
+fn test_latency_stats() {
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .capacity(page_size)
+        .open()
+        .unwrap();
+    cache.write(0, &vec![1; 4 * page_size]).unwrap();
+    for page_id in [3, 0, 2, 1] {
+        cache
+            .write(page_id * page_size as u64 + 10, &[2; 10])
+            .unwrap();
+    }
+
+    let latency = cache.stats().latency;
+    assert_eq!(latency.page_writes.count(), 5);
+    assert_eq!(latency.fsyncs.count(), 5);
+    assert!(latency.page_reads.count() >= 4);
+    let fsyncs = &latency.fsyncs;
+    assert!(fsyncs.percentile(50.0) <= fsyncs.percentile(99.9));
+    assert!(fsyncs.percentile(99.9) <= fsyncs.max());
+    assert!(fsyncs.mean() <= fsyncs.max());
+    assert!(fsyncs.max() > std::time::Duration::ZERO);
+
+    cache.reset_stats();
+    assert_eq!(cache.stats().latency, Default::default());
+    assert_eq!(
+        cache.stats().latency.fsyncs.percentile(99.0),
+        std::time::Duration::ZERO
+    );
+}
+
+#[cfg(feature = "latency")]
+#[test]
+fn test_compressed_latency_stats() {
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .compression(Compression::Lz4)
+        .open()
+        .unwrap();
+    cache.write(0, &vec![1; 2 * page_size]).unwrap();
+
+    // Each page syncs the data file and the page index
+    let latency = cache.stats().latency;
+    assert_eq!(latency.page_writes.count(), 2);
+    assert_eq!(latency.fsyncs.count(), 4);
+    assert!(latency.fsyncs.max() > std::time::Duration::ZERO);
+}
+
+#[derive(Clone, Default)]
+struct EventLog(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+impl CacheEvents for EventLog {
+    fn on_hit(&self, page_id: u64) {
+        self.0.lock().unwrap().push(format!("hit {}", page_id));
+    }
+
+    fn on_miss(&self, page_id: u64) {
+        self.0.lock().unwrap().push(format!("miss {}", page_id));
+    }
+
+    fn on_evict(&self, page_id: u64) {
+        self.0.lock().unwrap().push(format!("evict {}", page_id));
+    }
+
+    fn on_flush(&self, _elapsed: std::time::Duration) {
+        self.0.lock().unwrap().push("flush".to_string());
+    }
+
+    fn on_error(&self, error: &std::io::Error) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("error {:?}", error.kind()));
+    }
+
+    fn on_slow_op(&self, op: DiskOp, page_id: u64, _elapsed: std::time::Duration) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("slow {:?} {}", op, page_id));
+    }
+}
+
+#[test]
+fn test_cache_events() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::new(&path, Some(page_size), None).unwrap();
+    cache.write(0, &vec![1; 4 * page_size]).unwrap();
+    drop(cache);
+
+    let events = EventLog::default();
+    let mut cache = WriteThroughCache::builder(&path)
+        .capacity(2 * page_size)
+        .events(Box::new(events.clone()))
+        .open()
+        .unwrap();
+    cache.read(0, 10).unwrap();
+    cache.read(0, 10).unwrap();
+    // The small capacity only holds one page
+    cache.write(page_size as u64, &[2; 10]).unwrap();
+    assert_eq!(
+        *events.0.lock().unwrap(),
+        ["miss 0", "hit 0", "miss 1", "evict 0", "flush"]
+    );
+
+    cache.read(1 << 40, 10).unwrap_err();
+    assert_eq!(
+        events.0.lock().unwrap().last().unwrap(),
+        "error InvalidInput"
+    );
+}
+
+#[test]
+fn test_compressed_cache_events() {
+    let page_size = 4 * 1024;
+    let events = EventLog::default();
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .compression(Compression::Lz4)
+        .events(Box::new(events.clone()))
+        .open()
+        .unwrap();
+    cache.write(0, &vec![1; page_size]).unwrap();
+    // The data file and the page index are each synced
+    assert_eq!(*events.0.lock().unwrap(), ["flush", "flush"]);
+    assert_eq!(cache.stats().fsyncs, 2);
+}
+
+#[test]
+fn test_slow_op_threshold() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let events = EventLog::default();
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .slow_op_threshold(std::time::Duration::ZERO)
+        .events(Box::new(events.clone()))
+        .open()
+        .unwrap();
+    cache
+        .write(2 * page_size as u64, &vec![1; page_size])
+        .unwrap();
+    assert_eq!(
+        *events.0.lock().unwrap(),
+        ["slow Write 2", "slow Fsync 2", "flush"]
+    );
+    drop(cache);
+
+    // Nothing is that slow
+    let events = EventLog::default();
+    let mut cache = WriteThroughCache::builder(&path)
+        .slow_op_threshold(std::time::Duration::from_secs(3600))
+        .events(Box::new(events.clone()))
+        .open()
+        .unwrap();
+    cache.read(0, 10).unwrap();
+    cache.write(10, &[2; 10]).unwrap();
+    assert_eq!(*events.0.lock().unwrap(), ["miss 0", "hit 0", "flush"]);
+}
+
+#[test]
+fn test_compressed_slow_op_threshold() {
+    let page_size = 4 * 1024;
+    let events = EventLog::default();
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .compression(Compression::Lz4)
+        .slow_op_threshold(std::time::Duration::ZERO)
+        .events(Box::new(events.clone()))
+        .open()
+        .unwrap();
+    cache.write(page_size as u64, &vec![1; page_size]).unwrap();
+    // The page write is timed around both syncs, so it is reported last
+    assert_eq!(
+        *events.0.lock().unwrap(),
+        [
+            "slow Fsync 1",
+            "flush",
+            "slow Fsync 1",
+            "flush",
+            "slow Write 1"
+        ]
+    );
+}
+
+#[test]
+fn test_dump_state() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::new(&path, Some(page_size), None).unwrap();
+    cache.write(0, &vec![1; 8 * page_size]).unwrap();
+    drop(cache);
+
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    for page_id in [0, 7, 3, 0] {
+        cache.read(page_id * page_size as u64, 10).unwrap();
+    }
+    let page = |page_id, location| PageState {
+        page_id,
+        location,
+        size: page_size,
+    };
+    assert_eq!(
+        cache.dump_state(),
+        CacheState {
+            page_size,
+            file_size: 8 * page_size as u64,
+            resident: vec![
+                page(7, PageLocation::Cold),
+                page(3, PageLocation::Cold),
+                page(0, PageLocation::Hot),
+            ],
+            tier: vec![],
+        }
+    );
+    drop(cache);
+
+    // Room for one resident page; the other goes to the tier
+    let mut cache = WriteThroughCache::builder(&path)
+        .capacity(2 * page_size)
+        .compressed_tier(page_size)
+        .open()
+        .unwrap();
+    cache.read(0, 10).unwrap();
+    cache.read(7 * page_size as u64, 10).unwrap();
+    let state = cache.dump_state();
+    assert_eq!(state.resident, [page(7, PageLocation::Hot)]);
+    assert_eq!(state.tier.len(), 1);
+    assert_eq!(state.tier[0].page_id, 0);
+    assert!(state.tier[0].size < page_size);
+
+    let mut text = Vec::new();
+    cache.write_state(&mut text).unwrap();
+    assert_eq!(
+        String::from_utf8(text).unwrap(),
+        format!(
+            "page size 4096, file size 32768, 1 resident, 1 in tier\n\
+             Hot 7 4096\n\
+             Tier 0 {}\n",
+            state.tier[0].size
+        )
+    );
+}
+
+#[test]
+fn test_heatmap() {
+    let page_size = 4 * 1024;
+    let bucket_size = 2 * page_size as u64;
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .heatmap(bucket_size)
+        .open()
+        .unwrap();
+    assert_eq!(
+        cache.export_heatmap(),
+        Some(Heatmap {
+            bucket_size,
+            reads: vec![],
+            writes: vec![],
+        })
+    );
+
+    cache.write(0, &vec![1; 6 * page_size]).unwrap();
+    cache.read(10, 10).unwrap();
+    cache.read(10, 10).unwrap();
+    // Straddles the first two buckets
+    cache.read(bucket_size - 5, 10).unwrap();
+    cache.write(5 * page_size as u64, &[2; 10]).unwrap();
+    assert!(cache.read(1 << 40, 10).is_err());
+    assert_eq!(
+        cache.export_heatmap().unwrap(),
+        Heatmap {
+            bucket_size,
+            reads: vec![3, 1, 0],
+            writes: vec![1, 1, 2],
+        }
+    );
+
+    cache.reset_heatmap();
+    assert_eq!(cache.export_heatmap().unwrap().reads, [0, 0, 0]);
+
+    // Off unless asked for, and buckets must line up with pages
+    let cache = WriteThroughCache::new(&tmp_file(), None, None).unwrap();
+    assert_eq!(cache.export_heatmap(), None);
+    let result = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .heatmap(page_size as u64 + 1)
+        .open();
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_stats_snapshot_diff() {
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .open()
+        .unwrap();
+    cache.write(0, &vec![1; 4 * page_size]).unwrap();
+    let older = cache.stats_snapshot();
+
+    cache.read(0, 10).unwrap();
+    cache.read(0, 10).unwrap();
+    cache.read(0, 10).unwrap();
+    // A partial write looks its page up too
+    cache.write(10, &[2; 10]).unwrap();
+    let newer = StatsSnapshot {
+        taken_at: older.taken_at + std::time::Duration::from_secs(2),
+        ..cache.stats_snapshot()
+    };
+
+    let rates = newer.diff(&older);
+    assert_eq!(rates.interval, std::time::Duration::from_secs(2));
+    assert_eq!(rates.hits_per_sec, 2.0);
+    assert_eq!(rates.misses_per_sec, 0.0);
+    assert_eq!(rates.miss_ratio, 0.0);
+    assert_eq!(rates.fsyncs_per_sec, 0.5);
+    assert_eq!(rates.bytes_written_per_sec, page_size as f64 / 2.0);
+
+    // Counters reset in between count from zero, and an empty interval
+    // has no rates
+    cache.reset_stats();
+    let reset = cache.stats_snapshot();
+    assert_eq!(reset.diff(&newer).hits_per_sec, 0.0);
+    assert_eq!(reset.diff(&reset), StatsRates::default());
+}
+
+// Keeps every metric registered with it, keyed by name and labels
+#[cfg(feature = "metrics")]
+#[derive(Default)]
+struct TestRecorder {
+    values: std::sync::Mutex<Vec<(String, std::sync::Arc<std::sync::atomic::AtomicU64>)>>,
+    samples: std::sync::Mutex<Vec<(String, std::sync::Arc<TestHistogram>)>>,
+}
+
+#[cfg(feature = "metrics")]
+#[derive(Default)]
+struct TestHistogram(std::sync::Mutex<Vec<f64>>);
+
+#[cfg(feature = "metrics")]
+impl metrics::HistogramFn for TestHistogram {
+    fn record(&self, value: f64) {
+        self.0.lock().unwrap().push(value);
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl TestRecorder {
+    fn name(key: &metrics::Key) -> String {
+        let labels: Vec<String> = key
+            .labels()
+            .map(|label| format!("{}={}", label.key(), label.value()))
+            .collect();
+        format!("{}{{{}}}", key.name(), labels.join(","))
+    }
+
+    fn value(&self, name: &str) -> u64 {
+        let values = self.values.lock().unwrap();
+        let (_, value) = values.iter().find(|(key, _)| key == name).unwrap();
+        value.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    fn samples(&self, name: &str) -> usize {
+        let samples = self.samples.lock().unwrap();
+        let (_, histogram) = samples.iter().find(|(key, _)| key == name).unwrap();
+        let len = histogram.0.lock().unwrap().len();
+        len
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl metrics::Recorder for TestRecorder {
+    fn describe_counter(
+        &self,
+        _: metrics::KeyName,
+        _: Option<metrics::Unit>,
+        _: metrics::SharedString,
+    ) {
+    }
+    fn describe_gauge(
+        &self,
+        _: metrics::KeyName,
+        _: Option<metrics::Unit>,
+        _: metrics::SharedString,
+    ) {
+    }
+    fn describe_histogram(
+        &self,
+        _: metrics::KeyName,
+        _: Option<metrics::Unit>,
+        _: metrics::SharedString,
+    ) {
+    }
+
+    fn register_counter(&self, key: &metrics::Key, _: &metrics::Metadata<'_>) -> metrics::Counter {
+        let value = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+        self.values
+            .lock()
+            .unwrap()
+            .push((Self::name(key), value.clone()));
+        metrics::Counter::from_arc(value)
+    }
+
+    fn register_gauge(&self, key: &metrics::Key, _: &metrics::Metadata<'_>) -> metrics::Gauge {
+        let value = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+        self.values
+            .lock()
+            .unwrap()
+            .push((Self::name(key), value.clone()));
+        metrics::Gauge::from_arc(value)
+    }
+
+    fn register_histogram(
+        &self,
+        key: &metrics::Key,
+        _: &metrics::Metadata<'_>,
+    ) -> metrics::Histogram {
+        let histogram = std::sync::Arc::new(TestHistogram::default());
+        self.samples
+            .lock()
+            .unwrap()
+            .push((Self::name(key), histogram.clone()));
+        metrics::Histogram::from_arc(histogram)
+    }
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_metrics_facade() {
+    use wt_cache::MetricsFacade;
+
+    let page_size = 4 * 1024;
+    let recorder = TestRecorder::default();
+    let facade = metrics::with_local_recorder(&recorder, || {
+        MetricsFacade::new("wt_cache", &[("file", "data")])
+    });
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .metrics(facade)
+        .open()
+        .unwrap();
+    cache.write(0, &vec![1; 2 * page_size]).unwrap();
+    cache.write(100, &[2; 10]).unwrap();
+    cache.read(0, 10).unwrap();
+
+    let stats = cache.stats();
+    assert_eq!(recorder.value("wt_cache_hits{file=data}"), stats.hits);
+    assert_eq!(recorder.value("wt_cache_fsyncs{file=data}"), 2);
+    assert_eq!(
+        recorder.value("wt_cache_bytes_written{file=data}"),
+        stats.bytes_written
+    );
+    assert_eq!(
+        f64::from_bits(recorder.value("wt_cache_hit_ratio{file=data}")),
+        1.0
+    );
+    assert_eq!(
+        f64::from_bits(recorder.value("wt_cache_resident_bytes{file=data}")) as usize,
+        cache.memory_usage()
+    );
+    assert_eq!(
+        recorder.samples("wt_cache_disk_write_seconds{file=data}"),
+        2
+    );
+    assert_eq!(recorder.samples("wt_cache_fsync_seconds{file=data}"), 2);
+
+    // Counters keep going up across a reset of the cache's own
+    cache.reset_stats();
+    cache.write(200, &[3; 10]).unwrap();
+    assert_eq!(cache.stats().fsyncs, 1);
+    assert_eq!(recorder.value("wt_cache_fsyncs{file=data}"), 3);
+    assert_eq!(
+        recorder.value("wt_cache_bytes_written{file=data}"),
+        stats.bytes_written + page_size as u64
+    );
+}
+
+#[test]
+fn test_verify() {
+    let path = tmp_file();
+    let page_size = 4 * 1024;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .audit_log(true)
+        .open()
+        .unwrap();
+    cache.write(0, &vec![1; 4 * page_size]).unwrap();
+    let report = cache.verify().unwrap();
+    assert!(report.is_ok());
+    assert_eq!(
+        report,
+        VerifyReport {
+            pages: 4,
+            bad_pages: vec![],
+            header_present: true,
+            header_error: None,
+            audit_log: Some(Ok(1)),
+        }
+    );
+
+    // A page changed behind the cache's back no longer matches its
+    // resident copy
+    cache.read(page_size as u64, 10).unwrap();
+    let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+    std::os::unix::fs::FileExt::write_all_at(&file, &[9], page_size as u64 + 5).unwrap();
+    let report = cache.verify().unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(
+        report.bad_pages,
+        [(1, "Resident copy differs from disk".to_string())]
+    );
+
+    let mut audit_path = path.clone().into_os_string();
+    audit_path.push(".audit");
+    let mut bytes = std::fs::read(&audit_path).unwrap();
+    bytes[0] ^= 1;
+    std::fs::write(&audit_path, bytes).unwrap();
+    assert!(matches!(cache.verify().unwrap().audit_log, Some(Err(_))));
+    drop(cache);
+
+    // Compressed pages that no longer decode
+    let path = tmp_file();
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .compression(Compression::Lz4)
+        .open()
+        .unwrap();
+    cache.write(0, &vec![1; 2 * page_size]).unwrap();
+    drop(cache);
+    let len = std::fs::metadata(&path).unwrap().len() as usize;
+    std::fs::write(&path, vec![0xff; len]).unwrap();
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    let report = cache.verify().unwrap();
+    assert_eq!(report.pages, 2);
+    assert_eq!(report.bad_pages.len(), 2);
+    assert_eq!(report.audit_log, None);
+}
+
+#[test]
+fn test_reload() {
+    let path = tmp_file();
+    let page_size = 4096;
+    let mut writer = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .open()
+        .unwrap();
+    writer.write(0, b"hello").unwrap();
+    let mut reader = WriteThroughCache::new(&path, None, None).unwrap();
+    assert_eq!(reader.page_size(), page_size);
+    assert_eq!(reader.read(0, 10).unwrap(), b"hello\0\0\0\0\0");
+
+    writer.write(5, b" world").unwrap();
+    writer.write(page_size as u64, b"more").unwrap();
+    // The reader still has the old copy of page 0 and the old size
+    assert_eq!(reader.read(0, 11).unwrap(), b"hello\0\0\0\0\0\0");
+    assert!(reader.read(page_size as u64, 4).is_err());
+
+    assert_eq!(reader.reload(0).unwrap(), 2 * page_size as u64);
+    assert_eq!(reader.read(0, 11).unwrap(), b"hello world");
+    assert_eq!(reader.read(page_size as u64, 4).unwrap(), b"more");
+
+    let path = tmp_file();
+    let mut cache = WriteThroughCache::builder(&path)
+        .compression(Compression::Lz4)
+        .open()
+        .unwrap();
+    assert_eq!(
+        cache.reload(0).err().unwrap().kind(),
+        std::io::ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn test_evict_all() {
+    let path = tmp_file();
+    let page_size = 4096;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .capacity(2 * page_size + 4096)
+        .compressed_tier(64 * 1024)
+        .open()
+        .unwrap();
+    cache.write(0, &vec![1; 4 * page_size]).unwrap();
+    for page_id in 0..4 {
+        cache.read(page_id * page_size as u64, 1).unwrap();
+    }
+    cache.reset_stats();
+
+    assert!(cache.evict_all() > 0);
+    assert_eq!(cache.evict_all(), 0);
+    assert!(cache.dump_state().resident.is_empty());
+    assert!(cache.dump_state().tier.is_empty());
+    cache.flush().unwrap();
+    assert_eq!(cache.stats().fsyncs, 1);
+
+    // Every page now comes from disk
+    assert_eq!(
+        cache.read(0, 4 * page_size).unwrap(),
+        vec![1; 4 * page_size]
+    );
+    assert_eq!(cache.stats().misses, 4);
+    assert_eq!(cache.stats().hits, 0);
+}
+
+#[test]
+fn test_info() {
+    let path = tmp_file();
+    let page_size = 4096;
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .page_versions(true)
+        .audit_log(true)
+        .open()
+        .unwrap();
+    cache.write(3 * page_size as u64, b"tail").unwrap();
+    cache.write(0, b"head").unwrap();
+
+    let info = cache.info().unwrap();
+    assert_eq!(info.file_size, 4 * page_size as u64);
+    assert_eq!(info.disk_size, 4 * page_size as u64);
+    assert_eq!(info.page_size, page_size);
+    assert_eq!(info.pages, 4);
+    assert!(info.page_versions);
+    assert!(!info.compressed);
+    assert_eq!(info.audit_log, Some(Ok(2)));
+    assert_eq!(info.last_lsn, Some(2));
+    // Whether the unwritten pages are holes depends on the filesystem, but
+    // the written ones are always allocated
+    assert!(info.allocated() <= info.disk_size);
+    for offset in [0, 3 * page_size as u64] {
+        assert!(info
+            .data_extents
+            .iter()
+            .any(|extent| extent.contains(&offset)));
+    }
+
+    let path = tmp_file();
+    let mut cache = WriteThroughCache::builder(&path)
+        .compression(Compression::Lz4)
+        .open()
+        .unwrap();
+    cache.write(0, &[7; 10000]).unwrap();
+    let info = cache.info().unwrap();
+    assert!(info.compressed);
+    assert!(!info.page_versions);
+    assert!(info.disk_size < info.file_size);
+    assert_eq!(info.audit_log, None);
+    assert_eq!(info.last_lsn, None);
+}
+
+#[test]
+fn test_compact_to_page_size() {
+    let path = tmp_file();
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(4096)
+        .open()
+        .unwrap();
+    let data: Vec<u8> = (0..20 * 1024).map(|i| (i % 253) as u8 + 1).collect();
+    cache.write(0, &data).unwrap();
+
+    // Down to smaller pages, with the zero pages past the data left as holes
+    let small = tmp_file();
+    let options = CompactOptions {
+        page_size: Some(1024),
+        ..CompactOptions::default()
+    };
+    cache.write(24 * 1024, &[0]).unwrap();
+    let report = cache.compact_to(&small, options).unwrap();
+    assert_eq!(report.pages, 28);
+    assert_eq!(report.zero_pages, 7);
+    let mut converted = WriteThroughCache::new(&small, None, None).unwrap();
+    assert_eq!(converted.file_size(), 28 * 1024);
+    assert_eq!(converted.read(0, data.len()).unwrap(), data);
+
+    // Up to larger ones, the last padded to a whole page
+    let large = tmp_file();
+    let options = CompactOptions {
+        page_size: Some(16384),
+        ..CompactOptions::default()
+    };
+    let report = cache.compact_to(&large, options).unwrap();
+    assert_eq!(report.pages, 2);
+    let mut converted = WriteThroughCache::new(&large, None, None).unwrap();
+    assert_eq!(converted.file_size(), 32768);
+    assert_eq!(converted.read(0, data.len()).unwrap(), data);
+}
+
+#[test]
+fn test_kv_store() {
+    let path = tmp_file();
+    let cache = WriteThroughCache::builder(&path)
+        .page_size(4096)
+        .open()
+        .unwrap();
+    let mut store = KvStore::open(cache).unwrap();
+    assert!(store.is_empty());
+    assert_eq!(store.get(b"missing").unwrap(), None);
+
+    store.put(b"b", b"two").unwrap();
+    store.put(b"a", b"one").unwrap();
+    // Larger than a page, so it spans several
+    store.put(b"c", &vec![3; 10000]).unwrap();
+    store.put(b"b", b"deux").unwrap();
+    assert!(store.delete(b"a").unwrap());
+    assert!(!store.delete(b"a").unwrap());
+    store.put(b"", b"empty key").unwrap();
+
+    let entries: Vec<_> = store.iter().map(Result::unwrap).collect();
+    assert_eq!(
+        entries,
+        [
+            (b"".to_vec(), b"empty key".to_vec()),
+            (b"b".to_vec(), b"deux".to_vec()),
+            (b"c".to_vec(), vec![3; 10000]),
+        ]
+    );
+    let log_size = store.log_size();
+    drop(store);
+
+    // The index is rebuilt from the log
+    let mut store = KvStore::open(WriteThroughCache::new(&path, None, None).unwrap()).unwrap();
+    assert_eq!(store.len(), 3);
+    assert!(!store.contains_key(b"a"));
+    assert_eq!(store.get(b"b").unwrap().unwrap(), b"deux");
+    assert_eq!(store.get(b"c").unwrap().unwrap(), vec![3; 10000]);
+    assert_eq!(store.log_size(), log_size);
+
+    // A torn last record is dropped and overwritten by the next put
+    store.put(b"d", b"torn").unwrap();
+    let mut cache = store.into_inner();
+    cache.write(log_size + 13, b"x").unwrap();
+    let mut store = KvStore::open(cache).unwrap();
+    assert!(!store.contains_key(b"d"));
+    assert_eq!(store.log_size(), log_size);
+    store.put(b"e", b"five").unwrap();
+    let mut store = KvStore::open(store.into_inner()).unwrap();
+    assert_eq!(store.get(b"e").unwrap().unwrap(), b"five");
+    assert_eq!(store.len(), 4);
+}
+
+#[test]
+fn test_kv_store_corruption() {
+    let path = tmp_file();
+    let cache = WriteThroughCache::builder(&path)
+        .page_size(4096)
+        .open()
+        .unwrap();
+    let mut store = KvStore::open(cache).unwrap();
+    store.put(b"a", b"one").unwrap();
+    let second = store.log_size();
+    store.put(b"b", b"two").unwrap();
+    store.put(b"c", b"three").unwrap();
+
+    // A damaged record with more of the log after it is reported, not
+    // taken as the end of the log
+    let mut cache = store.into_inner();
+    cache.write(second + 13, b"x").unwrap();
+    let result = KvStore::open(cache);
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+
+    // So is a damaged length field that makes the first record seem to run
+    // past the end of the file, and the log is left as it was
+    let path = tmp_file();
+    let mut store =
+        KvStore::open(WriteThroughCache::new(&path, Some(4096), None).unwrap()).unwrap();
+    store.put(b"a", b"one").unwrap();
+    store.put(b"b", b"two").unwrap();
+    let mut cache = store.into_inner();
+    let before = cache.read(0, cache.file_size() as usize).unwrap();
+    cache.write(8, &[0x80]).unwrap();
+    let result = KvStore::open(cache);
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    cache.write(8, &before[8..9]).unwrap();
+    let mut store = KvStore::open(cache).unwrap();
+    assert_eq!(store.len(), 2);
+    assert_eq!(store.get(b"b").unwrap().unwrap(), b"two");
+
+    // A long torn record is cleared, so a shorter one in its place reopens
+    let path = tmp_file();
+    let mut store =
+        KvStore::open(WriteThroughCache::new(&path, Some(4096), None).unwrap()).unwrap();
+    store.put(b"a", b"one").unwrap();
+    let end = store.log_size();
+    store.put(b"long", &[7; 100]).unwrap();
+    let mut cache = store.into_inner();
+    cache.write(end + 13, b"x").unwrap();
+    let mut store = KvStore::open(cache).unwrap();
+    assert_eq!(store.log_size(), end);
+    store.put(b"b", b"two").unwrap();
+    let mut store = KvStore::open(store.into_inner()).unwrap();
+    assert_eq!(store.len(), 2);
+    assert_eq!(store.get(b"b").unwrap().unwrap(), b"two");
+
+    // A last record cut short by the end of the file is torn too
+    let end = store.log_size();
+    let mut cache = store.into_inner();
+    cache
+        .write(end, &[1, 1, 0, 0, 0, 200, 0, 0, 0, 0, 0, 0, 0, b'k'])
+        .unwrap();
+    let store = KvStore::open(cache).unwrap();
+    assert_eq!(store.log_size(), end);
+    assert_eq!(store.len(), 2);
+    let mut cache = store.into_inner();
+    assert_eq!(cache.read(end, 14).unwrap(), [0; 14]);
+}
+
+#[test]
+fn test_kv_store_compact() {
+    let mut store =
+        KvStore::open(WriteThroughCache::new(&tmp_file(), Some(4096), None).unwrap()).unwrap();
+    for round in 0..10u8 {
+        store.put(b"a", &[round; 100]).unwrap();
+        store.put(b"b", &[round; 50]).unwrap();
+    }
+    store.delete(b"b").unwrap();
+    store.put(b"c", b"three").unwrap();
+
+    let dest = tmp_file();
+    let compacted = store
+        .compact(WriteThroughCache::new(&dest, Some(4096), None).unwrap())
+        .unwrap();
+    assert!(compacted.log_size() < store.log_size());
+    drop(compacted);
+
+    let mut compacted = KvStore::open(WriteThroughCache::new(&dest, None, None).unwrap()).unwrap();
+    let entries: Vec<_> = compacted.iter().map(Result::unwrap).collect();
+    assert_eq!(
+        entries,
+        [
+            (b"a".to_vec(), vec![9; 100]),
+            (b"c".to_vec(), b"three".to_vec()),
+        ]
+    );
+
+    // Only into an empty file
+    let result = store.compact(compacted.into_inner());
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_ring_cache() {
+    let path = tmp_file();
+    let page_size = 4096;
+    let cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .open()
+        .unwrap();
+    let mut ring = RingCache::open(cache, 10).unwrap();
+    assert!(ring.is_empty());
+
+    assert_eq!(ring.append(b"abcdef").unwrap(), 0);
+    assert_eq!(ring.read(2, 4).unwrap(), b"cdef");
+    // Wraps around, overwriting "abc"
+    assert_eq!(ring.append(b"ghijkl").unwrap(), 6);
+    assert_eq!((ring.tail(), ring.head(), ring.len()), (2, 12, 10));
+    assert_eq!(ring.read(2, 10).unwrap(), b"cdefghijkl");
+    assert_eq!(
+        ring.read(0, 4).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        ring.read(10, 4).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        ring.append(&[0; 11]).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    // The data after the header page is the ring itself
+    let cache = ring.into_inner();
+    assert_eq!(cache.file_size(), 2 * page_size as u64);
+    drop(cache);
+
+    // Positions survive reopening
+    let cache = WriteThroughCache::new(&path, None, None).unwrap();
+    let mut ring = RingCache::open(cache, 10).unwrap();
+    assert_eq!((ring.tail(), ring.head()), (2, 12));
+    assert_eq!(ring.read(8, 4).unwrap(), b"ijkl");
+    ring.append(b"0123456789").unwrap();
+    assert_eq!(ring.read(12, 10).unwrap(), b"0123456789");
+
+    let cache = ring.into_inner();
+    assert_eq!(
+        RingCache::open(cache, 20).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    cache.write(8, &[0xff]).unwrap();
+    assert_eq!(
+        RingCache::open(cache, 10).err().unwrap().kind(),
+        ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn test_page_allocator() {
+    let path = tmp_file();
+    let page_size = 4096;
+    let cache = WriteThroughCache::builder(&path)
+        .page_size(page_size)
+        .open()
+        .unwrap();
+    let mut allocator = PageAllocator::open(cache).unwrap();
+    let pages: Vec<u64> = (0..4).map(|_| allocator.alloc_page().unwrap()).collect();
+    assert_eq!(pages, [1, 2, 3, 4]);
+    for &page_id in &pages {
+        let address = allocator.address(page_id);
+        allocator
+            .cache()
+            .write(address, &vec![page_id as u8; page_size])
+            .unwrap();
+    }
+
+    allocator.free_page(2).unwrap();
+    allocator.free_page(4).unwrap();
+    assert_eq!(
+        (allocator.allocated_pages(), allocator.free_pages()),
+        (2, 2)
+    );
+    assert_eq!(
+        allocator.free_page(5).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        allocator.free_page(0).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    drop(allocator);
+
+    // The free list survives reopening and is used before the file grows
+    let cache = WriteThroughCache::new(&path, None, None).unwrap();
+    let mut allocator = PageAllocator::open(cache).unwrap();
+    assert_eq!(
+        (allocator.allocated_pages(), allocator.free_pages()),
+        (2, 2)
+    );
+    assert_eq!(allocator.alloc_page().unwrap(), 4);
+    assert_eq!(allocator.alloc_page().unwrap(), 2);
+    assert_eq!(allocator.alloc_page().unwrap(), 5);
+    assert_eq!(allocator.free_pages(), 0);
+    // Untouched pages keep their data
+    let address = allocator.address(3);
+    assert_eq!(allocator.cache().read(address, 2).unwrap(), [3, 3]);
+
+    // A page put on the free list behind the allocator's back
+    allocator.free_page(1).unwrap();
+    let address = allocator.address(1);
+    allocator.cache().write(address, &[0; 16]).unwrap();
+    assert_eq!(
+        allocator.alloc_page().err().unwrap().kind(),
+        ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn test_slotted_page() {
+    let mut page = SlottedPage::new(128);
+    // 8 bytes of header, and each record costs 8 bytes of slot
+    assert_eq!(page.free_space(), 120);
+    let a = page.insert(&[1; 40]).unwrap();
+    let b = page.insert(&[2; 30]).unwrap();
+    let c = page.insert(b"").unwrap();
+    assert_eq!((a, b, c), (0, 1, 2));
+    assert_eq!(page.free_space(), 120 - 3 * 8 - 70);
+    assert_eq!(page.insert(&[3; 27]), None);
+
+    // Freeing the first record leaves a hole that compaction reclaims
+    assert!(page.delete(a));
+    assert!(!page.delete(a));
+    assert_eq!(page.get(a), None);
+    let d = page.insert(&[4; 66]).unwrap();
+    assert_eq!(d, a);
+    assert_eq!(page.get(b).unwrap(), [2; 30]);
+    assert_eq!(page.get(c).unwrap(), b"");
+    assert_eq!(page.get(d).unwrap(), [4; 66]);
+    assert_eq!(page.free_space(), 0);
+
+    assert!(page.update(b, b"short"));
+    assert!(page.update(b, &[5; 29]));
+    assert!(!page.update(b, &[5; 31]));
+    assert!(!page.update(7, b"x"));
+    assert_eq!(page.get(b).unwrap(), [5; 29]);
+    // Deleting from the end shrinks the directory
+    assert!(page.delete(c));
+    assert_eq!(page.slot_count(), 2);
+    let records: Vec<(u32, Vec<u8>)> = page.iter().map(|(slot, r)| (slot, r.to_vec())).collect();
+    assert_eq!(records, [(0, vec![4; 66]), (1, vec![5; 29])]);
+
+    // Through the cache, starting from a zeroed page
+    let path = tmp_file();
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(4096)
+        .open()
+        .unwrap();
+    cache.write(2 * 4096, &[0; 4096]).unwrap();
+    let mut page = SlottedPage::load(&mut cache, 1).unwrap();
+    assert_eq!(page.slot_count(), 0);
+    page.insert(b"hello").unwrap();
+    page.store(&mut cache, 1).unwrap();
+    let page = SlottedPage::load(&mut cache, 1).unwrap();
+    assert_eq!(page.get(0).unwrap(), b"hello");
+
+    let mut bytes = page.as_bytes().to_vec();
+    bytes[..4].copy_from_slice(&1000u32.to_le_bytes());
+    assert_eq!(
+        SlottedPage::from_bytes(bytes).unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn test_btree() {
+    let path = tmp_file();
+    let open = |path: &PathBuf| {
+        let cache = WriteThroughCache::builder(path)
+            .page_size(512)
+            .open()
+            .unwrap();
+        BTree::open(PageAllocator::open(cache).unwrap()).unwrap()
+    };
+    let key = |i: u64| format!("key{:05}", i).into_bytes();
+
+    let mut tree = open(&path);
+    assert_eq!(tree.lookup(b"missing").unwrap(), None);
+    // Scattered insert order, with enough keys for the root to split twice
+    for i in 0..2000 {
+        let i = i * 7919 % 2000;
+        assert_eq!(tree.insert(&key(i), &i.to_le_bytes()).unwrap(), None);
+    }
+    assert_eq!(
+        tree.insert(&key(5), b"five").unwrap(),
+        Some(5u64.to_le_bytes().to_vec())
+    );
+    assert_eq!(tree.lookup(&key(5)).unwrap().unwrap(), b"five");
+    assert_eq!(
+        tree.insert(b"big", &[0; 512]).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    drop(tree);
+
+    let mut tree = open(&path);
+    assert_eq!(
+        tree.lookup(&key(1234)).unwrap().unwrap(),
+        1234u64.to_le_bytes()
+    );
+    let keys: Vec<Vec<u8>> = tree
+        .range::<Vec<u8>, _>(..)
+        .unwrap()
+        .map(|entry| entry.unwrap().0)
+        .collect();
+    assert_eq!(keys, (0..2000).map(key).collect::<Vec<_>>());
+
+    for i in (0..2000).step_by(2) {
+        assert!(tree.delete(&key(i)).unwrap().is_some());
+    }
+    assert_eq!(tree.delete(&key(0)).unwrap(), None);
+    assert_eq!(tree.lookup(&key(10)).unwrap(), None);
+    let keys: Vec<Vec<u8>> = tree
+        .range(key(100)..=key(111))
+        .unwrap()
+        .map(|entry| entry.unwrap().0)
+        .collect();
+    assert_eq!(keys, [101, 103, 105, 107, 109, 111].map(key));
+    let keys: Vec<Vec<u8>> = tree
+        .range((
+            std::ops::Bound::Excluded(key(1995)),
+            std::ops::Bound::Unbounded,
+        ))
+        .unwrap()
+        .map(|entry| entry.unwrap().0)
+        .collect();
+    assert_eq!(keys, [1997, 1999].map(key));
+    drop(tree);
+
+    // Deletes survive reopening, and deleted keys can come back
+    let mut tree = open(&path);
+    assert_eq!(tree.lookup(&key(10)).unwrap(), None);
+    tree.insert(&key(10), b"ten").unwrap();
+    assert_eq!(tree.lookup(&key(10)).unwrap().unwrap(), b"ten");
+    assert_eq!(tree.range::<Vec<u8>, _>(..).unwrap().count(), 1001);
+}
+
+#[test]
+fn test_page_allocator_extents() {
+    let path = tmp_file();
+    let cache = WriteThroughCache::builder(&path)
+        .page_size(4096)
+        .open()
+        .unwrap();
+    let mut allocator = PageAllocator::open(cache).unwrap();
+    assert_eq!(allocator.alloc_extent(8).unwrap(), 1);
+    assert_eq!(allocator.alloc_page().unwrap(), 9);
+    assert_eq!(allocator.alloc_extent(4).unwrap(), 10);
+    assert_eq!(
+        allocator.alloc_extent(0).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    allocator.free_extent(1, 8).unwrap();
+    allocator.free_page(9).unwrap();
+    assert_eq!(
+        (allocator.allocated_pages(), allocator.free_pages()),
+        (4, 9)
+    );
+    assert_eq!(
+        allocator.free_extent(12, 3).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    drop(allocator);
+
+    let cache = WriteThroughCache::new(&path, None, None).unwrap();
+    let mut allocator = PageAllocator::open(cache).unwrap();
+    // First fit skips the single free page for the run behind it, and
+    // leaves the rest of that run on the list
+    assert_eq!(allocator.alloc_extent(3).unwrap(), 1);
+    assert_eq!(allocator.alloc_extent(5).unwrap(), 4);
+    assert_eq!(allocator.free_pages(), 1);
+    // Nothing free is large enough, so the file grows
+    assert_eq!(allocator.alloc_extent(2).unwrap(), 14);
+    assert_eq!(allocator.alloc_page().unwrap(), 9);
+    assert_eq!(allocator.free_pages(), 0);
+    assert_eq!(allocator.alloc_page().unwrap(), 16);
+
+    // Freeing twice, or freeing an extent over a free page, is refused
+    allocator.free_page(16).unwrap();
+    assert_eq!(
+        allocator.free_page(16).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        allocator.free_extent(15, 2).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(allocator.free_pages(), 1);
+    // A reallocated page can be freed again
+    assert_eq!(allocator.alloc_page().unwrap(), 16);
+    allocator.free_page(16).unwrap();
+
+    // A free list that loops back on itself is reported, not walked forever
+    let address = allocator.address(16);
+    let mut link = b"WTFREE\0\0".to_vec();
+    link.extend_from_slice(&16u64.to_le_bytes());
+    link.extend_from_slice(&1u64.to_le_bytes());
+    allocator.cache().write(address, &link).unwrap();
+    assert_eq!(
+        allocator.alloc_extent(2).err().unwrap().kind(),
+        ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn test_page_allocator_interior_free() {
+    let cache = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    let mut allocator = PageAllocator::open(cache).unwrap();
+    allocator.alloc_page().unwrap();
+
+    let extent = allocator.alloc_extent(4).unwrap();
+    let allocated = allocator.allocated_pages();
+    // Pages whose data looks like a free run can still be freed
+    for page_id in extent..extent + 4 {
+        let address = allocator.address(page_id);
+        allocator.cache().write(address, b"WTFREE\0\0").unwrap();
+    }
+    allocator.free_page(extent + 3).unwrap();
+    allocator.free_extent(extent, 3).unwrap();
+    let free = allocator.free_pages();
+    assert_eq!(allocator.allocated_pages(), allocated - 4);
+
+    // Every page of a free run is refused, not just its first
+    for page_id in extent..extent + 4 {
+        assert_eq!(
+            allocator.free_page(page_id).err().unwrap().kind(),
+            ErrorKind::InvalidInput
+        );
+    }
+    assert_eq!(
+        allocator.free_extent(extent + 2, 2).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(allocator.free_pages(), free);
+    assert_eq!(allocator.allocated_pages(), allocated - 4);
+}
+
+#[test]
+fn test_bitmap() {
+    let path = tmp_file();
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(512)
+        .open()
+        .unwrap();
+    let bits = 4999;
+    assert_eq!(Bitmap::reserved_pages(bits, 512), 2);
+    // Something else owns page 0
+    cache.write(0, &[7; 512]).unwrap();
+    let bitmap = Bitmap::create(&mut cache, 1, bits).unwrap();
+    assert_eq!(bitmap.find_first_clear(&mut cache).unwrap(), Some(0));
+
+    for bit in 0..4100 {
+        bitmap.set(&mut cache, bit).unwrap();
+    }
+    assert!(bitmap.test(&mut cache, 4099).unwrap());
+    assert!(!bitmap.test(&mut cache, 4100).unwrap());
+    // Past the first page of bits
+    assert_eq!(bitmap.find_first_clear(&mut cache).unwrap(), Some(4100));
+    bitmap.clear(&mut cache, 17).unwrap();
+    assert_eq!(bitmap.find_first_clear(&mut cache).unwrap(), Some(17));
+    assert_eq!(
+        bitmap.set(&mut cache, bits).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(cache.read(0, 512).unwrap(), [7; 512]);
+    drop(cache);
+
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    let bitmap = Bitmap::open(&mut cache, 1).unwrap();
+    assert_eq!(bitmap.len(), bits);
+    assert!(!bitmap.test(&mut cache, 17).unwrap());
+    assert!(bitmap.test(&mut cache, 18).unwrap());
+    for bit in (0..bits).filter(|&bit| bit == 17 || bit >= 4100) {
+        bitmap.set(&mut cache, bit).unwrap();
+    }
+    assert_eq!(bitmap.find_first_clear(&mut cache).unwrap(), None);
+
+    assert_eq!(
+        Bitmap::open(&mut cache, 0).err().unwrap().kind(),
+        ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn test_blob_store() {
+    let path = tmp_file();
+    let open = |path: &PathBuf| {
+        let cache = WriteThroughCache::builder(path)
+            .page_size(4096)
+            .open()
+            .unwrap();
+        BlobStore::open(PageAllocator::open(cache).unwrap())
+    };
+
+    let mut blobs = open(&path);
+    let small = blobs.store(b"small").unwrap();
+    let empty = blobs.store(b"").unwrap();
+    let large_data: Vec<u8> = (0..20000u32).map(|i| (i * 31 % 251) as u8).collect();
+    let large = blobs.store(&large_data).unwrap();
+    assert_eq!((small, empty, large), (BlobId(1), BlobId(2), BlobId(3)));
+    assert_eq!(blobs.load(small).unwrap(), b"small");
+    assert_eq!(blobs.load(empty).unwrap(), b"");
+    assert_eq!(blobs.load(large).unwrap(), large_data);
+    assert_eq!(blobs.size(large).unwrap(), 20000);
+    // Five pages, contiguous
+    assert_eq!(blobs.store(b"next").unwrap(), BlobId(8));
+    drop(blobs);
+
+    // Compressible blobs over the threshold take less space
+    let mut blobs = open(&path).compression(Box::new(Lz4Codec), 1024);
+    assert_eq!(blobs.load(large).unwrap(), large_data);
+    let zeros = blobs.store(&[0; 64 * 1024]).unwrap();
+    let after = blobs.store(b"after").unwrap();
+    assert_eq!(after.0, zeros.0 + 1);
+    assert_eq!(blobs.load(zeros).unwrap(), [0; 64 * 1024]);
+    drop(blobs);
+    // Without the codec they can't be read back
+    let mut blobs = open(&path);
+    assert_eq!(
+        blobs.load(zeros).err().unwrap().kind(),
+        ErrorKind::Unsupported
+    );
+    assert_eq!(blobs.size(zeros).unwrap(), 64 * 1024);
+
+    // Deleted extents are reused
+    blobs.delete(large).unwrap();
+    assert_eq!(
+        blobs.load(large).err().unwrap().kind(),
+        ErrorKind::InvalidData
+    );
+    assert_eq!(blobs.store(&[1; 10000]).unwrap(), large);
+    assert_eq!(
+        blobs.load(BlobId(100)).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    // A corrupted blob fails its checksum
+    let mut allocator = blobs.into_inner();
+    let address = allocator.address(small.0);
+    // Past the header, into the data
+    allocator.cache().write(address + 45, b"x").unwrap();
+    let mut blobs = BlobStore::open(allocator);
+    assert_eq!(
+        blobs.load(small).err().unwrap().kind(),
+        ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn test_cache_manager() {
+    let paths: Vec<PathBuf> = (0..3).map(|_| tmp_file()).collect();
+    let mut manager = CacheManager::new(17 * 4096)
+        .unwrap()
+        .page_size(4096)
+        .file_capacity(16 * 4096);
+    for (i, path) in paths.iter().enumerate() {
+        let mut cache = manager.cache(path).unwrap();
+        cache.write(0, &vec![i as u8; 8 * 4096]).unwrap();
+    }
+    // Reading each file in turn keeps the total within the budget, taking
+    // the pages used longest ago
+    for path in &paths {
+        let mut cache = manager.cache(path).unwrap();
+        for page in 0..8 {
+            cache.read(page * 4096, 1).unwrap();
+        }
+    }
+    assert!(manager.memory_usage() <= manager.budget());
+    assert_eq!(manager.resident_bytes(), 16 * 4096);
+    // The two files used last are still cached, the first is not
+    let misses = |manager: &CacheManager, path| manager.stats(path).unwrap().misses;
+    let before = [0, 1, 2].map(|i| misses(&manager, &paths[i]));
+    for path in &paths[1..] {
+        let mut cache = manager.cache(path).unwrap();
+        for page in 0..8 {
+            cache.read(page * 4096, 1).unwrap();
+        }
+    }
+    assert_eq!(misses(&manager, &paths[1]), before[1]);
+    assert_eq!(misses(&manager, &paths[2]), before[2]);
+    let mut cache = manager.cache(&paths[0]).unwrap();
+    assert_eq!(cache.read(4096, 2).unwrap(), [0, 0]);
+    drop(cache);
+    assert_eq!(misses(&manager, &paths[0]), before[0] + 1);
+    assert!(manager.memory_usage() <= manager.budget());
+
+    // By default one file takes at most a quarter of the budget
+    let mut small = CacheManager::new(16 * 4096).unwrap();
+    let mut cache = small.cache(&paths[1]).unwrap();
+    for page in 0..8 {
+        cache.read(page * 4096, 1).unwrap();
+    }
+    drop(cache);
+    assert!(small.resident_bytes() <= 4 * 4096);
+
+    assert_eq!(manager.paths().count(), 3);
+    assert!(manager.close(&paths[0]));
+    assert!(!manager.close(&paths[0]));
+    assert!(manager.stats(&paths[0]).is_none());
+    assert!(manager.stats(&paths[2]).is_some());
+
+    assert_eq!(
+        CacheManager::new(0).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cache_manager_path_spellings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    let dotted = dir.path().join(".").join("data");
+    let link = dir.path().join("link");
+    let mut manager = CacheManager::new(1024 * 1024).unwrap().page_size(4096);
+
+    // Created through one spelling, then reached through the others
+    manager.cache(&dotted).unwrap().write(0, &[1; 100]).unwrap();
+    std::os::unix::fs::symlink(&path, &link).unwrap();
+    assert_eq!(manager.cache(&path).unwrap().read(0, 4).unwrap(), [1; 4]);
+    manager.cache(&link).unwrap().write(0, &[2; 4]).unwrap();
+    assert_eq!(manager.cache(&dotted).unwrap().read(0, 4).unwrap(), [2; 4]);
+    assert_eq!(manager.paths().count(), 1);
+    assert!(manager.stats(&link).is_some());
+
+    assert!(manager.close(&link));
+    assert!(!manager.close(&path));
+}
+
+#[test]
+fn test_cache_manager_many_files() {
+    let budget = 1024 * 1024;
+    let page_size = 4096;
+    let mut manager = CacheManager::new(budget).unwrap().page_size(page_size);
+    let paths: Vec<PathBuf> = (0..300).map(|_| tmp_file()).collect();
+    let misses = |manager: &CacheManager, path| manager.stats(path).unwrap().misses;
+
+    manager
+        .cache(&paths[0])
+        .unwrap()
+        .write(0, &vec![1; 2 * page_size])
+        .unwrap();
+    for path in &paths[1..] {
+        manager
+            .cache(path)
+            .unwrap()
+            .write(0, &vec![2; 2 * page_size])
+            .unwrap();
+        // Keeps the first file in use, but only its first page
+        manager.cache(&paths[0]).unwrap().read(0, 1).unwrap();
+    }
+
+    // Each file's index costs only what it holds, so most of the budget
+    // goes to pages
+    assert!(manager.memory_usage() <= budget);
+    assert!(manager.resident_bytes() >= budget / 2);
+
+    // Pages are evicted by their own last use, not their file's
+    let before = misses(&manager, &paths[0]);
+    manager.cache(&paths[0]).unwrap().read(0, 1).unwrap();
+    assert_eq!(misses(&manager, &paths[0]), before);
+    manager
+        .cache(&paths[0])
+        .unwrap()
+        .read(page_size as u64, 1)
+        .unwrap();
+    assert_eq!(misses(&manager, &paths[0]), before + 1);
+    // The files written last are still cached
+    let before = misses(&manager, &paths[299]);
+    manager
+        .cache(&paths[299])
+        .unwrap()
+        .read(0, 2 * page_size)
+        .unwrap();
+    assert_eq!(misses(&manager, &paths[299]), before);
+}
+
+#[test]
+fn test_segmented_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let base = dir.path().join("log");
+    let segment_size = 256 * 1024;
+    let mut segments = SegmentedCache::open(&base, segment_size, 1024 * 1024).unwrap();
+    assert_eq!(segments.segments().count(), 0);
+
+    // A write across two segment boundaries creates three segments
+    let data: Vec<u8> = (0..600 * 1024).map(|i| (i % 253) as u8).collect();
+    segments.write(100, &data).unwrap();
+    assert_eq!(segments.segments().collect::<Vec<_>>(), [0, 1, 2]);
+    assert_eq!(segments.read(100, data.len()).unwrap(), data);
+    assert!(segments.segment_path(1).exists());
+    // Far ahead, skipping segments
+    segments.write(10 * segment_size, b"tenth").unwrap();
+    drop(segments);
+
+    let mut segments = SegmentedCache::open(&base, segment_size, 1024 * 1024).unwrap();
+    assert_eq!(segments.segments().collect::<Vec<_>>(), [0, 1, 2, 10]);
+    assert_eq!(
+        segments.read(segment_size - 2, 4).unwrap(),
+        &data[segment_size as usize - 102..segment_size as usize - 98]
+    );
+    assert_eq!(segments.read(10 * segment_size, 5).unwrap(), b"tenth");
+    assert_eq!(
+        segments.read(5 * segment_size, 1).err().unwrap().kind(),
+        ErrorKind::NotFound
+    );
+
+    // Retention drops whole segments only
+    assert_eq!(segments.drop_before(2 * segment_size + 1).unwrap(), 2);
+    assert_eq!(segments.segments().collect::<Vec<_>>(), [2, 10]);
+    assert!(!segments.segment_path(1).exists());
+    // Sidecars go with their segment
+    let mut header_path = segments.segment_path(1).into_os_string();
+    header_path.push(".hdr");
+    assert!(!PathBuf::from(header_path).exists());
+    // Two segments, their headers and the manifest
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 5);
+    assert_eq!(
+        segments.read(segment_size, 1).err().unwrap().kind(),
+        ErrorKind::NotFound
+    );
+    assert_eq!(segments.read(2 * segment_size, 2).unwrap(), {
+        let at = 2 * segment_size as usize - 100;
+        &data[at..at + 2]
+    });
+    assert!(segments.drop_segment(10).unwrap());
+    assert!(!segments.drop_segment(10).unwrap());
+    assert_eq!(segments.segments().collect::<Vec<_>>(), [2]);
+    drop(segments);
+
+    // The manifest holds the segment size they were written with
+    assert_eq!(
+        SegmentedCache::open(&base, 2 * segment_size, 1024 * 1024)
+            .err()
+            .unwrap()
+            .kind(),
+        ErrorKind::InvalidInput
+    );
+    let manifest = SegmentedCache::manifest_path_for(&base);
+    let mut bytes = std::fs::read(&manifest).unwrap();
+    bytes[8] ^= 1;
+    std::fs::write(&manifest, bytes).unwrap();
+    assert_eq!(
+        SegmentedCache::open(&base, segment_size, 1024 * 1024)
+            .err()
+            .unwrap()
+            .kind(),
+        ErrorKind::InvalidData
+    );
+
+    assert_eq!(
+        SegmentedCache::open(&base, 0, 1024 * 1024)
+            .err()
+            .unwrap()
+            .kind(),
+        ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn test_tiered_cache() {
+    let origin_path = tmp_file();
+    let data: Vec<u8> = (0..10 * 4096 + 100).map(|i| (i % 241) as u8).collect();
+    std::fs::write(&origin_path, &data).unwrap();
+    let origin = Box::new(FileOrigin::open(&origin_path).unwrap());
+    let mut cache = TieredCache::builder(origin, tmp_file())
+        .page_size(4096)
+        .ram_capacity(2 * 4096)
+        .disk_capacity(4 * 4096)
+        .open()
+        .unwrap();
+    assert_eq!(cache.size(), data.len() as u64);
+
+    // Across pages, including the short last one
+    assert_eq!(cache.read(4000, 200).unwrap(), &data[4000..4200]);
+    assert_eq!(cache.read(10 * 4096, 100).unwrap(), &data[10 * 4096..]);
+    assert_eq!(
+        cache.stats(),
+        TieredStats {
+            ram_hits: 0,
+            disk_hits: 0,
+            origin_reads: 3,
+            spills: 1,
+        }
+    );
+    // Page 1 is still in memory, page 0 spilled to disk
+    cache.read(4096, 1).unwrap();
+    cache.read(0, 1).unwrap();
+    assert_eq!((cache.stats().ram_hits, cache.stats().disk_hits), (1, 1));
+
+    // Reading every page overflows the disk tier, so the first pages read
+    // go back to the origin
+    for page in 0..11 {
+        assert_eq!(
+            cache.read(page * 4096, 10).unwrap(),
+            &data[page as usize * 4096..page as usize * 4096 + 10]
+        );
+    }
+    let origin_reads = cache.stats().origin_reads;
+    cache.read(2 * 4096, 1).unwrap();
+    assert_eq!(cache.stats().origin_reads, origin_reads + 1);
+    cache.read(8 * 4096, 1).unwrap();
+    assert_eq!(cache.stats().origin_reads, origin_reads + 1);
+
+    assert_eq!(
+        cache.read(data.len() as u64 - 1, 2).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    let origin = Box::new(FileOrigin::open(&origin_path).unwrap());
+    let result = TieredCache::builder(origin, tmp_file()).page_size(0).open();
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+}
+
+// Origin whose data and epoch a test can change under the cache
+#[derive(Clone)]
+struct SharedOrigin(std::sync::Arc<std::sync::Mutex<(Vec<u8>, u64)>>);
+
+impl Origin for SharedOrigin {
+    fn size(&mut self) -> std::io::Result<u64> {
+        Ok(self.0.lock().unwrap().0.len() as u64)
+    }
+
+    fn read_at(&mut self, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        Ok(self.0.lock().unwrap().0[offset as usize..offset as usize + len].to_vec())
+    }
+
+    fn version(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(Some(self.0.lock().unwrap().1.to_le_bytes().to_vec()))
+    }
+}
+
+#[test]
+fn test_read_through_cache() {
+    let local_path = tmp_file();
+    let data: Vec<u8> = (0..5 * 4096 + 10).map(|i| (i % 239) as u8).collect();
+    let origin = SharedOrigin(std::sync::Arc::new(std::sync::Mutex::new((
+        data.clone(),
+        1,
+    ))));
+    let open = |origin: &SharedOrigin| {
+        let local = WriteThroughCache::builder(&local_path)
+            .page_size(4096)
+            .open()
+            .unwrap();
+        ReadThroughCache::open(Box::new(origin.clone()), local).unwrap()
+    };
+
+    let mut cache = open(&origin);
+    assert_eq!(cache.read(4000, 200).unwrap(), &data[4000..4200]);
+    assert_eq!(cache.origin_reads(), 2);
+    assert_eq!(cache.read(4096, 10).unwrap(), &data[4096..4106]);
+    assert_eq!(cache.origin_reads(), 2);
+    assert_eq!(cache.read(5 * 4096, 10).unwrap(), &data[5 * 4096..]);
+    assert_eq!(cache.origin_reads(), 3);
+    assert_eq!(
+        cache.read(5 * 4096, 11).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    drop(cache);
+
+    // Fetched pages persist across reopening
+    let mut cache = open(&origin);
+    assert_eq!(cache.read(0, 8192).unwrap(), &data[..8192]);
+    assert_eq!(cache.origin_reads(), 0);
+    assert!(!cache.revalidate().unwrap());
+
+    // A new epoch at the origin drops them
+    origin.0.lock().unwrap().0[0] = 0xaa;
+    origin.0.lock().unwrap().1 = 2;
+    assert_eq!(cache.read(0, 1).unwrap(), [0]);
+    assert!(cache.revalidate().unwrap());
+    assert_eq!(cache.read(0, 1).unwrap(), [0xaa]);
+    assert_eq!(cache.origin_reads(), 1);
+    drop(cache);
+
+    origin.0.lock().unwrap().1 = 3;
+    let mut cache = open(&origin);
+    assert_eq!(cache.read(4096, 1).unwrap(), [data[4096]]);
+    assert_eq!(cache.origin_reads(), 1);
+}
+
+#[test]
+fn test_partitions() {
+    let path = tmp_file();
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(4096)
+        .open()
+        .unwrap();
+    assert!(cache.partitions().is_empty());
+    let index = cache.create_partition("index", 10000).unwrap();
+    let data = cache.create_partition("data", 1 << 20).unwrap();
+    assert_eq!((index.start, data.start), (0, 12288));
+    assert_eq!(
+        cache.create_partition("index", 1).err().unwrap().kind(),
+        ErrorKind::AlreadyExists
+    );
+
+    cache
+        .partition("index")
+        .unwrap()
+        .write(0, b"index")
+        .unwrap();
+    cache.partition("data").unwrap().write(0, b"data").unwrap();
+    let mut partition = cache.partition("index").unwrap();
+    assert_eq!(partition.size(), 10000);
+    assert_eq!(
+        partition.write(9999, b"xy").err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(cache.read(12288, 4).unwrap(), b"data");
+    drop(cache);
+
+    // The table is kept in the file header
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    assert_eq!(
+        cache.partitions(),
+        [
+            Partition {
+                name: "index".to_string(),
+                start: 0,
+                size: 10000,
+            },
+            data
+        ]
+    );
+    assert_eq!(
+        cache.partition("index").unwrap().read(0, 5).unwrap(),
+        b"index"
+    );
+    assert_eq!(
+        cache.partition("data").unwrap().read(0, 4).unwrap(),
+        b"data"
+    );
+    assert_eq!(
+        cache.partition("missing").err().unwrap().kind(),
+        ErrorKind::NotFound
+    );
+    let log = cache.create_partition("log", 100).unwrap();
+    assert_eq!(log.start, 12288 + (1 << 20));
+    assert_eq!(cache.info().unwrap().header_version, 3);
+}
+
+#[test]
+fn test_partitions_survive_compaction() {
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    Superblock::open(&mut cache).unwrap();
+    cache.create_partition("data", 100_000).unwrap();
+    cache.partition("data").unwrap().write(10, b"data").unwrap();
+    let partitions = cache.partitions().to_vec();
+
+    let dest = tmp_file();
+    let options = CompactOptions {
+        compression: Compression::Lz4,
+        ..CompactOptions::default()
+    };
+    cache.compact_to(&dest, options).unwrap();
+    let mut compacted = WriteThroughCache::new(&dest, None, None).unwrap();
+    assert_eq!(compacted.partitions(), partitions);
+    assert_eq!(
+        compacted.partition("data").unwrap().read(10, 4).unwrap(),
+        b"data"
+    );
+    assert!(Superblock::open(&mut compacted).is_ok());
+
+    // Partitions have to stay aligned to the new page size
+    let options = CompactOptions {
+        page_size: Some(64 * 1024),
+        ..CompactOptions::default()
+    };
+    let dest = tmp_file();
+    assert_eq!(
+        cache.compact_to(&dest, options).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert!(!dest.exists());
+}
+
+#[test]
+fn test_superblock() {
+    let path = tmp_file();
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(4096)
+        .open()
+        .unwrap();
+    let mut superblock = Superblock::open(&mut cache).unwrap();
+    assert_eq!(superblock.generation(), 0);
+    assert_eq!(cache.partitions()[0].name, "superblock");
+    // Reserved but never written
+    assert_eq!(Superblock::open(&mut cache).unwrap().generation(), 0);
+    superblock.set_str("owner", "index");
+    superblock.set_u64("root", 42);
+    assert_eq!(superblock.increment("opens"), 1);
+    assert_eq!(superblock.commit(&mut cache).unwrap(), 1);
+    superblock.set_u64("root", 43);
+    assert_eq!(superblock.commit(&mut cache).unwrap(), 2);
+    // Staged but never committed
+    superblock.set_u64("root", 44);
+    drop(cache);
+
+    let mut cache = WriteThroughCache::new(&path, None, None).unwrap();
+    let mut superblock = Superblock::open(&mut cache).unwrap();
+    assert_eq!(superblock.generation(), 2);
+    assert_eq!(superblock.get_u64("root"), Some(43));
+    assert_eq!(superblock.get_str("owner"), Some("index"));
+    assert_eq!(superblock.get_u64("owner"), None);
+    assert_eq!(superblock.increment("opens"), 2);
+    assert!(superblock.remove("owner"));
+    assert_eq!(superblock.keys().collect::<Vec<_>>(), ["opens", "root"]);
+    superblock.commit(&mut cache).unwrap();
+
+    // A torn write of generation 4 leaves generation 3 in the other page
+    superblock.set(&"x".repeat(100), &[1; 100]);
+    let start = cache.partition("superblock").unwrap().size() / 2;
+    superblock.commit(&mut cache).unwrap();
+    cache
+        .partition("superblock")
+        .unwrap()
+        .write(start - 4096 + 30, &[0xff])
+        .unwrap();
+    let superblock = Superblock::open(&mut cache).unwrap();
+    assert_eq!(superblock.generation(), 3);
+    assert_eq!(superblock.get_str("owner"), None);
+    assert_eq!(superblock.get_u64("opens"), Some(2));
+
+    let mut superblock = superblock;
+    superblock.set("big", &[0; 4096]);
+    assert_eq!(
+        superblock.commit(&mut cache).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    // Data already at the start of an unpartitioned file is left alone
+    let mut unpartitioned = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    unpartitioned.write(0, b"USERDATA-AT-ZERO").unwrap();
+    assert_eq!(
+        Superblock::open(&mut unpartitioned).err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(unpartitioned.read(0, 16).unwrap(), b"USERDATA-AT-ZERO");
+}
+
+#[test]
+fn test_backup() {
+    let path = tmp_file();
+    let dest = tmp_file();
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(4096)
+        .open()
+        .unwrap();
+    for page_id in 0..8u8 {
+        cache
+            .write(page_id as u64 * 4096, &[page_id; 4096])
+            .unwrap();
+    }
+    cache.write(8 * 4096, b"tail").unwrap();
+
+    let mut backup: Backup = cache.start_backup(&dest).unwrap();
+    assert!(!backup.step(&mut cache, 4).unwrap());
+    // One page already copied and one not yet, then the file grows
+    cache.write(4096, b"changed").unwrap();
+    cache.write(6 * 4096, b"changed").unwrap();
+    cache.write(8 * 4096, b"longer tail").unwrap();
+    let report = backup.finish(&mut cache).unwrap();
+    assert_eq!(
+        report,
+        BackupReport {
+            pages_copied: 9,
+            pages_recopied: 3,
+        }
+    );
+
+    let original = cache.read(0, cache.file_size() as usize).unwrap();
+    let mut copy = WriteThroughCache::builder(&dest).open().unwrap();
+    assert_eq!(copy.file_size(), cache.file_size());
+    assert_eq!(copy.read(0, original.len()).unwrap(), original);
+
+    assert_eq!(
+        cache.backup_to(&dest).err().unwrap().kind(),
+        ErrorKind::AlreadyExists
+    );
+}
+
+#[test]
+fn test_backup_partial_last_page() {
+    let path = tmp_file();
+    let dest = tmp_file();
+    let data: Vec<u8> = (0..10000).map(|i| (i % 251) as u8).collect();
+    std::fs::write(&path, &data).unwrap();
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(4096)
+        .open()
+        .unwrap();
+
+    let mut backup = cache.start_backup(&dest).unwrap();
+    assert!(!backup.step(&mut cache, 1).unwrap());
+    // A page already copied is written, so the copy ends with a recopy
+    cache.write(100, b"changed").unwrap();
+    assert_eq!(backup.finish(&mut cache).unwrap().pages_recopied, 1);
+    assert_eq!(cache.file_size(), 10000);
+
+    assert_eq!(std::fs::metadata(&dest).unwrap().len(), 10000);
+    let mut copy = WriteThroughCache::builder(&dest).open().unwrap();
+    assert_eq!(copy.file_size(), 10000);
+    assert_eq!(copy.read(0, 10000).unwrap(), cache.read(0, 10000).unwrap());
+}
+
+#[test]
+fn test_backup_with_copy_range() {
+    let dest = tmp_file();
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    for page_id in 0..8u8 {
+        cache
+            .write(page_id as u64 * 4096, &[page_id; 4096])
+            .unwrap();
+    }
+
+    let mut backup = cache.start_backup(&dest).unwrap();
+    assert!(!backup.step(&mut cache, 4).unwrap());
+    // Copies over a page already backed up and one not yet reached
+    cache.copy_range(0, 2 * 4096, 4096).unwrap();
+    cache.copy_range(5 * 4096, 7 * 4096, 4096).unwrap();
+    let report = backup.finish(&mut cache).unwrap();
+    assert_eq!(report.pages_copied, 8);
+    assert_eq!(report.pages_recopied, 2);
+
+    let original = cache.read(0, cache.file_size() as usize).unwrap();
+    assert_eq!(original[2 * 4096..3 * 4096], [0; 4096]);
+    let mut copy = WriteThroughCache::builder(&dest).open().unwrap();
+    assert_eq!(copy.read(0, original.len()).unwrap(), original);
+}
+
+#[test]
+fn test_changed_page_tracking() {
+    let path = tmp_file();
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(4096)
+        .open()
+        .unwrap();
+    cache.write(0, &[1; 4 * 4096]).unwrap();
+
+    let first: Epoch = cache.begin_tracking();
+    assert!(cache.changed_pages_since(first).is_empty());
+    cache.write(3 * 4096, b"x").unwrap();
+    cache.write(4095, b"xy").unwrap();
+    let second = cache.begin_tracking();
+    cache.write(6 * 4096, b"grown").unwrap();
+    cache.write(4096, b"again").unwrap();
+
+    assert_eq!(cache.changed_pages_since(first), vec![0, 1, 3, 6]);
+    assert_eq!(cache.changed_pages_since(second), vec![1, 6]);
+
+    // A backup leaves tracking asked for by the caller in place
+    cache.backup_to(&tmp_file()).unwrap();
+    cache.write(2 * 4096, b"after").unwrap();
+    assert_eq!(cache.changed_pages_since(second), vec![1, 2, 6]);
+
+    // Copies within the file change their destination pages too
+    let third = cache.begin_tracking();
+    cache.copy_range(0, 8 * 4096 + 100, 4096).unwrap();
+    assert_eq!(cache.changed_pages_since(third), vec![8, 9]);
+}
+
+#[test]
+fn test_replication() {
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    cache.write(0, &[7; 10000]).unwrap();
+    let mut primary = Primary::bind(cache, "127.0.0.1:0").unwrap();
+    let addr = primary.local_addr().unwrap();
+
+    let standby = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    let mut follower = Follower::connect(standby, addr).unwrap();
+    assert_eq!(primary.accept_followers().unwrap(), 1);
+    while !follower.in_sync() {
+        assert!(follower.apply_next().unwrap());
+    }
+    assert_eq!(follower.read(0, 10000).unwrap(), vec![7; 10000]);
+
+    primary.write(5000, b"replicated").unwrap();
+    primary.write(12000, b"grown").unwrap();
+    while follower.lsn() < primary.lsn() {
+        assert!(follower.apply_next().unwrap());
+    }
+    let size = primary.cache().file_size() as usize;
+    assert_eq!(
+        follower.read(0, size).unwrap(),
+        primary.read(0, size).unwrap()
+    );
+
+    // Writes don't take on followers, so never wait for a snapshot
+    let standby = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    let late = Follower::connect(standby, addr).unwrap();
+    primary.write(0, b"direct").unwrap();
+    assert_eq!(primary.followers(), 1);
+    assert!(follower.apply_next().unwrap());
+    assert_eq!(follower.read(0, 6).unwrap(), b"direct");
+
+    drop(late);
+    assert_eq!(
+        primary
+            .set_write_timeout(std::time::Duration::ZERO)
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidInput
+    );
+
+    drop(primary);
+    assert!(!follower.apply_next().unwrap());
+
+    // A follower that doesn't read its snapshot is dropped once sends time
+    // out
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    cache.write(0, &vec![1; 32 << 20]).unwrap();
+    let mut primary = Primary::bind(cache, "127.0.0.1:0").unwrap();
+    primary
+        .set_write_timeout(std::time::Duration::from_millis(50))
+        .unwrap();
+    let _stalled = std::net::TcpStream::connect(primary.local_addr().unwrap()).unwrap();
+    assert_eq!(primary.accept_followers().unwrap(), 0);
+    assert_eq!(primary.followers(), 0);
+
+    // Writes too large for one frame are refused before reaching the file
+    let size = primary.cache().file_size();
+    assert_eq!(
+        primary
+            .write(size, &vec![2; (4 << 20) + 1])
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(primary.cache().file_size(), size);
+
+    // A follower refuses a frame claiming more data than any frame carries
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let standby = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    let mut follower = Follower::connect(standby, listener.local_addr().unwrap()).unwrap();
+    let (mut stream, _) = listener.accept().unwrap();
+    let mut header = vec![2];
+    header.extend_from_slice(&1u64.to_le_bytes());
+    header.extend_from_slice(&0u64.to_le_bytes());
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    std::io::Write::write_all(&mut stream, &header).unwrap();
+    assert_eq!(
+        follower.apply_next().unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn test_change_subscription() {
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .page_versions(true)
+        .open()
+        .unwrap();
+    cache.write(0, b"before").unwrap();
+    let receiver = cache.subscribe();
+    let dropped = cache.subscribe();
+    drop(dropped);
+    cache.write(100, b"first").unwrap();
+    cache.write(5000, b"second!").unwrap();
+    let events: Vec<ChangeEvent> = receiver.try_iter().collect();
+    assert_eq!(
+        events,
+        vec![
+            ChangeEvent {
+                offset: 100,
+                len: 5,
+                lsn: Some(2),
+            },
+            ChangeEvent {
+                offset: 5000,
+                len: 7,
+                lsn: Some(3),
+            },
+        ]
+    );
+
+    cache.copy_range(0, 8192, 10).unwrap();
+    assert_eq!(
+        receiver.try_iter().collect::<Vec<_>>(),
+        vec![ChangeEvent {
+            offset: 8192,
+            len: 10,
+            lsn: Some(4),
+        }]
+    );
+}
+
+#[test]
+fn test_sync_cache() {
+    let cache = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    let shared = std::sync::Arc::new(SyncCache::new(cache));
+    let threads: Vec<_> = (0..4u8)
+        .map(|i| {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                for round in 0..50u64 {
+                    shared.write(i as u64 * 4096, &[i; 4096]).unwrap();
+                    // Overlapping writes land whole
+                    shared.write(20000 + round % 3, &[i; 1000]).unwrap();
+                    assert_eq!(shared.read(i as u64 * 4096, 4096).unwrap(), vec![i; 4096]);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let overlap = shared.read(20002, 998).unwrap();
+    assert!(overlap.iter().all(|&b| b == overlap[0]));
+
+    let size = {
+        let mut cache = shared.lock().unwrap();
+        let size = cache.file_size();
+        cache.write(size, b"appended").unwrap();
+        size
+    };
+    let mut cache = std::sync::Arc::into_inner(shared)
+        .unwrap()
+        .into_inner()
+        .unwrap();
+    assert_eq!(cache.read(size, 8).unwrap(), b"appended");
+}
+
+#[test]
+fn test_single_writer_readers() {
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    cache.write(0, &[1; 8 * 4096]).unwrap();
+    let (writer, reader) = cache.into_split();
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let reader: CacheReader = reader.clone();
+            std::thread::spawn(move || {
+                for round in 0..200u64 {
+                    let address = (round % 8) * 4096 + 100;
+                    let data = reader.read(address, 200).unwrap();
+                    // Whole writes only, so each range is all old or all new
+                    assert!(data.iter().all(|&b| b == data[0]));
+                }
+            })
+        })
+        .collect();
+    for round in 0..50u8 {
+        writer
+            .write((round % 8) as u64 * 4096, &[round; 4096])
+            .unwrap();
+    }
+    for thread in readers {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(reader.read(7 * 4096, 4096).unwrap(), vec![47; 4096]);
+    assert_eq!(writer.read(0, 4096).unwrap(), vec![48; 4096]);
+    assert_eq!(writer.reader().file_size().unwrap(), 8 * 4096);
+    assert!(reader.read(8 * 4096, 1).is_err());
+    let hits = writer.lock().unwrap().stats().hits;
+    assert!(hits >= 802);
+}
+
+#[test]
+fn test_single_writer_reader_hits_reported() {
+    let page_size = 4096;
+    let events = EventLog::default();
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(page_size)
+        .heatmap(page_size as u64)
+        .events(Box::new(events.clone()))
+        .open()
+        .unwrap();
+    cache.write(0, &[1; 2 * 4096]).unwrap();
+    let (writer, reader) = cache.into_split();
+    events.0.lock().unwrap().clear();
+
+    reader.read(4096 + 10, 10).unwrap();
+    reader.read(4096 + 20, 10).unwrap();
+    assert_eq!(*events.0.lock().unwrap(), ["hit 1", "hit 1"]);
+    let cache = writer.lock().unwrap();
+    assert_eq!(cache.export_heatmap().unwrap().reads, [0, 2]);
+    assert_eq!(cache.stats().hits, 2);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_warm() {
+    let path = tmp_file();
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(4096)
+        .open()
+        .unwrap();
+    for page_id in 0..64u8 {
+        cache
+            .write(page_id as u64 * 4096, &[page_id; 4096])
+            .unwrap();
+    }
+    drop(cache);
+
+    let mut cache = WriteThroughCache::builder(&path)
+        .page_size(4096)
+        .capacity(16 * 4096)
+        .open()
+        .unwrap();
+    cache.read(0, 1).unwrap();
+    // Overlapping ranges, one page already cached and one past the end
+    let loaded = cache
+        .warm(&[
+            (0, 3 * 4096),
+            (8192, 4096 + 1),
+            (40 * 4096, 2),
+            (64 * 4096, 10),
+        ])
+        .unwrap();
+    assert_eq!(loaded, 4);
+    let misses = cache.stats().misses;
+    for page_id in [0u8, 1, 2, 3, 40] {
+        assert_eq!(
+            cache.read(page_id as u64 * 4096, 4096).unwrap(),
+            vec![page_id; 4096]
+        );
+    }
+    assert_eq!(cache.stats().misses, misses);
+
+    // Stops once the cache is full
+    assert!(cache.warm(&[(0, 64 * 4096)]).unwrap() < 16);
+}
+
+#[test]
+fn test_file_lock() {
+    let path = tmp_file();
+    let mut first = WriteThroughCache::builder(&path)
+        .file_lock(FileLock::Fail)
+        .open()
+        .unwrap();
+    assert_eq!(
+        WriteThroughCache::builder(&path)
+            .file_lock(FileLock::Fail)
+            .open()
+            .err()
+            .unwrap()
+            .kind(),
+        ErrorKind::WouldBlock
+    );
+
+    let waiter = {
+        let path = path.clone();
+        std::thread::spawn(move || {
+            let mut cache = WriteThroughCache::builder(&path)
+                .file_lock(FileLock::Wait)
+                .open()
+                .unwrap();
+            cache.read(0, 7).unwrap()
+        })
+    };
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    first.write(0, b"written").unwrap();
+    drop(first);
+    // The waiter only got the file once the holder closed it
+    assert_eq!(waiter.join().unwrap(), b"written");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_shm_cache() {
+    let path = tmp_file();
+    let segment = tmp_file();
+    // Two handles with their own descriptors behave like two processes
+    let mut a = ShmCache::open(&path, &segment, 4096, 16).unwrap();
+    let mut b = ShmCache::open(&path, &segment, 4096, 16).unwrap();
+    for page_id in 0..40u8 {
+        a.write(page_id as u64 * 4096, &[page_id; 4096]).unwrap();
+    }
+    a.write(40 * 4096, b"partial").unwrap();
+
+    assert_eq!(b.read(4096 + 10, 4096).unwrap()[4086..], [2; 10]);
+    assert_eq!(b.misses(), 2);
+    assert_eq!(a.read(4096, 8192).unwrap(), [[1; 4096], [2; 4096]].concat());
+    assert_eq!((a.hits(), a.misses()), (2, 0));
+
+    // A write through one handle updates the copy the other reads
+    b.write(4096 + 100, b"changed").unwrap();
+    assert_eq!(a.read(4096 + 100, 7).unwrap(), b"changed");
+    assert_eq!(a.misses(), 0);
+
+    // Every page reads back right as sets fill up and evict
+    for page_id in (0..40u8).rev() {
+        assert_eq!(a.read(page_id as u64 * 4096, 100).unwrap(), [page_id; 100]);
+    }
+    assert_eq!(b.read(40 * 4096, 7).unwrap(), b"partial");
+    assert!(b.read(40 * 4096, 8).is_err());
+
+    assert_eq!(
+        ShmCache::open(&path, &segment, 4096, 8)
+            .err()
+            .unwrap()
+            .kind(),
+        ErrorKind::InvalidData
+    );
+    // The segment is tied to the file it was created for
+    assert_eq!(
+        ShmCache::open(&tmp_file(), &segment, 4096, 16)
+            .err()
+            .unwrap()
+            .kind(),
+        ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn test_actor_handle() {
+    let path = tmp_file();
+    let cache = WriteThroughCache::builder(&path)
+        .page_size(4096)
+        .open()
+        .unwrap();
+    let handle = actor::CacheHandle::spawn(cache).unwrap();
+    let threads: Vec<_> = (0..4u8)
+        .map(|i| {
+            let handle = handle.clone();
+            std::thread::spawn(move || {
+                handle.write(i as u64 * 4096, vec![i; 4096]).unwrap();
+                assert_eq!(handle.read(i as u64 * 4096, 4096).unwrap(), vec![i; 4096]);
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(handle.file_size().unwrap(), 4 * 4096);
+    assert!(handle.read(4 * 4096, 1).is_err());
+    assert_eq!(handle.call(|cache| cache.page_size()).unwrap(), 4096);
+
+    // A panicking operation stops the thread, failing later calls
+    assert!(handle.call(|_| panic!("in the cache thread")).is_err());
+    assert!(handle.stats().is_err());
+}
+
+#[cfg(feature = "epoch")]
+#[test]
+fn test_read_mostly_cache() {
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    cache.write(0, &[1; 8 * 4096 + 100]).unwrap();
+    let shared = std::sync::Arc::new(ReadMostlyCache::new(cache, 4).unwrap());
+
+    assert_eq!(shared.read(4000, 200).unwrap(), vec![1; 200]);
+    assert_eq!(shared.cached_pages(), 2);
+    let readers: Vec<_> = (0..4u64)
+        .map(|i| {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                for round in 0..100 {
+                    let data = shared.read(((i + round) % 9) * 4096, 100).unwrap();
+                    assert!(data.iter().all(|&b| b == data[0]));
+                }
+            })
+        })
+        .collect();
+    for round in 0..20u8 {
+        shared.write(4096, &[round; 4096]).unwrap();
+    }
+    for thread in readers {
+        thread.join().unwrap();
+    }
+    assert!(shared.cached_pages() <= 4);
+    assert_eq!(shared.read(4096, 4096).unwrap(), vec![19; 4096]);
+
+    // Growing the file past a cached partial last page
+    assert_eq!(shared.read(8 * 4096, 100).unwrap(), vec![1; 100]);
+    shared.write(9 * 4096, b"grown").unwrap();
+    assert_eq!(shared.read(8 * 4096 + 99, 2).unwrap(), [1, 0]);
+    assert_eq!(shared.read(9 * 4096, 5).unwrap(), b"grown");
+    assert!(shared.read(shared.file_size(), 1).is_err());
+}
+
+#[cfg(feature = "epoch")]
+#[test]
+fn test_read_mostly_cache_secure() {
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .secure(true)
+        .open()
+        .unwrap();
+    cache.write(0, &[1; 4 * 4096]).unwrap();
+    let shared = ReadMostlyCache::new(cache, 2).unwrap();
+
+    // Pages replaced by writes and evicted by reads are wiped once freed,
+    // while the published copies stay intact
+    for page_id in 0..4u64 {
+        assert_eq!(shared.read(page_id * 4096, 10).unwrap(), [1; 10]);
+        shared.write(page_id * 4096, &[2; 10]).unwrap();
+    }
+    assert_eq!(shared.cached_pages(), 2);
+    assert_eq!(shared.read(3 * 4096, 12).unwrap()[9..], [2, 1, 1]);
+    assert_eq!(shared.read(0, 12).unwrap()[9..], [2, 1, 1]);
+}
+
+#[test]
+fn test_endian_accessors() {
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    cache.write_u32_le(0, 0x0102_0304).unwrap();
+    cache.write_u32_be(4, 0x0102_0304).unwrap();
+    assert_eq!(cache.read(0, 8).unwrap(), [4, 3, 2, 1, 1, 2, 3, 4]);
+    assert_eq!(cache.read_u32_le(4).unwrap(), 0x0403_0201);
+    assert_eq!(cache.read_u16_be(0).unwrap(), 0x0403);
+
+    // Values spanning a page boundary
+    cache.write_i64_be(4092, -2).unwrap();
+    assert_eq!(cache.read_i64_be(4092).unwrap(), -2);
+    cache.write_f64_le(8, 1.5).unwrap();
+    assert_eq!(cache.read_f64_le(8).unwrap(), 1.5);
+    cache.write_f32_be(16, -0.25).unwrap();
+    assert_eq!(cache.read_f32_be(16).unwrap(), -0.25);
+    cache.write_i8(20, -1).unwrap();
+    assert_eq!(
+        (cache.read_u8(20).unwrap(), cache.read_i8(20).unwrap()),
+        (255, -1)
+    );
+    cache.write_u8(21, 7).unwrap();
+    assert_eq!(cache.read_i16_le(20).unwrap(), 0x07ff);
+
+    assert!(cache.read_u64_le(cache.file_size() - 4).is_err());
+}
+
+#[cfg(feature = "zerocopy")]
+#[test]
+fn test_typed_records() {
+    use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+    #[derive(Debug, PartialEq, FromBytes, IntoBytes, Immutable, KnownLayout)]
+    #[repr(C)]
+    struct RecordHeader {
+        magic: [u8; 4],
+        version: u32,
+        length: u64,
+    }
+
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    let header = RecordHeader {
+        magic: *b"REC1",
+        version: 2,
+        length: 1 << 40,
+    };
+    // Straddling a page boundary
+    cache.write_as(4090, &header).unwrap();
+    assert_eq!(cache.read_as::<RecordHeader>(4090).unwrap(), header);
+    assert_eq!(cache.read(4090, 4).unwrap(), b"REC1");
+    assert_eq!(cache.read_as::<u32>(4094).unwrap(), 2);
+    assert!(cache
+        .read_as::<RecordHeader>(cache.file_size() - 8)
+        .is_err());
+}
+
+#[cfg(feature = "postcard")]
+#[test]
+fn test_serde_records() {
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Checkpoint {
+        name: String,
+        offsets: Vec<u64>,
+        ratio: Option<f32>,
+    }
+
+    let mut cache = WriteThroughCache::builder(tmp_file())
+        .page_size(4096)
+        .open()
+        .unwrap();
+    let checkpoint = Checkpoint {
+        name: "nightly".to_string(),
+        offsets: (0..1000).map(|i| i * 4096).collect(),
+        ratio: Some(0.5),
+    };
+    let written = cache.write_serde(100, &checkpoint).unwrap();
+    assert!(written > 8 && written < 4096);
+    assert_eq!(
+        cache.read_serde::<Checkpoint>(100, 4096).unwrap(),
+        checkpoint
+    );
+    assert_eq!(
+        cache
+            .read_serde::<Checkpoint>(100, written - 1)
+            .err()
+            .unwrap()
+            .kind(),
+        ErrorKind::InvalidData
+    );
+
+    cache.write(100 + written as u64 - 1, &[0xff]).unwrap();
+    assert_eq!(
+        cache
+            .read_serde::<Checkpoint>(100, 4096)
+            .err()
+            .unwrap()
+            .kind(),
+        ErrorKind::InvalidData
+    );
+}